@@ -0,0 +1,102 @@
+//! Throwaway single-key keypairs for regtest/signet scripting
+//!
+//! No mnemonic, no derivation, no wallet: just a fresh secret key and every
+//! common encoding of it, including the BIP86 tweak used for Taproot key-path
+//! spends.
+
+use bdk_wallet::bitcoin::key::{Keypair, Secp256k1, TapTweak};
+use bdk_wallet::bitcoin::secp256k1::{All, SecretKey};
+use bdk_wallet::bitcoin::{Address, CompressedPublicKey, Network, PrivateKey};
+use rand::RngCore;
+use serde::Serialize;
+
+/// Every encoding of one keypair
+#[derive(Debug, PartialEq, Eq, Serialize)]
+pub struct KeyInfo {
+    pub wif: String,
+    pub secret_hex: String,
+    /// Compressed SEC1 public key
+    pub pubkey: String,
+    pub xonly_pubkey: String,
+    /// Secret key after the BIP86 tweak, for signing P2TR key-path spends
+    pub tweaked_secret_hex: String,
+    /// The tweaked x-only key committed to in the P2TR output
+    pub tweaked_xonly_pubkey: String,
+    pub p2tr: String,
+    pub p2wpkh: String,
+}
+
+/// Draws a uniformly random valid secret key
+pub fn random_secret() -> SecretKey {
+    let mut bytes = [0u8; 32];
+    loop {
+        rand::thread_rng().fill_bytes(&mut bytes);
+        // Fails only for zero or values above the curve order
+        if let Ok(secret) = SecretKey::from_slice(&bytes) {
+            return secret;
+        }
+    }
+}
+
+/// Derives all encodings of `secret` for the given network
+pub fn key_info(secp: &Secp256k1<All>, secret: SecretKey, network: Network) -> KeyInfo {
+    let private_key = PrivateKey::new(secret, network);
+    let pubkey = CompressedPublicKey::from_private_key(secp, &private_key)
+        .expect("keys built from a SecretKey are always compressed");
+    let keypair = Keypair::from_secret_key(secp, &secret);
+    let (xonly, _) = keypair.x_only_public_key();
+    let tweaked = keypair.tap_tweak(secp, None).to_keypair();
+
+    KeyInfo {
+        wif: private_key.to_wif(),
+        secret_hex: secret.display_secret().to_string(),
+        pubkey: pubkey.to_string(),
+        xonly_pubkey: xonly.to_string(),
+        tweaked_secret_hex: tweaked.secret_key().display_secret().to_string(),
+        tweaked_xonly_pubkey: tweaked.x_only_public_key().0.to_string(),
+        p2tr: Address::p2tr(secp, xonly, None, network).to_string(),
+        p2wpkh: Address::p2wpkh(&pubkey, network).to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Secret key 1, whose public key is the generator point G
+    fn secret_one() -> SecretKey {
+        let mut bytes = [0u8; 32];
+        bytes[31] = 1;
+        SecretKey::from_slice(&bytes).unwrap()
+    }
+
+    #[test]
+    fn test_key_info_for_known_secret() {
+        let secp = Secp256k1::new();
+        let info = key_info(&secp, secret_one(), Network::Bitcoin);
+
+        assert_eq!(info.wif, "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn");
+        assert_eq!(info.pubkey, "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        assert_eq!(info.xonly_pubkey, "79be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798");
+        assert_eq!(info.p2wpkh, "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+        assert!(info.p2tr.starts_with("bc1p"));
+        assert_ne!(info.tweaked_xonly_pubkey, info.xonly_pubkey);
+    }
+
+    #[test]
+    fn test_tweaked_key_matches_p2tr_output() {
+        let secp = Secp256k1::new();
+        let info = key_info(&secp, random_secret(), Network::Regtest);
+
+        let address: Address = info.p2tr.parse::<Address<_>>().unwrap().assume_checked();
+        // Witness program of a P2TR output is the tweaked x-only key
+        let script = address.script_pubkey();
+        assert_eq!(&script.as_bytes()[2..], hex_bytes(&info.tweaked_xonly_pubkey));
+        assert!(info.p2tr.starts_with("bcrt1p"));
+        assert!(info.p2wpkh.starts_with("bcrt1q"));
+    }
+
+    fn hex_bytes(hex: &str) -> Vec<u8> {
+        (0..hex.len()).step_by(2).map(|i| u8::from_str_radix(&hex[i..i + 2], 16).unwrap()).collect()
+    }
+}
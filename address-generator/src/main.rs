@@ -1,3 +1,6 @@
+mod keygen;
+
+use bdk_wallet::bitcoin::key::Secp256k1;
 use bdk_wallet::bitcoin::Network;
 use bdk_wallet::keys::bip39::Mnemonic;
 use bdk_wallet::keys::{DerivableKey, ExtendedKey};
@@ -19,25 +22,38 @@ struct Args {
     #[arg(short, long, value_enum, default_value = "segwit")]
     address_type: AddressType,
     
-    /// Network (testnet or bitcoin)
+    /// Network (testnet, signet, regtest, or bitcoin)
     #[arg(short, long, default_value = "testnet")]
     network: String,
     
-    /// Number of addresses to generate
+    /// Number of addresses (or keypairs with --keygen) to generate
     #[arg(short = 'c', long, default_value = "1")]
     count: u32,
     
     /// Optional mnemonic seed phrase (generates random if not provided)
     #[arg(short, long)]
     seed: Option<String>,
+
+    /// Generate throwaway single-key keypairs instead of wallet addresses
+    #[arg(long, conflicts_with_all = ["seed", "address_type"])]
+    keygen: bool,
+
+    /// Print keypairs as JSON, one object per line
+    #[arg(long, requires = "keygen")]
+    json: bool,
 }
 
 /// Validates and parses the network name
 fn parse_network(network: &str) -> Result<Network, String> {
     match network {
         "testnet" => Ok(Network::Testnet),
+        "signet" => Ok(Network::Signet),
+        "regtest" => Ok(Network::Regtest),
         "bitcoin" | "mainnet" => Ok(Network::Bitcoin),
-        _ => Err(format!("Invalid network: '{}'. Use 'testnet' or 'bitcoin'", network)),
+        _ => Err(format!(
+            "Invalid network: '{}'. Use 'testnet', 'signet', 'regtest', or 'bitcoin'",
+            network
+        )),
     }
 }
 
@@ -83,6 +99,38 @@ fn create_change_descriptor(address_type: &AddressType, xprv: &str, network: Net
     }
 }
 
+/// Prints `count` fresh keypairs as text blocks or JSON lines
+fn run_keygen(network: Network, count: u32, json: bool) {
+    let secp = Secp256k1::new();
+
+    if !json {
+        println!("=== Throwaway Keypairs ===\n");
+        println!("Network: {:?}", network);
+    }
+
+    for i in 0..count {
+        let info = keygen::key_info(&secp, keygen::random_secret(), network);
+        if json {
+            println!("{}", serde_json::to_string(&info).expect("key info always serializes"));
+            continue;
+        }
+
+        println!("\n[{}]", i);
+        println!("  WIF:                {}", info.wif);
+        println!("  Secret key:         {}", info.secret_hex);
+        println!("  Public key:         {}", info.pubkey);
+        println!("  X-only public key:  {}", info.xonly_pubkey);
+        println!("  Tweaked secret key: {}", info.tweaked_secret_hex);
+        println!("  Tweaked x-only key: {}", info.tweaked_xonly_pubkey);
+        println!("  P2TR:               {}", info.p2tr);
+        println!("  P2WPKH:             {}", info.p2wpkh);
+    }
+
+    if network == Network::Bitcoin {
+        eprintln!("\n⚠️  WARNING: These are REAL Bitcoin keys, printed in plain text!");
+    }
+}
+
 fn main() {
     let args = Args::parse();
 
@@ -94,6 +142,11 @@ fn main() {
             return;
         }
     };
+
+    if args.keygen {
+        run_keygen(network, args.count, args.json);
+        return;
+    }
     
     // Generate or parse mnemonic
     let mnemonic = if let Some(seed_phrase) = args.seed {
@@ -161,10 +214,16 @@ mod tests {
         assert_eq!(parse_network("mainnet").unwrap(), Network::Bitcoin);
     }
 
+    #[test]
+    fn test_parse_network_signet_and_regtest() {
+        assert_eq!(parse_network("signet").unwrap(), Network::Signet);
+        assert_eq!(parse_network("regtest").unwrap(), Network::Regtest);
+    }
+
     #[test]
     fn test_parse_network_invalid() {
         assert!(parse_network("invalid").is_err());
-        assert!(parse_network("regtest").is_err());
+        assert!(parse_network("Regtest").is_err());
         assert!(parse_network("").is_err());
     }
 
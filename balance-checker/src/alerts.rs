@@ -0,0 +1,214 @@
+//! Alerts for `--watch`: each balance change as a JSON payload, POSTed to
+//! `--webhook` and piped to the `--exec` command's stdin
+//!
+//! The payload carries the old and new balance and every transaction behind
+//! the change: `new` when it first appears, `confirmed` when it gets into a
+//! block, and `dropped` when it leaves the mempool unconfirmed (replaced or
+//! evicted). Its `text` field is the line the watch prints, so a Slack or
+//! Mattermost incoming webhook shows it as is. An alert that fails only
+//! warns; the watch goes on.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::Txid;
+use bdk::esplora_client::Tx;
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use std::collections::{HashMap, HashSet};
+use std::io::Write;
+use std::process::Stdio;
+use std::time::Duration;
+use url::Url;
+
+use crate::export;
+use crate::proxy::Proxy;
+use crate::utxos;
+use crate::BalanceInfo;
+
+const WEBHOOK_TIMEOUT_SECS: u64 = 10;
+
+/// Net effect on the balance and confirmation height of each transaction
+pub type TxEffects = HashMap<Txid, (i64, Option<u32>)>;
+
+/// What each transaction did to the balance of `scripts`, and where it confirmed
+pub fn tx_effects(txs: &[Tx], scripts: &HashSet<sha256::Hash>) -> TxEffects {
+    export::history_rows(txs, scripts).iter().map(|row| (row.tx.txid, (row.amount, row.tx.status.block_height))).collect()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TxEvent {
+    New,
+    Confirmed,
+    Dropped,
+}
+
+impl TxEvent {
+    fn name(&self) -> &'static str {
+        match self {
+            TxEvent::New => "new",
+            TxEvent::Confirmed => "confirmed",
+            TxEvent::Dropped => "dropped",
+        }
+    }
+}
+
+/// One transaction behind a change
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TxChange {
+    pub txid: Txid,
+    pub event: TxEvent,
+    /// Sats added to (or, if negative, taken from) the balance
+    pub amount: i64,
+    pub height: Option<u32>,
+}
+
+/// Transactions that appeared, confirmed, or disappeared between two polls,
+/// newest first
+pub fn tx_changes(previous: &TxEffects, current: &TxEffects) -> Vec<TxChange> {
+    let mut changes: Vec<TxChange> = current
+        .iter()
+        .filter_map(|(&txid, &(amount, height))| {
+            let event = match previous.get(&txid) {
+                None => TxEvent::New,
+                Some((_, None)) if height.is_some() => TxEvent::Confirmed,
+                Some(_) => return None,
+            };
+            Some(TxChange { txid, event, amount, height })
+        })
+        .chain(
+            previous
+                .iter()
+                .filter(|(txid, _)| !current.contains_key(*txid))
+                .map(|(&txid, &(amount, height))| TxChange { txid, event: TxEvent::Dropped, amount, height }),
+        )
+        .collect();
+    changes.sort_by_key(|change| (std::cmp::Reverse(change.height.unwrap_or(u32::MAX)), change.txid));
+    changes
+}
+
+/// The JSON sent to `--webhook` and `--exec`; confirmations are counted
+/// from `tip_height` when it's known
+pub fn payload(
+    text: &str,
+    previous: &BalanceInfo,
+    current: &BalanceInfo,
+    changes: &[TxChange],
+    tip_height: Option<u32>,
+    time: DateTime<Utc>,
+) -> Value {
+    let balance = |balance: &BalanceInfo| {
+        json!({ "confirmed": balance.confirmed, "unconfirmed": balance.unconfirmed, "total": balance.total() })
+    };
+    let transactions: Vec<Value> = changes
+        .iter()
+        .map(|change| {
+            let confirmations = match tip_height {
+                Some(tip) => Some(utxos::confirmations(change.height, tip)),
+                // Unconfirmed needs no tip to count
+                None => change.height.is_none().then_some(0),
+            };
+            json!({
+                "txid": change.txid.to_string(),
+                "event": change.event.name(),
+                "amount": change.amount,
+                "confirmations": confirmations,
+                "block_height": change.height,
+            })
+        })
+        .collect();
+    json!({
+        "event": "balance_changed",
+        "text": text,
+        "time": time.to_rfc3339_opts(SecondsFormat::Secs, true),
+        "tip_height": tip_height,
+        "previous": balance(previous),
+        "balance": balance(current),
+        "change": current.total() as i64 - previous.total() as i64,
+        "transactions": transactions,
+    })
+}
+
+/// Checks a `--webhook` URL up front, so a typo fails before the watch starts
+pub fn validate_webhook(url: &str) -> Result<(), String> {
+    let parsed = Url::parse(url).map_err(|e| format!("Invalid --webhook URL: {}", e))?;
+    if !matches!(parsed.scheme(), "http" | "https") {
+        return Err(format!("Invalid --webhook URL: expected http:// or https://, not {}://", parsed.scheme()));
+    }
+    Ok(())
+}
+
+/// POSTs the payload as JSON, through `proxy` if given
+pub fn post_webhook(url: &str, payload: &Value, proxy: Option<&Proxy>) -> Result<(), String> {
+    let mut agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(WEBHOOK_TIMEOUT_SECS));
+    if let Some(proxy) = proxy {
+        agent = agent.proxy(proxy.for_ureq()?);
+    }
+    agent.build().post(url).send_json(payload).map_err(|e| e.to_string())?;
+    Ok(())
+}
+
+/// Runs the command through the shell with the payload on stdin, and waits
+/// for it
+pub fn run_exec(command: &str, payload: &Value, env: &[(&'static str, String)]) -> Result<(), String> {
+    let mut child = crate::watch::shell(command)
+        .envs(env.iter().cloned())
+        .stdin(Stdio::piped())
+        .spawn()
+        .map_err(|e| format!("Could not run it: {}", e))?;
+    if let Some(mut stdin) = child.stdin.take() {
+        // A command that doesn't read its input closes the pipe early
+        let _ = writeln!(stdin, "{}", payload);
+    }
+    let status = child.wait().map_err(|e| e.to_string())?;
+    if !status.success() {
+        return Err(format!("it exited with {}", status));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::txid;
+
+    #[test]
+    fn test_tx_changes() {
+        let previous = TxEffects::from([(txid(1), (50_000, Some(100))), (txid(2), (20_000, None)), (txid(3), (-5_000, None))]);
+        let current = TxEffects::from([(txid(1), (50_000, Some(100))), (txid(2), (20_000, Some(101))), (txid(4), (7_000, None))]);
+        let changes = tx_changes(&previous, &current);
+        let events: Vec<(Txid, TxEvent)> = changes.iter().map(|change| (change.txid, change.event)).collect();
+        assert_eq!(events, vec![(txid(3), TxEvent::Dropped), (txid(4), TxEvent::New), (txid(2), TxEvent::Confirmed)]);
+        assert!(tx_changes(&current, &current).is_empty());
+    }
+
+    #[test]
+    fn test_payload() {
+        let previous = BalanceInfo { confirmed: 100_000, unconfirmed: 0 };
+        let current = BalanceInfo { confirmed: 100_000, unconfirmed: 50_000 };
+        let changes = [
+            TxChange { txid: txid(4), event: TxEvent::New, amount: 50_000, height: None },
+            TxChange { txid: txid(2), event: TxEvent::Confirmed, amount: -1_000, height: Some(99) },
+        ];
+        let time = DateTime::from_timestamp(1_700_000_000, 0).unwrap();
+        let payload = payload("Balance changed", &previous, &current, &changes, Some(100), time);
+
+        assert_eq!(payload["text"], "Balance changed");
+        assert_eq!(payload["time"], "2023-11-14T22:13:20Z");
+        assert_eq!(payload["balance"]["total"], 150_000);
+        assert_eq!(payload["change"], 50_000);
+        assert_eq!(payload["transactions"][0]["txid"], txid(4).to_string());
+        assert_eq!(payload["transactions"][0]["event"], "new");
+        assert_eq!(payload["transactions"][0]["confirmations"], 0);
+        assert_eq!(payload["transactions"][1]["amount"], -1_000);
+        assert_eq!(payload["transactions"][1]["confirmations"], 2);
+
+        let without_tip = super::payload("", &previous, &current, &changes, None, time);
+        assert!(without_tip["transactions"][1]["confirmations"].is_null());
+    }
+
+    #[test]
+    fn test_validate_webhook() {
+        assert!(validate_webhook("https://hooks.slack.com/services/T0/B0/x").is_ok());
+        assert!(validate_webhook("ftp://example.com").is_err());
+        assert!(validate_webhook("hooks.slack.com").is_err());
+    }
+}
@@ -0,0 +1,139 @@
+//! Batch checks for `--stdin` and `--input-file`: one address, descriptor or
+//! other input per line, one tab-separated record per input on stdout
+//!
+//! A failed input still gets its record, with the numbers left empty and the
+//! reason in the last column, so output lines match input lines.
+
+use std::io::{self, BufRead};
+
+use crate::chain::Chain;
+use crate::error::CheckerError;
+use crate::wallet::ScanLimits;
+use crate::{concurrent, query};
+use crate::{check_network, fetch_target, tally, BalanceInfo, FetchOptions, Source};
+
+const HEADER: &str = "input\tkind\tconfirmed\tunconfirmed\ttotal\tutxos\ttxs\terror";
+
+/// The balance of one input
+#[derive(Debug, PartialEq, Eq)]
+struct Checked {
+    kind: &'static str,
+    balance: BalanceInfo,
+    unspent_count: usize,
+    tx_count: usize,
+}
+
+/// Reads one input per line, skipping blank lines and `#` comments
+pub fn read_inputs(reader: impl BufRead) -> io::Result<Vec<String>> {
+    let mut inputs = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            inputs.push(trimmed.to_string());
+        }
+    }
+    Ok(inputs)
+}
+
+fn check(
+    source: &Source,
+    network: Chain,
+    input: &str,
+    limits: ScanLimits,
+    concurrency: usize,
+) -> Result<Checked, CheckerError> {
+    let target = query::parse_query_target(input).map_err(CheckerError::InvalidInput)?;
+    check_network(input, &target, network).map_err(CheckerError::InvalidInput)?;
+    let history = fetch_target(source, &target, FetchOptions::quiet(limits, concurrency))?;
+    let (balance, unspent_count) = tally(&history);
+    Ok(Checked { kind: target.kind(), balance, unspent_count, tx_count: history.txs.len() })
+}
+
+/// Checks up to `concurrency` inputs at once and prints their records in
+/// input order as each group finishes. If any input failed, the error says
+/// how many, as a network failure if any of them was one.
+pub fn run(
+    source: &mut Source,
+    network: Chain,
+    inputs: &[String],
+    limits: ScanLimits,
+    concurrency: usize,
+) -> Result<(), CheckerError> {
+    println!("{}", HEADER);
+    let mut failures = Vec::new();
+    // Simulated inputs each need their own chain, so they go one at a time
+    let group_size = if matches!(source, Source::Simulated(_)) { 1 } else { concurrency };
+    for group in inputs.chunks(group_size) {
+        // Inputs checked side by side scan their own scripts one at a time,
+        // keeping to `concurrency` requests overall
+        let per_input = if group.len() > 1 { 1 } else { concurrency };
+        let shared = &*source;
+        let results = concurrent::map(group, concurrency, |input| check(shared, network, input, limits, per_input));
+        for (input, result) in group.iter().zip(&results) {
+            println!("{}", format_record(input, result));
+        }
+        failures.extend(results.into_iter().filter_map(Result::err));
+        source.next_input();
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+    let message = format!("{} of {} inputs could not be checked; see the error column", failures.len(), inputs.len());
+    Err(if failures.iter().any(|failure| matches!(failure, CheckerError::Network(_))) {
+        CheckerError::Network(message)
+    } else {
+        CheckerError::InvalidInput(message)
+    })
+}
+
+/// Tab-separated record matching [`HEADER`]
+fn format_record(input: &str, result: &Result<Checked, CheckerError>) -> String {
+    match result {
+        Ok(checked) => format!(
+            "{}\t{}\t{}\t{}\t{}\t{}\t{}\t",
+            input,
+            checked.kind,
+            checked.balance.confirmed,
+            checked.balance.unconfirmed,
+            checked.balance.total(),
+            checked.unspent_count,
+            checked.tx_count
+        ),
+        // Keep the record on one line whatever the error says
+        Err(err) => format!("{}\t\t\t\t\t\t\t{}", input, err.to_string().replace(['\t', '\n'], " ")),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_inputs_skips_blanks_and_comments() {
+        let input = "# deposit addresses\ntb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx\n\n  wpkh(02aa)  \n";
+        assert_eq!(
+            read_inputs(input.as_bytes()).unwrap(),
+            vec!["tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx", "wpkh(02aa)"]
+        );
+    }
+
+    #[test]
+    fn test_format_record() {
+        let checked = Checked {
+            kind: "address",
+            balance: BalanceInfo { confirmed: 1_500_000, unconfirmed: 2_000 },
+            unspent_count: 3,
+            tx_count: 7,
+        };
+        let record = format_record("tb1qexample", &Ok(checked));
+        assert_eq!(record, "tb1qexample\taddress\t1500000\t2000\t1502000\t3\t7\t");
+
+        let failed = format_record("nonsense", &Err(CheckerError::InvalidInput("Invalid input:\n'nonsense'".to_string())));
+        assert_eq!(failed, "nonsense\t\t\t\t\t\t\tInvalid input: 'nonsense'");
+        // Every record has as many columns as the header
+        assert_eq!(record.split('\t').count(), HEADER.split('\t').count());
+        assert_eq!(failed.split('\t').count(), HEADER.split('\t').count());
+    }
+}
@@ -0,0 +1,369 @@
+//! Bitcoin Core backend for `--backend bitcoind`: balances from the node's
+//! `scantxoutset` RPC, so no indexer is needed and a pruned node will do
+//!
+//! The UTXO set holds only unspent, confirmed outputs, so that is all this
+//! backend sees: no spends, no mempool, and no block times. Each unspent
+//! output is returned inside a stand-in transaction so the usual balance and
+//! `--utxos` code can run on it; features that need the full history are
+//! refused before scanning. Silent payment addresses are the exception:
+//! their blocks are read whole, with `getblock`'s prevouts, so their
+//! transactions are complete.
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::hashes::hex::FromHex;
+use bdk::bitcoin::hashes::Hash;
+use bdk::bitcoin::{BlockHash, Script, Txid};
+use bdk::esplora_client::{PrevOut, Tx, TxStatus, Vin, Vout};
+use serde_json::{json, Value};
+use std::collections::{BTreeMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+use crate::chain::Chain;
+use crate::query::{script_hash, QueryTarget};
+
+/// Derivation indexes scanned for each ranged descriptor without a
+/// `--scan-limit`, like Core's default
+const SCAN_RANGE: u32 = 1000;
+
+/// Seconds to wait for the node to accept a connection; the scan itself can
+/// take minutes, so reads have no limit
+const CONNECT_TIMEOUT_SECS: u64 = 10;
+
+pub struct BitcoindSource {
+    agent: ureq::Agent,
+    url: String,
+    /// Value of the Authorization header
+    auth: String,
+}
+
+/// The node's default RPC endpoint
+pub fn default_url(chain: Chain) -> &'static str {
+    match chain {
+        Chain::Bitcoin => "http://127.0.0.1:8332",
+        Chain::Testnet => "http://127.0.0.1:18332",
+        Chain::Testnet4 => "http://127.0.0.1:48332",
+        Chain::Signet => "http://127.0.0.1:38332",
+        Chain::Regtest => "http://127.0.0.1:18443",
+    }
+}
+
+/// Where the node writes its auth cookie by default
+pub fn default_cookie(chain: Chain) -> Option<PathBuf> {
+    let data_dir = PathBuf::from(std::env::var_os("HOME")?).join(".bitcoin");
+    let data_dir = match chain {
+        Chain::Bitcoin => data_dir,
+        Chain::Testnet => data_dir.join("testnet3"),
+        Chain::Testnet4 | Chain::Signet | Chain::Regtest => data_dir.join(chain.name()),
+    };
+    Some(data_dir.join(".cookie"))
+}
+
+impl BitcoindSource {
+    /// Credentials come from `user`/`password` when given, otherwise from the
+    /// cookie file
+    pub fn new(url: &str, user: Option<&str>, password: Option<&str>, cookie: Option<&Path>) -> Result<Self, String> {
+        let credentials = match (user, cookie) {
+            (Some(user), _) => format!("{}:{}", user, password.unwrap_or("")),
+            (None, Some(cookie)) => std::fs::read_to_string(cookie)
+                .map(|contents| contents.trim().to_string())
+                .map_err(|e| format!("Could not read RPC cookie {}: {}", cookie.display(), e))?,
+            (None, None) => return Err("No RPC credentials: give --rpc-user and --rpc-password, or --rpc-cookie".to_string()),
+        };
+        Ok(BitcoindSource {
+            agent: ureq::AgentBuilder::new().timeout_connect(Duration::from_secs(CONNECT_TIMEOUT_SECS)).build(),
+            url: url.to_string(),
+            auth: format!("Basic {}", BASE64_STANDARD.encode(credentials)),
+        })
+    }
+
+    fn call(&self, method: &str, params: Value) -> Result<Value, String> {
+        let request = json!({ "jsonrpc": "1.0", "id": "balance-checker", "method": method, "params": params });
+        let response = match self.agent.post(&self.url).set("Authorization", &self.auth).send_json(request) {
+            Ok(response) => response,
+            Err(ureq::Error::Status(401, _)) => {
+                return Err("RPC authentication failed; check the RPC user, password, or cookie".to_string())
+            }
+            // RPC errors come back as HTTP 500 with the reason in the body
+            Err(ureq::Error::Status(_, response)) => response,
+            Err(e) => return Err(format!("Could not reach the node at {}: {}", self.url, e)),
+        };
+        let mut body: Value = response.into_json().map_err(|e| e.to_string())?;
+        if let Some(message) = body["error"]["message"].as_str() {
+            return Err(format!("{} failed: {}", method, message));
+        }
+        Ok(body["result"].take())
+    }
+
+    pub fn tip_height(&self) -> Result<u32, String> {
+        let count = self.call("getblockcount", json!([]))?;
+        count.as_u64().map(|height| height as u32).ok_or_else(|| "getblockcount returned no height".to_string())
+    }
+
+    /// Unspent outputs of the target, wrapped in stand-in transactions, and
+    /// the hashes of the scripts holding them; ranged descriptors are
+    /// scanned to `scan_limit` scripts
+    pub fn scan(&self, target: &QueryTarget, scan_limit: Option<u32>) -> Result<(Vec<Tx>, HashSet<sha256::Hash>), String> {
+        let result = self.call("scantxoutset", json!(["start", scan_objects(target, scan_limit.unwrap_or(SCAN_RANGE))?]))?;
+        parse_unspents(&result)
+    }
+
+    /// Every transaction of the block at `height`, with the outputs its
+    /// inputs spend
+    pub fn block_txs(&self, height: u32) -> Result<Vec<Tx>, String> {
+        let hash = self.call("getblockhash", json!([height]))?;
+        // Verbosity 3 adds each input's prevout
+        let block = self.call("getblock", json!([hash, 3]))?;
+        parse_block(&block)
+    }
+}
+
+/// `scantxoutset` scan objects for a target, with indexes below `range` of
+/// each ranged descriptor
+fn scan_objects(target: &QueryTarget, range: u32) -> Result<Vec<Value>, String> {
+    match target {
+        QueryTarget::Address(address) => Ok(vec![json!(format!("addr({})", address))]),
+        QueryTarget::Script(script) => Ok(vec![json!(format!("raw({:x})", script))]),
+        QueryTarget::ScriptHash(_) => {
+            Err("--backend bitcoind needs the address or script itself, not its hash".to_string())
+        }
+        QueryTarget::SilentPayment(_) => Err("Silent payments are found by scanning blocks, not the UTXO set".to_string()),
+        QueryTarget::Wallet(wallet) => Ok(wallet
+            .descriptors
+            .iter()
+            .map(|entry| {
+                let descriptor = entry.descriptor.to_string();
+                if entry.descriptor.has_wildcard() {
+                    json!({ "desc": descriptor, "range": range - 1 })
+                } else {
+                    json!(descriptor)
+                }
+            })
+            .collect()),
+    }
+}
+
+/// Groups the scan's unspent outputs by transaction, newest first. Outputs
+/// the scan didn't return are zero-value placeholders so each `vout` keeps
+/// its index, and a coinbase (flagged since Bitcoin Core 25) gets a coinbase
+/// input.
+fn parse_unspents(result: &Value) -> Result<(Vec<Tx>, HashSet<sha256::Hash>), String> {
+    let unspents = result["unspents"].as_array().ok_or("scantxoutset returned no unspents")?;
+    let mut by_txid: BTreeMap<Txid, Tx> = BTreeMap::new();
+    let mut scripts = HashSet::new();
+
+    for unspent in unspents {
+        let malformed = || format!("scantxoutset returned a malformed output: {}", unspent);
+        let txid = unspent["txid"].as_str().and_then(|txid| Txid::from_str(txid).ok()).ok_or_else(malformed)?;
+        let vout = unspent["vout"].as_u64().ok_or_else(malformed)? as usize;
+        let script = unspent["scriptPubKey"].as_str().and_then(|hex| Script::from_str(hex).ok()).ok_or_else(malformed)?;
+        let amount = unspent["amount"].as_f64().ok_or_else(malformed)?;
+        let height = unspent["height"].as_u64().ok_or_else(malformed)? as u32;
+        let coinbase = unspent["coinbase"].as_bool().unwrap_or(false);
+
+        scripts.insert(script_hash(&script));
+        let tx = by_txid.entry(txid).or_insert_with(|| Tx {
+            txid,
+            version: 2,
+            locktime: 0,
+            vin: if coinbase {
+                vec![Vin {
+                    txid: Txid::all_zeros(),
+                    vout: u32::MAX,
+                    prevout: None,
+                    scriptsig: Script::new(),
+                    witness: vec![],
+                    sequence: u32::MAX,
+                    is_coinbase: true,
+                }]
+            } else {
+                vec![]
+            },
+            vout: vec![],
+            status: TxStatus { confirmed: true, block_height: Some(height), block_hash: None, block_time: None },
+            fee: 0,
+        });
+        if tx.vout.len() <= vout {
+            tx.vout.resize(vout + 1, Vout { value: 0, scriptpubkey: Script::new() });
+        }
+        tx.vout[vout] = Vout { value: (amount * 100_000_000.0).round() as u64, scriptpubkey: script };
+    }
+
+    let mut txs: Vec<Tx> = by_txid.into_values().collect();
+    txs.sort_by_key(|tx| std::cmp::Reverse(tx.status.block_height));
+    Ok((txs, scripts))
+}
+
+fn sats(btc: &Value) -> Option<u64> {
+    btc.as_f64().map(|btc| (btc * 100_000_000.0).round() as u64)
+}
+
+fn script(hex: &Value) -> Option<Script> {
+    hex.as_str().and_then(|hex| Script::from_str(hex).ok())
+}
+
+/// A `getblock` result at verbosity 3, as transactions in Esplora's form
+fn parse_block(block: &Value) -> Result<Vec<Tx>, String> {
+    let malformed = || "getblock returned a malformed block".to_string();
+    let height = block["height"].as_u64().ok_or_else(malformed)? as u32;
+    let hash = block["hash"].as_str().and_then(|hash| BlockHash::from_str(hash).ok()).ok_or_else(malformed)?;
+    let time = block["time"].as_u64().ok_or_else(malformed)?;
+    let status = TxStatus { confirmed: true, block_height: Some(height), block_hash: Some(hash), block_time: Some(time) };
+
+    let txs = block["tx"].as_array().ok_or_else(malformed)?;
+    txs.iter()
+        .map(|tx| {
+            let malformed = || format!("getblock returned a malformed transaction: {}", tx["txid"]);
+            let vin = tx["vin"]
+                .as_array()
+                .ok_or_else(malformed)?
+                .iter()
+                .map(|input| {
+                    let is_coinbase = input["coinbase"].is_string();
+                    let (txid, vout) = if is_coinbase {
+                        (Txid::all_zeros(), u32::MAX)
+                    } else {
+                        let txid = input["txid"].as_str().and_then(|txid| Txid::from_str(txid).ok());
+                        (txid.ok_or_else(malformed)?, input["vout"].as_u64().ok_or_else(malformed)? as u32)
+                    };
+                    let prevout = if is_coinbase {
+                        None
+                    } else {
+                        let prevout = &input["prevout"];
+                        Some(PrevOut {
+                            value: sats(&prevout["value"]).ok_or_else(malformed)?,
+                            scriptpubkey: script(&prevout["scriptPubKey"]["hex"]).ok_or_else(malformed)?,
+                        })
+                    };
+                    let witness = match input["txinwitness"].as_array() {
+                        Some(items) => items
+                            .iter()
+                            .map(|item| item.as_str().and_then(|hex| Vec::<u8>::from_hex(hex).ok()).ok_or_else(malformed))
+                            .collect::<Result<Vec<_>, _>>()?,
+                        None => Vec::new(),
+                    };
+                    Ok(Vin {
+                        txid,
+                        vout,
+                        prevout,
+                        scriptsig: script(&input["scriptSig"]["hex"]).unwrap_or_default(),
+                        witness,
+                        sequence: input["sequence"].as_u64().ok_or_else(malformed)? as u32,
+                        is_coinbase,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            let vout = tx["vout"]
+                .as_array()
+                .ok_or_else(malformed)?
+                .iter()
+                .map(|output| {
+                    Ok(Vout {
+                        value: sats(&output["value"]).ok_or_else(malformed)?,
+                        scriptpubkey: script(&output["scriptPubKey"]["hex"]).ok_or_else(malformed)?,
+                    })
+                })
+                .collect::<Result<Vec<_>, String>>()?;
+            Ok(Tx {
+                txid: tx["txid"].as_str().and_then(|txid| Txid::from_str(txid).ok()).ok_or_else(malformed)?,
+                version: tx["version"].as_i64().ok_or_else(malformed)? as i32,
+                locktime: tx["locktime"].as_u64().ok_or_else(malformed)? as u32,
+                vin,
+                vout,
+                status: status.clone(),
+                // Coinbase transactions have no fee
+                fee: tx["fee"].as_f64().map_or(0, |fee| (fee * 100_000_000.0).round() as u64),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parse_query_target;
+
+    const SCRIPT_HEX: &str = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+
+    #[test]
+    fn test_scan_objects() {
+        let address = parse_query_target("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap();
+        assert_eq!(scan_objects(&address, SCAN_RANGE).unwrap(), vec![json!("addr(tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx)")]);
+        let script = parse_query_target(SCRIPT_HEX).unwrap();
+        assert_eq!(scan_objects(&script, SCAN_RANGE).unwrap(), vec![json!(format!("raw({})", SCRIPT_HEX))]);
+
+        let zpub = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+        let objects = scan_objects(&parse_query_target(zpub).unwrap(), SCAN_RANGE).unwrap();
+        assert_eq!(objects.len(), 2);
+        assert_eq!(objects[0]["range"], json!(999));
+        assert_eq!(scan_objects(&parse_query_target(zpub).unwrap(), 5000).unwrap()[1]["range"], json!(4999));
+        assert!(objects[0]["desc"].as_str().unwrap().starts_with("wpkh(xpub"));
+
+        let hash = parse_query_target(&format!("scripthash:{}", "ab".repeat(32))).unwrap();
+        assert!(scan_objects(&hash, SCAN_RANGE).is_err());
+    }
+
+    #[test]
+    fn test_parse_unspents() {
+        let txid = "11".repeat(32);
+        let result = json!({
+            "success": true,
+            "height": 850000,
+            "unspents": [
+                { "txid": txid, "vout": 2, "scriptPubKey": SCRIPT_HEX, "amount": 0.015, "height": 840000 },
+                { "txid": txid, "vout": 0, "scriptPubKey": SCRIPT_HEX, "amount": 0.00002, "height": 840000 },
+                { "txid": "22".repeat(32), "vout": 0, "scriptPubKey": SCRIPT_HEX, "amount": 1.0, "height": 845000, "coinbase": true }
+            ],
+            "total_amount": 1.01502
+        });
+
+        let (txs, scripts) = parse_unspents(&result).unwrap();
+        assert_eq!(scripts.len(), 1);
+        assert_eq!(txs.len(), 2);
+        assert_eq!(txs[0].status.block_height, Some(845000));
+        let values: Vec<u64> = txs[1].vout.iter().map(|output| output.value).collect();
+        assert_eq!(values, vec![2_000, 0, 1_500_000]);
+        assert!(txs[0].vin[0].is_coinbase && txs[1].vin.is_empty());
+
+        assert!(parse_unspents(&json!({ "unspents": [{ "txid": "zz" }] })).is_err());
+    }
+
+    #[test]
+    fn test_parse_block() {
+        let block = json!({
+            "hash": "00".repeat(32),
+            "height": 850000,
+            "time": 1_718_000_000,
+            "tx": [
+                {
+                    "txid": "11".repeat(32), "version": 2, "locktime": 0,
+                    "vin": [{ "coinbase": "03", "sequence": 4294967295u32 }],
+                    "vout": [{ "value": 3.125, "scriptPubKey": { "hex": SCRIPT_HEX } }]
+                },
+                {
+                    "txid": "22".repeat(32), "version": 2, "locktime": 849999, "fee": 0.000015,
+                    "vin": [{
+                        "txid": "11".repeat(32), "vout": 0, "sequence": 4294967293u32,
+                        "scriptSig": { "hex": "" }, "txinwitness": ["3044", "02aa"],
+                        "prevout": { "value": 3.125, "scriptPubKey": { "hex": SCRIPT_HEX } }
+                    }],
+                    "vout": [{ "value": 3.124985, "scriptPubKey": { "hex": SCRIPT_HEX } }]
+                }
+            ]
+        });
+
+        let txs = parse_block(&block).unwrap();
+        assert!(txs[0].vin[0].is_coinbase && txs[0].vin[0].prevout.is_none());
+        assert_eq!(txs[0].fee, 0);
+        let spend = &txs[1];
+        assert_eq!(spend.vin[0].prevout.as_ref().unwrap().value, 312_500_000);
+        assert_eq!(spend.vin[0].witness, vec![vec![0x30, 0x44], vec![0x02, 0xaa]]);
+        assert_eq!(spend.vout[0].value, 312_498_500);
+        assert_eq!(spend.fee, 1_500);
+        assert_eq!(spend.status.block_time, Some(1_718_000_000));
+
+        assert!(parse_block(&json!({ "height": 1 })).is_err());
+    }
+}
@@ -0,0 +1,951 @@
+//! Compact block filter backend for `--backend cbf`: a light client that
+//! fetches each block's BIP158 filter from a P2P peer and matches the
+//! scripts against it locally, so no server learns which addresses are
+//! checked
+//!
+//! Headers are checked to connect, to follow the chain's difficulty
+//! adjustments and to carry the proof of work they ask for. They're kept
+//! next to the history store, so later runs only download the new ones.
+//! Filters are fetched from `--birthday` up. Only the blocks whose filters
+//! match are downloaded, and each is searched as it arrives and dropped;
+//! the peer sees which blocks those are among everything it serves, but
+//! never the scripts. A wallet's
+//! scripts are derived `--gap-limit` past the last one used, and when a
+//! scan finds use further on, the newly derived scripts are matched against
+//! the filters again. The peer is trusted to send honest filters, and
+//! there's no mempool to see, so every balance is confirmed.
+
+use bdk::bitcoin::blockdata::constants::genesis_block;
+use bdk::bitcoin::consensus::encode::{deserialize, serialize};
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::hashes::Hash;
+use bdk::bitcoin::network::address::Address as PeerAddress;
+use bdk::bitcoin::network::constants::ServiceFlags;
+use bdk::bitcoin::network::message::{NetworkMessage, RawNetworkMessage};
+use bdk::bitcoin::network::message_blockdata::{GetHeadersMessage, Inventory};
+use bdk::bitcoin::network::message_filter::GetCFilters;
+use bdk::bitcoin::network::message_network::VersionMessage;
+use bdk::bitcoin::util::bip158::BlockFilter;
+use bdk::bitcoin::util::uint::Uint256;
+use bdk::bitcoin::{Block, BlockHash, BlockHeader, Script, Transaction, TxMerkleNode, Txid};
+use bdk::esplora_client::Tx;
+use bdk::KeychainKind;
+use chrono::Utc;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::net::{SocketAddr, TcpStream, ToSocketAddrs};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::chain::Chain;
+use crate::electrum::to_esplora_tx;
+use crate::progress::Progress;
+use crate::proxy::Proxy;
+use crate::query::{script_hash, QueryTarget};
+use crate::wallet::{ChainUsage, ScanLimits, ScanResult};
+
+/// Protocol version sent in `version`; BIP157 peers speak 70016
+const PROTOCOL_VERSION: u32 = 70016;
+const USER_AGENT: &str = "/balance-checker:0.1.0/";
+/// Most headers a peer sends per `getheaders`
+const MAX_HEADERS: usize = 2000;
+/// Most filters a peer serves per `getcfilters`
+const MAX_FILTERS: u32 = 1000;
+/// Blocks asked for per `getdata`
+const BLOCKS_PER_REQUEST: usize = 16;
+/// Largest message accepted; a block is at most 4 MB
+const MAX_MESSAGE_SIZE: usize = 32 * 1024 * 1024;
+/// BIP158 basic filter
+const BASIC_FILTER: u8 = 0;
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const READ_TIMEOUT: Duration = Duration::from_secs(60);
+/// Peers tried from a DNS seed before giving up; most don't serve filters
+const MAX_ATTEMPTS: usize = 16;
+/// Blocks between difficulty adjustments
+const RETARGET_INTERVAL: u32 = 2016;
+/// Seconds a retarget interval's blocks are meant to take
+const TARGET_TIMESPAN: u32 = 14 * 24 * 60 * 60;
+/// Gap after which testnet and regtest allow a block at minimum difficulty
+const MIN_DIFFICULTY_GAP: u32 = 20 * 60;
+/// File in the history store's directory the headers are kept in
+pub const HEADERS_FILE: &str = "cbf-headers";
+/// Size of a serialized block header
+const HEADER_SIZE: usize = 80;
+
+/// A DNS seed for the chain, which answers with a different set of peers
+/// on every lookup; regtest has none
+pub fn default_peer(chain: Chain) -> Option<&'static str> {
+    match chain {
+        Chain::Bitcoin => Some("seed.bitcoin.sipa.be:8333"),
+        Chain::Testnet => Some("testnet-seed.bitcoin.jonasschnelli.ch:18333"),
+        Chain::Testnet4 => Some("seed.testnet4.bitcoin.sprovoost.nl:48333"),
+        Chain::Signet => Some("seed.signet.bitcoin.sprovoost.nl:38333"),
+        Chain::Regtest => None,
+    }
+}
+
+/// Bytes every message on the chain starts with, as a little-endian number
+fn magic(chain: Chain) -> u32 {
+    match chain {
+        // Not known to the bitcoin library; 1c163f28 on the wire
+        Chain::Testnet4 => 0x283f_161c,
+        _ => chain.network().magic(),
+    }
+}
+
+fn genesis_header(chain: Chain) -> BlockHeader {
+    match chain {
+        // Not known to the bitcoin library either
+        Chain::Testnet4 => BlockHeader {
+            version: 1,
+            prev_blockhash: BlockHash::all_zeros(),
+            merkle_root: TxMerkleNode::from_str("7aa0a7ae1e223414cb807e40cd57e667b718e42aaf9306db9102fe28912b7b4e")
+                .expect("valid merkle root"),
+            time: 1_714_777_860,
+            bits: 0x1d00_ffff,
+            nonce: 393_743_547,
+        },
+        _ => genesis_block(chain.network()).header,
+    }
+}
+
+/// A chain's difficulty rules, as Bitcoin Core's `GetNextWorkRequired`
+/// applies them
+#[derive(Clone, Copy)]
+struct Difficulty {
+    /// Easiest target allowed, in compact form
+    pow_limit: u32,
+    /// A block more than 20 minutes after its parent may be at the easiest
+    /// target, and the blocks after it go back to the last real one's
+    min_difficulty_blocks: bool,
+    /// The target never changes (regtest)
+    no_retargeting: bool,
+    /// A retarget scales the interval's first target rather than its last
+    /// (BIP94, testnet4)
+    retarget_from_first: bool,
+}
+
+impl Difficulty {
+    fn of(chain: Chain) -> Self {
+        let (pow_limit, min_difficulty_blocks) = match chain {
+            Chain::Bitcoin => (0x1d00_ffff, false),
+            Chain::Testnet | Chain::Testnet4 => (0x1d00_ffff, true),
+            Chain::Signet => (0x1e03_77ae, false),
+            Chain::Regtest => (0x207f_ffff, true),
+        };
+        Difficulty {
+            pow_limit,
+            min_difficulty_blocks,
+            no_retargeting: chain == Chain::Regtest,
+            retarget_from_first: chain == Chain::Testnet4,
+        }
+    }
+
+    /// The compact target due for a block at `time` on top of `links`
+    fn next_bits(&self, links: &[Link], time: u32) -> u32 {
+        let height = links.len() as u32;
+        let last = links.last().expect("genesis is always there");
+        if !height.is_multiple_of(RETARGET_INTERVAL) {
+            if !self.min_difficulty_blocks {
+                return last.bits;
+            }
+            if time > last.time.saturating_add(MIN_DIFFICULTY_GAP) {
+                return self.pow_limit;
+            }
+            return links
+                .iter()
+                .enumerate()
+                .rev()
+                .find(|(height, link)| (*height as u32).is_multiple_of(RETARGET_INTERVAL) || link.bits != self.pow_limit)
+                .map(|(_, link)| link.bits)
+                .expect("genesis starts an interval");
+        }
+        if self.no_retargeting {
+            return last.bits;
+        }
+
+        let first = &links[(height - RETARGET_INTERVAL) as usize];
+        let timespan = (i64::from(last.time) - i64::from(first.time))
+            .clamp(i64::from(TARGET_TIMESPAN / 4), i64::from(TARGET_TIMESPAN * 4));
+        let base = if self.retarget_from_first { first.bits } else { last.bits };
+        let target = BlockHeader::u256_from_compact_target(base).mul_u32(timespan as u32)
+            / Uint256::from_u64(u64::from(TARGET_TIMESPAN)).expect("fits");
+        let limit = BlockHeader::u256_from_compact_target(self.pow_limit);
+        BlockHeader::compact_target_from_u256(&target.min(limit))
+    }
+
+    /// Checks that `header`, on top of `links`, has the target that's due
+    /// and the proof of work it asks for
+    fn check(&self, links: &[Link], header: &BlockHeader) -> Result<BlockHash, String> {
+        let due = self.next_bits(links, header.time);
+        if header.bits != due {
+            return Err(format!(
+                "header {} has target {:#010x} where {:#010x} is due",
+                header.block_hash(),
+                header.bits,
+                due
+            ));
+        }
+        header
+            .validate_pow(&header.target())
+            .map_err(|_| format!("header {} lacks its proof of work", header.block_hash()))
+    }
+}
+
+/// What later headers are checked against, for each block of the chain
+#[derive(Clone, Copy)]
+struct Link {
+    hash: BlockHash,
+    bits: u32,
+    time: u32,
+}
+
+impl Link {
+    fn new(hash: BlockHash, header: &BlockHeader) -> Self {
+        Link { hash, bits: header.bits, time: header.time }
+    }
+}
+
+/// The headers kept between runs: each block's from height 1 up, as they
+/// go over the wire
+struct HeaderFile {
+    file: File,
+    path: PathBuf,
+}
+
+impl HeaderFile {
+    /// Opens the file, creating it and its directory if needed, and reads
+    /// the headers in it
+    fn open(path: &Path) -> Result<(Self, Vec<BlockHeader>), String> {
+        let error = |e: std::io::Error| format!("Could not open the stored headers {}: {}", path.display(), e);
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir).map_err(error)?;
+        }
+        let mut file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path).map_err(error)?;
+        let mut bytes = Vec::new();
+        file.read_to_end(&mut bytes).map_err(error)?;
+        let headers = bytes
+            .chunks_exact(HEADER_SIZE)
+            .map(deserialize)
+            .collect::<Result<_, _>>()
+            .map_err(|e| format!("The stored headers {} are corrupt: {}", path.display(), e))?;
+        Ok((HeaderFile { file, path: path.to_path_buf() }, headers))
+    }
+
+    /// Replaces the headers from `height` up with `headers`
+    fn write(&mut self, height: u32, headers: &[BlockHeader]) -> Result<(), String> {
+        let error = |e: std::io::Error| format!("Could not write the stored headers {}: {}", self.path.display(), e);
+        let kept = (u64::from(height) - 1) * HEADER_SIZE as u64;
+        self.file.set_len(kept).map_err(error)?;
+        self.file.seek(SeekFrom::Start(kept)).map_err(error)?;
+        let bytes: Vec<u8> = headers.iter().flat_map(serialize).collect();
+        self.file.write_all(&bytes).map_err(error)
+    }
+}
+
+/// The chain from genesis through the stored `headers` that still pass
+/// the checks; the file is cut back to those if some don't
+fn load_chain(chain: Chain, rules: &Difficulty, file: &mut Option<HeaderFile>, headers: Vec<BlockHeader>) -> Vec<Link> {
+    let genesis = genesis_header(chain);
+    let mut links = vec![Link::new(genesis.block_hash(), &genesis)];
+    let stored = headers.len();
+    for header in headers {
+        if header.prev_blockhash != links[links.len() - 1].hash {
+            break;
+        }
+        match rules.check(&links, &header) {
+            Ok(hash) => links.push(Link::new(hash, &header)),
+            Err(_) => break,
+        }
+    }
+    if links.len() <= stored {
+        let height = links.len() as u32;
+        eprintln!("Warning: the stored headers are invalid from height {}; syncing them again from there", height);
+        if let Some(Err(e)) = file.as_mut().map(|file| file.write(height, &[])) {
+            eprintln!("Warning: {}", e);
+            *file = None;
+        }
+    }
+    links
+}
+
+/// A connection to one peer, past the handshake
+struct Peer {
+    stream: TcpStream,
+    magic: u32,
+}
+
+impl Peer {
+    /// Exchanges `version` and `verack`, refusing a peer that doesn't serve
+    /// compact block filters
+    fn handshake(stream: TcpStream, chain: Chain) -> Result<Peer, String> {
+        stream.set_read_timeout(Some(READ_TIMEOUT)).map_err(|e| e.to_string())?;
+        stream.set_write_timeout(Some(READ_TIMEOUT)).map_err(|e| e.to_string())?;
+        let mut peer = Peer { stream, magic: magic(chain) };
+
+        // Nothing about this client is given away, not even its address
+        let unknown = PeerAddress::new(&SocketAddr::from(([0, 0, 0, 0], 0)), ServiceFlags::NONE);
+        peer.send(NetworkMessage::Version(VersionMessage {
+            version: PROTOCOL_VERSION,
+            services: ServiceFlags::NONE,
+            timestamp: Utc::now().timestamp(),
+            receiver: unknown.clone(),
+            sender: unknown,
+            nonce: rand::random(),
+            user_agent: USER_AGENT.to_string(),
+            start_height: 0,
+            relay: false,
+        }))?;
+
+        let (mut services, mut acknowledged) = (None, false);
+        while services.is_none() || !acknowledged {
+            match peer.receive()? {
+                NetworkMessage::Version(version) => {
+                    services = Some(version.services);
+                    peer.send(NetworkMessage::Verack)?;
+                }
+                NetworkMessage::Verack => acknowledged = true,
+                _ => {}
+            }
+        }
+        if !services.is_some_and(|services| services.has(ServiceFlags::COMPACT_FILTERS)) {
+            return Err("the peer doesn't serve compact block filters (it needs peerblockfilters=1)".to_string());
+        }
+        Ok(peer)
+    }
+
+    fn send(&mut self, payload: NetworkMessage) -> Result<(), String> {
+        let message = serialize(&RawNetworkMessage { magic: self.magic, payload });
+        self.stream.write_all(&message).map_err(|e| format!("Could not send to the peer: {}", e))
+    }
+
+    /// The next message from the peer, answering pings on the way
+    fn receive(&mut self) -> Result<NetworkMessage, String> {
+        loop {
+            let read_error = |e: std::io::Error| format!("Could not read from the peer: {}", e);
+            let mut message = vec![0; 24];
+            self.stream.read_exact(&mut message).map_err(read_error)?;
+            let length = u32::from_le_bytes(message[16..20].try_into().expect("four bytes")) as usize;
+            if length > MAX_MESSAGE_SIZE {
+                return Err(format!("The peer sent a {} byte message", length));
+            }
+            message.resize(24 + length, 0);
+            self.stream.read_exact(&mut message[24..]).map_err(read_error)?;
+
+            let raw: RawNetworkMessage =
+                deserialize(&message).map_err(|e| format!("The peer sent a malformed message: {}", e))?;
+            if raw.magic != self.magic {
+                return Err("The peer is on another network".to_string());
+            }
+            match raw.payload {
+                NetworkMessage::Ping(nonce) => self.send(NetworkMessage::Pong(nonce))?,
+                payload => return Ok(payload),
+            }
+        }
+    }
+}
+
+/// The peer and the best chain it has shown so far
+struct State {
+    peer: Peer,
+    /// The chain's blocks by height, from genesis
+    links: Vec<Link>,
+    rules: Difficulty,
+    /// Where the headers are kept, unless --no-store
+    file: Option<HeaderFile>,
+}
+
+impl State {
+    /// Brings `links` up to the peer's tip, and the header file with it.
+    /// Headers must connect and pass the difficulty checks; a header that
+    /// connects further back replaces the blocks after it, as after a reorg.
+    fn sync_headers(&mut self) -> Result<(), String> {
+        loop {
+            let hashes: Vec<BlockHash> = self.links.iter().map(|link| link.hash).collect();
+            self.peer.send(NetworkMessage::GetHeaders(GetHeadersMessage::new(locator(&hashes), BlockHash::all_zeros())))?;
+            let headers: Vec<BlockHeader> = loop {
+                if let NetworkMessage::Headers(headers) = self.peer.receive()? {
+                    break headers;
+                }
+            };
+            let count = headers.len();
+            // The headers from `changed` up are new to the file
+            let mut changed = self.links.len() as u32;
+            let mut new = Vec::new();
+            for header in headers {
+                if header.prev_blockhash != self.links[self.links.len() - 1].hash {
+                    let Some(fork) = self.links.iter().rposition(|link| link.hash == header.prev_blockhash) else {
+                        return Err(format!("The peer sent header {} that doesn't connect to the chain", header.block_hash()));
+                    };
+                    self.links.truncate(fork + 1);
+                    let height = fork as u32 + 1;
+                    if height < changed {
+                        changed = height;
+                        new.clear();
+                    } else {
+                        new.truncate((height - changed) as usize);
+                    }
+                }
+                let hash = self.rules.check(&self.links, &header).map_err(|e| format!("The peer sent {}", e))?;
+                self.links.push(Link::new(hash, &header));
+                new.push(header);
+            }
+            if let Some(Err(e)) = self.file.as_mut().filter(|_| !new.is_empty()).map(|file| file.write(changed, &new)) {
+                eprintln!("Warning: {}", e);
+                self.file = None;
+            }
+            if count < MAX_HEADERS {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Heights from `start` up whose filters match any of `scripts`
+    fn matching_heights(&mut self, start: u32, scripts: &[Script], progress: &Progress) -> Result<Vec<u32>, String> {
+        let tip = self.links.len() as u32 - 1;
+        let mut heights = Vec::new();
+        if scripts.is_empty() {
+            return Ok(heights);
+        }
+        let mut batch_start = start;
+        while batch_start <= tip {
+            let stop = (batch_start + MAX_FILTERS - 1).min(tip);
+            self.peer.send(NetworkMessage::GetCFilters(GetCFilters {
+                filter_type: BASIC_FILTER,
+                start_height: batch_start,
+                stop_hash: self.links[stop as usize].hash,
+            }))?;
+            let mut height = batch_start;
+            while height <= stop {
+                let NetworkMessage::CFilter(filter) = self.peer.receive()? else { continue };
+                let hash = self.links[height as usize].hash;
+                if filter.filter_type != BASIC_FILTER || filter.block_hash != hash {
+                    return Err(format!("The peer sent a filter for another block than {}", hash));
+                }
+                let matched = BlockFilter::new(&filter.filter)
+                    .match_any(&hash, &mut scripts.iter().map(Script::as_bytes))
+                    .map_err(|e| format!("The peer sent a malformed filter for block {}: {}", hash, e))?;
+                if matched {
+                    heights.push(height);
+                }
+                height += 1;
+            }
+            progress.filters(stop, tip);
+            batch_start = stop + 1;
+        }
+        Ok(heights)
+    }
+
+    /// Downloads the blocks at `heights` (ascending), with witnesses, and
+    /// hands each to `found` in height order, so only a request's worth is
+    /// held at a time
+    fn blocks(&mut self, heights: &[u32], found: &mut Found, scripts: &HashSet<Script>) -> Result<(), String> {
+        for group in heights.chunks(BLOCKS_PER_REQUEST) {
+            let wanted: HashMap<BlockHash, u32> = group.iter().map(|&height| (self.links[height as usize].hash, height)).collect();
+            self.peer.send(NetworkMessage::GetData(
+                group.iter().map(|&height| Inventory::WitnessBlock(self.links[height as usize].hash)).collect(),
+            ))?;
+            // Blocks that arrive ahead of their turn wait here
+            let mut early: BTreeMap<u32, Block> = BTreeMap::new();
+            let mut next = 0;
+            while next < group.len() {
+                match self.peer.receive()? {
+                    NetworkMessage::Block(block) => {
+                        if let Some(&height) = wanted.get(&block.block_hash()) {
+                            early.insert(height, block);
+                        }
+                    }
+                    NetworkMessage::NotFound(_) => {
+                        return Err("The peer doesn't have a matching block (it may be pruned)".to_string());
+                    }
+                    _ => {}
+                }
+                while let Some(block) = group.get(next).and_then(|height| early.remove(height)) {
+                    found.add(group[next], &block, scripts);
+                    next += 1;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Hashes the peer can find where our chain and its own part: the last ten
+/// blocks, then exponentially further back, and genesis
+fn locator(hashes: &[BlockHash]) -> Vec<BlockHash> {
+    let mut locator = Vec::new();
+    let mut height = hashes.len() - 1;
+    let mut step = 1;
+    loop {
+        locator.push(hashes[height]);
+        if height == 0 {
+            return locator;
+        }
+        if locator.len() >= 10 {
+            step *= 2;
+        }
+        height = height.saturating_sub(step);
+    }
+}
+
+pub struct CbfSource {
+    state: Mutex<State>,
+    /// First height whose filter is fetched
+    birthday: u32,
+}
+
+impl CbfSource {
+    /// Connects to `peer` (`host:port`), trying the addresses it resolves to
+    /// until one serves filters, and syncs its headers from those stored at
+    /// `headers`, if given. Through a proxy the name is resolved by the
+    /// proxy, so a DNS seed gives a new peer on each attempt.
+    pub fn connect(
+        peer: &str,
+        chain: Chain,
+        proxy: Option<&Proxy>,
+        birthday: u32,
+        headers: Option<&Path>,
+    ) -> Result<Self, String> {
+        let rules = Difficulty::of(chain);
+        let (mut file, stored) = match headers.map(HeaderFile::open) {
+            Some(Ok((file, stored))) => (Some(file), stored),
+            Some(Err(e)) => {
+                eprintln!("Warning: {}; syncing headers from genesis", e);
+                (None, Vec::new())
+            }
+            None => (None, Vec::new()),
+        };
+        let mut links = load_chain(chain, &rules, &mut file, stored);
+
+        let attempts: Vec<Option<SocketAddr>> = match proxy {
+            Some(_) => vec![None; MAX_ATTEMPTS],
+            None => peer
+                .to_socket_addrs()
+                .map_err(|e| format!("Could not resolve {}: {}", peer, e))?
+                .take(MAX_ATTEMPTS)
+                .map(Some)
+                .collect(),
+        };
+        let tried = attempts.len();
+        let mut last_error = format!("{} resolved to no addresses", peer);
+        for address in attempts {
+            let stream = match (proxy, address) {
+                (Some(proxy), _) => proxy.connect(peer),
+                (None, Some(address)) => TcpStream::connect_timeout(&address, CONNECT_TIMEOUT).map_err(|e| e.to_string()),
+                (None, None) => unreachable!("addresses are given without a proxy"),
+            };
+            match stream.and_then(|stream| Peer::handshake(stream, chain)) {
+                Ok(peer) => {
+                    let mut state = State { peer, links: std::mem::take(&mut links), rules, file: file.take() };
+                    state.sync_headers()?;
+                    let tip = state.links.len() as u32 - 1;
+                    if birthday > tip {
+                        return Err(format!("--birthday {} is above the chain tip ({})", birthday, tip));
+                    }
+                    return Ok(CbfSource { state: Mutex::new(state), birthday });
+                }
+                Err(e) => last_error = e,
+            }
+        }
+        Err(if tried > 1 {
+            format!("None of {} peers tried at {} would serve compact block filters; last error: {}", tried, peer, last_error)
+        } else {
+            format!("Could not use peer {}: {}", peer, last_error)
+        })
+    }
+
+    pub fn tip_height(&self) -> Result<u32, String> {
+        Ok(self.state.lock().unwrap().links.len() as u32 - 1)
+    }
+
+    pub fn birthday(&self) -> u32 {
+        self.birthday
+    }
+
+    /// Every transaction of the target from `birthday` on. A wallet's
+    /// chains are derived to `limits.gap_limit` past their last used script
+    /// and rescanned for the new scripts until no more are needed.
+    pub fn scan(&self, target: &QueryTarget, limits: ScanLimits, progress: &Progress) -> Result<ScanResult, String> {
+        let mut state = self.state.lock().unwrap();
+        // Watch mode scans again on every poll
+        state.sync_headers()?;
+        let start = self.birthday.max(1);
+
+        let wallet = match target {
+            QueryTarget::Wallet(wallet) => wallet,
+            QueryTarget::ScriptHash(_) => {
+                return Err("--backend cbf needs the address or script itself, not its hash".to_string())
+            }
+            _ => {
+                let script = target.script_pubkey().expect("addresses and scripts have one");
+                let heights = state.matching_heights(start, std::slice::from_ref(&script), progress)?;
+                progress.script_done();
+                let mut found = Found::default();
+                state.blocks(&heights, &mut found, &HashSet::from([script.clone()]))?;
+                return Ok(ScanResult {
+                    txs: found.txs(),
+                    scripts: HashSet::from([script_hash(&script)]),
+                    keychains: HashMap::new(),
+                    chains: Vec::new(),
+                });
+            }
+        };
+
+        // Script -> (descriptor, index)
+        let mut derived: HashMap<Script, (usize, u32)> = HashMap::new();
+        let mut counts = vec![0; wallet.descriptors.len()];
+        let mut wanted: Vec<u32> = wallet
+            .descriptors
+            .iter()
+            .map(|entry| if entry.descriptor.has_wildcard() { limits.scan_limit.map_or(limits.gap_limit, |limit| limits.gap_limit.min(limit)) } else { 1 })
+            .collect();
+        let mut found = Found::default();
+        loop {
+            let mut new_scripts = Vec::new();
+            for (position, entry) in wallet.descriptors.iter().enumerate() {
+                for index in counts[position]..wanted[position] {
+                    let script = entry.descriptor.at_derivation_index(index).script_pubkey();
+                    derived.insert(script.clone(), (position, index));
+                    new_scripts.push(script);
+                }
+                if wanted[position] > counts[position] && entry.descriptor.has_wildcard() {
+                    progress.derived(&entry.label, wanted[position] - 1);
+                }
+                counts[position] = wanted[position];
+            }
+            if new_scripts.is_empty() {
+                break;
+            }
+
+            // Blocks searched before for the older scripts are downloaded
+            // again, since they weren't kept
+            let heights = state.matching_heights(start, &new_scripts, progress)?;
+            new_scripts.iter().for_each(|_| progress.script_done());
+            let all: HashSet<Script> = derived.keys().cloned().collect();
+            state.blocks(&heights, &mut found, &all)?;
+
+            // Use further along a chain than it was derived needs more scripts
+            for tx in found.txs.values() {
+                for output in &tx.output {
+                    if let Some(&(position, index)) = derived.get(&output.script_pubkey) {
+                        if wallet.descriptors[position].descriptor.has_wildcard() {
+                            let needed = index + 1 + limits.gap_limit;
+                            let needed = limits.scan_limit.map_or(needed, |limit| needed.min(limit));
+                            wanted[position] = wanted[position].max(needed);
+                        }
+                    }
+                }
+            }
+        }
+
+        let all: HashSet<Script> = derived.keys().cloned().collect();
+        let txs = found.txs();
+        let used: HashSet<&Script> = txs.iter().flat_map(|tx| &tx.vout).map(|output| &output.scriptpubkey).filter(|script| all.contains(*script)).collect();
+        let mut keychains: HashMap<KeychainKind, HashSet<sha256::Hash>> = HashMap::new();
+        for (script, &(position, _)) in &derived {
+            if let Some(keychain) = wallet.descriptors[position].keychain {
+                keychains.entry(keychain).or_default().insert(script_hash(script));
+            }
+        }
+        let chains = wallet
+            .descriptors
+            .iter()
+            .enumerate()
+            .map(|(position, entry)| {
+                let used_here: HashSet<u32> = derived
+                    .iter()
+                    .filter(|(script, &(owner, _))| owner == position && used.contains(script))
+                    .map(|(_, &(_, index))| index)
+                    .collect();
+                let last_used = used_here.iter().map(|index| index + 1).max().unwrap_or(0);
+                ChainUsage {
+                    label: entry.label.clone(),
+                    scanned: counts[position],
+                    used: used_here.len() as u32,
+                    next_index: last_used,
+                    unused: (0..last_used).filter(|index| !used_here.contains(index)).collect(),
+                    limited: entry.descriptor.has_wildcard()
+                        && limits.scan_limit.is_some_and(|limit| counts[position] == limit && last_used + limits.gap_limit > limit),
+                }
+            })
+            .collect();
+        Ok(ScanResult { txs, scripts: derived.keys().map(script_hash).collect(), keychains, chains })
+    }
+}
+
+/// The transactions of the downloaded blocks that pay one of the scripts
+/// or spend an output that did, kept instead of the blocks
+#[derive(Default)]
+struct Found {
+    txs: HashMap<Txid, Transaction>,
+    /// Height and header of the block each transaction is in
+    blocks: HashMap<Txid, (u32, BlockHeader)>,
+}
+
+impl Found {
+    /// Keeps the transactions of `block` related to `scripts`. Blocks come
+    /// in height order, so a spend's parent has been seen before it.
+    fn add(&mut self, height: u32, block: &Block, scripts: &HashSet<Script>) {
+        for tx in &block.txdata {
+            let pays = tx.output.iter().any(|output| scripts.contains(&output.script_pubkey));
+            let spends = tx.input.iter().any(|input| {
+                self.txs
+                    .get(&input.previous_output.txid)
+                    .and_then(|parent| parent.output.get(input.previous_output.vout as usize))
+                    .is_some_and(|output| scripts.contains(&output.script_pubkey))
+            });
+            if pays || spends {
+                let txid = tx.txid();
+                self.txs.insert(txid, tx.clone());
+                self.blocks.insert(txid, (height, block.header));
+            }
+        }
+    }
+
+    /// Newest block first. Inputs spending anyone else's outputs have no
+    /// prevout, as their parents were never downloaded.
+    fn txs(&self) -> Vec<Tx> {
+        let mut txs: Vec<Tx> = self
+            .txs
+            .iter()
+            .map(|(txid, tx)| {
+                let (height, header) = &self.blocks[txid];
+                to_esplora_tx(tx, Some(*height), Some(header), &self.txs)
+            })
+            .collect();
+        txs.sort_by_key(|tx| std::cmp::Reverse(tx.status.block_height));
+        txs
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::query::parse_query_target;
+    use crate::test_util::{script, transaction};
+    use bdk::bitcoin::network::message_filter::CFilter;
+    use bdk::bitcoin::OutPoint;
+    use std::net::TcpListener;
+    use std::thread;
+
+    const DESCRIPTOR: &str = "wpkh([73c5da0a/84'/0'/0']xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V/0/*)";
+
+    /// Mines `txs` on `prev` with regtest's trivial target
+    fn mine(prev: BlockHash, time: u32, txs: Vec<Transaction>) -> Block {
+        let header = BlockHeader {
+            version: 0x2000_0000,
+            prev_blockhash: prev,
+            merkle_root: TxMerkleNode::all_zeros(),
+            time,
+            bits: 0x207f_ffff,
+            nonce: 0,
+        };
+        let mut block = Block { header, txdata: txs };
+        block.header.merkle_root = block.compute_merkle_root().unwrap();
+        solve(&mut block.header);
+        block
+    }
+
+    fn solve(header: &mut BlockHeader) {
+        while header.validate_pow(&header.target()).is_err() {
+            header.nonce += 1;
+        }
+    }
+
+    /// Blocks 1 up, each a coinbase paying the given outputs plus any
+    /// further transactions
+    fn chain(blocks: Vec<Vec<Transaction>>) -> Vec<Block> {
+        let mut chain: Vec<Block> = Vec::new();
+        for (height, txs) in blocks.into_iter().enumerate() {
+            let prev = chain.last().map_or(genesis_header(Chain::Regtest).block_hash(), Block::block_hash);
+            chain.push(mine(prev, 1_700_000_000 + height as u32 * 600, txs));
+        }
+        chain
+    }
+
+    /// Serves `blocks` as a regtest peer on a local port, for one client
+    fn serve(blocks: Vec<Block>, services: ServiceFlags) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let address = listener.local_addr().unwrap().to_string();
+        thread::spawn(move || {
+            let (stream, _) = listener.accept().unwrap();
+            let mut peer = Peer { stream, magic: magic(Chain::Regtest) };
+            let outputs: HashMap<OutPoint, Script> = blocks
+                .iter()
+                .flat_map(|block| &block.txdata)
+                .flat_map(|tx| {
+                    tx.output.iter().enumerate().map(move |(vout, output)| (OutPoint::new(tx.txid(), vout as u32), output.script_pubkey.clone()))
+                })
+                .collect();
+            let mut hashes = vec![genesis_header(Chain::Regtest).block_hash()];
+            hashes.extend(blocks.iter().map(Block::block_hash));
+
+            while let Ok(message) = peer.receive() {
+                let replies = match message {
+                    NetworkMessage::Version(mut version) => {
+                        version.services = services;
+                        vec![NetworkMessage::Version(version), NetworkMessage::Verack]
+                    }
+                    NetworkMessage::GetHeaders(request) => {
+                        let known = request.locator_hashes.iter().find_map(|hash| hashes.iter().position(|known| known == hash)).unwrap_or(0);
+                        vec![NetworkMessage::Headers(blocks[known..].iter().map(|block| block.header).collect())]
+                    }
+                    NetworkMessage::GetCFilters(request) => {
+                        let stop = hashes.iter().position(|hash| *hash == request.stop_hash).unwrap();
+                        (request.start_height as usize..=stop)
+                            .map(|height| {
+                                let block = &blocks[height - 1];
+                                let filter = BlockFilter::new_script_filter(block, |outpoint| Ok(outputs[outpoint].clone())).unwrap();
+                                NetworkMessage::CFilter(CFilter { filter_type: BASIC_FILTER, block_hash: hashes[height], filter: filter.content })
+                            })
+                            .collect()
+                    }
+                    NetworkMessage::GetData(inventory) => inventory
+                        .iter()
+                        .map(|item| {
+                            let Inventory::WitnessBlock(hash) = item else { panic!("only blocks are asked for") };
+                            NetworkMessage::Block(blocks.iter().find(|block| block.block_hash() == *hash).unwrap().clone())
+                        })
+                        .collect(),
+                    _ => vec![],
+                };
+                for reply in replies {
+                    peer.send(reply).unwrap();
+                }
+            }
+        });
+        address
+    }
+
+    #[test]
+    fn test_scan_an_address() {
+        let funding = transaction(&[], &[(script(1), 50_000), (script(7), 10_000)]);
+        let spend = transaction(&[OutPoint::new(funding.txid(), 0)], &[(script(9), 30_000), (script(1), 19_000)]);
+        let blocks = chain(vec![vec![funding.clone()], vec![transaction(&[], &[(script(8), 1)])], vec![transaction(&[], &[(script(8), 2)]), spend.clone()]]);
+        let peer = serve(blocks, ServiceFlags::NETWORK | ServiceFlags::WITNESS | ServiceFlags::COMPACT_FILTERS);
+
+        let node = CbfSource::connect(&peer, Chain::Regtest, None, 0, None).unwrap();
+        assert_eq!(node.tip_height().unwrap(), 3);
+        let result = node.scan(&QueryTarget::Script(script(1)), ScanLimits::default(), &Progress::new(false)).unwrap();
+
+        let txids: Vec<Txid> = result.txs.iter().map(|tx| tx.txid).collect();
+        assert_eq!(txids, vec![spend.txid(), funding.txid()]);
+        assert_eq!(result.txs[0].status.block_height, Some(3));
+        assert_eq!(result.txs[0].vin[0].prevout.as_ref().map(|prevout| prevout.value), Some(50_000));
+        assert_eq!(result.txs[0].fee, 1_000);
+        // The coinbase's parent was never downloaded
+        assert!(result.txs[1].vin[0].prevout.is_none());
+    }
+
+    #[test]
+    fn test_scan_extends_wallet_past_the_gap() {
+        let QueryTarget::Wallet(wallet) = parse_query_target(DESCRIPTOR).unwrap() else { panic!("a wallet") };
+        let receive = |index| wallet.descriptors[0].descriptor.at_derivation_index(index).script_pubkey();
+        // Index 30 is past the first 20 derived, but within 20 of index 15
+        let blocks = chain(vec![
+            vec![transaction(&[], &[(receive(0), 1_000)])],
+            vec![transaction(&[], &[(receive(15), 2_000)])],
+            vec![transaction(&[], &[(receive(30), 3_000)])],
+        ]);
+        let peer = serve(blocks, ServiceFlags::COMPACT_FILTERS);
+
+        let node = CbfSource::connect(&peer, Chain::Regtest, None, 0, None).unwrap();
+        let result = node.scan(&QueryTarget::Wallet(wallet.clone()), ScanLimits::default(), &Progress::new(false)).unwrap();
+        assert_eq!(result.txs.len(), 3);
+        assert_eq!((result.chains[0].used, result.chains[0].scanned, result.chains[0].next_index), (3, 51, 31));
+        assert_eq!(result.chains[0].unused.len(), 28);
+        assert_eq!((result.chains[1].used, result.chains[1].scanned), (0, 20));
+        assert_eq!(result.scripts.len(), 71);
+    }
+
+    #[test]
+    fn test_peer_without_filters_is_refused() {
+        let peer = serve(chain(vec![vec![transaction(&[], &[(script(1), 1)])]]), ServiceFlags::NETWORK);
+        let error = CbfSource::connect(&peer, Chain::Regtest, None, 0, None).err().unwrap();
+        assert!(error.contains("doesn't serve compact block filters"), "{}", error);
+    }
+
+    #[test]
+    fn test_peer_header_with_wrong_target_is_refused() {
+        let mut blocks = chain(vec![vec![transaction(&[], &[(script(1), 1)])], vec![transaction(&[], &[(script(2), 1)])]]);
+        // Easier than regtest's target, which never changes
+        blocks[1].header.bits = 0x2100_ffff;
+        solve(&mut blocks[1].header);
+        let peer = serve(blocks, ServiceFlags::COMPACT_FILTERS);
+        let error = CbfSource::connect(&peer, Chain::Regtest, None, 0, None).err().unwrap();
+        assert!(error.contains("has target 0x2100ffff where 0x207fffff is due"), "{}", error);
+    }
+
+    #[test]
+    fn test_headers_are_stored_and_follow_reorgs() {
+        let dir = std::env::temp_dir().join(format!("balance-checker-cbf-{}", rand::random::<u64>()));
+        let path = dir.join(HEADERS_FILE);
+        let blocks = chain(vec![vec![transaction(&[], &[(script(1), 1)])], vec![transaction(&[], &[(script(2), 1)])], vec![transaction(&[], &[(script(3), 1)])]]);
+        CbfSource::connect(&serve(blocks.clone(), ServiceFlags::COMPACT_FILTERS), Chain::Regtest, None, 0, Some(&path)).unwrap();
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 3 * HEADER_SIZE as u64);
+
+        // A longer chain forking after block 1 replaces blocks 2 and 3
+        let mut fork = vec![blocks[0].clone()];
+        for height in 2..=4 {
+            fork.push(mine(fork[fork.len() - 1].block_hash(), 1_800_000_000 + height, vec![transaction(&[], &[(script(4), height as u64)])]));
+        }
+        let node = CbfSource::connect(&serve(fork.clone(), ServiceFlags::COMPACT_FILTERS), Chain::Regtest, None, 0, Some(&path)).unwrap();
+        assert_eq!(node.tip_height().unwrap(), 4);
+        let (_, stored) = HeaderFile::open(&path).unwrap();
+        assert_eq!(stored, fork.iter().map(|block| block.header).collect::<Vec<_>>());
+
+        // A stored header that fails the checks is cut off with those after it
+        let mut bad = stored[2];
+        bad.bits = 0x2100_ffff;
+        solve(&mut bad);
+        HeaderFile::open(&path).unwrap().0.write(3, &[bad]).unwrap();
+        let (file, stored) = HeaderFile::open(&path).unwrap();
+        let mut file = Some(file);
+        let links = load_chain(Chain::Regtest, &Difficulty::of(Chain::Regtest), &mut file, stored);
+        assert_eq!(links.len(), 3);
+        assert_eq!(std::fs::metadata(&path).unwrap().len(), 2 * HEADER_SIZE as u64);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_difficulty_retarget() {
+        let link = |bits, time| Link { hash: BlockHash::all_zeros(), bits, time };
+        // An interval mined in half the time halves the target; it's timed
+        // from its first block to its last
+        let bitcoin = Difficulty::of(Chain::Bitcoin);
+        let mut links: Vec<Link> = (0..RETARGET_INTERVAL).map(|height| link(0x1d00_ffff, height * 300)).collect();
+        links[RETARGET_INTERVAL as usize - 1].time = TARGET_TIMESPAN / 2;
+        assert_eq!(bitcoin.next_bits(&links[..100], 0), 0x1d00_ffff);
+        assert_eq!(bitcoin.next_bits(&links, 0), 0x1c7f_ff80);
+        // Ten times too slow is capped at four, and at the easiest target
+        let links: Vec<Link> = (0..RETARGET_INTERVAL).map(|height| link(0x1c7f_ff80, height * 6000)).collect();
+        assert_eq!(bitcoin.next_bits(&links, 0), 0x1d00_ffff);
+
+        // Testnet allows an easy block after 20 minutes, then goes back
+        let testnet = Difficulty::of(Chain::Testnet);
+        let mut links: Vec<Link> = (0..10).map(|height| link(0x1c7f_ff80, height * 600)).collect();
+        assert_eq!(testnet.next_bits(&links, 5400 + 1201), 0x1d00_ffff);
+        assert_eq!(testnet.next_bits(&links, 5400 + 600), 0x1c7f_ff80);
+        links.push(link(0x1d00_ffff, 6601));
+        assert_eq!(testnet.next_bits(&links, 6700), 0x1c7f_ff80);
+    }
+
+    #[test]
+    fn test_testnet4_genesis() {
+        assert_eq!(
+            genesis_header(Chain::Testnet4).block_hash().to_string(),
+            "00000000da84f2bafbbc53dee25a72ae507ff4914b867c565be350b0da8bf043"
+        );
+    }
+
+    #[test]
+    fn test_locator() {
+        let hashes: Vec<BlockHash> = (0..100u8).map(|byte| BlockHash::from_inner([byte; 32])).collect();
+        let heights: Vec<u8> = locator(&hashes).iter().map(|hash| hash.into_inner()[0]).collect();
+        assert_eq!(heights, vec![99, 98, 97, 96, 95, 94, 93, 92, 91, 90, 88, 84, 76, 60, 28, 0]);
+        assert_eq!(locator(&hashes[..1]), vec![hashes[0]]);
+    }
+}
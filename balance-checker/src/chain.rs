@@ -0,0 +1,63 @@
+//! The networks the checker can query
+//!
+//! The bitcoin library predates testnet4, so it has no `Network` for it.
+//! Testnet4 addresses and extended keys are encoded exactly like testnet3's,
+//! so it parses and checks them as testnet and differs only in its servers,
+//! ports, and data directories.
+
+use bdk::bitcoin::Network;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Chain {
+    Bitcoin,
+    Testnet,
+    Testnet4,
+    Signet,
+    Regtest,
+}
+
+impl Chain {
+    pub const ALL: [Chain; 5] = [Chain::Bitcoin, Chain::Testnet, Chain::Testnet4, Chain::Signet, Chain::Regtest];
+
+    /// The network addresses and keys on this chain are encoded for
+    pub fn network(&self) -> Network {
+        match self {
+            Chain::Bitcoin => Network::Bitcoin,
+            Chain::Testnet | Chain::Testnet4 => Network::Testnet,
+            Chain::Signet => Network::Signet,
+            Chain::Regtest => Network::Regtest,
+        }
+    }
+
+    /// Name used on the command line and for data directories
+    pub fn name(&self) -> &'static str {
+        match self {
+            Chain::Bitcoin => "bitcoin",
+            Chain::Testnet => "testnet",
+            Chain::Testnet4 => "testnet4",
+            Chain::Signet => "signet",
+            Chain::Regtest => "regtest",
+        }
+    }
+
+    /// Whether keys made for `network` (mainnet or test) can be used here;
+    /// every test chain shares testnet's extended key versions
+    pub fn accepts_keys(&self, network: Network) -> bool {
+        (network == Network::Bitcoin) == (*self == Chain::Bitcoin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_test_chains_share_keys() {
+        assert_eq!(Chain::Testnet4.network(), Network::Testnet);
+        assert!(Chain::Signet.accepts_keys(Network::Testnet));
+        assert!(Chain::Regtest.accepts_keys(Network::Testnet));
+        assert!(!Chain::Testnet4.accepts_keys(Network::Bitcoin));
+        assert!(!Chain::Bitcoin.accepts_keys(Network::Testnet));
+        assert!(Chain::Bitcoin.accepts_keys(Network::Bitcoin));
+    }
+}
@@ -0,0 +1,154 @@
+//! UTXO export for `--export-utxos`: the unspent outputs as JSON, for a
+//! transaction builder or PSBT tool to pick coins from
+//!
+//! Each output carries what a PSBT input needs besides the funding
+//! transaction: its outpoint, value and scriptPubKey, and for an output of a
+//! checked descriptor, the descriptor derived at its index and each key's
+//! fingerprint, derivation path and public key (BIP 174's
+//! `PSBT_IN_BIP32_DERIVATION`). Outputs known only by address have no
+//! descriptor or derivations.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::secp256k1::Secp256k1;
+use bdk::bitcoin::{Address, Network};
+use bdk::miniscript::ForEachKey;
+use bdk::KeychainKind;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+use crate::query::script_hash;
+use crate::utxos::{confirmations, script_type, Utxo};
+use crate::wallet::DerivedScript;
+
+/// The file's top level
+#[derive(Serialize)]
+struct Export {
+    network: &'static str,
+    tip_height: u32,
+    total: u64,
+    utxos: Vec<Record>,
+}
+
+#[derive(Serialize)]
+struct Record {
+    txid: String,
+    vout: u32,
+    value: u64,
+    script_pubkey: String,
+    script_type: &'static str,
+    address: Option<String>,
+    block_height: Option<u32>,
+    confirmations: u32,
+    /// `receive` or `change`, when the descriptor is one of a pair
+    keychain: Option<&'static str>,
+    index: Option<u32>,
+    descriptor: Option<String>,
+    bip32_derivations: Vec<Derivation>,
+}
+
+#[derive(Serialize)]
+struct Derivation {
+    pubkey: String,
+    master_fingerprint: String,
+    path: String,
+}
+
+fn record(utxo: &Utxo, origin: Option<&DerivedScript>, tip_height: u32, network: Network) -> Record {
+    let derived = origin.map(|origin| &origin.descriptor);
+    let mut bip32_derivations = Vec::new();
+    if let Some(derived) = derived {
+        let secp = Secp256k1::verification_only();
+        derived.for_each_key(|key| {
+            if let Ok(pubkey) = key.derive_public_key(&secp) {
+                bip32_derivations.push(Derivation {
+                    pubkey: pubkey.to_string(),
+                    master_fingerprint: key.master_fingerprint().to_string(),
+                    path: key.full_derivation_path().to_string(),
+                });
+            }
+            true
+        });
+    }
+    Record {
+        txid: utxo.txid.to_string(),
+        vout: utxo.vout,
+        value: utxo.value,
+        script_pubkey: format!("{:x}", utxo.script),
+        script_type: script_type(&utxo.script),
+        address: Address::from_script(&utxo.script, network).ok().map(|address| address.to_string()),
+        block_height: utxo.height,
+        confirmations: confirmations(utxo.height, tip_height),
+        keychain: origin.and_then(|origin| origin.entry.keychain).map(|keychain| match keychain {
+            KeychainKind::External => "receive",
+            KeychainKind::Internal => "change",
+        }),
+        index: origin.filter(|origin| origin.entry.descriptor.has_wildcard()).map(|origin| origin.index),
+        descriptor: derived.map(|derived| derived.to_string()),
+        bip32_derivations,
+    }
+}
+
+/// Writes the outputs, in their order, as one pretty-printed JSON object
+pub fn write_json(
+    mut writer: impl Write,
+    utxos: &[Utxo],
+    origins: &HashMap<sha256::Hash, DerivedScript>,
+    tip_height: u32,
+    network: Network,
+    network_name: &'static str,
+) -> io::Result<()> {
+    let export = Export {
+        network: network_name,
+        tip_height,
+        total: utxos.iter().map(|utxo| utxo.value).sum(),
+        utxos: utxos
+            .iter()
+            .map(|utxo| record(utxo, origins.get(&script_hash(&utxo.script)), tip_height, network))
+            .collect(),
+    };
+    serde_json::to_writer_pretty(&mut writer, &export)?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{p2wpkh, utxo};
+    use crate::wallet::{derive_scripts, parse_descriptor, WalletDescriptor};
+    use serde_json::Value;
+
+    const DESCRIPTOR: &str = "wpkh([73c5da0a/84'/1'/0']tpubDC8msFGeGuwnKG9Upg7DM2b4DaRqg3CUZa5g8v2SRQ6K4NSkxUgd7HsL2XVWbVm39yBA4LAxysQAm397zwQSQoQgewGiYZqrA9DsP4zbQ1M/<0;1>/*)";
+
+    #[test]
+    fn test_write_json() {
+        let wallet = parse_descriptor(DESCRIPTOR).unwrap();
+        let chains: Vec<(&WalletDescriptor, u32)> = wallet.descriptors.iter().map(|entry| (entry, 5)).collect();
+        let origins = derive_scripts(&chains);
+        let change = wallet.descriptors[1].descriptor.at_derivation_index(3).script_pubkey();
+        let utxos = [Utxo { vout: 1, ..utxo(1, 25_000, Some(990), change) }, Utxo { vout: 1, ..utxo(2, 25_000, None, p2wpkh()) }];
+
+        let mut out = Vec::new();
+        write_json(&mut out, &utxos, &origins, 1_000, Network::Testnet, "testnet").unwrap();
+        let json: Value = serde_json::from_slice(&out).unwrap();
+        assert_eq!(json["tip_height"], 1_000);
+        assert_eq!(json["total"], 50_000);
+
+        let ours = &json["utxos"][0];
+        assert_eq!(ours["vout"], 1);
+        assert_eq!(ours["confirmations"], 11);
+        assert_eq!(ours["script_type"], "P2WPKH");
+        assert_eq!((ours["keychain"].as_str(), ours["index"].as_u64()), (Some("change"), Some(3)));
+        assert!(ours["descriptor"].as_str().unwrap().starts_with("wpkh([73c5da0a/84'/1'/0']tpub"));
+        assert!(ours["descriptor"].as_str().unwrap().contains("/1/3)#"));
+        let derivation = &ours["bip32_derivations"][0];
+        assert_eq!(derivation["master_fingerprint"], "73c5da0a");
+        assert_eq!(derivation["path"], "m/84'/1'/0'/1/3");
+        assert_eq!(derivation["pubkey"].as_str().unwrap().len(), 66);
+
+        let theirs = &json["utxos"][1];
+        assert_eq!(theirs["address"], "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx");
+        assert_eq!(theirs["confirmations"], 0);
+        assert!(theirs["descriptor"].is_null() && theirs["bip32_derivations"].as_array().unwrap().is_empty());
+    }
+}
@@ -0,0 +1,64 @@
+//! Runs lookups side by side for `--concurrency`, keeping results in input
+//! order so output doesn't depend on which request finished first
+//!
+//! Esplora pages a script's history by the last transaction seen, so pages
+//! of one script must be fetched in turn; it's separate scripts and inputs
+//! that are looked up together.
+
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::thread;
+
+/// Calls `f` on every item using up to `concurrency` threads, returning the
+/// results in the order of `items`
+pub fn map<T: Sync, R: Send>(items: &[T], concurrency: usize, f: impl Fn(&T) -> R + Sync) -> Vec<R> {
+    if concurrency <= 1 || items.len() <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+    thread::scope(|scope| {
+        for _ in 0..concurrency.min(items.len()) {
+            scope.spawn(|| loop {
+                let index = next.fetch_add(1, Ordering::Relaxed);
+                let Some(item) = items.get(index) else { break };
+                let result = f(item);
+                results.lock().unwrap()[index] = Some(result);
+            });
+        }
+    });
+    results.into_inner().unwrap().into_iter().map(|result| result.expect("every item was mapped")).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_map_keeps_input_order() {
+        let items: Vec<u64> = (0..20).collect();
+        // Later items finish first
+        let results = map(&items, 4, |&item| {
+            thread::sleep(Duration::from_millis(20 - item));
+            item * 2
+        });
+        assert_eq!(results, items.iter().map(|item| item * 2).collect::<Vec<_>>());
+        assert_eq!(map(&items, 1, |&item| item + 1)[19], 20);
+        assert!(map(&[] as &[u64], 4, |&item| item).is_empty());
+    }
+
+    #[test]
+    fn test_map_uses_several_threads() {
+        let running = AtomicUsize::new(0);
+        let peak = AtomicUsize::new(0);
+        map(&[(); 8], 4, |_| {
+            let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+            peak.fetch_max(now, Ordering::SeqCst);
+            thread::sleep(Duration::from_millis(20));
+            running.fetch_sub(1, Ordering::SeqCst);
+        });
+        assert!(peak.load(Ordering::SeqCst) > 1);
+    }
+}
@@ -0,0 +1,302 @@
+//! Consolidation advice for `--consolidate-advice`: whether sweeping the
+//! unspent outputs into one at `--feerate` now beats spending them one by
+//! one later, when fees may be at `--future-feerate`
+//!
+//! Every input costs the same block space whatever its value, so each output
+//! swept today is paid for at today's rate instead of the future one; the
+//! sweep itself adds a transaction overhead and one new output, which is
+//! spent later as a single input. Outputs worth less than their own input
+//! fee now are left out, as are unconfirmed ones.
+//!
+//! Input sizes are estimated from the script type, assuming single-key
+//! spends, P2SH-wrapped P2WPKH for P2SH, and 2-of-3 multisig for P2WSH.
+
+use bdk::bitcoin::Script;
+use units::Unit;
+
+use crate::utxos::{script_type, Utxo};
+
+/// Fee rate assumed for later with no `--future-feerate`, as in a busy mempool
+pub const DEFAULT_FUTURE_FEE_RATE: f64 = 50.0;
+
+/// Version, locktime, and one-byte input and output counts, in weight units
+pub const BASE_WEIGHT: u64 = (4 + 4 + 1 + 1) * 4;
+
+/// Segwit marker and flag
+pub const SEGWIT_WEIGHT: u64 = 2;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Action {
+    Consolidate,
+    /// Worth less than its input fee at the current rate
+    Uneconomical,
+    Unconfirmed,
+}
+
+impl Action {
+    fn name(&self) -> &'static str {
+        match self {
+            Action::Consolidate => "consolidate",
+            Action::Uneconomical => "leave (dust)",
+            Action::Unconfirmed => "leave (unconfirmed)",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plan<'a> {
+    pub fee_rate: f64,
+    pub future_fee_rate: f64,
+    pub decisions: Vec<(&'a Utxo, Action)>,
+    /// Size of the sweep transaction
+    pub sweep_vsize: u64,
+    pub sweep_fee: u64,
+    /// Input fees for spending the swept outputs separately later
+    pub separate_cost: u64,
+    /// Input fee for spending the sweep's one output later
+    pub consolidated_cost: u64,
+}
+
+impl Plan<'_> {
+    pub fn swept(&self) -> impl Iterator<Item = &Utxo> {
+        self.decisions.iter().filter(|(_, action)| *action == Action::Consolidate).map(|(utxo, _)| *utxo)
+    }
+
+    /// What sweeping now saves over spending the outputs separately later,
+    /// after paying for the sweep; negative when it costs more
+    pub fn net_saving(&self) -> i64 {
+        self.separate_cost as i64 - self.consolidated_cost as i64 - self.sweep_fee as i64
+    }
+
+    pub fn recommended(&self) -> bool {
+        self.swept().count() >= 2 && self.net_saving() > 0
+    }
+}
+
+/// Decides which outputs to sweep at `fee_rate` and what that saves if
+/// they'd otherwise be spent at `future_fee_rate`
+pub fn plan(utxos: &[Utxo], fee_rate: f64, future_fee_rate: f64) -> Plan<'_> {
+    let decisions: Vec<(&Utxo, Action)> = utxos
+        .iter()
+        .map(|utxo| {
+            let action = if utxo.height.is_none() {
+                Action::Unconfirmed
+            } else if utxo.value <= fee(input_weight(&utxo.script), fee_rate) {
+                Action::Uneconomical
+            } else {
+                Action::Consolidate
+            };
+            (utxo, action)
+        })
+        .collect();
+
+    let swept: Vec<&Utxo> = decisions.iter().filter(|(_, action)| *action == Action::Consolidate).map(|(utxo, _)| *utxo).collect();
+    // The sweep pays to the same kind of script as its largest output
+    let Some(destination) = swept.first().map(|utxo| &utxo.script) else {
+        return Plan { fee_rate, future_fee_rate, decisions, sweep_vsize: 0, sweep_fee: 0, separate_cost: 0, consolidated_cost: 0 };
+    };
+
+    let inputs_weight: u64 = swept.iter().map(|utxo| input_weight(&utxo.script)).sum();
+    let segwit = swept.iter().any(|utxo| !utxo.script.is_p2pkh() && !utxo.script.is_p2pk());
+    let weight = BASE_WEIGHT
+        + count_weight(swept.len())
+        + if segwit { SEGWIT_WEIGHT } else { 0 }
+        + inputs_weight
+        + output_weight(destination);
+    let sweep_vsize = weight.div_ceil(4);
+
+    Plan {
+        fee_rate,
+        future_fee_rate,
+        sweep_vsize,
+        sweep_fee: (sweep_vsize as f64 * fee_rate).ceil() as u64,
+        separate_cost: swept.iter().map(|utxo| fee(input_weight(&utxo.script), future_fee_rate)).sum(),
+        consolidated_cost: fee(input_weight(destination), future_fee_rate),
+        decisions,
+    }
+}
+
+/// The advice's lines in `unit`, without a heading
+pub fn format_plan(plan: &Plan, unit: Unit) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:<70}  {:>20}  {:<7}  {:>14}  {:>21}  {}",
+        "Outpoint",
+        format!("Value ({})", unit.label()),
+        "Type",
+        "Spend now",
+        "Spend later",
+        "Action"
+    )];
+    for (utxo, action) in &plan.decisions {
+        let weight = input_weight(&utxo.script);
+        let later = fee(weight, plan.future_fee_rate);
+        lines.push(format!(
+            "{:<70}  {:>20}  {:<7}  {:>14}  {:>21}  {}",
+            format!("{}:{}", utxo.txid, utxo.vout),
+            unit.number(utxo.value),
+            script_type(&utxo.script),
+            unit.number(fee(weight, plan.fee_rate)),
+            format!("{} ({:.1}%)", unit.number(later), later as f64 / utxo.value as f64 * 100.0),
+            action.name()
+        ));
+    }
+
+    let swept: Vec<&Utxo> = plan.swept().collect();
+    let swept_value: u64 = swept.iter().map(|utxo| utxo.value).sum();
+    if swept.len() >= 2 {
+        lines.push(format!(
+            "Sweep:       {} of {} UTXOs, {}, into one output",
+            swept.len(),
+            plan.decisions.len(),
+            unit.format(swept_value)
+        ));
+        lines.push(format!(
+            "Fee now:     {} for {} vB ({:.2}% of the amount)",
+            unit.format(plan.sweep_fee),
+            plan.sweep_vsize,
+            plan.sweep_fee as f64 / swept_value as f64 * 100.0
+        ));
+        lines.push(format!(
+            "Later:       {} to spend them separately, {} as one output",
+            unit.format(plan.separate_cost),
+            unit.format(plan.consolidated_cost)
+        ));
+        lines.push(format!("Net saving:  {}", unit.format_i64(plan.net_saving())));
+    }
+    lines.push(format!("Advice:      {}", advice(plan, swept.len(), unit)));
+
+    for (action, reason) in [(Action::Uneconomical, "worth less than their input fee now"), (Action::Unconfirmed, "unconfirmed")] {
+        let left: Vec<&Utxo> = plan.decisions.iter().filter(|(_, a)| *a == action).map(|(utxo, _)| *utxo).collect();
+        if !left.is_empty() {
+            let outputs = if left.len() == 1 { "UTXO" } else { "UTXOs" };
+            let value: u64 = left.iter().map(|utxo| utxo.value).sum();
+            lines.push(format!("Left out:    {} {} {} ({})", left.len(), outputs, reason, unit.format(value)));
+        }
+    }
+    lines
+}
+
+fn advice(plan: &Plan, swept_count: usize, unit: Unit) -> String {
+    if swept_count < 2 {
+        return "Nothing to consolidate: fewer than two confirmed UTXOs are worth sweeping".to_string();
+    }
+    if plan.recommended() {
+        format!(
+            "Consolidate now at {} sat/vB; it saves {} if fees reach {} sat/vB later. It also links these outputs on-chain",
+            plan.fee_rate,
+            unit.format_i64(plan.net_saving()),
+            plan.future_fee_rate
+        )
+    } else {
+        format!(
+            "Don't consolidate now: at {} sat/vB the sweep costs more than it would save at {} sat/vB",
+            plan.fee_rate, plan.future_fee_rate
+        )
+    }
+}
+
+/// Fee for `weight` at `fee_rate` sat/vB, rounded up
+pub fn fee(weight: u64, fee_rate: f64) -> u64 {
+    (weight as f64 / 4.0 * fee_rate).ceil() as u64
+}
+
+/// Estimated weight of an input spending this script, signatures included
+pub fn input_weight(script: &Script) -> u64 {
+    // Outpoint, sequence and a one-byte script length, without witness
+    const BASE: u64 = (32 + 4 + 4 + 1) * 4;
+    if script.is_p2pkh() {
+        BASE + 107 * 4
+    } else if script.is_p2sh() {
+        // A 22-byte redeem script pushed in the scriptSig, then a P2WPKH witness
+        BASE + 23 * 4 + 108
+    } else if script.is_v0_p2wsh() {
+        // Empty dummy, two signatures and the 2-of-3 witness script
+        BASE + 1 + 1 + 2 * 73 + 106
+    } else if script.is_v1_p2tr() {
+        BASE + 66
+    } else if script.is_p2pk() {
+        BASE + 73 * 4
+    } else {
+        // P2WPKH, and the guess for anything else: signature and key
+        BASE + 108
+    }
+}
+
+pub fn output_weight(script: &Script) -> u64 {
+    (8 + 1 + script.len() as u64) * 4
+}
+
+/// Extra weight of an input count too large for one byte
+pub fn count_weight(inputs: usize) -> u64 {
+    match inputs {
+        0..=252 => 0,
+        253..=0xffff => 2 * 4,
+        _ => 4 * 4,
+    }
+}
+
+/// Parses a fee rate in sat/vB for clap
+pub fn parse_fee_rate(input: &str) -> Result<f64, String> {
+    match input.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate > 0.0 => Ok(rate),
+        _ => Err(format!("'{}' is not a fee rate; give a positive number of sat/vB, e.g. 2 or 1.5", input)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{p2wpkh, utxo, P2WPKH};
+    use std::str::FromStr;
+
+    #[test]
+    fn test_input_sizes() {
+        let vsize = |hex: &str| input_weight(&Script::from_str(hex).unwrap()) as f64 / 4.0;
+        assert_eq!(vsize(P2WPKH), 68.0);
+        assert_eq!(vsize("5120a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"), 57.5);
+        assert_eq!(vsize("76a914751e76e8199196d454941c45d1b3a323f1433bd688ac"), 148.0);
+        assert_eq!(vsize("a914751e76e8199196d454941c45d1b3a323f1433bd687"), 91.0);
+    }
+
+    #[test]
+    fn test_plan_sweeps_confirmed_economical_outputs() {
+        // 68 vB inputs cost 340 sats at 5 sat/vB
+        let utxos = vec![utxo(1, 500_000, Some(100), p2wpkh()), utxo(2, 20_000, Some(110), p2wpkh()), utxo(3, 300, Some(120), p2wpkh()), utxo(4, 9_000, None, p2wpkh())];
+        let plan = plan(&utxos, 5.0, 50.0);
+        let actions: Vec<Action> = plan.decisions.iter().map(|(_, action)| *action).collect();
+        assert_eq!(actions, vec![Action::Consolidate, Action::Consolidate, Action::Uneconomical, Action::Unconfirmed]);
+
+        // 10 + 0.5 overhead, two 68 vB inputs, one 31 vB output
+        assert_eq!(plan.sweep_vsize, 178);
+        assert_eq!(plan.sweep_fee, 890);
+        assert_eq!((plan.separate_cost, plan.consolidated_cost), (6_800, 3_400));
+        assert_eq!(plan.net_saving(), 2_510);
+        assert!(plan.recommended());
+
+        let lines = format_plan(&plan, Unit::Sats);
+        assert!(lines.contains(&"Sweep:       2 of 4 UTXOs, 520,000 sats, into one output".to_string()));
+        assert!(lines.contains(&"Left out:    1 UTXO unconfirmed (9,000 sats)".to_string()));
+        assert!(lines.contains(&"Net saving:  2,510 sats".to_string()));
+        assert!(format_plan(&plan, Unit::Btc).contains(&"Net saving:  0.00002510 BTC".to_string()));
+    }
+
+    #[test]
+    fn test_plan_advises_against_sweeping_at_higher_fees() {
+        let utxos = vec![utxo(1, 500_000, Some(100), p2wpkh()), utxo(2, 20_000, Some(110), p2wpkh())];
+        let expensive = plan(&utxos, 60.0, 50.0);
+        assert!(expensive.net_saving() < 0);
+        assert!(!expensive.recommended());
+        assert!(format_plan(&expensive, Unit::Sats).last().unwrap().starts_with("Advice:      Don't consolidate now"));
+
+        let single = plan(&utxos[..1], 1.0, 50.0);
+        assert!(!single.recommended());
+        assert!(format_plan(&single, Unit::Sats).last().unwrap().starts_with("Advice:      Nothing to consolidate"));
+    }
+
+    #[test]
+    fn test_parse_fee_rate() {
+        assert_eq!(parse_fee_rate("1.5"), Ok(1.5));
+        assert!(parse_fee_rate("0").is_err());
+        assert!(parse_fee_rate("fast").is_err());
+    }
+}
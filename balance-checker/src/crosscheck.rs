@@ -0,0 +1,152 @@
+//! Cross-check for `--verify-with`: the unspent outputs one backend found,
+//! compared with those a second backend finds for the same inputs
+//!
+//! Outputs are matched by outpoint and value, so a missing or invented
+//! transaction, a spend one indexer didn't see, or a wrong amount all show
+//! up. When either backend has no mempool, unconfirmed transactions are left
+//! out on both sides first.
+
+use bdk::bitcoin::Txid;
+use std::collections::HashSet;
+use units::Unit;
+
+use crate::utxos::Utxo;
+
+/// Outputs listed before the rest are counted
+const MAX_LISTED: usize = 20;
+
+/// What one backend found
+pub struct Side {
+    pub name: String,
+    pub tip_height: u32,
+    pub utxos: Vec<Utxo>,
+}
+
+impl Side {
+    fn confirmed(&self) -> u64 {
+        self.utxos.iter().filter(|utxo| utxo.height.is_some()).map(|utxo| utxo.value).sum()
+    }
+
+    fn unconfirmed(&self) -> u64 {
+        self.utxos.iter().filter(|utxo| utxo.height.is_none()).map(|utxo| utxo.value).sum()
+    }
+}
+
+/// Outputs found by only one of the backends
+#[derive(Debug, Default)]
+pub struct Comparison {
+    pub only_main: Vec<Utxo>,
+    pub only_other: Vec<Utxo>,
+}
+
+impl Comparison {
+    pub fn matches(&self) -> bool {
+        self.only_main.is_empty() && self.only_other.is_empty()
+    }
+
+    pub fn discrepancies(&self) -> usize {
+        self.only_main.len() + self.only_other.len()
+    }
+}
+
+pub fn compare(main: &[Utxo], other: &[Utxo]) -> Comparison {
+    let key = |utxo: &Utxo| (utxo.txid, utxo.vout, utxo.value);
+    let main_keys: HashSet<(Txid, u32, u64)> = main.iter().map(key).collect();
+    let other_keys: HashSet<(Txid, u32, u64)> = other.iter().map(key).collect();
+    Comparison {
+        only_main: main.iter().filter(|utxo| !other_keys.contains(&key(utxo))).cloned().collect(),
+        only_other: other.iter().filter(|utxo| !main_keys.contains(&key(utxo))).cloned().collect(),
+    }
+}
+
+/// Lines of the `Cross-Check` section in `unit`; `confirmed_only` when
+/// unconfirmed transactions were left out
+pub fn format_report(main: &Side, other: &Side, comparison: &Comparison, confirmed_only: bool, unit: Unit) -> Vec<String> {
+    let mut lines = vec![
+        format!("Tip height:  {} ({} {})", other.tip_height, main.name, main.tip_height),
+        format!("Confirmed:   {} ({} {})", unit.format(other.confirmed()), main.name, unit.number(main.confirmed())),
+    ];
+    if !confirmed_only {
+        lines.push(format!(
+            "Unconfirmed: {} ({} {})",
+            unit.format(other.unconfirmed()),
+            main.name,
+            unit.number(main.unconfirmed())
+        ));
+    }
+    lines.push(format!("UTXOs:       {} ({} {})", other.utxos.len(), main.name, main.utxos.len()));
+
+    if comparison.matches() {
+        lines.push("Result:      the unspent outputs match".to_string());
+    } else {
+        let count = comparison.discrepancies();
+        lines.push(format!("Result:      {} {}", count, if count == 1 { "discrepancy" } else { "discrepancies" }));
+        for (utxos, side) in [(&comparison.only_main, main), (&comparison.only_other, other)] {
+            for utxo in utxos.iter().take(MAX_LISTED) {
+                lines.push(format!("  {}:{} {} only on {}", utxo.txid, utxo.vout, unit.format(utxo.value), side.name));
+            }
+            if utxos.len() > MAX_LISTED {
+                lines.push(format!("  and {} more only on {}", utxos.len() - MAX_LISTED, side.name));
+            }
+        }
+        if main.tip_height != other.tip_height {
+            lines.push("Note: The tips differ, so a block found in between may explain some of this".to_string());
+        }
+    }
+    if confirmed_only {
+        lines.push("Note: Only confirmed outputs are compared, since one backend has no mempool".to_string());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::utxo;
+    use bdk::bitcoin::Script;
+
+    fn output(id: u8, vout: u32, value: u64, height: Option<u32>) -> Utxo {
+        Utxo { vout, ..utxo(id, value, height, Script::new()) }
+    }
+
+    fn side(name: &str, tip_height: u32, utxos: Vec<Utxo>) -> Side {
+        Side { name: name.to_string(), tip_height, utxos }
+    }
+
+    #[test]
+    fn test_matching_backends() {
+        let main = side("main", 100, vec![output(1, 0, 5_000, Some(90)), output(2, 1, 700, None)]);
+        let other = side("electrum", 100, vec![output(2, 1, 700, None), output(1, 0, 5_000, Some(90))]);
+        let comparison = compare(&main.utxos, &other.utxos);
+        assert!(comparison.matches());
+        assert_eq!(
+            format_report(&main, &other, &comparison, false, Unit::Sats),
+            vec![
+                "Tip height:  100 (main 100)",
+                "Confirmed:   5,000 sats (main 5,000)",
+                "Unconfirmed: 700 sats (main 700)",
+                "UTXOs:       2 (main 2)",
+                "Result:      the unspent outputs match",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_missing_and_changed_outputs() {
+        let main = side("main", 100, vec![output(1, 0, 5_000, Some(90)), output(3, 0, 1_000, Some(95))]);
+        // The second backend has 3:0 spent and 1:0 at another value
+        let other = side("bitcoind", 101, vec![output(1, 0, 4_000, Some(90))]);
+        let comparison = compare(&main.utxos, &other.utxos);
+        assert_eq!(comparison.only_main.len(), 2);
+        assert_eq!(comparison.only_other.len(), 1);
+
+        let lines = format_report(&main, &other, &comparison, true, Unit::Sats);
+        assert_eq!(lines[0], "Tip height:  101 (main 100)");
+        assert_eq!(lines[2], "UTXOs:       1 (main 2)");
+        assert_eq!(lines[3], "Result:      3 discrepancies");
+        assert!(lines[4].ends_with(":0 5,000 sats only on main"));
+        assert!(lines[6].ends_with(":0 4,000 sats only on bitcoind"));
+        assert!(lines[7].starts_with("Note: The tips differ"));
+        assert!(lines[8].starts_with("Note: Only confirmed outputs"));
+    }
+}
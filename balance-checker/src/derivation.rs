@@ -0,0 +1,205 @@
+//! Derivation usage report for wallet scans: how far each chain of an
+//! extended key has been used, and the next fresh receive address
+//!
+//! The highest used index is where a wallet restored from the key would
+//! need to look up to, and the unused indexes below it are addresses that
+//! were handed out but never paid. Those holes are listed, since a wallet
+//! with a gap limit no longer than the longest of them stops scanning there
+//! and misses every later payment. Chains of a descriptor without a
+//! wildcard have nothing to derive, so they're left out.
+
+use bdk::bitcoin::{Address, Network};
+use bdk::KeychainKind;
+
+use crate::wallet::{ChainUsage, WalletDescriptor};
+
+/// One ranged chain's usage
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainReport {
+    pub label: String,
+    pub highest_used: Option<u32>,
+    pub used: u32,
+    pub scanned: u32,
+    pub limited: bool,
+    /// Runs of unused indexes below the highest used one, first and last
+    pub holes: Vec<(u32, u32)>,
+    /// Index and address after the last used one, for receive chains (and
+    /// ranged descriptors that aren't one of a pair)
+    pub next_receive: Option<(u32, String)>,
+}
+
+impl ChainReport {
+    /// Unused indexes up to the highest used one
+    pub fn skipped(&self) -> u32 {
+        self.highest_used.map_or(0, |highest| highest + 1 - self.used)
+    }
+
+    /// Unused indexes scanned past the highest used one
+    pub fn trailing(&self) -> u32 {
+        self.scanned.saturating_sub(self.highest_used.map_or(0, |highest| highest + 1))
+    }
+
+    /// The longest hole; the first if several are as long
+    pub fn longest_hole(&self) -> Option<(u32, u32)> {
+        self.holes.iter().copied().rev().max_by_key(|(first, last)| last - first)
+    }
+}
+
+/// Consecutive indexes of `unused` (in order) as first and last of each run
+fn holes(unused: &[u32]) -> Vec<(u32, u32)> {
+    let mut holes: Vec<(u32, u32)> = Vec::new();
+    for &index in unused {
+        match holes.last_mut() {
+            Some((_, last)) if *last + 1 == index => *last = index,
+            _ => holes.push((index, index)),
+        }
+    }
+    holes
+}
+
+/// Pairs each descriptor with how far it was scanned; `chains` is in the
+/// order of `descriptors`
+pub fn report(descriptors: &[WalletDescriptor], chains: &[ChainUsage], network: Network) -> Vec<ChainReport> {
+    descriptors
+        .iter()
+        .zip(chains)
+        .filter(|(entry, _)| entry.descriptor.has_wildcard())
+        .map(|(entry, usage)| {
+            let next_receive = (entry.keychain != Some(KeychainKind::Internal)).then(|| {
+                let script = entry.descriptor.at_derivation_index(usage.next_index).script_pubkey();
+                let address = Address::from_script(&script, network).map_or_else(|_| format!("{:x}", script), |a| a.to_string());
+                (usage.next_index, address)
+            });
+            ChainReport {
+                label: entry.label.clone(),
+                highest_used: usage.next_index.checked_sub(1),
+                used: usage.used,
+                scanned: usage.scanned,
+                limited: usage.limited,
+                holes: holes(&usage.unused),
+                next_receive,
+            }
+        })
+        .collect()
+}
+
+pub fn format_report(reports: &[ChainReport]) -> Vec<String> {
+    let width = reports.iter().map(|report| report.label.len()).max().unwrap_or(0);
+    let mut lines = Vec::new();
+    for report in reports {
+        let limited = if report.limited { " (stopped at --scan-limit)" } else { "" };
+        let usage = match report.highest_used {
+            Some(highest) => format!(
+                "highest used index {}: {} used, {} unused up to it, {} unused after{}",
+                highest,
+                report.used,
+                report.skipped(),
+                report.trailing(),
+                limited
+            ),
+            None => format!("none used: {} unused{}", report.trailing(), limited),
+        };
+        lines.push(format!("{:<width$}  {}", report.label, usage, width = width));
+
+        if let Some((first, last)) = report.longest_hole() {
+            let holes: Vec<String> = report
+                .holes
+                .iter()
+                .map(|(first, last)| if first == last { first.to_string() } else { format!("{}-{}", first, last) })
+                .collect();
+            lines.push(format!(
+                "{:<width$}  unused below it: {}; a wallet with a gap limit of {} or less stops by index {}",
+                "",
+                holes.join(", "),
+                last - first + 1,
+                last,
+                width = width
+            ));
+        }
+    }
+
+    let next: Vec<&ChainReport> = reports.iter().filter(|report| report.next_receive.is_some()).collect();
+    // An extended key derives every standard script type; once any is used,
+    // the unused types are just noise
+    let any_used = next.iter().any(|report| report.highest_used.is_some());
+    let next: Vec<&ChainReport> = next.into_iter().filter(|report| !any_used || report.highest_used.is_some()).collect();
+    if let [report] = &next[..] {
+        let (index, address) = report.next_receive.as_ref().expect("filtered above");
+        lines.push(format!("Next receive address: {} ({}, index {})", address, report.label, index));
+    } else if !next.is_empty() {
+        lines.push("Next receive addresses:".to_string());
+        for report in next {
+            let (index, address) = report.next_receive.as_ref().expect("filtered above");
+            lines.push(format!("  {:<width$}  {} (index {})", report.label, address, index, width = width));
+        }
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::parse_extended_key;
+
+    // BIP84 test vector account key
+    const ZPUB: &str = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+    // BIP86 test vector account key
+    const XPUB: &str = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+
+    fn usage(label: &str, scanned: u32, used: u32, next_index: u32) -> ChainUsage {
+        ChainUsage { label: label.to_string(), scanned, used, next_index, unused: Vec::new(), limited: false }
+    }
+
+    #[test]
+    fn test_report_for_a_zpub() {
+        let wallet = parse_extended_key(ZPUB).unwrap().unwrap();
+        let chains = [usage("BIP84 receive", 35, 12, 15), usage("BIP84 change", 20, 0, 0)];
+        let reports = report(&wallet.descriptors, &chains, Network::Bitcoin);
+
+        assert_eq!(reports[0].highest_used, Some(14));
+        assert_eq!((reports[0].skipped(), reports[0].trailing()), (3, 20));
+        assert_eq!(reports[1].next_receive, None);
+        assert_eq!(
+            format_report(&reports),
+            vec![
+                "BIP84 receive  highest used index 14: 12 used, 3 unused up to it, 20 unused after",
+                "BIP84 change   none used: 20 unused",
+                &format!("Next receive address: {} (BIP84 receive, index 15)", reports[0].next_receive.as_ref().unwrap().1),
+            ]
+        );
+
+        let chains = [usage("BIP84 receive", 20, 0, 0), usage("BIP84 change", 20, 0, 0)];
+        let fresh = report(&wallet.descriptors, &chains, Network::Bitcoin);
+        assert_eq!(fresh[0].next_receive.as_ref().unwrap().1, "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+    }
+
+    #[test]
+    fn test_holes_below_the_highest_used_index() {
+        let wallet = parse_extended_key(ZPUB).unwrap().unwrap();
+        let receive = ChainUsage { unused: vec![1, 2, 5, 7, 8, 9, 10], ..usage("BIP84 receive", 32, 5, 12) };
+        let reports = report(&wallet.descriptors, &[receive, usage("BIP84 change", 20, 0, 0)], Network::Bitcoin);
+
+        assert_eq!(reports[0].holes, vec![(1, 2), (5, 5), (7, 10)]);
+        assert_eq!(reports[0].longest_hole(), Some((7, 10)));
+        assert_eq!(
+            format_report(&reports)[1],
+            "               unused below it: 1-2, 5, 7-10; a wallet with a gap limit of 4 or less stops by index 10"
+        );
+        assert!(reports[1].holes.is_empty());
+    }
+
+    #[test]
+    fn test_next_addresses_of_used_script_types_only() {
+        let wallet = parse_extended_key(XPUB).unwrap().unwrap();
+        let mut chains: Vec<ChainUsage> = wallet.descriptors.iter().map(|entry| usage(&entry.label, 20, 0, 0)).collect();
+        let reports = report(&wallet.descriptors, &chains, Network::Bitcoin);
+        let lines = format_report(&reports);
+        // Nothing used: each script type's first receive address
+        assert_eq!(lines.iter().filter(|line| line.contains("(index 0)")).count(), 4);
+
+        chains[4] = usage("BIP84 receive", 22, 1, 2);
+        let lines = format_report(&report(&wallet.descriptors, &chains, Network::Bitcoin));
+        assert!(lines.last().unwrap().starts_with("Next receive address: bc1q"));
+        assert!(lines.last().unwrap().ends_with("(BIP84 receive, index 2)"));
+    }
+}
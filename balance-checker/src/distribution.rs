@@ -0,0 +1,135 @@
+//! UTXO statistics for `--utxo-stats`: how the balance is spread over
+//! unspent outputs, by value and by age, to judge at a glance how
+//! fragmented a wallet is
+
+use units::Unit;
+
+use crate::utxos::Utxo;
+
+/// Age brackets in blocks since confirmation, at ~144 blocks a day; the
+/// last one is open-ended
+const AGE_BRACKETS: [(&str, u32); 6] = [
+    ("Under 1 day", 144),
+    ("1 day-1 week", 1_008),
+    ("1 week-1 month", 4_320),
+    ("1-6 months", 26_280),
+    ("6 months-1 year", 52_560),
+    ("Over 1 year", u32::MAX),
+];
+
+/// Width of the longest bar in the age distribution
+const BAR_WIDTH: usize = 30;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stats<'a> {
+    pub count: usize,
+    pub total: u64,
+    pub mean: f64,
+    /// The middle value, or the mean of the two middle ones
+    pub median: f64,
+    pub largest: &'a Utxo,
+    pub smallest: &'a Utxo,
+    /// Count and value of unconfirmed outputs, then of each age bracket
+    pub ages: Vec<(&'static str, usize, u64)>,
+}
+
+/// Statistics of `utxos` with the chain at `tip_height`; None without any
+pub fn summarize(utxos: &[Utxo], tip_height: u32) -> Option<Stats<'_>> {
+    let largest = utxos.iter().max_by_key(|utxo| utxo.value)?;
+    let smallest = utxos.iter().min_by_key(|utxo| utxo.value)?;
+    let total: u64 = utxos.iter().map(|utxo| utxo.value).sum();
+    let mut values: Vec<u64> = utxos.iter().map(|utxo| utxo.value).collect();
+    values.sort_unstable();
+    let middle = values.len() / 2;
+    let median = if values.len() % 2 == 1 {
+        values[middle] as f64
+    } else {
+        (values[middle - 1] + values[middle]) as f64 / 2.0
+    };
+
+    let mut ages = vec![("Unconfirmed", 0, 0)];
+    ages.extend(AGE_BRACKETS.iter().map(|(name, _)| (*name, 0, 0)));
+    for utxo in utxos {
+        let bracket = match utxo.age(tip_height) {
+            None => 0,
+            Some(age) => 1 + AGE_BRACKETS.iter().position(|(_, below)| age < *below).unwrap_or(AGE_BRACKETS.len() - 1),
+        };
+        ages[bracket].1 += 1;
+        ages[bracket].2 += utxo.value;
+    }
+
+    Some(Stats {
+        count: utxos.len(),
+        total,
+        mean: total as f64 / utxos.len() as f64,
+        median,
+        largest,
+        smallest,
+        ages,
+    })
+}
+
+/// Lines of the `UTXO Statistics` section, amounts in `unit`; the mean and
+/// median are rounded to the sat
+pub fn format_stats(stats: &Stats, unit: Unit) -> Vec<String> {
+    let outpoint = |utxo: &Utxo| format!("{}:{}", utxo.txid, utxo.vout);
+    let mut lines = vec![
+        format!("Count:     {}", stats.count),
+        format!("Total:     {}", unit.format(stats.total)),
+        format!("Mean:      {}", unit.format(stats.mean.round() as u64)),
+        format!("Median:    {}", unit.format(stats.median.round() as u64)),
+        format!("Largest:   {} ({})", unit.format(stats.largest.value), outpoint(stats.largest)),
+        format!("Smallest:  {} ({})", unit.format(stats.smallest.value), outpoint(stats.smallest)),
+        "By age:".to_string(),
+    ];
+    let most = stats.ages.iter().map(|(_, count, _)| *count).max().unwrap_or(0);
+    for (name, count, value) in &stats.ages {
+        // Any output at all gets at least one mark
+        let bar = if *count == 0 { 0 } else { (count * BAR_WIDTH).div_ceil(most) };
+        lines.push(
+            format!("  {:<15}  {:>5}  {:>20}  {}", name, count, unit.format(*value), "#".repeat(bar)).trim_end().to_string(),
+        );
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{p2wpkh, utxo};
+
+    #[test]
+    fn test_summarize() {
+        let utxos = [
+            utxo(1, 500_000, Some(100_000), p2wpkh()),
+            utxo(2, 20_000, Some(99_990), p2wpkh()),
+            utxo(3, 1_000, None, p2wpkh()),
+            utxo(4, 80_000, Some(40_000), p2wpkh()),
+        ];
+        let stats = summarize(&utxos, 100_000).unwrap();
+        assert_eq!((stats.count, stats.total), (4, 601_000));
+        assert_eq!(stats.mean, 150_250.0);
+        assert_eq!(stats.median, 50_000.0);
+        assert_eq!((stats.largest.value, stats.smallest.value), (500_000, 1_000));
+        assert_eq!(stats.ages[0], ("Unconfirmed", 1, 1_000));
+        assert_eq!(stats.ages[1], ("Under 1 day", 2, 520_000));
+        assert_eq!(stats.ages[6], ("Over 1 year", 1, 80_000));
+        assert_eq!(stats.ages.iter().map(|(_, count, _)| count).sum::<usize>(), 4);
+
+        assert_eq!(summarize(&utxos[..3], 100_000).unwrap().median, 20_000.0);
+        assert!(summarize(&[], 100_000).is_none());
+    }
+
+    #[test]
+    fn test_format_stats() {
+        let utxos = [utxo(1, 300, Some(990), p2wpkh()), utxo(2, 100, Some(990), p2wpkh()), utxo(3, 50, None, p2wpkh())];
+        let lines = format_stats(&summarize(&utxos, 1_000).unwrap(), Unit::Sats);
+        assert_eq!(lines[2], "Mean:      150 sats");
+        assert_eq!(lines[5], format!("Smallest:  50 sats ({}:0)", "03".repeat(32)));
+        assert_eq!(lines[7], "  Unconfirmed          1               50 sats  ###############");
+        assert_eq!(lines[8], "  Under 1 day          2              400 sats  ##############################");
+        assert_eq!(lines[9], "  1 day-1 week         0                0 sats");
+        let in_btc = format_stats(&summarize(&utxos, 1_000).unwrap(), Unit::Btc);
+        assert_eq!(in_btc[1], "Total:     0.00000450 BTC");
+    }
+}
@@ -0,0 +1,311 @@
+//! Electrum protocol backend for `--backend electrum`, for users running
+//! electrs or Fulcrum without the Esplora HTTP API
+//!
+//! Electrum returns raw transactions, so each script's history is rebuilt
+//! into the same shape Esplora returns: every input's spent output is looked
+//! up from its parent transaction, and block times come from the headers.
+//! Parents and headers are cached, since wallet scripts share them. With the
+//! history store, only transactions newer than a script's settled ones are
+//! downloaded.
+//!
+//! Only `get_history` is used: the balance, UTXOs and every report are
+//! derived from the full history, so `get_balance` and `listunspent` would
+//! cost an extra round trip per script without adding anything.
+
+use bdk::bitcoin::hashes::hex::ToHex;
+use bdk::bitcoin::hashes::{sha256, Hash};
+use bdk::bitcoin::{BlockHeader, Transaction, Txid};
+use bdk::electrum_client::{Client, ConfigBuilder, ElectrumApi, GetHistoryRes, Param};
+use bdk::esplora_client::{PrevOut, Tx, TxStatus, Vin, Vout};
+use std::collections::{HashMap, HashSet};
+use std::sync::Mutex;
+
+use crate::chain::Chain;
+use crate::proxy::Proxy;
+use crate::retry::RetryPolicy;
+
+/// Seconds to wait for the server before giving up
+const TIMEOUT_SECS: u8 = 20;
+
+pub struct ElectrumSource {
+    client: Client,
+    txs: Mutex<HashMap<Txid, Transaction>>,
+    headers: Mutex<HashMap<u32, BlockHeader>>,
+    /// Only its rate limit is used; the client retries by itself
+    retry: RetryPolicy,
+}
+
+/// Blockstream's public Electrum servers, or mempool.space's for the chains
+/// Blockstream doesn't serve; regtest has none
+pub fn default_server(chain: Chain) -> Option<&'static str> {
+    match chain {
+        Chain::Bitcoin => Some("ssl://electrum.blockstream.info:50002"),
+        Chain::Testnet => Some("ssl://electrum.blockstream.info:60002"),
+        Chain::Testnet4 => Some("ssl://mempool.space:40002"),
+        Chain::Signet => Some("ssl://mempool.space:60602"),
+        Chain::Regtest => None,
+    }
+}
+
+impl ElectrumSource {
+    /// Connects to a `tcp://` or `ssl://` server URL, through `proxy` if given
+    pub fn connect(url: &str, proxy: Option<&Proxy>, retry: RetryPolicy) -> Result<Self, String> {
+        let config = ConfigBuilder::new()
+            .timeout(Some(TIMEOUT_SECS))
+            .and_then(|builder| builder.socks5(proxy.map(Proxy::for_electrum)))
+            .map_err(|e| e.to_string())?
+            // Reconnects after a dropped connection, backing off in between
+            .retry(retry.retries() as u8)
+            .build();
+        let client =
+            Client::from_config(url, config).map_err(|e| format!("Could not connect to {}: {}", url, e))?;
+        Ok(ElectrumSource {
+            client,
+            txs: Mutex::new(HashMap::new()),
+            headers: Mutex::new(HashMap::new()),
+            retry,
+        })
+    }
+
+    pub fn tip_height(&self) -> Result<u32, String> {
+        self.retry.throttle();
+        let tip = self.client.block_headers_subscribe().map_err(|e| e.to_string())?;
+        Ok(tip.height as u32)
+    }
+
+    /// The server's fee rate estimates in sat/vB for each of `targets` (in
+    /// blocks) it has one for; Electrum gives them in BTC/kvB, and -1 for
+    /// none
+    pub fn fee_estimates(&self, targets: &[u16]) -> Result<Vec<(u16, f64)>, String> {
+        self.retry.throttle();
+        let estimates = self
+            .client
+            .batch_estimate_fee(targets.iter().map(|target| usize::from(*target)))
+            .map_err(|e| e.to_string())?;
+        Ok(targets
+            .iter()
+            .zip(estimates)
+            .filter(|(_, estimate)| *estimate > 0.0)
+            .map(|(target, estimate)| (*target, estimate * 100_000.0))
+            .collect())
+    }
+
+    /// Every transaction touching the script with this hash, newest first
+    /// like Esplora. Transactions of `settled` (from the history store) that
+    /// the server still has at the same height are reused rather than
+    /// downloaded again with their parents and headers.
+    pub fn fetch_history(&self, script_hash: &sha256::Hash, settled: &[Tx]) -> Result<Vec<Tx>, String> {
+        // Electrum takes the hash byte-reversed
+        let mut electrum_hash = script_hash.into_inner();
+        electrum_hash.reverse();
+        self.retry.throttle();
+        let history: Vec<GetHistoryRes> = self
+            .client
+            .raw_call("blockchain.scripthash.get_history", [Param::String(electrum_hash.to_hex())])
+            .and_then(|result| Ok(serde_json::from_value(result)?))
+            .map_err(|e| e.to_string())?;
+        let (reused, history) = split_settled(&history, settled);
+        let txids: Vec<Txid> = history.iter().map(|entry| entry.tx_hash).collect();
+        self.load_txs(&txids)?;
+
+        // Parents supply the value and script of each spent output
+        let parents: Vec<Txid> = {
+            let txs = self.txs.lock().unwrap();
+            let mut parents = Vec::new();
+            for txid in &txids {
+                let tx = txs.get(txid).ok_or_else(|| format!("server did not return {}", txid))?;
+                parents.extend(
+                    tx.input
+                        .iter()
+                        .filter(|input| !input.previous_output.is_null())
+                        .map(|input| input.previous_output.txid),
+                );
+            }
+            parents
+        };
+        self.load_txs(&parents)?;
+
+        // 0 and -1 both mean unconfirmed
+        let heights: Vec<u32> = history.iter().filter(|entry| entry.height > 0).map(|entry| entry.height as u32).collect();
+        self.load_headers(&heights)?;
+
+        let txs = self.txs.lock().unwrap();
+        let headers = self.headers.lock().unwrap();
+        let mut converted: Vec<Tx> = history
+            .iter()
+            .map(|entry| {
+                let tx = txs.get(&entry.tx_hash).ok_or_else(|| format!("server did not return {}", entry.tx_hash))?;
+                let height = (entry.height > 0).then_some(entry.height as u32);
+                let header = height.and_then(|height| headers.get(&height));
+                Ok(to_esplora_tx(tx, height, header, &txs))
+            })
+            .collect::<Result<_, String>>()?;
+        converted.extend(reused);
+        converted.sort_by_key(|tx| std::cmp::Reverse(tx.status.block_height.unwrap_or(u32::MAX)));
+        Ok(converted)
+    }
+
+    /// Fetches the transactions not already cached, in one batch
+    fn load_txs(&self, txids: &[Txid]) -> Result<(), String> {
+        let missing: Vec<Txid> = {
+            let cached = self.txs.lock().unwrap();
+            let unique: HashSet<&Txid> = txids.iter().filter(|&txid| !cached.contains_key(txid)).collect();
+            unique.into_iter().copied().collect()
+        };
+        if missing.is_empty() {
+            return Ok(());
+        }
+        self.retry.throttle();
+        let fetched = self.client.batch_transaction_get(&missing).map_err(|e| e.to_string())?;
+        self.txs.lock().unwrap().extend(fetched.into_iter().map(|tx| (tx.txid(), tx)));
+        Ok(())
+    }
+
+    fn load_headers(&self, heights: &[u32]) -> Result<(), String> {
+        let missing: Vec<u32> = {
+            let cached = self.headers.lock().unwrap();
+            let unique: HashSet<u32> = heights.iter().copied().filter(|height| !cached.contains_key(height)).collect();
+            unique.into_iter().collect()
+        };
+        if missing.is_empty() {
+            return Ok(());
+        }
+        self.retry.throttle();
+        let fetched = self.client.batch_block_header(missing.clone()).map_err(|e| e.to_string())?;
+        self.headers.lock().unwrap().extend(missing.into_iter().zip(fetched));
+        Ok(())
+    }
+}
+
+/// Splits a script's history into the `settled` transactions it still has
+/// at the height they were stored at, and the entries left to download. A
+/// settled transaction the server no longer lists, or lists at another
+/// height, was reorged and is dropped.
+fn split_settled<'a>(history: &'a [GetHistoryRes], settled: &[Tx]) -> (Vec<Tx>, Vec<&'a GetHistoryRes>) {
+    let settled: HashMap<Txid, &Tx> = settled.iter().map(|tx| (tx.txid, tx)).collect();
+    let mut reused = Vec::new();
+    let mut missing = Vec::new();
+    for entry in history {
+        match settled.get(&entry.tx_hash) {
+            Some(tx) if entry.height > 0 && tx.status.block_height == Some(entry.height as u32) => reused.push((*tx).clone()),
+            _ => missing.push(entry),
+        }
+    }
+    (reused, missing)
+}
+
+/// Converts a raw transaction to Esplora's form, resolving spent outputs
+/// from `parents`. The fee is only known when every parent is.
+pub fn to_esplora_tx(
+    tx: &Transaction,
+    height: Option<u32>,
+    header: Option<&BlockHeader>,
+    parents: &HashMap<Txid, Transaction>,
+) -> Tx {
+    let vin: Vec<Vin> = tx
+        .input
+        .iter()
+        .map(|input| {
+            let outpoint = input.previous_output;
+            let prevout = parents
+                .get(&outpoint.txid)
+                .and_then(|parent| parent.output.get(outpoint.vout as usize))
+                .map(|output| PrevOut { value: output.value, scriptpubkey: output.script_pubkey.clone() });
+            Vin {
+                txid: outpoint.txid,
+                vout: outpoint.vout,
+                prevout,
+                scriptsig: input.script_sig.clone(),
+                witness: input.witness.to_vec(),
+                sequence: input.sequence.0,
+                is_coinbase: outpoint.is_null(),
+            }
+        })
+        .collect();
+
+    let output_value: u64 = tx.output.iter().map(|output| output.value).sum();
+    let fee = vin
+        .iter()
+        .map(|input| input.prevout.as_ref().map(|prevout| prevout.value))
+        .sum::<Option<u64>>()
+        .map_or(0, |input_value| input_value.saturating_sub(output_value));
+
+    Tx {
+        txid: tx.txid(),
+        version: tx.version,
+        locktime: tx.lock_time.0,
+        vin,
+        vout: tx
+            .output
+            .iter()
+            .map(|output| Vout { value: output.value, scriptpubkey: output.script_pubkey.clone() })
+            .collect(),
+        status: TxStatus {
+            confirmed: height.is_some(),
+            block_height: height,
+            block_hash: header.map(|header| header.block_hash()),
+            block_time: header.map(|header| u64::from(header.time)),
+        },
+        fee,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{p2wpkh, transaction};
+    use bdk::bitcoin::OutPoint;
+
+    /// Spends `input`, paying `values` to the same script
+    fn paying(input: OutPoint, values: &[u64]) -> Transaction {
+        let outputs: Vec<_> = values.iter().map(|&value| (p2wpkh(), value)).collect();
+        transaction(&[input], &outputs)
+    }
+
+    #[test]
+    fn test_to_esplora_tx_resolves_prevouts_and_fee() {
+        let parent = paying(OutPoint::null(), &[10_000, 20_000]);
+        let child = paying(OutPoint::new(parent.txid(), 1), &[19_000]);
+        let parents = HashMap::from([(parent.txid(), parent.clone())]);
+
+        let converted = to_esplora_tx(&child, None, None, &parents);
+        assert_eq!(converted.txid, child.txid());
+        assert_eq!(converted.vin[0].prevout.as_ref().unwrap().value, 20_000);
+        assert_eq!(converted.fee, 1_000);
+        assert!(!converted.status.confirmed);
+
+        // An unknown parent leaves the prevout and fee unknown
+        let converted = to_esplora_tx(&child, Some(5), None, &HashMap::new());
+        assert!(converted.vin[0].prevout.is_none());
+        assert_eq!(converted.fee, 0);
+        assert_eq!(converted.status.block_height, Some(5));
+    }
+
+    #[test]
+    fn test_split_settled_reuses_only_unmoved_transactions() {
+        let parent = paying(OutPoint::null(), &[10_000]);
+        let stored = |tx: &Transaction, height| to_esplora_tx(tx, Some(height), None, &HashMap::new());
+        let (a, b, c) = (
+            paying(OutPoint::new(parent.txid(), 0), &[1]),
+            paying(OutPoint::new(parent.txid(), 0), &[2]),
+            paying(OutPoint::new(parent.txid(), 0), &[3]),
+        );
+        let settled = [stored(&a, 100), stored(&b, 101), stored(&c, 102)];
+        let entry = |tx: &Transaction, height| GetHistoryRes { height, tx_hash: tx.txid(), fee: None };
+        // b was reorged into a later block, c out of the chain, and d is new
+        let d = paying(OutPoint::new(parent.txid(), 0), &[4]);
+        let history = [entry(&a, 100), entry(&b, 105), entry(&d, 0)];
+
+        let (reused, missing) = split_settled(&history, &settled);
+        assert_eq!(reused.iter().map(|tx| tx.txid).collect::<Vec<_>>(), vec![a.txid()]);
+        assert_eq!(missing.iter().map(|entry| entry.tx_hash).collect::<Vec<_>>(), vec![b.txid(), d.txid()]);
+    }
+
+    #[test]
+    fn test_coinbase_has_no_fee() {
+        let coinbase = paying(OutPoint::null(), &[625_000_000]);
+        let converted = to_esplora_tx(&coinbase, Some(1), None, &HashMap::new());
+        assert!(converted.vin[0].is_coinbase);
+        assert_eq!(converted.fee, 0);
+    }
+}
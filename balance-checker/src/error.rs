@@ -0,0 +1,63 @@
+//! Error taxonomy and process exit codes
+//!
+//! Each failure class gets its own exit code so scripts can tell a mistyped
+//! address apart from a server that is down. 2 matches clap's own usage
+//! errors, and 4 block-explorer's network failures.
+
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CheckerError {
+    /// A bad input, option, or combination of options
+    InvalidInput(String),
+    /// A local file or store could not be read or written
+    File(String),
+    /// The server or node could not be reached, or answered with an error
+    Network(String),
+    /// A proof didn't verify, such as a `verify-por` signature
+    Unverified(String),
+}
+
+impl CheckerError {
+    /// Process exit code
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            CheckerError::InvalidInput(_) => 2,
+            CheckerError::File(_) => 3,
+            CheckerError::Network(_) => 4,
+            CheckerError::Unverified(_) => 5,
+        }
+    }
+}
+
+impl fmt::Display for CheckerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CheckerError::InvalidInput(msg)
+            | CheckerError::File(msg)
+            | CheckerError::Network(msg)
+            | CheckerError::Unverified(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let codes = [
+            CheckerError::InvalidInput(String::new()).exit_code(),
+            CheckerError::File(String::new()).exit_code(),
+            CheckerError::Network(String::new()).exit_code(),
+            CheckerError::Unverified(String::new()).exit_code(),
+        ];
+        assert_eq!(codes, [2, 3, 4, 5]);
+    }
+
+    #[test]
+    fn test_display_is_the_message() {
+        assert_eq!(CheckerError::Network("status code 503".to_string()).to_string(), "status code 503");
+    }
+}
@@ -0,0 +1,173 @@
+//! CSV export of the checked history for `--export-csv`, oldest first with a
+//! running balance, for accounting imports
+//!
+//! Amounts are signed sats: what the transaction paid to the checked scripts
+//! minus what it spent from them. The fee is only given for transactions the
+//! checked scripts helped fund, since that's who paid it.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::esplora_client::Tx;
+use chrono::DateTime;
+use std::collections::HashSet;
+use std::io::{self, Write};
+
+use crate::query::script_hash;
+
+const HEADER: &str = "txid,block_height,timestamp,direction,amount,fee,balance";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    Incoming,
+    Outgoing,
+    /// Funded by the checked scripts and paid only back to them
+    SelfTransfer,
+}
+
+impl Direction {
+    fn label(&self) -> &'static str {
+        match self {
+            Direction::Incoming => "incoming",
+            Direction::Outgoing => "outgoing",
+            Direction::SelfTransfer => "self",
+        }
+    }
+
+    /// Name for the `--txs` listing
+    pub fn title(&self) -> &'static str {
+        match self {
+            Direction::Incoming => "Incoming",
+            Direction::Outgoing => "Outgoing",
+            Direction::SelfTransfer => "Self-transfer",
+        }
+    }
+}
+
+/// One transaction as it affected the checked scripts
+#[derive(Debug)]
+pub struct HistoryRow<'a> {
+    pub tx: &'a Tx,
+    pub direction: Direction,
+    pub amount: i64,
+    pub fee: Option<u64>,
+    /// Balance after this transaction
+    pub balance: i64,
+}
+
+/// Rows for every transaction touching `scripts`, oldest first, with
+/// unconfirmed transactions last
+pub fn history_rows<'a>(txs: &'a [Tx], scripts: &HashSet<sha256::Hash>) -> Vec<HistoryRow<'a>> {
+    let ours = |script: &bdk::bitcoin::Script| scripts.contains(&script_hash(script));
+
+    let mut ordered: Vec<&Tx> = txs.iter().collect();
+    ordered.sort_by_key(|tx| tx.status.block_height.unwrap_or(u32::MAX));
+
+    let mut balance = 0i64;
+    ordered
+        .into_iter()
+        .map(|tx| {
+            let received: u64 = tx.vout.iter().filter(|output| ours(&output.scriptpubkey)).map(|output| output.value).sum();
+            let spent: u64 = tx
+                .vin
+                .iter()
+                .filter_map(|input| input.prevout.as_ref())
+                .filter(|prevout| ours(&prevout.scriptpubkey))
+                .map(|prevout| prevout.value)
+                .sum();
+
+            let direction = if spent == 0 {
+                Direction::Incoming
+            } else if tx.vout.iter().all(|output| ours(&output.scriptpubkey)) {
+                Direction::SelfTransfer
+            } else {
+                Direction::Outgoing
+            };
+            let amount = received as i64 - spent as i64;
+            balance += amount;
+            HistoryRow { tx, direction, amount, fee: (spent > 0).then_some(tx.fee), balance }
+        })
+        .collect()
+}
+
+/// Block time in UTC as ISO 8601, or None while unconfirmed
+pub fn timestamp(tx: &Tx) -> Option<String> {
+    tx.status
+        .block_time
+        .and_then(|time| DateTime::from_timestamp(time as i64, 0))
+        .map(|datetime| datetime.format("%Y-%m-%dT%H:%M:%SZ").to_string())
+}
+
+/// Writes the rows as CSV with a header line; unconfirmed transactions have
+/// an empty height and timestamp
+pub fn write_csv(mut writer: impl Write, rows: &[HistoryRow]) -> io::Result<()> {
+    writeln!(writer, "{}", HEADER)?;
+    for row in rows {
+        let height = row.tx.status.block_height.map(|height| height.to_string()).unwrap_or_default();
+        let timestamp = timestamp(row.tx).unwrap_or_default();
+        let fee = row.fee.map(|fee| fee.to_string()).unwrap_or_default();
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{}",
+            row.tx.txid,
+            height,
+            timestamp,
+            row.direction.label(),
+            row.amount,
+            fee,
+            row.balance
+        )?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, input, output, script, status, txid};
+
+    /// Inputs spend from (owner, value); outputs pay (owner, value)
+    fn tx(id: u8, height: Option<u32>, inputs: &[(u8, u64)], outputs: &[(u8, u64)]) -> Tx {
+        let vin = inputs.iter().map(|&(owner, value)| input(txid(0), 0, script(owner), value)).collect();
+        let vout = outputs.iter().map(|&(owner, value)| output(script(owner), value)).collect();
+        let block_time = height.map(|height| 1_700_000_000 + u64::from(height) * 600);
+        Tx { status: status(height, block_time), fee: 500, ..test_util::tx(id, vin, vout) }
+    }
+
+    #[test]
+    fn test_history_rows() {
+        let scripts = HashSet::from([script_hash(&script(1))]);
+        // Newest first, as Esplora lists them
+        let txs = vec![
+            tx(4, None, &[(1, 99_500)], &[(1, 99_000)]),
+            tx(3, Some(20), &[(1, 50_000)], &[(2, 30_000), (1, 19_500)]),
+            tx(2, Some(10), &[(3, 80_000)], &[(1, 50_000), (3, 29_500)]),
+            tx(1, Some(15), &[(2, 100_500)], &[(1, 100_000)]),
+        ];
+
+        let rows = history_rows(&txs, &scripts);
+        let summary: Vec<(Direction, i64, Option<u64>, i64)> =
+            rows.iter().map(|row| (row.direction, row.amount, row.fee, row.balance)).collect();
+        assert_eq!(
+            summary,
+            vec![
+                (Direction::Incoming, 50_000, None, 50_000),
+                (Direction::Incoming, 100_000, None, 150_000),
+                (Direction::Outgoing, -30_500, Some(500), 119_500),
+                (Direction::SelfTransfer, -500, Some(500), 119_000),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_csv() {
+        let scripts = HashSet::from([script_hash(&script(1))]);
+        let txs = vec![tx(2, None, &[(3, 1_000)], &[(1, 500)]), tx(1, Some(10), &[(3, 2_000)], &[(1, 1_500)])];
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &history_rows(&txs, &scripts)).unwrap();
+
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], HEADER);
+        assert_eq!(lines[1], format!("{},10,2023-11-14T23:53:20Z,incoming,1500,,1500", "01".repeat(32)));
+        assert_eq!(lines[2], format!("{},,,incoming,500,,2000", "02".repeat(32)));
+    }
+}
@@ -0,0 +1,106 @@
+//! Lifetime fees for `--fees`: what the checked scripts paid in the
+//! transactions they helped fund, to judge whether consolidating is worth it
+//!
+//! A transaction's whole fee is counted even when other wallets also funded
+//! it (a coinjoin or payjoin), since the inputs don't say who paid what.
+//! The average fee rate is the total fee over the total virtual size, so
+//! large transactions weigh more than small ones.
+
+use units::Unit;
+
+use crate::export::HistoryRow;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct FeeSummary {
+    /// Transactions with an input from the checked scripts
+    pub tx_count: usize,
+    pub total_fee: u64,
+    pub total_vsize: u64,
+}
+
+impl FeeSummary {
+    /// Average fee rate in sat/vB
+    pub fn fee_rate(&self) -> Option<f64> {
+        (self.total_vsize > 0).then(|| self.total_fee as f64 / self.total_vsize as f64)
+    }
+}
+
+/// Adds up the fees of the rows the checked scripts helped fund
+pub fn summarize(rows: &[HistoryRow]) -> FeeSummary {
+    let mut summary = FeeSummary::default();
+    for row in rows {
+        let Some(fee) = row.fee else { continue };
+        summary.tx_count += 1;
+        summary.total_fee += fee;
+        summary.total_vsize += (row.tx.to_tx().weight() as u64).div_ceil(4);
+    }
+    summary
+}
+
+/// The summary's lines in `unit`, without a heading
+pub fn format_summary(summary: &FeeSummary, unit: Unit) -> Vec<String> {
+    if summary.tx_count == 0 {
+        return vec!["No transactions funded by the checked scripts".to_string()];
+    }
+    let mut lines = vec![
+        format!("Transactions: {}", summary.tx_count),
+        format!("Total:        {}", unit.format(summary.total_fee)),
+        format!("Average:      {} per transaction", unit.format(summary.total_fee / summary.tx_count as u64)),
+    ];
+    if let Some(rate) = summary.fee_rate() {
+        lines.push(format!("Fee rate:     {:.1} sat/vB on average ({} vB in all)", rate, summary.total_vsize));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::history_rows;
+    use bdk::bitcoin::hashes::sha256;
+    use crate::test_util::{input, output, status, txid, P2WPKH as OURS};
+    use bdk::bitcoin::Script;
+    use bdk::esplora_client::{Tx, Vin};
+    use std::collections::HashSet;
+    use std::str::FromStr;
+
+    const THEIRS: &str = "00141d0f172a0ecb48aee1be1f2687d2963ae33f71a1";
+
+    /// Spends output 0 of `spends` (someone else's coin when None) and pays
+    /// each (script, value)
+    fn tx(id: u8, height: u32, spends: Option<(u8, &str)>, pays: &[(&str, u64)], fee: u64) -> Tx {
+        let (prev, spent) = spends.unwrap_or((0xee, THEIRS));
+        let vin = Vin {
+            witness: vec![vec![0x30; 72], vec![0x02; 33]],
+            sequence: 0xffff_fffd,
+            ..input(txid(prev), 0, Script::from_str(spent).unwrap(), 1_000_000)
+        };
+        let vout = pays.iter().map(|(script, value)| output(Script::from_str(script).unwrap(), *value)).collect();
+        Tx { status: status(Some(height), None), fee, ..crate::test_util::tx(id, vec![vin], vout) }
+    }
+
+    #[test]
+    fn test_summarize_counts_only_funded_transactions() {
+        let txs = vec![
+            tx(1, 100, None, &[(OURS, 1_000_000)], 300),
+            tx(2, 110, Some((1, OURS)), &[(THEIRS, 600_000), (OURS, 399_000)], 1_000),
+            tx(3, 120, Some((2, OURS)), &[(OURS, 398_500)], 500),
+        ];
+        let scripts: HashSet<sha256::Hash> = HashSet::from([crate::query::script_hash(&Script::from_str(OURS).unwrap())]);
+        let summary = summarize(&history_rows(&txs, &scripts));
+        assert_eq!((summary.tx_count, summary.total_fee), (2, 1_500));
+        // One P2WPKH input: 110 vB with two outputs, 141 vB with one
+        assert_eq!(summary.total_vsize, 141 + 110);
+        assert!((summary.fee_rate().unwrap() - 1_500.0 / 251.0).abs() < 1e-9);
+
+        let lines = format_summary(&summary, Unit::Sats);
+        assert_eq!(lines[1], "Total:        1,500 sats");
+        assert_eq!(lines[2], "Average:      750 sats per transaction");
+        assert_eq!(lines[3], "Fee rate:     6.0 sat/vB on average (251 vB in all)");
+    }
+
+    #[test]
+    fn test_format_summary_without_funded_transactions() {
+        assert_eq!(format_summary(&FeeSummary::default(), Unit::Btc), vec!["No transactions funded by the checked scripts"]);
+    }
+}
@@ -0,0 +1,178 @@
+//! Fiat conversion for `--fiat`: the current BTC exchange rate from a public
+//! price provider, kept with its source and time so a report can be audited
+//!
+//! CoinGecko says when its price was last updated; Kraken's ticker doesn't,
+//! so its quotes are stamped with the time they were fetched.
+
+use chrono::{DateTime, Utc};
+use clap::ValueEnum;
+use serde_json::Value;
+use std::time::Duration;
+
+use crate::proxy::Proxy;
+
+/// How long to wait for the price provider before giving up
+const PRICE_TIMEOUT_SECS: u64 = 10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Currency {
+    Usd,
+    Eur,
+    Gbp,
+}
+
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Usd => "USD",
+            Currency::Eur => "EUR",
+            Currency::Gbp => "GBP",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum PriceProvider {
+    #[default]
+    Coingecko,
+    Kraken,
+}
+
+impl PriceProvider {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PriceProvider::Coingecko => "CoinGecko",
+            PriceProvider::Kraken => "Kraken",
+        }
+    }
+
+    pub fn default_url(&self) -> &'static str {
+        match self {
+            PriceProvider::Coingecko => "https://api.coingecko.com/api/v3",
+            PriceProvider::Kraken => "https://api.kraken.com",
+        }
+    }
+
+    fn endpoint(&self, base_url: &str, currency: Currency) -> String {
+        let base_url = base_url.trim_end_matches('/');
+        match self {
+            PriceProvider::Coingecko => format!(
+                "{}/simple/price?ids=bitcoin&vs_currencies={}&include_last_updated_at=true",
+                base_url,
+                currency.code().to_lowercase()
+            ),
+            PriceProvider::Kraken => format!("{}/0/public/Ticker?pair=XBT{}", base_url, currency.code()),
+        }
+    }
+
+    /// The rate, and the provider's own timestamp when it gives one
+    fn parse(&self, body: &Value, currency: Currency) -> Result<(f64, Option<DateTime<Utc>>), String> {
+        match self {
+            PriceProvider::Coingecko => {
+                let bitcoin = &body["bitcoin"];
+                let rate = bitcoin[currency.code().to_lowercase()]
+                    .as_f64()
+                    .ok_or_else(|| format!("no {} price in the response", currency.code()))?;
+                let updated = bitcoin["last_updated_at"].as_i64().and_then(|time| DateTime::from_timestamp(time, 0));
+                Ok((rate, updated))
+            }
+            PriceProvider::Kraken => {
+                if let Some(error) = body["error"].as_array().and_then(|errors| errors.first()) {
+                    return Err(error.as_str().unwrap_or("unknown error").to_string());
+                }
+                // The result is keyed by Kraken's own pair name, e.g. XXBTZUSD;
+                // `c` is the last trade as [price, volume]
+                let rate = body["result"]
+                    .as_object()
+                    .and_then(|result| result.values().next())
+                    .and_then(|ticker| ticker["c"][0].as_str())
+                    .and_then(|price| price.parse().ok())
+                    .ok_or_else(|| format!("no {} price in the response", currency.code()))?;
+                Ok((rate, None))
+            }
+        }
+    }
+}
+
+/// An exchange rate and where it came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Quote {
+    pub currency: Currency,
+    /// Fiat units per BTC
+    pub rate: f64,
+    pub provider: PriceProvider,
+    /// When the provider last updated the price, or when it was fetched
+    pub time: DateTime<Utc>,
+}
+
+impl Quote {
+    pub fn value_of(&self, sats: u64) -> f64 {
+        crate::sats_to_btc(sats) * self.rate
+    }
+}
+
+/// Fetches the current BTC price in `currency`, through `proxy` if given
+pub fn fetch_quote(provider: PriceProvider, base_url: &str, currency: Currency, proxy: Option<&Proxy>) -> Result<Quote, String> {
+    let mut agent = ureq::AgentBuilder::new().timeout(Duration::from_secs(PRICE_TIMEOUT_SECS));
+    if let Some(proxy) = proxy {
+        agent = agent.proxy(proxy.for_ureq()?);
+    }
+    let agent = agent.build();
+    let body: Value = agent
+        .get(&provider.endpoint(base_url, currency))
+        .call()
+        .map_err(|e| e.to_string())?
+        .into_json()
+        .map_err(|e| e.to_string())?;
+    let (rate, updated) = provider.parse(&body, currency)?;
+    Ok(Quote { currency, rate, provider, time: updated.unwrap_or_else(Utc::now) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoints() {
+        assert_eq!(
+            PriceProvider::Coingecko.endpoint("https://api.coingecko.com/api/v3/", Currency::Eur),
+            "https://api.coingecko.com/api/v3/simple/price?ids=bitcoin&vs_currencies=eur&include_last_updated_at=true"
+        );
+        assert_eq!(
+            PriceProvider::Kraken.endpoint("https://api.kraken.com", Currency::Gbp),
+            "https://api.kraken.com/0/public/Ticker?pair=XBTGBP"
+        );
+    }
+
+    #[test]
+    fn test_parse_coingecko() {
+        let body: Value = serde_json::from_str(r#"{"bitcoin":{"usd":67012.5,"last_updated_at":1700000000}}"#).unwrap();
+        let (rate, updated) = PriceProvider::Coingecko.parse(&body, Currency::Usd).unwrap();
+        assert_eq!(rate, 67012.5);
+        assert_eq!(updated.unwrap().timestamp(), 1_700_000_000);
+        assert!(PriceProvider::Coingecko.parse(&body, Currency::Eur).is_err());
+    }
+
+    #[test]
+    fn test_parse_kraken() {
+        let body: Value = serde_json::from_str(
+            r#"{"error":[],"result":{"XXBTZEUR":{"a":["61000.0","1","1.0"],"c":["61023.4","0.0015"]}}}"#,
+        )
+        .unwrap();
+        assert_eq!(PriceProvider::Kraken.parse(&body, Currency::Eur).unwrap(), (61023.4, None));
+
+        let failed: Value = serde_json::from_str(r#"{"error":["EQuery:Unknown asset pair"]}"#).unwrap();
+        assert_eq!(PriceProvider::Kraken.parse(&failed, Currency::Eur).unwrap_err(), "EQuery:Unknown asset pair");
+    }
+
+    #[test]
+    fn test_quote_value() {
+        let quote = Quote {
+            currency: Currency::Usd,
+            rate: 60_000.0,
+            provider: PriceProvider::Coingecko,
+            time: DateTime::from_timestamp(0, 0).unwrap(),
+        };
+        assert_eq!(quote.value_of(1_500_000), 900.0);
+    }
+}
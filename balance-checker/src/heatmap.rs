@@ -0,0 +1,157 @@
+//! GitHub-style calendar heatmap of transaction activity over the past year
+
+use chrono::{Datelike, Duration, NaiveDate};
+
+/// Number of week columns shown, like GitHub's contribution graph
+const WEEKS: usize = 53;
+
+/// Shades from no activity to the busiest day
+const SHADES: [char; 5] = ['·', '░', '▒', '▓', '█'];
+
+/// Row labels, Sunday first; blank rows keep the grid compact
+const WEEKDAY_LABELS: [&str; 7] = ["   ", "Mon", "   ", "Wed", "   ", "Fri", "   "];
+
+/// Per-day transaction counts laid out in week columns
+#[derive(Debug, PartialEq, Eq)]
+pub struct ActivityGrid {
+    /// The Sunday that starts the first column
+    start: NaiveDate,
+    /// Last day shown (inclusive)
+    end: NaiveDate,
+    /// counts[week][weekday], weekday 0 = Sunday
+    counts: Vec<[u32; 7]>,
+}
+
+impl ActivityGrid {
+    /// Buckets activity dates into the year ending on `today`; dates outside
+    /// the window are ignored
+    pub fn from_dates(dates: impl IntoIterator<Item = NaiveDate>, today: NaiveDate) -> Self {
+        let first_week_start = today - Duration::weeks(WEEKS as i64 - 1);
+        let start = first_week_start
+            - Duration::days(first_week_start.weekday().num_days_from_sunday() as i64);
+
+        let mut counts = vec![[0u32; 7]; WEEKS];
+        for date in dates {
+            if date < start || date > today {
+                continue;
+            }
+            let offset = (date - start).num_days() as usize;
+            counts[offset / 7][offset % 7] += 1;
+        }
+
+        ActivityGrid { start, end: today, counts }
+    }
+
+    /// Total transactions inside the window
+    pub fn total(&self) -> u32 {
+        self.counts.iter().flatten().sum()
+    }
+
+    /// Number of distinct days with at least one transaction
+    pub fn active_days(&self) -> usize {
+        self.counts.iter().flatten().filter(|&&c| c > 0).count()
+    }
+
+    /// Renders month labels, seven weekday rows, and a legend
+    pub fn render(&self) -> Vec<String> {
+        let max = self.counts.iter().flatten().copied().max().unwrap_or(0);
+        let mut lines = vec![format!("    {}", self.month_labels())];
+
+        for (weekday, label) in WEEKDAY_LABELS.iter().enumerate() {
+            let row: String = self
+                .counts
+                .iter()
+                .enumerate()
+                .map(|(week, days)| {
+                    let date = self.start + Duration::days((week * 7 + weekday) as i64);
+                    if date > self.end {
+                        ' '
+                    } else {
+                        SHADES[shade_level(days[weekday], max)]
+                    }
+                })
+                .collect();
+            lines.push(format!("{} {}", label, row));
+        }
+
+        let legend: String = SHADES.iter().collect();
+        lines.push(format!("    Less {} More", legend));
+        lines
+    }
+
+    /// Month abbreviations positioned over the first week of each month
+    fn month_labels(&self) -> String {
+        let mut labels = vec![' '; WEEKS + 3];
+        let mut last_month = None;
+        for week in 0..WEEKS {
+            let date = self.start + Duration::weeks(week as i64);
+            if last_month != Some(date.month()) {
+                last_month = Some(date.month());
+                // Skip a label that would overwrite the previous one
+                if week == 0 || labels[week - 1] == ' ' {
+                    for (i, ch) in date.format("%b").to_string().chars().enumerate() {
+                        labels[week + i] = ch;
+                    }
+                }
+            }
+        }
+        labels.into_iter().collect::<String>().trim_end().to_string()
+    }
+}
+
+/// Maps a day's count to a shade index, scaled to the busiest day
+fn shade_level(count: u32, max: u32) -> usize {
+    if count == 0 || max == 0 {
+        return 0;
+    }
+    let levels = (SHADES.len() - 1) as u32;
+    (count * levels).div_ceil(max) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(y: i32, m: u32, d: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(y, m, d).unwrap()
+    }
+
+    #[test]
+    fn test_grid_starts_on_sunday_and_counts_dates() {
+        let today = date(2024, 6, 15); // a Saturday
+        let grid = ActivityGrid::from_dates(
+            vec![today, today, date(2024, 6, 10), date(2020, 1, 1)],
+            today,
+        );
+
+        assert_eq!(grid.start.weekday(), chrono::Weekday::Sun);
+        assert_eq!(grid.total(), 3);
+        assert_eq!(grid.active_days(), 2);
+        assert_eq!(grid.counts[WEEKS - 1][6], 2);
+        assert_eq!(grid.counts[WEEKS - 1][1], 1);
+    }
+
+    #[test]
+    fn test_shade_level() {
+        assert_eq!(shade_level(0, 10), 0);
+        assert_eq!(shade_level(1, 10), 1);
+        assert_eq!(shade_level(5, 10), 2);
+        assert_eq!(shade_level(10, 10), 4);
+        assert_eq!(shade_level(3, 0), 0);
+    }
+
+    #[test]
+    fn test_render_shape() {
+        let today = date(2024, 6, 12); // a Wednesday
+        let grid = ActivityGrid::from_dates(vec![today], today);
+        let lines = grid.render();
+
+        // Month header, seven weekday rows, legend
+        assert_eq!(lines.len(), 9);
+        assert!(lines[0].trim_start().starts_with("Jun"));
+        assert!(lines[4].starts_with("Wed"));
+        assert!(lines[4].ends_with('█'));
+        // Days after today are left blank
+        assert!(lines[5].ends_with(' '));
+    }
+}
@@ -0,0 +1,96 @@
+//! Bitcoin Core import for `--export-core`: the checked descriptors and
+//! addresses as an `importdescriptors` request, to check the balance again
+//! on your own node
+//!
+//! Ranged descriptors are imported over the indexes that were scanned, and
+//! change chains as internal. None is made active, so Core watches exactly
+//! what this run checked. The rescan starts at the block time of the oldest
+//! confirmed transaction found; Core goes back two hours from it by itself.
+//! With no history, `now` skips the rescan.
+
+use bdk::bitcoin::{Address, Script};
+use bdk::descriptor::calc_checksum;
+use bdk::KeychainKind;
+use serde_json::{json, Value};
+
+use crate::wallet::WalletDescriptor;
+
+/// One thing to import
+pub enum Import<'a> {
+    /// A wallet descriptor and how many of its indexes were scanned
+    Descriptor(&'a WalletDescriptor, u32),
+    Address(&'a Address),
+    Script(&'a Script),
+}
+
+/// The descriptor with its checksum, for a body that's already valid
+fn checksummed(body: String) -> String {
+    let checksum = calc_checksum(&body).expect("addr() and raw() descriptors have valid characters");
+    format!("{}#{}", body, checksum)
+}
+
+fn request(import: &Import, timestamp: &Value) -> Value {
+    match import {
+        Import::Descriptor(entry, scanned) => {
+            let mut request = json!({ "desc": entry.descriptor.to_string(), "timestamp": timestamp });
+            if entry.descriptor.has_wildcard() {
+                request["range"] = json!([0, scanned.saturating_sub(1)]);
+            }
+            if entry.keychain == Some(KeychainKind::Internal) {
+                request["internal"] = json!(true);
+            }
+            request
+        }
+        Import::Address(address) => json!({ "desc": checksummed(format!("addr({})", address)), "timestamp": timestamp }),
+        Import::Script(script) => json!({ "desc": checksummed(format!("raw({:x})", script)), "timestamp": timestamp }),
+    }
+}
+
+/// The `importdescriptors` array, pretty-printed; `rescan_from` is the Unix
+/// time to rescan from, if any transaction was confirmed
+pub fn to_json(imports: &[Import], rescan_from: Option<u64>) -> String {
+    let timestamp = rescan_from.map_or(json!("now"), |time| json!(time));
+    let requests: Vec<Value> = imports.iter().map(|import| request(import, &timestamp)).collect();
+    serde_json::to_string_pretty(&requests).expect("JSON values always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::parse_descriptor;
+    use std::str::FromStr;
+
+    const DESCRIPTOR: &str = "wpkh([73c5da0a/84'/1'/0']tpubDC8msFGeGuwnKG9Upg7DM2b4DaRqg3CUZa5g8v2SRQ6K4NSkxUgd7HsL2XVWbVm39yBA4LAxysQAm397zwQSQoQgewGiYZqrA9DsP4zbQ1M/<0;1>/*)";
+
+    #[test]
+    fn test_descriptor_requests() {
+        let wallet = parse_descriptor(DESCRIPTOR).unwrap();
+        let imports = [Import::Descriptor(&wallet.descriptors[0], 25), Import::Descriptor(&wallet.descriptors[1], 20)];
+        let requests: Value = serde_json::from_str(&to_json(&imports, Some(1_700_000_000))).unwrap();
+
+        let receive = &requests[0];
+        let desc = receive["desc"].as_str().unwrap();
+        assert!(desc.starts_with("wpkh([73c5da0a/84'/1'/0']tpub") && desc.contains("/0/*)#"));
+        let (body, checksum) = desc.split_once('#').unwrap();
+        assert_eq!(calc_checksum(body).unwrap(), checksum);
+        assert_eq!(receive["range"], json!([0, 24]));
+        assert_eq!(receive["timestamp"], 1_700_000_000);
+        assert!(receive.get("internal").is_none() && receive.get("active").is_none());
+
+        assert!(requests[1]["desc"].as_str().unwrap().contains("/1/*)#"));
+        assert_eq!(requests[1]["internal"], true);
+    }
+
+    #[test]
+    fn test_address_and_script_requests() {
+        let address = Address::from_str("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap();
+        let script = address.script_pubkey();
+        let requests: Value = serde_json::from_str(&to_json(&[Import::Address(&address), Import::Script(&script)], None)).unwrap();
+
+        // The checksums Bitcoin Core's getdescriptorinfo gives
+        assert_eq!(requests[0]["desc"], "addr(tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx)#0wnhlaqf");
+        assert_eq!(requests[1]["desc"], "raw(0014751e76e8199196d454941c45d1b3a323f1433bd6)#t687veyk");
+        assert_eq!(requests[0]["timestamp"], "now");
+        assert!(requests[0].get("range").is_none());
+    }
+}
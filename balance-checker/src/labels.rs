@@ -0,0 +1,137 @@
+//! Labels for addresses and wallets, set with `--label` or
+//! `balance-checker labels set` and kept in
+//! `~/.local/share/bdk-experiments/balance-checker/labels` (or
+//! `$XDG_DATA_HOME`)
+//!
+//! A label belongs to an input as it is checked: an address in its standard
+//! form, so `BC1Q...` and `bc1q...` share one, or a descriptor, extended key,
+//! script, or script hash exactly as written. Address labels are also shown
+//! beside the unspent outputs paying that address, wherever it came from.
+//!
+//! Each run reads every label up front and closes the database, so a long
+//! `--watch` doesn't keep `labels set` from another shell waiting.
+
+use bdk::bitcoin::Address;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::store;
+
+/// Directory of the label database, inside the checker's data directory
+const LABELS_DIR: &str = "labels";
+/// Tree of the sled database holding the labels
+const LABELS_TREE: &str = "labels";
+
+pub struct LabelStore {
+    labels: sled::Tree,
+}
+
+impl LabelStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let db = sled::open(path).map_err(|e| format!("Could not open label store {}: {}", path.display(), e))?;
+        Ok(LabelStore { labels: db.open_tree(LABELS_TREE).map_err(|e| e.to_string())? })
+    }
+
+    pub fn set(&self, input: &str, label: &str) -> Result<(), String> {
+        let label = validate(label)?;
+        self.labels.insert(label_key(input), label.as_bytes()).map_err(|e| e.to_string())?;
+        self.labels.flush().map_err(|e| e.to_string())?;
+        Ok(())
+    }
+
+    /// Whether there was a label to remove
+    pub fn remove(&self, input: &str) -> Result<bool, String> {
+        let removed = self.labels.remove(label_key(input)).map_err(|e| e.to_string())?.is_some();
+        self.labels.flush().map_err(|e| e.to_string())?;
+        Ok(removed)
+    }
+
+    /// Every label, by labelled input, sorted by input
+    pub fn all(&self) -> Vec<(String, String)> {
+        self.labels
+            .iter()
+            .filter_map(Result::ok)
+            .map(|(key, label)| (String::from_utf8_lossy(&key).into_owned(), String::from_utf8_lossy(&label).into_owned()))
+            .collect()
+    }
+}
+
+pub fn default_path() -> Option<PathBuf> {
+    Some(store::data_dir()?.join(LABELS_DIR))
+}
+
+/// Every saved label, or none if the store can't be read; only warns, since
+/// labels don't change any balance
+pub fn load() -> HashMap<String, String> {
+    let Some(path) = default_path() else {
+        return HashMap::new();
+    };
+    if !path.exists() {
+        return HashMap::new();
+    }
+    match LabelStore::open(&path) {
+        Ok(store) => store.all().into_iter().collect(),
+        Err(e) => {
+            eprintln!("Warning: {}; labels won't be shown", e);
+            HashMap::new()
+        }
+    }
+}
+
+/// The key an input's label is saved under
+pub fn label_key(input: &str) -> String {
+    let input = input.trim();
+    match Address::from_str(input) {
+        Ok(address) => address.to_string(),
+        Err(_) => input.to_string(),
+    }
+}
+
+/// Labels are shown in tables and records, so they must fit on one line
+fn validate(label: &str) -> Result<&str, String> {
+    let label = label.trim();
+    if label.is_empty() {
+        return Err("A label can't be empty; use `labels rm` to remove one".to_string());
+    }
+    if label.contains(['\t', '\n', '\r']) {
+        return Err("A label must be a single line without tabs".to_string());
+    }
+    Ok(label)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temporary_store() -> LabelStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        LabelStore { labels: db.open_tree(LABELS_TREE).unwrap() }
+    }
+
+    #[test]
+    fn test_set_list_and_remove() {
+        let store = temporary_store();
+        store.set("TB1QW508D6QEJXTDG4Y5R3ZARVARY0C5XW7KXPJZSX", " cold storage ").unwrap();
+        store.set("wpkh(xpub.../0/*)", "savings").unwrap();
+        assert_eq!(
+            store.all(),
+            vec![
+                ("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx".to_string(), "cold storage".to_string()),
+                ("wpkh(xpub.../0/*)".to_string(), "savings".to_string()),
+            ]
+        );
+
+        assert!(store.remove("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap());
+        assert!(!store.remove("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap());
+        assert_eq!(store.all().len(), 1);
+    }
+
+    #[test]
+    fn test_labels_must_fit_on_one_line() {
+        let store = temporary_store();
+        assert!(store.set("x", "  ").is_err());
+        assert!(store.set("x", "two\nlines").is_err());
+        assert!(store.set("x", "tab\tseparated").is_err());
+    }
+}
@@ -0,0 +1,184 @@
+//! Ledger export for `--ledger`: the history as one entry per movement of
+//! funds, for accounting tools that book fees apart from payments
+//!
+//! A transaction becomes up to two entries: what it paid to or from the
+//! checked scripts (`in` or `out`), and the fee (`fee`) when they funded it,
+//! so a self-transfer is only its fee. A transaction's entries add up to its
+//! `--export-csv` amount, and each carries the balance after it. A path
+//! ending in `.json` gets a JSON array, anything else CSV.
+
+use bdk::esplora_client::Tx;
+use serde::Serialize;
+use std::io::{self, Write};
+use std::path::Path;
+
+use crate::export::{timestamp, HistoryRow};
+
+const HEADER: &str = "date,txid,category,amount,balance";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Category {
+    In,
+    Out,
+    Fee,
+}
+
+impl Category {
+    fn label(&self) -> &'static str {
+        match self {
+            Category::In => "in",
+            Category::Out => "out",
+            Category::Fee => "fee",
+        }
+    }
+}
+
+/// An entry as written to JSON, fields in the CSV's column order
+#[derive(Serialize)]
+struct Record {
+    date: Option<String>,
+    txid: String,
+    category: &'static str,
+    amount: i64,
+    balance: i64,
+}
+
+/// One movement of funds, in signed sats
+#[derive(Debug)]
+pub struct Entry<'a> {
+    pub tx: &'a Tx,
+    pub category: Category,
+    pub amount: i64,
+    /// Balance after this entry
+    pub balance: i64,
+}
+
+/// Splits each row into its payment and fee entries, in the rows' order
+pub fn entries<'a>(rows: &[HistoryRow<'a>]) -> Vec<Entry<'a>> {
+    let mut entries = Vec::new();
+    for row in rows {
+        let mut balance = row.balance - row.amount;
+        let fee = row.fee.map_or(0, |fee| fee as i64);
+        // What's left of the amount once the fee is booked on its own
+        let payment = row.amount + fee;
+        let category = if payment < 0 { Category::Out } else { Category::In };
+        for (category, amount) in [(category, payment), (Category::Fee, -fee)] {
+            if amount != 0 {
+                balance += amount;
+                entries.push(Entry { tx: row.tx, category, amount, balance });
+            }
+        }
+    }
+    entries
+}
+
+/// Whether `path` asks for JSON rather than CSV
+pub fn is_json(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json"))
+}
+
+/// Writes the entries as CSV with a header line; unconfirmed transactions
+/// have an empty date
+pub fn write_csv(mut writer: impl Write, entries: &[Entry]) -> io::Result<()> {
+    writeln!(writer, "{}", HEADER)?;
+    for entry in entries {
+        writeln!(
+            writer,
+            "{},{},{},{},{}",
+            timestamp(entry.tx).unwrap_or_default(),
+            entry.tx.txid,
+            entry.category.label(),
+            entry.amount,
+            entry.balance
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes the entries as a JSON array; unconfirmed transactions have a null
+/// date
+pub fn write_json(mut writer: impl Write, entries: &[Entry]) -> io::Result<()> {
+    let records: Vec<Record> = entries
+        .iter()
+        .map(|entry| Record {
+            date: timestamp(entry.tx),
+            txid: entry.tx.txid.to_string(),
+            category: entry.category.label(),
+            amount: entry.amount,
+            balance: entry.balance,
+        })
+        .collect();
+    serde_json::to_writer_pretty(&mut writer, &records)?;
+    writeln!(writer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::Direction;
+    use crate::test_util::status;
+    use serde_json::Value;
+
+    fn tx(id: u8, block_time: Option<u64>) -> Tx {
+        Tx { status: status(None, block_time), fee: 500, ..crate::test_util::tx(id, vec![], vec![]) }
+    }
+
+    fn row(tx: &Tx, direction: Direction, amount: i64, fee: Option<u64>, balance: i64) -> HistoryRow<'_> {
+        HistoryRow { tx, direction, amount, fee, balance }
+    }
+
+    #[test]
+    fn test_entries_book_fees_apart() {
+        let (received, sent, moved) = (tx(1, Some(1_700_000_000)), tx(2, Some(1_700_000_600)), tx(3, None));
+        let rows = [
+            row(&received, Direction::Incoming, 100_000, None, 100_000),
+            row(&sent, Direction::Outgoing, -30_500, Some(500), 69_500),
+            row(&moved, Direction::SelfTransfer, -300, Some(300), 69_200),
+        ];
+
+        let booked: Vec<(Category, i64, i64)> =
+            entries(&rows).iter().map(|entry| (entry.category, entry.amount, entry.balance)).collect();
+        assert_eq!(
+            booked,
+            vec![
+                (Category::In, 100_000, 100_000),
+                (Category::Out, -30_000, 70_000),
+                (Category::Fee, -500, 69_500),
+                (Category::Fee, -300, 69_200),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_write_csv_and_json() {
+        let (received, pending) = (tx(1, Some(1_700_000_000)), tx(2, None));
+        let rows = [
+            row(&received, Direction::Incoming, 1_500, None, 1_500),
+            row(&pending, Direction::Outgoing, -1_000, Some(200), 500),
+        ];
+        let entries = entries(&rows);
+
+        let mut csv = Vec::new();
+        write_csv(&mut csv, &entries).unwrap();
+        let csv = String::from_utf8(csv).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], HEADER);
+        assert_eq!(lines[1], format!("2023-11-14T22:13:20Z,{},in,1500,1500", "01".repeat(32)));
+        assert_eq!(lines[3], format!(",{},fee,-200,500", "02".repeat(32)));
+
+        let mut json = Vec::new();
+        write_json(&mut json, &entries).unwrap();
+        let json: Value = serde_json::from_slice(&json).unwrap();
+        assert_eq!(json.as_array().unwrap().len(), 3);
+        assert_eq!(json[1]["category"], "out");
+        assert_eq!(json[1]["amount"], -800);
+        assert!(json[2]["date"].is_null());
+    }
+
+    #[test]
+    fn test_is_json() {
+        assert!(is_json(Path::new("books/2024.JSON")));
+        assert!(!is_json(Path::new("ledger.csv")));
+        assert!(!is_json(Path::new("ledger")));
+    }
+}
@@ -1,251 +1,2786 @@
+mod alerts;
+mod batch;
+mod bitcoind;
+mod cbf;
+mod chain;
+mod coins;
+mod concurrent;
+mod consolidate;
+mod crosscheck;
+mod derivation;
+mod distribution;
+mod electrum;
+mod error;
+mod esplora;
+mod export;
+mod fees;
+mod fiat;
+mod heatmap;
+mod import;
+mod labels;
+mod ledger;
+mod mempool;
+mod mempool_space;
+mod networks;
+mod period;
+mod por;
+mod portfolio;
+mod privacy;
+mod progress;
+mod proxy;
+mod query;
+mod retry;
+mod reuse;
+mod silentpayments;
+mod simulate;
+mod spendability;
+mod store;
+mod sweep;
+mod sync;
+#[cfg(test)]
+mod test_util;
+mod timeline;
+mod timelocks;
+mod utxos;
+mod wallet;
+mod wallets;
+mod watch;
+
 use bdk::{
-    bitcoin::{Network, Address},
+    bitcoin::{hashes::sha256, Address, Network, Script, Txid},
     blockchain::esplora::EsploraBlockchain,
+    esplora_client::Tx,
+    Balance, KeychainKind,
 };
-use clap::Parser;
+use chain::Chain;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand, ValueEnum};
+use error::CheckerError;
+use progress::Progress;
+use proxy::Proxy;
+use query::QueryTarget;
+use retry::RetryPolicy;
 use std::collections::{HashMap, HashSet};
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
+use units::Unit;
 
 #[derive(Parser, Debug)]
 #[command(name = "balance-checker")]
 #[command(about = "Check Bitcoin address or wallet balance", long_about = None)]
+#[command(subcommand_negates_reqs = true)]
+#[command(group(clap::ArgGroup::new("fee_estimates").multiple(true)))]
+// What --networks replaces: it picks each network's default server, and
+// reports balances only
+#[command(group(clap::ArgGroup::new("one_network").multiple(true).conflicts_with("networks").args([
+    "network", "esplora_url", "server", "rpc_url", "peer", "rescan_from", "simulate", "wallet", "save_wallet", "label",
+    "txs", "first_seen", "heatmap", "export_csv", "ledger", "export_utxos", "export_core", "utxos", "utxo_stats",
+    "mempool_watch", "fiat", "history_chart", "fees", "consolidate_advice", "sweep_estimate", "privacy_report", "since",
+    "until", "at_height", "min_conf", "dust_limit", "non_signalling", "watch", "verify_with",
+])))]
 struct Args {
-    /// Bitcoin address or extended public key (xpub/ypub/zpub)
-    address: String,
-
-    /// Network (testnet or bitcoin)
-    #[arg(short, long, default_value = "testnet")]
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Bitcoin addresses, output descriptors, extended public keys
+    /// (xpub/ypub/zpub, or tpub/upub/vpub on testnet), silent payment
+    /// addresses (with --sp-scan-key), scriptPubKey hex, or Electrum-style
+    /// script hashes (prefix hex with script: or scripthash: when 64 chars
+    /// could be either); several are totalled together
+    #[arg(value_name = "ADDRESS", required_unless_present_any = ["multisig", "extra_addresses", "stdin", "input_file", "wallet"])]
+    addresses: Vec<String>,
+
+    /// Another address (or any input accepted positionally) to check; repeatable
+    #[arg(long = "address", value_name = "ADDRESS")]
+    extra_addresses: Vec<String>,
+
+    /// Check an M-of-N multisig wallet with this threshold M; give the N
+    /// cosigner keys with --xpub
+    #[arg(long, value_name = "M", requires = "xpubs")]
+    multisig: Option<usize>,
+
+    /// Cosigner account key for --multisig (repeat once per cosigner)
+    #[arg(long = "xpub", value_name = "KEY", requires = "multisig")]
+    xpubs: Vec<String>,
+
+    /// Unused scripts in a row after which a wallet chain is considered
+    /// exhausted
+    #[arg(long, value_name = "N", default_value_t = wallet::DEFAULT_GAP_LIMIT, value_parser = clap::value_parser!(u32).range(1..))]
+    gap_limit: u32,
+
+    /// Stop scanning each wallet chain after this many scripts, even if the
+    /// gap limit hasn't been reached (with --backend bitcoind, the number of
+    /// indexes scanned; default 1000 there)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    scan_limit: Option<u32>,
+
+    /// Save a label for the input (e.g. "cold storage"), shown beside it and
+    /// its unspent outputs in later runs
+    #[arg(long, value_name = "LABEL", conflicts_with = "multisig")]
+    label: Option<String>,
+
+    /// Save the checked wallet (a descriptor, extended key or multisig) as
+    /// a watch-only wallet with its sync state, to open later with --wallet
+    #[arg(long, value_name = "NAME", conflicts_with = "wallet")]
+    save_wallet: Option<String>,
+
+    /// Check a wallet saved with --save-wallet, by name, and update its
+    /// sync state
+    #[arg(long, value_name = "NAME")]
+    wallet: Option<String>,
+
+    /// Read inputs from stdin, one per line, and print one tab-separated
+    /// record per input
+    #[arg(long, conflicts_with_all = ["addresses", "extra_addresses", "multisig", "input_file", "txs", "first_seen", "heatmap", "export_csv", "ledger", "export_utxos", "export_core", "utxos", "utxo_stats", "mempool_watch", "fiat", "history_chart", "fees", "consolidate_advice", "sweep_estimate", "privacy_report", "since", "until", "at_height", "watch", "label", "save_wallet", "wallet", "verify_with", "networks"])]
+    stdin: bool,
+
+    /// Read inputs from a file, one per line (blank lines and `#` comments
+    /// are skipped), and print one tab-separated record per input
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["addresses", "extra_addresses", "multisig", "txs", "first_seen", "heatmap", "export_csv", "ledger", "export_utxos", "export_core", "utxos", "utxo_stats", "mempool_watch", "fiat", "history_chart", "fees", "consolidate_advice", "sweep_estimate", "privacy_report", "since", "until", "at_height", "watch", "label", "save_wallet", "wallet", "verify_with", "networks"])]
+    input_file: Option<PathBuf>,
+
+    /// Network (auto, testnet, testnet4, signet, regtest or bitcoin); auto
+    /// takes it from the first address or extended key that names one
+    #[arg(short, long, default_value = "auto")]
     network: String,
 
+    /// Check the inputs on each of these networks (comma-separated, e.g.
+    /// bitcoin,testnet,signet), with addresses and keys re-encoded for each,
+    /// and report the balances side by side. Takes public keys only, not a
+    /// mnemonic: derive the account key from the seed with your wallet first
+    #[arg(long, value_name = "NETWORKS", value_delimiter = ',', value_parser = parse_network)]
+    networks: Vec<Chain>,
+
+    /// Server protocol to query
+    #[arg(long, value_enum, default_value = "esplora")]
+    backend: Backend,
+
+    /// Esplora API for --backend esplora or mempool-space, e.g. your own
+    /// Esplora or mempool instance; credentials in the URL are sent as basic
+    /// auth (default: blockstream.info, or mempool.space; required on
+    /// regtest)
+    #[arg(long, value_name = "URL")]
+    esplora_url: Option<String>,
+
+    /// Electrum server for --backend electrum, as ssl://host:port or
+    /// tcp://host:port (default: Blockstream's public server, or
+    /// mempool.space's on testnet4 and signet; required on regtest)
+    #[arg(long, value_name = "URL")]
+    server: Option<String>,
+
+    /// Bitcoin Core RPC endpoint for --backend bitcoind (default: the local
+    /// node on the network's standard port)
+    #[arg(long, value_name = "URL")]
+    rpc_url: Option<String>,
+
+    /// RPC user for --backend bitcoind
+    #[arg(long, requires = "rpc_password")]
+    rpc_user: Option<String>,
+
+    /// RPC password for --backend bitcoind
+    #[arg(long, requires = "rpc_user")]
+    rpc_password: Option<String>,
+
+    /// Cookie file for --backend bitcoind, used when no --rpc-user is given
+    /// (default: .cookie in ~/.bitcoin, or ~/.bitcoin/testnet3)
+    #[arg(long, value_name = "PATH", conflicts_with = "rpc_user")]
+    rpc_cookie: Option<PathBuf>,
+
+    /// P2P peer for --backend cbf, as host:port; it must serve compact block
+    /// filters (default: a DNS seed, whose peers are tried until one does;
+    /// required on regtest)
+    #[arg(long, value_name = "HOST:PORT")]
+    peer: Option<String>,
+
+    /// First block height whose filter --backend cbf checks, or that is
+    /// scanned for silent payments, e.g. the wallet's creation height;
+    /// earlier transactions aren't seen (default: 0, the whole chain, for
+    /// cbf; the last 144 blocks for silent payments)
+    #[arg(long, value_name = "HEIGHT")]
+    birthday: Option<u32>,
+
+    /// Scan private key of the silent payment (sp1...) address being
+    /// checked, as 64 hex characters or WIF; it finds payments but can't
+    /// spend them
+    #[arg(long, value_name = "KEY")]
+    sp_scan_key: Option<String>,
+
+    /// SOCKS5 proxy for the Esplora or Electrum server and the price
+    /// provider, e.g. socks5://127.0.0.1:9050 for Tor; needed for .onion
+    /// servers
+    #[arg(long, value_name = "URL")]
+    proxy: Option<String>,
+
+    /// Check the same inputs against a second backend and report any
+    /// difference in balance or unspent outputs: a backend name (esplora,
+    /// mempool-space, electrum, bitcoind or cbf) for its default server,
+    /// BACKEND=URL, or an Esplora http(s):// or Electrum ssl:// or tcp:// URL
+    #[arg(long, value_name = "BACKEND[=URL]", conflicts_with_all = ["simulate", "watch"])]
+    verify_with: Option<String>,
+
+    /// Scripts or inputs to look up at once; a wallet's scripts and the lines
+    /// of --stdin/--input-file are fetched side by side. One script's Esplora
+    /// pages still come one after another, as each names the last
+    /// transaction of the one before
+    #[arg(long, default_value = "4", value_parser = clap::value_parser!(u8).range(1..))]
+    concurrency: u8,
+
+    /// Times to retry a request that timed out or was rate limited, with
+    /// exponential backoff; 0 fails at once
+    #[arg(long, value_name = "N", default_value = "3", value_parser = clap::value_parser!(u32).range(0..=10))]
+    retries: u32,
+
+    /// Send at most this many requests per second to the Esplora or
+    /// Electrum server, across all --concurrency threads
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    rate_limit: Option<u32>,
+
+    /// Don't read or update the local history store, or the block headers
+    /// --backend cbf keeps; fetch everything from the server or peer
+    #[arg(long, visible_alias = "no-cache")]
+    no_store: bool,
+
+    /// Seconds a stored history is reused whole, unconfirmed transactions
+    /// included, while the tip doesn't move; 0 always fetches what's newer
+    /// than the settled transactions
+    #[arg(long, value_name = "SECS", default_value = "60", conflicts_with = "no_store")]
+    cache_ttl: u64,
+
+    /// Discard what the history store holds from this block height up and
+    /// fetch it again, e.g. after a deep reorg or a sync that went wrong
+    #[arg(long, value_name = "HEIGHT", conflicts_with_all = ["no_store", "simulate"])]
+    rescan_from: Option<u32>,
+
+    /// Don't show the status line on stderr while histories are fetched
+    #[arg(long)]
+    no_progress: bool,
+
     /// Show transaction history
     #[arg(short, long)]
     txs: bool,
+
+    /// Unit for amounts in the report (sats, btc, mbtc or bits), shown with
+    /// thousands separators; exports and batch records stay in sats
+    #[arg(long, value_enum, value_name = "UNIT", default_value = "sats")]
+    unit: Unit,
+
+    /// Also show the balance in this fiat currency at the current exchange rate
+    #[arg(long, value_enum, value_name = "CURRENCY")]
+    fiat: Option<fiat::Currency>,
+
+    /// Where --fiat gets the exchange rate
+    #[arg(long, value_enum, default_value = "coingecko", requires = "fiat")]
+    price_provider: fiat::PriceProvider,
+
+    /// Base URL of the price provider's API, to use a mirror or proxy
+    #[arg(long, value_name = "URL", requires = "fiat")]
+    price_url: Option<String>,
+
+    /// Split the balance into spendable (at least N confirmations), pending
+    /// (fewer), and unconfirmed; with --txs, list only transactions with at
+    /// least N confirmations
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..))]
+    min_conf: Option<u32>,
+
+    /// Count outputs worth less than this many sats as dust rather than
+    /// spendable, and show their total separately
+    #[arg(long, value_name = "SATS")]
+    dust_limit: Option<u64>,
+
+    /// Also show the unconfirmed value that doesn't signal RBF (BIP125):
+    /// change, and incoming funds that don't opt in. Full RBF is the
+    /// default since Bitcoin Core 28, so this is still not safe from
+    /// replacement.
+    #[arg(long, alias = "safe-unconfirmed")]
+    non_signalling: bool,
+
+    /// List every unspent output with its value, confirmations, script type,
+    /// and age in blocks, largest first
+    #[arg(long)]
+    utxos: bool,
+
+    /// Summarize the unspent outputs: count, total, mean and median value,
+    /// largest and smallest, and how many are how old
+    #[arg(long)]
+    utxo_stats: bool,
+
+    /// Advise whether to sweep the unspent outputs into one now, at
+    /// --feerate, rather than spend them separately later
+    #[arg(long, group = "fee_estimates", requires = "feerate", conflicts_with = "at_height")]
+    consolidate_advice: bool,
+
+    /// Estimate the size and fee of one transaction sweeping every unspent
+    /// output to a single output of the wallet's type at --feerate, and the
+    /// amount left after the fee
+    #[arg(long, group = "fee_estimates", requires = "feerate", conflicts_with = "at_height")]
+    sweep_estimate: bool,
+
+    /// Fee rate in sat/vB a consolidation or sweep would pay now
+    #[arg(long, value_name = "SAT/VB", value_parser = consolidate::parse_fee_rate, requires = "fee_estimates")]
+    feerate: Option<f64>,
+
+    /// Fee rate in sat/vB expected when the outputs would otherwise be spent
+    #[arg(long, value_name = "SAT/VB", value_parser = consolidate::parse_fee_rate, requires = "consolidate_advice")]
+    future_feerate: Option<f64>,
+
+    /// Flag unspent outputs that chain analysis can link: by address reuse,
+    /// by inputs spent together, or as the change of a round payment; with
+    /// a score and advice
+    #[arg(long)]
+    privacy_report: bool,
+
+    /// Write the full history to a CSV file: txid, block height, timestamp,
+    /// direction, amount, fee, and running balance
+    #[arg(long, value_name = "PATH")]
+    export_csv: Option<PathBuf>,
+
+    /// Write the full history as a ledger with fees booked apart: date,
+    /// txid, category (in, out or fee), amount, and balance after; JSON if
+    /// PATH ends in .json, else CSV
+    #[arg(long, value_name = "PATH")]
+    ledger: Option<PathBuf>,
+
+    /// Write the unspent outputs to a JSON file for a transaction builder:
+    /// outpoint, value, scriptPubKey, and for wallets the derived descriptor
+    /// and each key's derivation path
+    #[arg(long, value_name = "PATH")]
+    export_utxos: Option<PathBuf>,
+
+    /// Print the checked descriptors and addresses as a Bitcoin Core
+    /// `importdescriptors` request, to check the balance on your own node
+    #[arg(long)]
+    export_core: bool,
+
+    /// Show the first block in which the address appeared
+    #[arg(long)]
+    first_seen: bool,
+
+    /// Show a calendar heatmap of transaction activity over the past year
+    #[arg(long)]
+    heatmap: bool,
+
+    /// Replay the history oldest first and show the balance after each transaction
+    #[arg(long)]
+    history_chart: bool,
+
+    /// With --history-chart, also draw the balance over time as an ASCII chart
+    #[arg(long, requires = "history_chart")]
+    chart: bool,
+
+    /// Total the fees of transactions the checked scripts helped fund, with
+    /// the average fee rate
+    #[arg(long)]
+    fees: bool,
+
+    /// Only list transactions confirmed at or after this UTC date or time
+    /// (e.g. 2024-07-01) in --txs, --history-chart, --export-csv, --fees and
+    /// --heatmap
+    #[arg(long, value_name = "DATE")]
+    since: Option<String>,
+
+    /// Only list transactions confirmed at or before this UTC date or time; a
+    /// date alone includes the whole day
+    #[arg(long, value_name = "DATE")]
+    until: Option<String>,
+
+    /// Report everything as of this block height, replaying only the
+    /// transactions confirmed at or below it
+    #[arg(long, value_name = "HEIGHT", conflicts_with_all = ["watch", "mempool_watch", "fiat"])]
+    at_height: Option<u32>,
+
+    /// List unconfirmed payments to the inputs with their fee rate and an
+    /// estimate of when they'll confirm, from the server's fee estimates;
+    /// with --watch, each new one is reported as it arrives
+    #[arg(long, conflicts_with = "simulate")]
+    mempool_watch: bool,
+
+    /// After the report, keep polling and print a line whenever the confirmed
+    /// or unconfirmed balance changes
+    #[arg(long, conflicts_with = "simulate")]
+    watch: bool,
+
+    /// Seconds between polls in --watch mode
+    #[arg(long, default_value = "60", requires = "watch", value_parser = clap::value_parser!(u64).range(1..))]
+    interval: u64,
+
+    /// Ring the terminal bell when --watch sees a change
+    #[arg(long, requires = "watch")]
+    beep: bool,
+
+    /// Shell command to run when --watch sees a change; it gets
+    /// BALANCE_CONFIRMED, BALANCE_UNCONFIRMED, BALANCE_TOTAL and
+    /// BALANCE_CHANGE (sats) in its environment
+    #[arg(long, value_name = "COMMAND", requires = "watch")]
+    on_change: Option<String>,
+
+    /// URL to POST a JSON description of each change seen by --watch to,
+    /// e.g. a Slack incoming webhook
+    #[arg(long, value_name = "URL", requires = "watch")]
+    webhook: Option<String>,
+
+    /// Shell command to run on each change seen by --watch, with the same
+    /// JSON as --webhook on its stdin and the --on-change variables set
+    #[arg(long, value_name = "COMMAND", requires = "watch")]
+    exec: Option<String>,
+
+    /// Run against a simulated in-memory chain instead of Esplora (offline)
+    #[arg(long)]
+    simulate: bool,
+
+    /// Number of blocks in the simulated chain
+    #[arg(long, default_value = "1000", requires = "simulate")]
+    sim_blocks: u32,
+
+    /// Number of simulated transactions touching the address
+    #[arg(long, default_value = "12", requires = "simulate")]
+    sim_txs: usize,
+
+    /// Seed for the simulated chain; the same seed gives the same history
+    #[arg(long, default_value = "1", requires = "simulate")]
+    sim_seed: u64,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// List, set or remove saved labels
+    Labels {
+        #[command(subcommand)]
+        action: LabelAction,
+    },
+    /// List or remove wallets saved with --save-wallet
+    Wallets {
+        #[command(subcommand)]
+        action: WalletAction,
+    },
+    /// Verify a proof of reserves: each address's signature of a message,
+    /// and the confirmed balance it attests
+    VerifyPor {
+        /// File of `ADDRESS SIGNATURE` lines, BIP-322 or legacy signmessage
+        /// signatures in base64 (`-` for stdin)
+        #[arg(value_name = "PATH")]
+        proofs: PathBuf,
+        /// The message every address signed, e.g. the auditor's challenge
+        #[arg(long)]
+        message: String,
+    },
+    /// Report several wallets side by side with a grand total, from a TOML
+    /// or JSON file listing each wallet's label and inputs
+    Portfolio {
+        /// Portfolio file; read as JSON if it ends in `.json`, else as TOML
+        #[arg(value_name = "PATH")]
+        path: PathBuf,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum LabelAction {
+    /// Print every label as a tab-separated input and label
+    List,
+    /// Label an address, descriptor, extended key, script or script hash
+    Set { input: String, label: String },
+    /// Remove an input's label
+    Rm { input: String },
 }
 
-/// Balance information for an address
-#[derive(Debug, PartialEq, Eq)]
-struct BalanceInfo {
-    confirmed: u64,
-    unconfirmed: u64,
+#[derive(Subcommand, Debug)]
+enum WalletAction {
+    /// Print every saved wallet with its network and last sync
+    List,
+    /// Delete a saved wallet
+    Rm { name: String },
+}
+
+/// Server protocol used to look up history
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Backend {
+    /// Esplora HTTP API
+    Esplora,
+    /// mempool.space's Esplora API, whose per-address stats give a plain
+    /// balance check in one request per address
+    MempoolSpace,
+    /// Electrum protocol, as served by electrs or Fulcrum
+    Electrum,
+    /// Bitcoin Core's scantxoutset RPC: no indexer needed, but only
+    /// confirmed unspent outputs are seen
+    Bitcoind,
+    /// Compact block filters (BIP158) from a P2P peer, matched locally so
+    /// no server learns the addresses; confirmed history only
+    Cbf,
+}
+
+/// Balance information for an address
+#[derive(Debug, PartialEq, Eq)]
+struct BalanceInfo {
+    confirmed: u64,
+    unconfirmed: u64,
+}
+
+impl BalanceInfo {
+    fn total(&self) -> u64 {
+        self.confirmed + self.unconfirmed
+    }
+}
+
+/// Network used by `--network auto` when no input names one
+const DEFAULT_NETWORK: Chain = Chain::Testnet;
+
+/// Esplora requests to read a full mainnet block a page of 25 transactions
+/// at a time, for warning about silent payment scans
+const ESPLORA_REQUESTS_PER_BLOCK: u32 = 120;
+
+/// Validates and parses the network name
+fn parse_network(network: &str) -> Result<Chain, String> {
+    match network {
+        "testnet" => Ok(Chain::Testnet),
+        "testnet4" => Ok(Chain::Testnet4),
+        "signet" => Ok(Chain::Signet),
+        "regtest" => Ok(Chain::Regtest),
+        "bitcoin" | "mainnet" => Ok(Chain::Bitcoin),
+        _ => Err(format!(
+            "Invalid network: '{}'. Use 'auto', 'testnet', 'testnet4', 'signet', 'regtest' or 'bitcoin'",
+            network
+        )),
+    }
+}
+
+/// Parses `--verify-with`: a backend name for its default server,
+/// `BACKEND=URL`, or a URL whose scheme names the backend
+fn parse_verify_with(value: &str) -> Result<(Backend, Option<String>), String> {
+    let backend = |name: &str| Backend::from_str(name, true).ok();
+    if let Some(backend) = backend(value) {
+        return Ok((backend, None));
+    }
+    if let Some((name, url)) = value.split_once('=') {
+        if let Some(backend) = backend(name) {
+            if url.is_empty() {
+                return Err(format!("--verify-with {}= needs a URL after the '='", name));
+            }
+            return Ok((backend, Some(url.to_string())));
+        }
+    }
+    match value.split_once("://").map(|(scheme, _)| scheme) {
+        Some("http" | "https") => Ok((Backend::Esplora, Some(value.to_string()))),
+        Some("ssl" | "tcp") => Ok((Backend::Electrum, Some(value.to_string()))),
+        _ => Err(format!(
+            "Invalid --verify-with '{}': expected esplora, mempool-space, electrum, bitcoind or cbf, BACKEND=URL, or an http(s)://, ssl:// or tcp:// URL",
+            value
+        )),
+    }
+}
+
+/// The backend's name as given to --backend
+fn backend_name(backend: Backend) -> String {
+    backend.to_possible_value().expect("no backend is skipped").get_name().to_string()
+}
+
+/// Whether the backend sees unconfirmed transactions
+fn has_mempool(backend: Backend) -> bool {
+    !matches!(backend, Backend::Bitcoind | Backend::Cbf)
+}
+
+/// The network named on the command line, or None for `auto`
+fn parse_network_choice(network: &str) -> Result<Option<Chain>, String> {
+    match network {
+        "auto" => Ok(None),
+        _ => parse_network(network).map(Some),
+    }
+}
+
+/// The network an input belongs to, if it says: addresses by their prefix
+/// and extended keys by their version. Scripts and script hashes don't.
+fn infer_network(target: &QueryTarget) -> Option<Chain> {
+    let network = match target {
+        QueryTarget::Address(address) => address.network,
+        QueryTarget::Wallet(wallet) => wallet.network?,
+        QueryTarget::SilentPayment(receiver) => receiver.address.network,
+        QueryTarget::Script(_) | QueryTarget::ScriptHash(_) => return None,
+    };
+    // Only regtest addresses (bcrt1) stand out among the test networks;
+    // signet and testnet4 share testnet's prefixes and key versions
+    Some(match network {
+        Network::Bitcoin => Chain::Bitcoin,
+        Network::Regtest => Chain::Regtest,
+        _ => Chain::Testnet,
+    })
+}
+
+/// Returns the Esplora API URL for the given network: Blockstream's, or
+/// mempool.space's for the test networks Blockstream doesn't serve. Regtest
+/// has none.
+fn get_esplora_url(network: Chain) -> Option<&'static str> {
+    match network {
+        Chain::Bitcoin => Some("https://blockstream.info/api"),
+        Chain::Testnet => Some("https://blockstream.info/testnet/api"),
+        Chain::Testnet4 => Some("https://mempool.space/testnet4/api"),
+        Chain::Signet => Some("https://mempool.space/signet/api"),
+        Chain::Regtest => None,
+    }
+}
+
+/// Verifies the target belongs to the network being queried
+fn check_network(input: &str, target: &QueryTarget, network: Chain) -> Result<(), String> {
+    match target {
+        QueryTarget::Address(address) if !address.is_valid_for_network(network.network()) => {
+            Err(format!("Address {} is not valid for {:?} network", input, network))
+        }
+        QueryTarget::Wallet(wallet) if wallet.network.is_some_and(|keys| !network.accepts_keys(keys)) => {
+            Err(format!("The {}'s keys are not valid for {:?} network", wallet.kind, network))
+        }
+        QueryTarget::SilentPayment(receiver) if !network.accepts_keys(receiver.address.network) => {
+            Err(format!("Silent payment address {} is not valid for {:?} network", input, network))
+        }
+        _ => Ok(()),
+    }
+}
+
+/// Converts satoshis to BTC
+fn sats_to_btc(sats: u64) -> f64 {
+    sats as f64 / 100_000_000.0
+}
+
+/// Calculates balance from outputs and spent outputs
+fn calculate_balance(
+    outputs: &HashMap<(String, u32), (u64, bool)>,
+    spent_outputs: &HashSet<(String, u32)>
+) -> BalanceInfo {
+    let mut confirmed = 0u64;
+    let mut unconfirmed = 0u64;
+
+    for (outpoint, (value, is_confirmed)) in outputs {
+        if !spent_outputs.contains(outpoint) {
+            // This output is unspent
+            if *is_confirmed {
+                confirmed += value;
+            } else {
+                unconfirmed += value;
+            }
+        }
+    }
+
+    BalanceInfo { confirmed, unconfirmed }
+}
+
+/// Whether the run needs more than each input's balance, so the
+/// mempool.space stats can't stand in for the history
+fn needs_history(args: &Args) -> bool {
+    args.txs
+        || args.first_seen
+        || args.heatmap
+        || args.export_csv.is_some()
+        || args.ledger.is_some()
+        || args.history_chart
+        || args.fees
+        || args.at_height.is_some()
+        || args.utxos
+        || args.utxo_stats
+        || args.export_utxos.is_some()
+        || args.export_core
+        || args.mempool_watch
+        || args.min_conf.is_some()
+        || args.dust_limit.is_some()
+        || args.non_signalling
+        || args.consolidate_advice
+        || args.sweep_estimate
+        || args.privacy_report
+        || args.watch
+        || args.verify_with.is_some()
+}
+
+/// Fetches a JSON response from the Esplora API, retrying as `retry` allows
+fn esplora_get<T: serde::de::DeserializeOwned>(
+    blockchain: &EsploraBlockchain,
+    retry: &RetryPolicy,
+    path: &str,
+) -> Result<T, String> {
+    let url = format!("{}{}", blockchain.url(), path);
+    retry.call(|| {
+        blockchain
+            .agent()
+            .get(&url)
+            .call()
+            .map_err(retry::http_failure)?
+            .into_json()
+            .map_err(retry::body_failure)
+    })
+}
+
+/// Fetches one page of history for an Esplora script hash, newest first
+fn fetch_scripthash_txs(
+    blockchain: &EsploraBlockchain,
+    retry: &RetryPolicy,
+    script_hash: &str,
+    last_seen: Option<Txid>,
+) -> Result<Vec<Tx>, String> {
+    let path = match last_seen {
+        Some(txid) => format!("/scripthash/{}/txs/chain/{}", script_hash, txid),
+        None => format!("/scripthash/{}/txs", script_hash),
+    };
+    esplora_get(blockchain, retry, &path)
+}
+
+/// Fetches the full history for an Esplora script hash, following
+/// pagination. Paging stops at the first transaction in `settled`; it and
+/// everything older are taken from `settled` instead. Each page is
+/// reported to `progress`.
+fn fetch_history(
+    blockchain: &EsploraBlockchain,
+    retry: &RetryPolicy,
+    script_hash: &str,
+    settled: &[Tx],
+    progress: &Progress,
+) -> Result<Vec<Tx>, String> {
+    page_history(settled, progress, |last_seen| fetch_scripthash_txs(blockchain, retry, script_hash, last_seen))
+}
+
+/// Follows the pages `fetch_page` returns after each `last_seen` txid, as
+/// `fetch_history` does
+fn page_history(
+    settled: &[Tx],
+    progress: &Progress,
+    mut fetch_page: impl FnMut(Option<Txid>) -> Result<Vec<Tx>, String>,
+) -> Result<Vec<Tx>, String> {
+    let settled_txids: HashSet<Txid> = settled.iter().map(|tx| tx.txid).collect();
+    let mut txs = Vec::new();
+    let mut fetched: HashSet<Txid> = HashSet::new();
+    let mut last_seen = None;
+
+    'pages: loop {
+        let batch = fetch_page(last_seen)?;
+
+        if batch.is_empty() {
+            break;
+        }
+
+        last_seen = Some(batch.last().unwrap().txid);
+        let batch_len = batch.len();
+        progress.page(batch_len);
+        let mut new = 0;
+        for tx in batch {
+            if settled_txids.contains(&tx.txid) {
+                break 'pages;
+            }
+            // A transaction mined or reorged while paging can show up on two
+            // pages; keep its first copy
+            if fetched.insert(tx.txid) {
+                txs.push(tx);
+                new += 1;
+            }
+        }
+
+        // If we got fewer than the page size, we're done; a page of nothing
+        // new means the server isn't moving past `last_seen`
+        if batch_len < 25 || new == 0 {
+            break;
+        }
+    }
+
+    txs.extend(settled.iter().filter(|tx| !fetched.contains(&tx.txid)).cloned());
+    Ok(txs)
+}
+
+/// Finds the earliest confirmed transaction in the address history
+fn find_first_seen(txs: &[Tx]) -> Option<&Tx> {
+    txs.iter()
+        .filter(|tx| tx.status.confirmed)
+        .filter_map(|tx| tx.status.block_height.map(|height| (height, tx)))
+        .min_by_key(|(height, _)| *height)
+        .map(|(_, tx)| tx)
+}
+
+/// Transactions touching a target, and the hashes of the scripts it owns
+struct History {
+    txs: Vec<Tx>,
+    scripts: HashSet<sha256::Hash>,
+    /// Scripts of wallet receive and change chains, a subset of `scripts`
+    keychains: HashMap<KeychainKind, HashSet<sha256::Hash>>,
+    /// How far each wallet chain was scanned, in descriptor order
+    chains: Vec<wallet::ChainUsage>,
+    /// The balance BDK gives a wallet it synced itself
+    balance: Option<Balance>,
+}
+
+/// Combines several targets' histories, counting each transaction once
+fn merge_histories(histories: Vec<History>) -> History {
+    // BDK's balance covers one synced wallet, not a mix of targets
+    let balance = match histories.as_slice() {
+        [history] => history.balance.clone(),
+        _ => None,
+    };
+    let mut txs = Vec::new();
+    let mut merged = History { txs: Vec::new(), scripts: HashSet::new(), keychains: HashMap::new(), chains: Vec::new(), balance: None };
+    for history in histories {
+        txs.extend(history.txs);
+        merged.scripts.extend(history.scripts);
+        for (keychain, scripts) in history.keychains {
+            merged.keychains.entry(keychain).or_default().extend(scripts);
+        }
+        merged.chains.extend(history.chains);
+    }
+    merged.txs = wallet::merge_txs(txs);
+    merged.balance = balance;
+    merged
+}
+
+/// The history as it stood once block `height` was mined: only the
+/// transactions confirmed at or below it
+fn history_at(mut history: History, height: u32) -> History {
+    history.txs.retain(|tx| tx.status.block_height.is_some_and(|block_height| block_height <= height));
+    history.balance = None;
+    history
+}
+
+/// Unspent value and output count on wallet receive chains, change chains,
+/// and any other inputs' scripts; empty when no wallet chains were scanned
+fn chain_balances(history: &History) -> Vec<(&'static str, u64, usize)> {
+    if history.keychains.is_empty() {
+        return Vec::new();
+    }
+    let receive = history.keychains.get(&KeychainKind::External).cloned().unwrap_or_default();
+    let change = history.keychains.get(&KeychainKind::Internal).cloned().unwrap_or_default();
+    let other: HashSet<sha256::Hash> =
+        history.scripts.iter().filter(|&hash| !receive.contains(hash) && !change.contains(hash)).copied().collect();
+    [("Receive", receive), ("Change", change), ("Other inputs", other)]
+        .into_iter()
+        .filter(|(name, scripts)| *name != "Other inputs" || !scripts.is_empty())
+        .map(|(name, scripts)| {
+            let unspent = utxos::unspent_outputs(&history.txs, &scripts);
+            (name, unspent.iter().map(|utxo| utxo.value).sum(), unspent.len())
+        })
+        .collect()
+}
+
+/// Balance and unspent output count of the history's own scripts
+fn tally(history: &History) -> (BalanceInfo, usize) {
+    let is_target = |script: &bdk::bitcoin::Script| history.scripts.contains(&query::script_hash(script));
+
+    // Track all outputs and which ones are spent
+    // Map of (txid, vout) -> (value, confirmed)
+    let mut outputs: HashMap<(String, u32), (u64, bool)> = HashMap::new();
+
+    // Set of spent outputs (txid, vout)
+    let mut spent_outputs: HashSet<(String, u32)> = HashSet::new();
+
+    // First pass: collect all outputs belonging to this address
+    for tx in &history.txs {
+        for (vout_index, output) in tx.vout.iter().enumerate() {
+            if is_target(&output.scriptpubkey) {
+                let key = (tx.txid.to_string(), vout_index as u32);
+                outputs.insert(key, (output.value, tx.status.confirmed));
+            }
+        }
+    }
+
+    // Second pass: mark spent outputs
+    for tx in &history.txs {
+        for input in &tx.vin {
+            if let Some(prevout) = &input.prevout {
+                if is_target(&prevout.scriptpubkey) {
+                    let key = (input.txid.to_string(), input.vout);
+                    spent_outputs.insert(key);
+                }
+            }
+        }
+    }
+
+    let unspent_count = outputs.iter().filter(|(k, _)| !spent_outputs.contains(k)).count();
+
+    // A wallet BDK synced has its balance from BDK
+    let balance = match &history.balance {
+        Some(balance) => BalanceInfo {
+            confirmed: balance.confirmed + balance.immature,
+            unconfirmed: balance.trusted_pending + balance.untrusted_pending,
+        },
+        None => calculate_balance(&outputs, &spent_outputs),
+    };
+    (balance, unspent_count)
+}
+
+/// Where histories come from
+enum Source {
+    /// With the history store unless --no-store
+    Esplora(EsploraBlockchain, Option<store::StoreDir>, RetryPolicy),
+    Electrum(Box<electrum::ElectrumSource>, Option<store::StoreDir>),
+    Bitcoind(bitcoind::BitcoindSource),
+    Cbf(Box<cbf::CbfSource>),
+    Simulated(simulate::SimConfig),
+}
+
+impl Source {
+    /// Moves a simulated source on to a fresh chain, so each input gets its
+    /// own history rather than copies of one
+    fn next_input(&mut self) {
+        if let Source::Simulated(config) = self {
+            config.seed = config.seed.wrapping_add(1);
+        }
+    }
+
+    /// The wallet database that keeps the scripts of `descriptor`, if the
+    /// history store is in use
+    fn store(&self, descriptor: &str, keychain: KeychainKind) -> Option<store::HistoryStore> {
+        match self {
+            Source::Esplora(_, Some(dir), _) | Source::Electrum(_, Some(dir)) => dir.wallet(descriptor, keychain),
+            _ => None,
+        }
+    }
+
+    /// Full history of the script with this hash; `stored` is the wallet
+    /// database keeping it, with the script and its derivation index
+    fn history(
+        &self,
+        hash: &sha256::Hash,
+        stored: Option<(&store::HistoryStore, &Script, u32)>,
+        progress: &Progress,
+    ) -> Result<Vec<Tx>, String> {
+        if let Some(txs) = stored.and_then(|(store, _, _)| store.fresh(hash)) {
+            progress.page(txs.len());
+            progress.script_done();
+            return Ok(txs);
+        }
+        let settled = stored.map(|(store, _, _)| store.settled(hash)).unwrap_or_default();
+        let txs = match self {
+            Source::Esplora(blockchain, _, retry) => {
+                fetch_history(blockchain, retry, &query::esplora_script_hash(hash), &settled, progress)?
+            }
+            Source::Electrum(electrum, _) => {
+                // Electrum sends the whole history at once
+                let txs = electrum.fetch_history(hash, &settled)?;
+                progress.page(txs.len());
+                txs
+            }
+            Source::Bitcoind(_) | Source::Cbf(_) | Source::Simulated(_) => {
+                unreachable!("scanned and simulated histories are built per target")
+            }
+        };
+        if let Some((store, script, index)) = stored {
+            store.save(script, index, &txs);
+        }
+        progress.script_done();
+        Ok(txs)
+    }
+}
+
+/// Chain and mempool stats of the script with this hash, from mempool.space
+fn fetch_stats(source: &Source, hash: &sha256::Hash) -> Result<mempool_space::ScriptStats, String> {
+    match source {
+        Source::Esplora(blockchain, _, retry) => {
+            esplora_get(blockchain, retry, &format!("/scripthash/{}", query::esplora_script_hash(hash)))
+        }
+        _ => unreachable!("stats are only fetched with --backend mempool-space"),
+    }
+}
+
+/// Opens the backend chosen on the command line; `verbose` says where it
+/// connects
+fn open_source(args: &Args, proxy: Option<&Proxy>, network: Chain, verbose: bool) -> Result<Source, CheckerError> {
+    if args.simulate {
+        return Ok(Source::Simulated(simulate::SimConfig {
+            blocks: args.sim_blocks,
+            txs: args.sim_txs,
+            seed: args.sim_seed,
+        }));
+    }
+    // A regtest chain is always someone's own, so there's no server to default to
+    let url = source_url(args, network).ok_or_else(|| {
+        let option = match args.backend {
+            Backend::Esplora | Backend::MempoolSpace => "--esplora-url",
+            Backend::Electrum => "--server",
+            Backend::Bitcoind => "--rpc-url",
+            Backend::Cbf => "--peer",
+        };
+        CheckerError::InvalidInput(format!("{:?} has no public server; give your own with {}", network, option))
+    })?;
+    connect_source(args, args.backend, url, proxy, network, verbose, !args.no_store)
+}
+
+/// The main backend's server: the one given, or its default on `network`
+fn source_url(args: &Args, network: Chain) -> Option<&str> {
+    let given = match args.backend {
+        Backend::Esplora | Backend::MempoolSpace => args.esplora_url.as_deref(),
+        Backend::Electrum => args.server.as_deref(),
+        Backend::Bitcoind => args.rpc_url.as_deref(),
+        Backend::Cbf => args.peer.as_deref(),
+    };
+    given.or(default_url(args.backend, network))
+}
+
+/// The public server (or local node) a backend uses on `network` by default
+fn default_url(backend: Backend, network: Chain) -> Option<&'static str> {
+    match backend {
+        Backend::Esplora => get_esplora_url(network),
+        Backend::MempoolSpace => mempool_space::default_url(network),
+        Backend::Electrum => electrum::default_server(network),
+        Backend::Bitcoind => Some(bitcoind::default_url(network)),
+        Backend::Cbf => cbf::default_peer(network),
+    }
+}
+
+/// Connects to `backend` at `url`; `use_store` opens the history store for
+/// Esplora and Electrum backends
+fn connect_source(
+    args: &Args,
+    backend: Backend,
+    url: &str,
+    proxy: Option<&Proxy>,
+    network: Chain,
+    verbose: bool,
+    use_store: bool,
+) -> Result<Source, CheckerError> {
+    if proxy.is_none() && proxy::is_onion(url) {
+        return Err(CheckerError::InvalidInput(format!(
+            "{} is a Tor onion service; reach it through Tor with --proxy socks5://127.0.0.1:9050",
+            esplora::display_url(url)
+        )));
+    }
+    if verbose {
+        match proxy {
+            Some(proxy) => println!("Connecting to {} through proxy {}...", esplora::display_url(url), proxy.address()),
+            None => println!("Connecting to {}...", esplora::display_url(url)),
+        }
+    }
+    match backend {
+        Backend::Esplora | Backend::MempoolSpace => {
+            // The client spaces out requests itself, so BDK's wallet sync keeps
+            // to the rate limit too
+            let blockchain = esplora::connect(url, proxy, args.rate_limit).map_err(CheckerError::InvalidInput)?;
+            let retry = RetryPolicy::new(args.retries, None);
+            let store = if use_store {
+                let tip_height = || esplora_get(&blockchain, &retry, "/blocks/tip/height");
+                open_store(args, network, tip_height, verbose)?
+            } else {
+                None
+            };
+            Ok(Source::Esplora(blockchain, store, retry))
+        }
+        Backend::Electrum => {
+            let electrum = electrum::ElectrumSource::connect(url, proxy, RetryPolicy::new(args.retries, args.rate_limit))
+                .map_err(CheckerError::Network)?;
+            let store = if use_store { open_store(args, network, || electrum.tip_height(), verbose)? } else { None };
+            Ok(Source::Electrum(Box::new(electrum), store))
+        }
+        Backend::Bitcoind => {
+            let cookie = args.rpc_cookie.clone().or_else(|| bitcoind::default_cookie(network));
+            bitcoind::BitcoindSource::new(url, args.rpc_user.as_deref(), args.rpc_password.as_deref(), cookie.as_deref())
+                .map(Source::Bitcoind)
+                .map_err(CheckerError::InvalidInput)
+        }
+        Backend::Cbf => {
+            if verbose {
+                println!("Syncing block headers...");
+            }
+            // Headers are checked chain data, so a second backend keeps them too
+            let headers = store::default_path(network).filter(|_| !args.no_store).map(|dir| dir.join(cbf::HEADERS_FILE));
+            cbf::CbfSource::connect(url, network, proxy, args.birthday.unwrap_or(0), headers.as_deref())
+                .map(|node| Source::Cbf(Box::new(node)))
+                .map_err(CheckerError::Network)
+        }
+    }
+}
+
+/// Opens the history store for this network, stamped with the current tip,
+/// and cuts it back to below --rescan-from if given. A store that can't be
+/// opened (e.g. another run is using it) only warns.
+fn open_store(
+    args: &Args,
+    network: Chain,
+    tip_height: impl FnOnce() -> Result<u32, String>,
+    verbose: bool,
+) -> Result<Option<store::StoreDir>, CheckerError> {
+    let Some(path) = store::default_path(network) else {
+        return Ok(None);
+    };
+    let (ttl, rescan_from) = (Duration::from_secs(args.cache_ttl), args.rescan_from);
+    let tip_height = tip_height().map_err(CheckerError::Network)?;
+    if let Some(height) = rescan_from.filter(|height| *height > tip_height) {
+        return Err(CheckerError::InvalidInput(format!("--rescan-from {} is above the tip ({})", height, tip_height)));
+    }
+    match store::StoreDir::open(&path, tip_height, ttl) {
+        Ok(store) => {
+            if verbose {
+                println!("History store: {}", path.display());
+            }
+            if let Some(height) = rescan_from {
+                let discarded = store.discard_from(height).map_err(CheckerError::File)?;
+                if verbose {
+                    let wallets = if discarded == 1 { "wallet" } else { "wallets" };
+                    println!("Rescanning from height {}: {} stored {} cut back", height, discarded, wallets);
+                }
+            }
+            Ok(Some(store))
+        }
+        Err(e) => {
+            eprintln!("Warning: {}; fetching full histories", e);
+            Ok(None)
+        }
+    }
+}
+
+fn scan_limits(args: &Args) -> wallet::ScanLimits {
+    wallet::ScanLimits { gap_limit: args.gap_limit, scan_limit: args.scan_limit }
+}
+
+/// How far `fetch_target` scans and what it shows on the way
+#[derive(Debug, Clone, Copy)]
+struct FetchOptions {
+    limits: wallet::ScanLimits,
+    /// Scripts looked up at once
+    concurrency: usize,
+    /// Print what's being fetched before the report
+    verbose: bool,
+    /// Show the status line on stderr
+    show_progress: bool,
+}
+
+impl FetchOptions {
+    /// Fetches without printing anything
+    fn quiet(limits: wallet::ScanLimits, concurrency: usize) -> Self {
+        FetchOptions { limits, concurrency, verbose: false, show_progress: false }
+    }
+}
+
+/// The command line's limits and concurrency, fetching quietly
+fn fetch_options(args: &Args) -> FetchOptions {
+    FetchOptions::quiet(scan_limits(args), args.concurrency.into())
+}
+
+/// Height of the chain tip, for confirmation counts
+fn tip_height(source: &Source) -> Result<u32, String> {
+    match source {
+        Source::Esplora(blockchain, _, retry) => esplora_get(blockchain, retry, "/blocks/tip/height"),
+        Source::Electrum(electrum, _) => electrum.tip_height(),
+        Source::Bitcoind(node) => node.tip_height(),
+        Source::Cbf(node) => node.tip_height(),
+        Source::Simulated(config) => Ok(config.blocks),
+    }
+}
+
+/// The server's fee rate estimates, for --mempool-watch
+fn fee_estimates(source: &Source) -> Result<mempool::FeeEstimates, String> {
+    match source {
+        Source::Esplora(blockchain, _, retry) => {
+            esplora_get(blockchain, retry, "/fee-estimates").map(|estimates| mempool::parse_estimates(&estimates))
+        }
+        Source::Electrum(electrum, _) => electrum.fee_estimates(&mempool::TARGETS),
+        Source::Bitcoind(_) | Source::Cbf(_) | Source::Simulated(_) => Err("The backend has no mempool".to_string()),
+    }
+}
+
+/// Fetches a target's history from the server, scanning wallets as far as
+/// `limits` allow and looking up to `concurrency` scripts at once, or builds
+/// it on a simulated chain; `verbose` prints what it's doing along the way,
+/// and `show_progress` keeps a status line on stderr while fetching
+fn fetch_target(source: &Source, target: &QueryTarget, options: FetchOptions) -> Result<History, CheckerError> {
+    let FetchOptions { limits, concurrency, verbose, show_progress } = options;
+    if let Source::Simulated(config) = source {
+        let script = target.script_pubkey().ok_or_else(|| {
+            CheckerError::InvalidInput(format!("--simulate needs an address or scriptPubKey, not a {}", target.kind()))
+        })?;
+        let chain = simulate::build_chain(&script, config, Utc::now().timestamp() as u64)
+            .map_err(CheckerError::InvalidInput)?;
+
+        if verbose {
+            println!("Simulating {} blocks offline (tip height {}, seed {})\n",
+                     config.blocks, chain.tip_height, config.seed);
+        }
+        return Ok(History {
+            txs: chain.txs,
+            scripts: HashSet::from([query::script_hash(&script)]),
+            keychains: HashMap::new(),
+            chains: Vec::new(),
+            balance: None,
+        });
+    }
+
+    if let QueryTarget::SilentPayment(receiver) = target {
+        return scan_silent_payments(source, receiver, verbose, show_progress);
+    }
+
+    // The node scans its UTXO set for every script at once
+    if let Source::Bitcoind(node) = source {
+        if let QueryTarget::ScriptHash(_) = target {
+            return Err(CheckerError::InvalidInput(
+                "--backend bitcoind needs the address or script itself, not its hash".to_string(),
+            ));
+        }
+        if verbose {
+            println!("Scanning the UTXO set (this can take a few minutes)...\n");
+        }
+        return node
+            .scan(target, limits.scan_limit)
+            .map(|(txs, scripts)| History { txs, scripts, keychains: HashMap::new(), chains: Vec::new(), balance: None })
+            .map_err(|e| CheckerError::Network(format!("Could not scan the UTXO set: {}", e)));
+    }
+
+    // Filters are matched against every script at once, block by block
+    if let Source::Cbf(node) = source {
+        if verbose {
+            let tip = node.tip_height().map_err(CheckerError::Network)?;
+            println!("Matching compact block filters from height {} to {}...\n", node.birthday(), tip);
+        }
+        let progress = Progress::new(show_progress);
+        let scanned = node.scan(target, limits, &progress);
+        progress.finish();
+        return scanned
+            .map(|result| {
+                if verbose && !result.chains.is_empty() {
+                    print_chain_usage(&result.chains);
+                }
+                History { txs: result.txs, scripts: result.scripts, keychains: result.keychains, chains: result.chains, balance: None }
+            })
+            .map_err(|e| CheckerError::Network(format!("Could not scan block filters: {}", e)));
+    }
+
+    // Esplora and Electrum index history by the hash of the scriptPubKey; a
+    // wallet has one per derived script
+    let progress = Progress::new(show_progress);
+    let progress = &progress;
+    let fetched = match target {
+        QueryTarget::Wallet(wallet) => {
+            if verbose {
+                match limits.scan_limit {
+                    Some(scan_limit) => println!(
+                        "Scanning wallet scripts (gap limit {}, at most {} per chain)...\n",
+                        limits.gap_limit, scan_limit
+                    ),
+                    None => println!("Scanning wallet scripts (gap limit {})...\n", limits.gap_limit),
+                }
+            }
+            // BDK syncs a whole wallet itself but can't stop at a scan limit
+            if let (Source::Esplora(blockchain, store, retry), None) = (source, limits.scan_limit) {
+                let synced = sync::sync(blockchain, retry, store.as_ref(), wallet, limits.gap_limit, concurrency, progress);
+                progress.finish();
+                return synced
+                    .map(|(result, balance)| {
+                        if verbose {
+                            print_chain_usage(&result.chains);
+                        }
+                        History {
+                            txs: result.txs,
+                            scripts: result.scripts,
+                            keychains: result.keychains,
+                            chains: result.chains,
+                            balance: Some(balance),
+                        }
+                    })
+                    .map_err(|e| CheckerError::Network(format!("Could not sync the wallet: {}", e)));
+            }
+            let stores: Vec<Option<store::HistoryStore>> = wallet
+                .descriptors
+                .iter()
+                .map(|entry| source.store(&entry.descriptor.to_string(), entry.keychain.unwrap_or(KeychainKind::External)))
+                .collect();
+            wallet::scan(wallet, limits, concurrency, progress, |position, index, script| {
+                let stored = stores[position].as_ref().map(|store| (store, script, index));
+                source.history(&query::script_hash(script), stored, progress)
+            })
+            .map(|result| {
+                progress.finish();
+                if verbose {
+                    print_chain_usage(&result.chains);
+                }
+                History { txs: result.txs, scripts: result.scripts, keychains: result.keychains, chains: result.chains, balance: None }
+            })
+        }
+        _ => {
+            if verbose {
+                println!("Fetching address information...\n");
+            }
+            let hash = target.script_hash().expect("single-script targets have a hash");
+            // A single script is stored as its own descriptor
+            let script = target.script_pubkey();
+            let store = match target {
+                QueryTarget::Address(address) => source.store(&format!("addr({})", address), KeychainKind::External),
+                QueryTarget::Script(script) => source.store(&format!("raw({:x})", script), KeychainKind::External),
+                _ => None,
+            };
+            let stored = store.as_ref().zip(script.as_ref()).map(|(store, script)| (store, script, 0));
+            // Get all transactions for this address (with pagination)
+            source.history(&hash, stored, progress)
+                .map(|txs| History { txs, scripts: HashSet::from([hash]), keychains: HashMap::new(), chains: Vec::new(), balance: None })
+        }
+    };
+    progress.finish();
+    fetched.map_err(|e| CheckerError::Network(format!("Could not fetch transactions: {}", e)))
+}
+
+/// Every block from the receiver's birthday to the tip, checked for
+/// payments to it; only Esplora and bitcoind serve blocks with prevouts
+fn scan_silent_payments(
+    source: &Source,
+    receiver: &silentpayments::Receiver,
+    verbose: bool,
+    show_progress: bool,
+) -> Result<History, CheckerError> {
+    let backend = match source {
+        Source::Esplora(..) | Source::Bitcoind(_) => None,
+        Source::Electrum(..) => Some("electrum"),
+        Source::Cbf(_) => Some("cbf"),
+        Source::Simulated(_) => Some("--simulate"),
+    };
+    if let Some(backend) = backend {
+        return Err(CheckerError::InvalidInput(format!(
+            "Silent payments are found by reading whole blocks with their prevouts, which {} can't serve; use --backend esplora, mempool-space or bitcoind",
+            backend
+        )));
+    }
+    let tip = tip_height(source).map_err(|e| CheckerError::Network(format!("Could not fetch the tip height: {}", e)))?;
+    let from = receiver.start_height(tip).map_err(CheckerError::InvalidInput)?;
+    if let Source::Esplora(..) = source {
+        let blocks = tip - from + 1;
+        eprintln!(
+            "Warning: Esplora serves blocks 25 transactions a page, so scanning {} blocks for silent payments can take about {} requests; set --birthday near the wallet's creation height, or use --backend bitcoind",
+            blocks,
+            blocks.saturating_mul(ESPLORA_REQUESTS_PER_BLOCK)
+        );
+    }
+    if verbose {
+        println!("Scanning blocks {} to {} for silent payments...\n", from, tip);
+    }
+    let progress = Progress::new(show_progress);
+    let scanned = silentpayments::scan(
+        receiver,
+        from,
+        tip,
+        |height| match source {
+            Source::Esplora(blockchain, _, retry) => esplora_block_txs(blockchain, retry, height, &progress),
+            Source::Bitcoind(node) => node.block_txs(height),
+            _ => unreachable!("refused above"),
+        },
+        &progress,
+    );
+    progress.finish();
+    scanned
+        .map(|(txs, scripts)| History { txs, scripts, keychains: HashMap::new(), chains: Vec::new(), balance: None })
+        .map_err(|e| CheckerError::Network(format!("Could not scan blocks: {}", e)))
+}
+
+/// Every transaction of the block at `height`, with prevouts, a page at a time
+fn esplora_block_txs(
+    blockchain: &EsploraBlockchain,
+    retry: &RetryPolicy,
+    height: u32,
+    progress: &Progress,
+) -> Result<Vec<Tx>, String> {
+    // Ten blocks down from the height, the first of which is the one wanted
+    let blocks: Vec<serde_json::Value> = esplora_get(blockchain, retry, &format!("/blocks/{}", height))?;
+    let block = blocks.first().filter(|block| block["height"] == height).ok_or_else(|| format!("No block at height {}", height))?;
+    let (Some(hash), Some(count)) = (block["id"].as_str(), block["tx_count"].as_u64()) else {
+        return Err(format!("Malformed block at height {}", height));
+    };
+    let mut txs = Vec::new();
+    while (txs.len() as u64) < count {
+        let page: Vec<Tx> = esplora_get(blockchain, retry, &format!("/block/{}/txs/{}", hash, txs.len()))?;
+        if page.is_empty() {
+            return Err(format!("Block {} listed {} of its {} transactions", hash, txs.len(), count));
+        }
+        progress.page(page.len());
+        txs.extend(page);
+    }
+    Ok(txs)
+}
+
+/// How many scripts of each wallet chain were scanned and used
+fn print_chain_usage(chains: &[wallet::ChainUsage]) {
+    for chain in chains {
+        let limited = if chain.limited { " (stopped at --scan-limit)" } else { "" };
+        println!("  {:<14} {} of {} scripts used{}", chain.label, chain.used, chain.scanned, limited);
+    }
+    println!();
+}
+
+/// With `split`, the balance is shown as spendable, pending (with
+/// `--min-conf`), unconfirmed, and dust (with `--dust-limit`) instead.
+/// The unconfirmed value is broken down into incoming, with the part that
+/// signals RBF, and change; `non_signalling` adds what doesn't signal.
+/// Amounts are shown in `unit`.
+#[allow(clippy::too_many_arguments)]
+fn print_balance_summary(
+    title: &str,
+    balance: &BalanceInfo,
+    unspent_count: usize,
+    quote: Option<&fiat::Quote>,
+    split: Option<&utxos::BalanceSplit>,
+    pending: &utxos::PendingSplit,
+    unit: Unit,
+    non_signalling: bool,
+) {
+    let print_pending = || {
+        if pending.incoming + pending.change > 0 {
+            println!("    Incoming:  {} (from others, not yours until confirmed)", unit.format(pending.incoming));
+            if pending.replaceable > 0 {
+                let transactions = if pending.replaceable_txs == 1 { "transaction" } else { "transactions" };
+                println!(
+                    "      Replaceable: {} in {} {} signalling RBF (BIP125)",
+                    unit.format(pending.replaceable),
+                    pending.replaceable_txs,
+                    transactions
+                );
+            }
+            println!("    Change:    {} (from your own transactions)", unit.format(pending.change));
+            if non_signalling {
+                println!(
+                    "    Non-signalling: {} (change and incoming that doesn't signal RBF; full-RBF nodes can still replace it)",
+                    unit.format(pending.non_signalling())
+                );
+            }
+        }
+    };
+    println!("{}:", title);
+    match split {
+        Some(split) => {
+            match split.min_conf {
+                Some(min_conf) => {
+                    println!("  Spendable:   {} ({}+ confirmations)", unit.format(split.spendable), min_conf);
+                    println!("  Pending:     {} (under {} confirmations)", unit.format(split.pending), min_conf);
+                }
+                None => println!("  Spendable:   {}", unit.format(split.spendable)),
+            }
+            println!("  Unconfirmed: {}", unit.format(split.unconfirmed));
+            print_pending();
+            if split.immature_count > 0 {
+                let outputs = if split.immature_count == 1 { "coinbase output" } else { "coinbase outputs" };
+                println!(
+                    "  Immature:    {} ({} {} under {} blocks old)",
+                    unit.format(split.immature),
+                    split.immature_count,
+                    outputs,
+                    utxos::COINBASE_MATURITY
+                );
+            }
+            if let Some(dust_limit) = split.dust_limit {
+                let outputs = if split.dust_count == 1 { "output" } else { "outputs" };
+                println!("  Dust:        {} ({} {} under {} sats)", unit.format(split.dust), split.dust_count, outputs, dust_limit);
+            }
+        }
+        None => {
+            println!("  Confirmed:   {}", unit.format(balance.confirmed));
+            println!("  Unconfirmed: {}", unit.format(balance.unconfirmed));
+            print_pending();
+        }
+    }
+    println!("  Total:       {}", unit.format(balance.total()));
+    if let Some(quote) = quote {
+        println!("  Total:       {:.2} {}", quote.value_of(balance.total()), quote.currency.code());
+        println!(
+            "  Rate:        {:.2} {}/BTC ({}, {})",
+            quote.rate,
+            quote.currency.code(),
+            quote.provider.name(),
+            quote.time.format("%Y-%m-%d %H:%M:%S UTC")
+        );
+    }
+    println!("  UTXOs:       {}", unspent_count);
+}
+
+/// Runs a `labels` subcommand
+fn manage_labels(action: &LabelAction) -> Result<(), CheckerError> {
+    let path = labels::default_path()
+        .ok_or_else(|| CheckerError::File("No data directory for labels; set $XDG_DATA_HOME or $HOME".to_string()))?;
+    let store = labels::LabelStore::open(&path).map_err(CheckerError::File)?;
+    match action {
+        LabelAction::List => {
+            let all = store.all();
+            if all.is_empty() {
+                eprintln!("No labels in {}", path.display());
+            }
+            for (input, label) in all {
+                println!("{}\t{}", input, label);
+            }
+        }
+        LabelAction::Set { input, label } => {
+            store.set(input, label).map_err(CheckerError::InvalidInput)?;
+            println!("Labelled {}", labels::label_key(input));
+        }
+        LabelAction::Rm { input } => {
+            if !store.remove(input).map_err(CheckerError::File)? {
+                return Err(CheckerError::InvalidInput(format!("{} has no label", labels::label_key(input))));
+            }
+            println!("Removed the label of {}", labels::label_key(input));
+        }
+    }
+    Ok(())
+}
+
+/// Records what was found for a saved wallet as its sync state and writes it
+fn save_wallet(mut saved: wallets::SavedWallet, history: &History, tip_height: u32) -> Result<PathBuf, String> {
+    let dir = wallets::default_dir().ok_or("No data directory for wallets; set $XDG_DATA_HOME or $HOME")?;
+    let (balance, _) = tally(history);
+    saved.sync = Some(wallets::SyncState {
+        tip_height,
+        synced_at: Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+        confirmed: balance.confirmed,
+        unconfirmed: balance.unconfirmed,
+    });
+    wallets::save(&dir, &saved)
+}
+
+/// Runs a `wallets` subcommand
+fn manage_wallets(action: &WalletAction) -> Result<(), CheckerError> {
+    let dir = wallets_dir()?;
+    match action {
+        WalletAction::List => {
+            let all = wallets::list(&dir).map_err(CheckerError::File)?;
+            if all.is_empty() {
+                eprintln!("No wallets in {}", dir.display());
+            }
+            // Name, network, then the tip, time and balance of the last sync
+            for wallet in all {
+                let synced = match &wallet.sync {
+                    Some(sync) => {
+                        format!("{}\t{}\t{}", sync.tip_height, sync.synced_at, sync.confirmed + sync.unconfirmed)
+                    }
+                    None => "-\t-\t-".to_string(),
+                };
+                println!("{}\t{}\t{}", wallet.name, wallet.network, synced);
+            }
+        }
+        WalletAction::Rm { name } => {
+            wallets::validate_name(name).map_err(CheckerError::InvalidInput)?;
+            if !wallets::remove(&dir, name).map_err(CheckerError::File)? {
+                return Err(CheckerError::InvalidInput(format!("No wallet named {}", name)));
+            }
+            println!("Removed wallet {}", name);
+        }
+    }
+    Ok(())
+}
+
+fn wallets_dir() -> Result<PathBuf, CheckerError> {
+    wallets::default_dir()
+        .ok_or_else(|| CheckerError::File("No data directory for wallets; set $XDG_DATA_HOME or $HOME".to_string()))
+}
+
+/// Verifies each address's signature of `message` and looks up its confirmed
+/// balance; only addresses whose signature verifies count toward the total
+fn verify_reserves(
+    args: &Args,
+    chosen_network: Option<Chain>,
+    proxy: Option<&Proxy>,
+    path: &Path,
+    message: &str,
+) -> Result<(), CheckerError> {
+    let proofs = if path == Path::new("-") {
+        por::read_proofs(io::stdin().lock())
+    } else {
+        File::open(path).and_then(|file| por::read_proofs(BufReader::new(file)))
+    };
+    let proofs = proofs.map_err(|e| match e.kind() {
+        io::ErrorKind::InvalidData => CheckerError::InvalidInput(format!("Invalid proofs in {}: {}", path.display(), e)),
+        _ => CheckerError::File(format!("Could not read proofs: {}", e)),
+    })?;
+    if proofs.is_empty() {
+        return Err(CheckerError::InvalidInput(format!("No proofs in {}", path.display())));
+    }
+
+    let mut addresses: Vec<Address> = Vec::new();
+    for (input, _) in &proofs {
+        let address = Address::from_str(input)
+            .map_err(|e| CheckerError::InvalidInput(format!("Invalid address '{}': {}", input, e)))?;
+        if addresses.contains(&address) {
+            return Err(CheckerError::InvalidInput(format!("{} is listed twice", input)));
+        }
+        addresses.push(address);
+    }
+    let network = chosen_network.unwrap_or_else(|| {
+        infer_network(&QueryTarget::Address(addresses[0].clone())).unwrap_or(DEFAULT_NETWORK)
+    });
+    for (address, (input, _)) in addresses.iter().zip(&proofs) {
+        check_network(input, &QueryTarget::Address(address.clone()), network).map_err(CheckerError::InvalidInput)?;
+    }
+
+    println!("=== Proof of Reserves ===\n");
+    println!("Network: {:?}", network);
+    println!("Message: {:?}\n", message);
+
+    let source = open_source(args, proxy, network, true)?;
+    let mut rows = Vec::new();
+    for (address, (input, signature)) in addresses.iter().zip(&proofs) {
+        let verdict = por::verify(address, message, signature);
+        let target = QueryTarget::Address(address.clone());
+        let history = fetch_target(&source, &target, fetch_options(args))?;
+        rows.push((input, verdict, tally(&history).0.confirmed));
+    }
+
+    let width = rows.iter().map(|(input, _, _)| input.len()).max().unwrap_or(0).max("Address".len());
+    let header = format!("Confirmed ({})", args.unit.label());
+    println!("  {:<width$}  {:>20}  Signature", "Address", header, width = width);
+    for (input, verdict, confirmed) in &rows {
+        let verdict = match verdict {
+            Ok(scheme) => format!("valid ({})", scheme.name()),
+            Err(reason) => format!("INVALID: {}", reason),
+        };
+        println!("  {:<width$}  {:>20}  {}", input, args.unit.number(*confirmed), verdict, width = width);
+    }
+
+    let proven: Vec<u64> = rows.iter().filter(|(_, verdict, _)| verdict.is_ok()).map(|(_, _, confirmed)| *confirmed).collect();
+    let attested: u64 = proven.iter().sum();
+    println!("\nAttested total: {} at {} of {} addresses", args.unit.format(attested), proven.len(), rows.len());
+    let failed = rows.len() - proven.len();
+    if failed > 0 {
+        let unproven: u64 = rows.iter().filter(|(_, verdict, _)| verdict.is_err()).map(|(_, _, confirmed)| confirmed).sum();
+        println!("Unproven:       {} at addresses whose signature didn't verify", args.unit.format(unproven));
+        return Err(CheckerError::Unverified(format!("{} of {} signatures did not verify", failed, rows.len())));
+    }
+    Ok(())
+}
+
+/// Checks each wallet of a portfolio file and prints a row per wallet and
+/// the grand total. A wallet that can't be checked gets a row saying why and
+/// is left out of the total, and the run fails once the report is printed.
+fn check_portfolio(args: &Args, chosen_network: Option<Chain>, proxy: Option<&Proxy>, path: &Path) -> Result<(), CheckerError> {
+    let text = std::fs::read_to_string(path)
+        .map_err(|e| CheckerError::File(format!("Could not read {}: {}", path.display(), e)))?;
+    let file = portfolio::parse(path, &text)
+        .map_err(|e| CheckerError::InvalidInput(format!("Invalid portfolio {}: {}", path.display(), e)))?;
+
+    let mut wallets: Vec<(&str, Vec<(&str, QueryTarget)>)> = Vec::new();
+    for wallet in &file.wallets {
+        let mut targets = Vec::new();
+        for input in &wallet.inputs {
+            let target = query::parse_query_target(input)
+                .map_err(|e| CheckerError::InvalidInput(format!("Invalid input '{}' in wallet '{}': {}", input, wallet.label, e)))?;
+            targets.push((input.as_str(), target));
+        }
+        wallets.push((&wallet.label, targets));
+    }
+    let network = chosen_network.unwrap_or_else(|| {
+        wallets.iter().flat_map(|(_, targets)| targets).find_map(|(_, target)| infer_network(target)).unwrap_or(DEFAULT_NETWORK)
+    });
+    for (input, target) in wallets.iter().flat_map(|(_, targets)| targets) {
+        check_network(input, target, network).map_err(CheckerError::InvalidInput)?;
+    }
+
+    println!("=== Portfolio ===\n");
+    println!("Network: {:?}", network);
+    println!("Wallets: {}\n", wallets.len());
+
+    let mut source = open_source(args, proxy, network, true)?;
+    let mut rows = Vec::new();
+    let mut checked = Vec::new();
+    let mut failures = Vec::new();
+    for (label, targets) in &wallets {
+        let mut histories = Vec::new();
+        let mut failure = None;
+        for (_, target) in targets {
+            match fetch_target(&source, target, fetch_options(args)) {
+                Ok(history) => histories.push(history),
+                Err(e) => {
+                    failure = Some(e);
+                    break;
+                }
+            }
+            source.next_input();
+        }
+        let result = match failure {
+            None => {
+                let history = merge_histories(histories);
+                let result = Ok(tally(&history));
+                checked.push(history);
+                result
+            }
+            Some(e) => {
+                let reason = e.to_string();
+                failures.push(e);
+                Err(reason)
+            }
+        };
+        rows.push(portfolio::Row { label, inputs: targets.len(), result });
+    }
+    let (total, total_utxos) = tally(&merge_histories(checked));
+
+    // --fiat overrides the file's currency; with neither, it's USD
+    let currency = args.fiat.or(file.currency().ok().flatten()).unwrap_or(fiat::Currency::Usd);
+    let base_url = args.price_url.as_deref().unwrap_or(args.price_provider.default_url());
+    let quote = fiat::fetch_quote(args.price_provider, base_url, currency, proxy)
+        .map_err(|e| eprintln!("Warning: Could not fetch the {} price from {}: {}", currency.code(), args.price_provider.name(), e))
+        .ok();
+    for line in portfolio::format_report(&rows, &total, total_utxos, quote.as_ref(), args.unit) {
+        println!("  {}", line);
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+    let message = format!("{} of {} wallets could not be checked", failures.len(), wallets.len());
+    Err(if failures.iter().any(|failure| matches!(failure, CheckerError::Network(_))) {
+        CheckerError::Network(message)
+    } else {
+        CheckerError::InvalidInput(message)
+    })
+}
+
+/// Checks the inputs on each chain of --networks and reports them side by
+/// side
+fn check_networks(
+    args: &Args,
+    proxy: Option<&Proxy>,
+    inputs: Vec<(String, Result<QueryTarget, String>)>,
+) -> Result<(), CheckerError> {
+    let chains = networks::dedup(&args.networks);
+    if chains.contains(&Chain::Regtest) {
+        // Its server would have to be given, and only one can be
+        return Err(CheckerError::InvalidInput("--networks can't include regtest, which has no public server".to_string()));
+    }
+    let mut targets = Vec::new();
+    for (input, target) in inputs {
+        let target = target.map_err(CheckerError::InvalidInput)?;
+        let per_chain = chains
+            .iter()
+            .map(|chain| target.for_network(chain.network()))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| CheckerError::InvalidInput(format!("{} can't be checked with --networks: {}", input, e)))?;
+        targets.push((input, target.kind(), per_chain));
+    }
+
+    println!("=== Bitcoin Balance Checker ===\n");
+    println!("Networks: {}", chains.iter().map(Chain::name).collect::<Vec<_>>().join(", "));
+    for (input, kind, _) in &targets {
+        println!("Checking: {} ({})", input, kind);
+    }
+    println!();
+
+    let mut rows = Vec::new();
+    let mut failures = Vec::new();
+    for (i, chain) in chains.iter().enumerate() {
+        let checked = open_source(args, proxy, *chain, false).and_then(|mut source| {
+            let mut histories = Vec::new();
+            for (_, _, per_chain) in &targets {
+                histories.push(fetch_target(&source, &per_chain[i], fetch_options(args))?);
+                source.next_input();
+            }
+            Ok(merge_histories(histories))
+        });
+        let result = match checked {
+            Ok(history) => {
+                let (balance, utxos) = tally(&history);
+                Ok((balance, utxos, history.txs.len()))
+            }
+            Err(e) => {
+                let reason = e.to_string();
+                failures.push(e);
+                Err(reason)
+            }
+        };
+        rows.push(networks::Row { chain: *chain, result });
+    }
+    println!("Balance by Network:");
+    for line in networks::format_report(&rows, args.unit) {
+        println!("  {}", line);
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+    let message = format!("{} of {} networks could not be checked", failures.len(), chains.len());
+    Err(if failures.iter().any(|failure| matches!(failure, CheckerError::Network(_))) {
+        CheckerError::Network(message)
+    } else {
+        CheckerError::InvalidInput(message)
+    })
 }
 
-impl BalanceInfo {
-    fn total(&self) -> u64 {
-        self.confirmed + self.unconfirmed
+/// The input followed by its label, if it has one
+fn with_label(input: &str, labels: &HashMap<String, String>) -> String {
+    match labels.get(&labels::label_key(input)) {
+        Some(label) => format!("{} [{}]", input, label),
+        None => input.to_string(),
     }
 }
 
-/// Validates and parses the network name
-fn parse_network(network: &str) -> Result<Network, String> {
-    match network {
-        "testnet" => Ok(Network::Testnet),
-        "bitcoin" | "mainnet" => Ok(Network::Bitcoin),
-        _ => Err(format!("Invalid network: '{}'. Use 'testnet' or 'bitcoin'", network)),
-    }
+/// One row per input with its balance in `unit`
+fn balance_table(rows: &[(String, BalanceInfo, usize)], unit: Unit) -> Vec<String> {
+    let width = rows.iter().map(|(input, _, _)| input.len()).max().unwrap_or(0).max("Address".len());
+    let mut lines = vec![format!(
+        "{:<width$}  {:>16}  {:>16}  {:>16}  {:>5}",
+        "Address", "Confirmed", "Unconfirmed", "Total", "UTXOs"
+    )];
+    lines.extend(rows.iter().map(|(input, balance, unspent_count)| {
+        format!(
+            "{:<width$}  {:>16}  {:>16}  {:>16}  {:>5}",
+            input,
+            unit.number(balance.confirmed),
+            unit.number(balance.unconfirmed),
+            unit.number(balance.total()),
+            unspent_count
+        )
+    }));
+    lines
 }
 
-/// Returns the Esplora API URL for the given network
-fn get_esplora_url(network: Network) -> &'static str {
-    if network == Network::Bitcoin {
-        "https://blockstream.info/api"
-    } else {
-        "https://blockstream.info/testnet/api"
+fn main() {
+    let args = Args::parse();
+
+    if let Err(err) = run(&args) {
+        eprintln!("Error: {}", err);
+        if matches!(err, CheckerError::Network(_)) && args.backend != Backend::Bitcoind {
+            eprintln!("\nNote: This tool requires internet access to query the blockchain.");
+        }
+        std::process::exit(err.exit_code());
     }
 }
 
-/// Converts satoshis to BTC
-fn sats_to_btc(sats: u64) -> f64 {
-    sats as f64 / 100_000_000.0
-}
+fn run(args: &Args) -> Result<(), CheckerError> {
+    if let Some(Command::Labels { action }) = &args.command {
+        return manage_labels(action);
+    }
+    if let Some(Command::Wallets { action }) = &args.command {
+        return manage_wallets(action);
+    }
 
-/// Calculates balance from outputs and spent outputs
-fn calculate_balance(
-    outputs: &HashMap<(String, u32), (u64, bool)>,
-    spent_outputs: &HashSet<(String, u32)>
-) -> BalanceInfo {
-    let mut confirmed = 0u64;
-    let mut unconfirmed = 0u64;
+    // Parse network; with auto it comes from the inputs
+    let chosen_network = parse_network_choice(&args.network).map_err(CheckerError::InvalidInput)?;
+    let period = period::Period::new(args.since.as_deref(), args.until.as_deref()).map_err(CheckerError::InvalidInput)?;
+    if period.is_some()
+        && !(args.txs || args.history_chart || args.export_csv.is_some() || args.ledger.is_some() || args.fees || args.heatmap)
+    {
+        eprintln!("Warning: --since and --until only filter --txs, --history-chart, --export-csv, --ledger, --fees and --heatmap");
+    }
 
-    for (outpoint, (value, is_confirmed)) in outputs {
-        if !spent_outputs.contains(outpoint) {
-            // This output is unspent
-            if *is_confirmed {
-                confirmed += value;
-            } else {
-                unconfirmed += value;
-            }
+    if args.esplora_url.is_some() && !matches!(args.backend, Backend::Esplora | Backend::MempoolSpace) {
+        return Err(CheckerError::InvalidInput(
+            "--esplora-url is only used with --backend esplora or mempool-space".to_string(),
+        ));
+    }
+    let verify = args.verify_with.as_deref().map(parse_verify_with).transpose().map_err(CheckerError::InvalidInput)?;
+    let uses = |backend: Backend| args.backend == backend || verify.as_ref().is_some_and(|(other, _)| *other == backend);
+    if args.rescan_from.is_some() && !matches!(args.backend, Backend::Esplora | Backend::MempoolSpace | Backend::Electrum) {
+        return Err(CheckerError::InvalidInput(
+            "--rescan-from is only used with --backend esplora, mempool-space or electrum, whose histories are stored".to_string(),
+        ));
+    }
+    if args.server.is_some() && args.backend != Backend::Electrum {
+        return Err(CheckerError::InvalidInput("--server is only used with --backend electrum".to_string()));
+    }
+    if args.backend == Backend::Bitcoind {
+        // The UTXO set has no spent outputs or times to build a history from
+        let history_flags = [
+            (args.txs, "--txs"),
+            (args.first_seen, "--first-seen"),
+            (args.heatmap, "--heatmap"),
+            (args.export_csv.is_some(), "--export-csv"),
+            (args.ledger.is_some(), "--ledger"),
+            (args.history_chart, "--history-chart"),
+            (args.fees, "--fees"),
+            (args.privacy_report, "--privacy-report"),
+            (args.since.is_some(), "--since"),
+            (args.until.is_some(), "--until"),
+            (args.at_height.is_some(), "--at-height"),
+        ];
+        if let Some((_, flag)) = history_flags.iter().find(|(set, _)| *set) {
+            return Err(CheckerError::InvalidInput(format!(
+                "{} needs transaction history, which --backend bitcoind doesn't have",
+                flag
+            )));
         }
+        if args.proxy.is_some() || args.rate_limit.is_some() {
+            let flag = if args.proxy.is_some() { "--proxy" } else { "--rate-limit" };
+            return Err(CheckerError::InvalidInput(format!("{} is only used with --backend esplora, mempool-space or electrum", flag)));
+        }
+    } else if !uses(Backend::Bitcoind) && (args.rpc_url.is_some() || args.rpc_user.is_some() || args.rpc_cookie.is_some()) {
+        return Err(CheckerError::InvalidInput(
+            "--rpc-url, --rpc-user and --rpc-cookie are only used with --backend bitcoind or --verify-with bitcoind".to_string(),
+        ));
+    }
+    if args.backend == Backend::Cbf {
+        if args.rate_limit.is_some() {
+            return Err(CheckerError::InvalidInput("--rate-limit is only used with --backend esplora, mempool-space or electrum".to_string()));
+        }
+    } else if !uses(Backend::Cbf) && (args.peer.is_some() || (args.birthday.is_some() && args.sp_scan_key.is_none())) {
+        return Err(CheckerError::InvalidInput(
+            "--peer and --birthday are only used with --backend cbf or --verify-with cbf, and --birthday with --sp-scan-key"
+                .to_string(),
+        ));
+    }
+    if args.mempool_watch && !has_mempool(args.backend) {
+        return Err(CheckerError::InvalidInput(format!(
+            "--mempool-watch needs the mempool, which --backend {} doesn't see",
+            backend_name(args.backend)
+        )));
     }
+    if verify.as_ref().is_some_and(|(backend, _)| *backend == Backend::Bitcoind) && args.at_height.is_some() {
+        return Err(CheckerError::InvalidInput(
+            "--at-height needs transaction history, which --verify-with bitcoind doesn't have".to_string(),
+        ));
+    }
+    if let Some(url) = &args.webhook {
+        alerts::validate_webhook(url).map_err(CheckerError::InvalidInput)?;
+    }
+    let proxy = args.proxy.as_deref().map(Proxy::parse).transpose().map_err(CheckerError::InvalidInput)?;
 
-    BalanceInfo { confirmed, unconfirmed }
-}
+    if let Some(Command::VerifyPor { proofs, message }) = &args.command {
+        return verify_reserves(args, chosen_network, proxy.as_ref(), proofs, message);
+    }
+    if let Some(Command::Portfolio { path }) = &args.command {
+        return check_portfolio(args, chosen_network, proxy.as_ref(), path);
+    }
 
-fn main() {
-    let args = Args::parse();
+    // Batch input prints only records, for scripts to consume
+    if args.stdin || args.input_file.is_some() {
+        let inputs = match &args.input_file {
+            Some(path) => File::open(path).and_then(|file| batch::read_inputs(BufReader::new(file))),
+            None => batch::read_inputs(io::stdin().lock()),
+        };
+        let inputs = inputs.map_err(|e| CheckerError::File(format!("Could not read inputs: {}", e)))?;
+        let network = chosen_network.unwrap_or_else(|| {
+            inputs
+                .iter()
+                .filter_map(|input| query::parse_query_target(input).ok())
+                .find_map(|target| infer_network(&target))
+                .unwrap_or(DEFAULT_NETWORK)
+        });
+        let mut source = open_source(args, proxy.as_ref(), network, false)?;
+        return batch::run(&mut source, network, &inputs, scan_limits(args), args.concurrency.into());
+    }
+
+    // Parse each address, script, script hash or wallet
+    let mut inputs: Vec<(String, Result<QueryTarget, String>)> = args
+        .addresses
+        .iter()
+        .chain(&args.extra_addresses)
+        .map(|input| (input.clone(), query::parse_query_target(input)))
+        .collect();
+    if args.label.is_some() && inputs.len() != 1 {
+        return Err(CheckerError::InvalidInput("--label needs exactly one input to label".to_string()));
+    }
+    if let Some(threshold) = args.multisig {
+        inputs.push((
+            format!("{}-of-{} multisig", threshold, args.xpubs.len()),
+            wallet::multisig(threshold, &args.xpubs).map(QueryTarget::Wallet),
+        ));
+    }
+
+    if !args.networks.is_empty() {
+        return check_networks(args, proxy.as_ref(), inputs);
+    }
 
-    // Parse network
-    let network = match parse_network(&args.network) {
-        Ok(net) => net,
-        Err(err) => {
-            eprintln!("{}", err);
-            return;
+    let opened = match &args.wallet {
+        Some(name) => {
+            wallets::validate_name(name).map_err(CheckerError::InvalidInput)?;
+            let saved = wallets::load(&wallets_dir()?, name).map_err(CheckerError::File)?.ok_or_else(|| {
+                CheckerError::InvalidInput(format!("No wallet named {}; save one with --save-wallet {}", name, name))
+            })?;
+            let chain = saved.chain().map_err(CheckerError::File)?;
+            inputs.push((name.clone(), saved.wallet().map(QueryTarget::Wallet)));
+            Some((saved, chain))
         }
+        None => None,
     };
 
+    // A saved wallet knows its network; testnet4 and signet keys can't tell
+    let (network, network_note) = match (chosen_network, &opened) {
+        (Some(network), _) => (network, ""),
+        (None, Some((_, chain))) => (*chain, " (from the saved wallet)"),
+        (None, None) => match inputs.iter().filter_map(|(_, target)| target.as_ref().ok()).find_map(infer_network) {
+            Some(network) => (network, " (detected from the input)"),
+            None => (DEFAULT_NETWORK, " (default; no input names a network)"),
+        },
+    };
+    if let Some((saved, chain)) = &opened {
+        if *chain != network {
+            return Err(CheckerError::InvalidInput(format!(
+                "Wallet {} was saved on {}, not {}",
+                saved.name,
+                chain.name(),
+                network.name()
+            )));
+        }
+    }
     println!("=== Bitcoin Balance Checker ===\n");
-    println!("Network: {:?}", network);
-    println!("Checking: {}\n", args.address);
+    println!("Network: {:?}{}", network, network_note);
 
-    // Parse the address
-    let address = Address::from_str(&args.address)
-        .expect("Invalid Bitcoin address");
+    let mut saved_labels = labels::load();
 
-    // Verify network matches
-    if !address.is_valid_for_network(network) {
-        eprintln!("Error: Address is not valid for {:?} network", network);
-        return;
+    let mut targets = Vec::new();
+    for (input, target) in inputs {
+        let target = target.map_err(CheckerError::InvalidInput)?;
+        if let Err(err) = check_network(&input, &target, network) {
+            println!("Checking: {} ({})", input, target.kind());
+            return Err(CheckerError::InvalidInput(if chosen_network.is_some() {
+                err
+            } else {
+                format!("{}\n\nNote: The network was taken from the first input that names one; check inputs for different networks in separate runs.", err)
+            }));
+        }
+        // The scan key is a secret, so it comes from its own option
+        let target = match target {
+            QueryTarget::SilentPayment(receiver) => {
+                let key = args.sp_scan_key.as_deref().ok_or_else(|| {
+                    CheckerError::InvalidInput(format!(
+                        "Scanning for payments to {} needs its scan private key; give it with --sp-scan-key",
+                        input
+                    ))
+                })?;
+                let receiver = receiver.with_scan_key(key, args.birthday).map_err(CheckerError::InvalidInput)?;
+                QueryTarget::SilentPayment(Box::new(receiver))
+            }
+            target => target,
+        };
+        if let Some(label) = &args.label {
+            let saved = labels::default_path()
+                .ok_or_else(|| "No data directory for labels; set $XDG_DATA_HOME or $HOME".to_string())
+                .and_then(|path| labels::LabelStore::open(&path))
+                .and_then(|store| store.set(&input, label));
+            match saved {
+                Ok(()) => {
+                    saved_labels.insert(labels::label_key(&input), label.trim().to_string());
+                }
+                Err(e) => eprintln!("Warning: Could not save the label: {}", e),
+            }
+        }
+        println!("Checking: {} ({})", with_label(&input, &saved_labels), target.kind());
+        // A checksum guards the descriptor against typos wherever it's kept
+        if target.kind() == "descriptor" && !input.contains('#') {
+            if let Ok(descriptor) = wallet::with_checksum(&input) {
+                println!("With checksum: {}", descriptor);
+            }
+        }
+        targets.push((input, target));
     }
+    if let Some(sync) = opened.as_ref().and_then(|(saved, _)| saved.sync.as_ref()) {
+        let when = DateTime::parse_from_rfc3339(&sync.synced_at)
+            .map_or_else(|_| sync.synced_at.clone(), |time| time.format("%Y-%m-%d %H:%M UTC").to_string());
+        println!("Last synced: {} at height {}, {}", when, sync.tip_height, args.unit.format(sync.confirmed + sync.unconfirmed));
+    }
+    println!();
+
+    // The wallet whose sync state this run records: the one being saved,
+    // or the saved one opened
+    let to_save = match &args.save_wallet {
+        Some(name) => {
+            let [(_, QueryTarget::Wallet(wallet))] = &targets[..] else {
+                return Err(CheckerError::InvalidInput(
+                    "--save-wallet needs exactly one descriptor, extended key or multisig wallet to save".to_string(),
+                ));
+            };
+            let saved = wallets::SavedWallet::new(name, network, wallet).map_err(CheckerError::InvalidInput)?;
+            if let Some(existing) = wallets::load(&wallets_dir()?, name).map_err(CheckerError::File)? {
+                if !existing.same_wallet(&saved) {
+                    return Err(CheckerError::InvalidInput(format!(
+                        "Another wallet is already saved as {}; remove it with `balance-checker wallets rm {}` or pick another name",
+                        name, name
+                    )));
+                }
+            }
+            Some(saved)
+        }
+        None => opened.map(|(saved, _)| saved),
+    };
 
-    // Connect to Esplora
-    let esplora_url = get_esplora_url(network);
+    let mut source = open_source(args, proxy.as_ref(), network, true)?;
+    // The second backend's server, from the value or the defaults; a local
+    // node or peer given for the main backend's options is used here too
+    let verify = match verify {
+        Some((backend, url)) => {
+            let url = url.as_deref().or(match backend {
+                Backend::Bitcoind => args.rpc_url.as_deref(),
+                Backend::Cbf => args.peer.as_deref(),
+                _ => None,
+            });
+            let url = url.or(default_url(backend, network)).ok_or_else(|| {
+                CheckerError::InvalidInput(format!(
+                    "{:?} has no public server; give one as --verify-with {}=URL",
+                    network,
+                    backend_name(backend)
+                ))
+            })?;
+            if backend == args.backend && source_url(args, network) == Some(url) {
+                return Err(CheckerError::InvalidInput("--verify-with names the main backend's own server".to_string()));
+            }
+            Some((backend, url.to_string()))
+        }
+        None => None,
+    };
 
-    println!("Connecting to {}...", esplora_url);
-    let blockchain = EsploraBlockchain::new(esplora_url, 20);
+    // mempool.space totals a script's outputs itself, so a plain balance
+    // check of addresses and scripts needs no transactions
+    let stats = if args.backend == Backend::MempoolSpace
+        && !needs_history(args)
+        && targets.iter().all(|(_, target)| target.script_hash().is_some())
+    {
+        println!("Fetching address stats...\n");
+        let stats = targets
+            .iter()
+            .map(|(_, target)| {
+                let hash = target.script_hash().expect("checked above");
+                fetch_stats(&source, &hash).map(|stats| (hash, stats))
+            })
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| CheckerError::Network(format!("Could not fetch address stats: {}", e)))?;
+        Some(stats)
+    } else {
+        None
+    };
 
-    println!("Fetching address information...\n");
+    let mut histories = Vec::new();
+    if stats.is_none() {
+        let options = FetchOptions { verbose: true, show_progress: !args.no_progress, ..fetch_options(args) };
+        for (i, (input, target)) in targets.iter().enumerate() {
+            if targets.len() > 1 {
+                println!("[{}/{}] {}", i + 1, targets.len(), input);
+            }
+            histories.push(fetch_target(&source, target, options)?);
+            source.next_input();
+        }
+    }
 
-    // Get script from address
-    let script = address.script_pubkey();
+    // Saved from the full history, before --at-height looks back
+    if let Some(saved) = to_save {
+        let name = saved.name.clone();
+        let written = tip_height(&source)
+            .and_then(|tip| save_wallet(saved, histories.last().expect("the wallet was fetched"), tip));
+        match written {
+            Ok(path) if args.save_wallet.is_some() => println!("Saved wallet {} to {}\n", name, path.display()),
+            Ok(_) => {}
+            Err(e) => eprintln!("Warning: Could not save wallet {}: {}", name, e),
+        }
+    }
 
-    // Get all transactions for this address (with pagination)
-    let mut txs = Vec::new();
-    let mut last_seen = None;
+    // The same targets from the second backend, compared after the report
+    let mut cross_check = match &verify {
+        Some((backend, url)) => {
+            let name = backend_name(*backend);
+            println!("Cross-checking with {} at {}...\n", name, esplora::display_url(url));
+            let failed = |e: CheckerError| match e {
+                CheckerError::Network(msg) => CheckerError::Network(format!("Cross-check with {} failed: {}", name, msg)),
+                e => e,
+            };
+            let other = connect_source(args, *backend, url, proxy.as_ref(), network, false, false).map_err(failed)?;
+            let mut other_histories = Vec::new();
+            for (_, target) in &targets {
+                other_histories.push(
+                    fetch_target(&other, target, FetchOptions { show_progress: !args.no_progress, ..fetch_options(args) })
+                        .map_err(failed)?,
+                );
+            }
+            let tip = tip_height(&other).map_err(|e| failed(CheckerError::Network(format!("Could not fetch the tip height: {}", e))))?;
+            Some((*backend, merge_histories(other_histories), tip))
+        }
+        None => None,
+    };
 
-    loop {
-        let batch = match blockchain.scripthash_txs(&script, last_seen) {
-            Ok(batch) => batch,
+    if let Some(height) = args.at_height {
+        let tip = tip_height(&source)
+            .map_err(|e| CheckerError::Network(format!("Could not fetch the tip height for --at-height: {}", e)))?;
+        if height > tip {
+            return Err(CheckerError::InvalidInput(format!("--at-height {} is above the chain tip ({})", height, tip)));
+        }
+        println!("As of block {} ({} blocks before the tip)\n", height, tip - height);
+        histories = histories.into_iter().map(|history| history_at(history, height)).collect();
+        cross_check = cross_check.map(|(backend, history, tip)| (backend, history_at(history, height), tip));
+    }
+    // Confirmations are counted from --at-height when given
+    let report_height = || args.at_height.map_or_else(|| tip_height(&source), Ok);
+
+    if targets.len() > 1 {
+        let balances: Vec<(BalanceInfo, usize)> = match &stats {
+            Some(stats) => stats.iter().map(|(_, stats)| stats.balance()).collect(),
+            None => histories.iter().map(tally).collect(),
+        };
+        let rows: Vec<(String, BalanceInfo, usize)> = targets
+            .iter()
+            .zip(balances)
+            .map(|((input, _), (balance, unspent_count))| (with_label(input, &saved_labels), balance, unspent_count))
+            .collect();
+        println!("Balances ({}):", args.unit.label());
+        for line in balance_table(&rows, args.unit) {
+            println!("  {}", line);
+        }
+        println!();
+    }
+
+    // Totals come from the combined history, so a transaction between two
+    // of the inputs, or an input given twice, is counted once
+    let single = targets.len() == 1;
+    let combined = merge_histories(histories);
+
+    let (balance, unspent_count) = match &stats {
+        Some(stats) => mempool_space::combined_balance(stats),
+        None => tally(&combined),
+    };
+    let quote = args.fiat.and_then(|currency| {
+        let base_url = args.price_url.as_deref().unwrap_or(args.price_provider.default_url());
+        fiat::fetch_quote(args.price_provider, base_url, currency, proxy.as_ref())
+            .map_err(|e| {
+                eprintln!("Warning: Could not fetch the {} price from {}: {}", currency.code(), args.price_provider.name(), e)
+            })
+            .ok()
+    });
+    let title = match (single, args.at_height) {
+        (true, None) => "Balance Summary".to_string(),
+        (false, None) => "Combined Balance".to_string(),
+        (true, Some(height)) => format!("Balance Summary at Height {}", height),
+        (false, Some(height)) => format!("Combined Balance at Height {}", height),
+    };
+    // Coinbase outputs need 100 blocks before they can be spent, so a
+    // miner's balance is split even without --min-conf or --dust-limit
+    let unspent = utxos::unspent_outputs(&combined.txs, &combined.scripts);
+    let coinbase = utxos::coinbase_txids(&combined.txs);
+    let asked = args.min_conf.is_some() || args.dust_limit.is_some();
+    let split = if asked || unspent.iter().any(|utxo| coinbase.contains(&utxo.txid)) {
+        match report_height() {
+            Ok(tip) => Some(utxos::split_balance(&unspent, tip, &coinbase, args.min_conf, args.dust_limit))
+                .filter(|split| asked || split.immature_count > 0),
             Err(e) => {
-                eprintln!("Error fetching transactions: {}", e);
-                eprintln!("\nNote: This tool requires internet access to query the blockchain.");
-                return;
+                eprintln!("Warning: Could not fetch the tip height to split the balance: {}", e);
+                None
             }
-        };
+        }
+    } else {
+        None
+    };
+    let pending = utxos::split_pending(
+        &unspent,
+        &utxos::trusted_pending(&combined.txs, &combined.scripts),
+        &utxos::replaceable_pending(&combined.txs),
+        split.and_then(|split| split.dust_limit),
+    );
+    if pending.replaceable > 0 {
+        eprintln!(
+            "Warning: {} of incoming unconfirmed funds can be replaced by the sender (BIP125 RBF), so they can be double-spent with a higher fee; don't count on them until confirmed",
+            args.unit.format(pending.replaceable)
+        );
+    }
+    print_balance_summary(
+        &title,
+        &balance,
+        unspent_count,
+        quote.as_ref(),
+        split.as_ref(),
+        &pending,
+        args.unit,
+        args.non_signalling,
+    );
+
+    let by_chain = chain_balances(&combined);
+    if !by_chain.is_empty() {
+        println!("\nBy Chain:");
+        for (name, value, count) in by_chain {
+            let outputs = if count == 1 { "UTXO" } else { "UTXOs" };
+            println!("  {:<14}{} ({} {})", format!("{}:", name), args.unit.format(value), count, outputs);
+        }
+    }
 
-        if batch.is_empty() {
-            break;
+    // Wallets hand out a fresh address for each payment, so any address
+    // paid twice is worth pointing out
+    if targets.iter().any(|(_, target)| matches!(target, QueryTarget::Wallet(_))) {
+        let (reused, used) = reuse::find_reuse(&combined.txs, &combined.scripts);
+        if reused.is_empty() {
+            println!("\nAddress Reuse:");
+            println!("  No address received funds more than once ({} used)", used);
+        } else {
+            println!("\nAddress Reuse ({} of {} used addresses received funds more than once):", reused.len(), used);
+            let name = |script: &bdk::bitcoin::Script| match Address::from_script(script, network.network()) {
+                Ok(address) => with_label(&address.to_string(), &saved_labels),
+                Err(_) => format!("{:x}", script),
+            };
+            for line in reuse::format_reuse(&reused, name, args.unit) {
+                println!("  {}", line);
+            }
         }
+    }
 
-        last_seen = Some(batch.last().unwrap().txid);
-        let batch_len = batch.len();
-        txs.extend(batch);
+    // How far each ranged chain of the wallets has been used
+    let descriptors: Vec<wallet::WalletDescriptor> = targets
+        .iter()
+        .filter_map(|(_, target)| match target {
+            QueryTarget::Wallet(wallet) => Some(wallet.descriptors.iter().cloned()),
+            _ => None,
+        })
+        .flatten()
+        .collect();
+    if !combined.chains.is_empty() && combined.chains.len() == descriptors.len() {
+        let reports = derivation::report(&descriptors, &combined.chains, network.network());
+        if !reports.is_empty() {
+            println!("\nDerivation Usage:");
+            for line in derivation::format_report(&reports) {
+                println!("  {}", line);
+            }
+        }
+    }
 
-        // If we got fewer than the page size, we're done
-        if batch_len < 25 {
-            break;
+    // Each descriptor with how many of its indexes were scanned, and their
+    // scripts derived once for the reports below that need to know them
+    let scanned: Vec<(&wallet::WalletDescriptor, u32)> = descriptors
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let count = combined.chains.get(i).filter(|_| combined.chains.len() == descriptors.len());
+            (entry, count.map_or(wallet::UNSCANNED_INDEXES, |chain| chain.scanned))
+        })
+        .collect();
+    let derived = wallet::derive_scripts(&scanned);
+
+    // Outputs behind a CLTV or CSV lock, from the descriptors or from
+    // scripts earlier spends revealed
+    let conditions = timelocks::spending_conditions(&derived, &combined.txs, &combined.scripts);
+    if !conditions.is_empty() {
+        let utxos = utxos::unspent_outputs(&combined.txs, &combined.scripts);
+        let block_times: HashMap<Txid, u64> =
+            combined.txs.iter().filter_map(|tx| Some((tx.txid, tx.status.block_time?))).collect();
+        let tip = report_height()
+            .map_err(|e| CheckerError::Network(format!("Could not fetch the tip height for timelocked outputs: {}", e)))?;
+        let now = Utc::now().timestamp() as u64;
+        let locked = timelocks::locked_outputs(&utxos, &conditions, |utxo| block_times.get(&utxo.txid).copied(), tip, now);
+        if !locked.is_empty() {
+            let locked_value: u64 = locked.iter().map(|output| output.utxo.value).sum();
+            let spendable: u64 = utxos.iter().map(|utxo| utxo.value).sum::<u64>() - locked_value;
+            println!(
+                "\nTimelocked Outputs ({}, {} locked; {} spendable now):",
+                locked.len(),
+                args.unit.format(locked_value),
+                args.unit.format(spendable)
+            );
+            for line in timelocks::format_report(&locked, tip, now, args.unit) {
+                println!("  {}", line);
+            }
+        }
+    }
+
+    if args.utxos {
+        let utxos = utxos::unspent_outputs(&combined.txs, &combined.scripts);
+        match report_height() {
+            Ok(tip) => {
+                let height = if args.at_height.is_some() { "at height" } else { "tip height" };
+                println!("\nUnspent Outputs ({}, {} {}):", utxos.len(), height, tip);
+                if utxos.is_empty() {
+                    println!("  No unspent outputs");
+                } else {
+                    let utxo_labels: Vec<Option<String>> = utxos
+                        .iter()
+                        .map(|utxo| {
+                            Address::from_script(&utxo.script, network.network())
+                                .ok()
+                                .and_then(|address| saved_labels.get(&address.to_string()).cloned())
+                        })
+                        .collect();
+                    let known = spendability::known(&derived, &combined.txs, &combined.scripts);
+                    let classes: Vec<spendability::Spendability> =
+                        utxos.iter().map(|utxo| spendability::classify(utxo, &known)).collect();
+                    let requirements: Vec<String> = classes.iter().map(|class| class.describe()).collect();
+                    for line in utxos::format_table(&utxos, tip, &requirements, &utxo_labels, args.unit) {
+                        println!("  {}", line);
+                    }
+                    let unproven: Vec<&utxos::Utxo> = utxos
+                        .iter()
+                        .zip(&classes)
+                        .filter(|(_, class)| class.basis == spendability::Basis::ScriptType)
+                        .map(|(utxo, _)| utxo)
+                        .collect();
+                    if !unproven.is_empty() {
+                        eprintln!(
+                            "Warning: {} of the unspent outputs ({}) {} known only by address, so what spends them is a guess from the script type and nothing shows the keys are yours; check the wallet's descriptor instead to be sure",
+                            unproven.len(),
+                            args.unit.format(unproven.iter().map(|utxo| utxo.value).sum::<u64>()),
+                            if unproven.len() == 1 { "is" } else { "are" }
+                        );
+                    }
+                }
+            }
+            Err(e) => return Err(CheckerError::Network(format!("Could not fetch the tip height for --utxos: {}", e))),
         }
     }
 
-    eprintln!("DEBUG: Fetched {} total transactions", txs.len());
+    if args.utxo_stats {
+        let utxos = utxos::unspent_outputs(&combined.txs, &combined.scripts);
+        let tip = report_height()
+            .map_err(|e| CheckerError::Network(format!("Could not fetch the tip height for --utxo-stats: {}", e)))?;
+        println!("\nUTXO Statistics:");
+        match distribution::summarize(&utxos, tip) {
+            Some(stats) => {
+                for line in distribution::format_stats(&stats, args.unit) {
+                    println!("  {}", line);
+                }
+            }
+            None => println!("  No unspent outputs"),
+        }
+    }
 
-    // Track all outputs and which ones are spent
-    // Map of (txid, vout) -> (value, confirmed)
-    let mut outputs: HashMap<(String, u32), (u64, bool)> = HashMap::new();
+    if args.mempool_watch {
+        let payments = mempool::incoming(&combined.txs, &combined.scripts);
+        println!("\nIncoming Mempool Payments:");
+        if payments.is_empty() {
+            println!("  None");
+        } else {
+            let estimates = fee_estimates(&source).unwrap_or_else(|e| {
+                eprintln!("Warning: Could not fetch fee estimates: {}; confirmation times are unknown", e);
+                Vec::new()
+            });
+            for payment in &payments {
+                println!("  {}", mempool::format_payment(payment, &estimates, args.unit));
+            }
+            println!("  Total: +{}", args.unit.format(payments.iter().map(|payment| payment.amount).sum::<u64>()));
+        }
+    }
 
-    // Set of spent outputs (txid, vout)
-    let mut spent_outputs: HashSet<(String, u32)> = HashSet::new();
+    if let Some(path) = &args.export_utxos {
+        let utxos = utxos::unspent_outputs(&combined.txs, &combined.scripts);
+        let tip = report_height()
+            .map_err(|e| CheckerError::Network(format!("Could not fetch the tip height for --export-utxos: {}", e)))?;
+        File::create(path)
+            .and_then(|file| {
+                coins::write_json(io::BufWriter::new(file), &utxos, &derived, tip, network.network(), network.name())
+            })
+            .map_err(|e| CheckerError::File(format!("Could not write {}: {}", path.display(), e)))?;
+        println!("\nUnspent outputs written to {} ({} UTXOs)", path.display(), utxos.len());
+    }
 
-    // First pass: collect all outputs belonging to this address
-    for tx in &txs {
-        for (vout_index, output) in tx.vout.iter().enumerate() {
-            if output.scriptpubkey == script {
-                let key = (tx.txid.to_string(), vout_index as u32);
-                outputs.insert(key, (output.value, tx.status.confirmed));
+    if args.export_core {
+        let mut imports = Vec::new();
+        let mut skipped = Vec::new();
+        let mut counts = scanned.iter().map(|(_, count)| *count);
+        for (input, target) in &targets {
+            match target {
+                QueryTarget::Wallet(wallet) => imports.extend(
+                    wallet.descriptors.iter().zip(counts.by_ref()).map(|(entry, count)| import::Import::Descriptor(entry, count)),
+                ),
+                QueryTarget::Address(address) => imports.push(import::Import::Address(address)),
+                QueryTarget::Script(script) => imports.push(import::Import::Script(script)),
+                QueryTarget::ScriptHash(_) | QueryTarget::SilentPayment(_) => skipped.push(input.as_str()),
+            }
+        }
+        let oldest = combined.txs.iter().filter_map(|tx| tx.status.block_time).min();
+        println!("\nBitcoin Core Import:");
+        if !imports.is_empty() {
+            for line in import::to_json(&imports, oldest).lines() {
+                println!("  {}", line);
             }
+            println!("  Import into a wallet without private keys, e.g.:");
+            println!("    bitcoin-cli -named createwallet wallet_name=check disable_private_keys=true");
+            println!("    bitcoin-cli -rpcwallet=check importdescriptors '<the array above>'");
+        }
+        for input in skipped {
+            println!("  Note: {} has no descriptor Bitcoin Core can import", input);
         }
     }
 
-    // Second pass: mark spent outputs
-    for tx in &txs {
-        for input in &tx.vin {
-            if let Some(prevout) = &input.prevout {
-                if prevout.scriptpubkey == script {
-                    let key = (input.txid.to_string(), input.vout);
-                    spent_outputs.insert(key);
+    if let (true, Some(fee_rate)) = (args.consolidate_advice, args.feerate) {
+        let utxos = utxos::unspent_outputs(&combined.txs, &combined.scripts);
+        let future_fee_rate = args.future_feerate.unwrap_or(consolidate::DEFAULT_FUTURE_FEE_RATE);
+        println!("\nConsolidation Advice ({} sat/vB now, {} sat/vB later):", fee_rate, future_fee_rate);
+        if utxos.is_empty() {
+            println!("  No unspent outputs");
+        } else {
+            for line in consolidate::format_plan(&consolidate::plan(&utxos, fee_rate, future_fee_rate), args.unit) {
+                println!("  {}", line);
+            }
+        }
+    }
+
+    if let (true, Some(fee_rate)) = (args.sweep_estimate, args.feerate) {
+        let utxos = utxos::unspent_outputs(&combined.txs, &combined.scripts);
+        println!("\nSweep Estimate ({} sat/vB):", fee_rate);
+        // The wallet's own script type, or else the largest output's
+        let destination = descriptors
+            .first()
+            .map(|entry| entry.descriptor.at_derivation_index(0).script_pubkey())
+            .or_else(|| utxos.first().map(|utxo| utxo.script.clone()));
+        match destination {
+            Some(destination) if !utxos.is_empty() => {
+                let weights = sweep::input_weights(&derived);
+                for line in sweep::format_sweep(&sweep::estimate(&utxos, &weights, &destination, fee_rate), args.unit) {
+                    println!("  {}", line);
                 }
             }
+            _ => println!("  No unspent outputs"),
         }
     }
 
-    let unspent_count = outputs.iter().filter(|(k, _)| !spent_outputs.contains(k)).count();
-    eprintln!("DEBUG: Total outputs: {}, Spent: {}, Unspent UTXOs: {}",
-              outputs.len(), spent_outputs.len(), unspent_count);
+    if args.privacy_report {
+        println!("\nPrivacy Report:");
+        for line in privacy::format_report(&privacy::analyze(&combined.txs, &combined.scripts), args.unit) {
+            println!("  {}", line);
+        }
+    }
+
+    let mut disagreement = None;
+    if let Some((backend, other, other_tip)) = cross_check {
+        let main_tip = tip_height(&source)
+            .map_err(|e| CheckerError::Network(format!("Could not fetch the tip height for --verify-with: {}", e)))?;
+        // Without a mempool on one side, unconfirmed spends can't match
+        let confirmed_only = !has_mempool(args.backend) || !has_mempool(backend);
+        let unspent = |history: &History| {
+            let txs: Vec<Tx> = history.txs.iter().filter(|tx| !confirmed_only || tx.status.confirmed).cloned().collect();
+            utxos::unspent_outputs(&txs, &history.scripts)
+        };
+        let main = crosscheck::Side { name: "main".to_string(), tip_height: main_tip, utxos: unspent(&combined) };
+        let other = crosscheck::Side { name: backend_name(backend), tip_height: other_tip, utxos: unspent(&other) };
+        let comparison = crosscheck::compare(&main.utxos, &other.utxos);
+        println!("\nCross-Check with {}:", other.name);
+        for line in crosscheck::format_report(&main, &other, &comparison, confirmed_only, args.unit) {
+            println!("  {}", line);
+        }
+        if !comparison.matches() {
+            disagreement = Some((other.name, comparison.discrepancies()));
+        }
+    }
+
+    let History { txs, scripts, .. } = combined;
+
+    // The history listings share these rows, cut to --since/--until; each
+    // running balance is still counted from the first transaction
+    let now = Utc::now().timestamp() as u64;
+    let mut rows = export::history_rows(&txs, &scripts);
+    if let Some(period) = &period {
+        rows.retain(|row| period.contains(row.tx, now));
+        println!("\nPeriod: {} ({} of {} transactions)", period.describe(), rows.len(), txs.len());
+    }
+
+    if let Some(path) = &args.export_csv {
+        File::create(path)
+            .and_then(|file| export::write_csv(io::BufWriter::new(file), &rows))
+            .map_err(|e| CheckerError::File(format!("Could not write {}: {}", path.display(), e)))?;
+        println!("\nHistory written to {} ({} transactions)", path.display(), rows.len());
+    }
+
+    if let Some(path) = &args.ledger {
+        let entries = ledger::entries(&rows);
+        File::create(path)
+            .and_then(|file| {
+                let writer = io::BufWriter::new(file);
+                if ledger::is_json(path) {
+                    ledger::write_json(writer, &entries)
+                } else {
+                    ledger::write_csv(writer, &entries)
+                }
+            })
+            .map_err(|e| CheckerError::File(format!("Could not write {}: {}", path.display(), e)))?;
+        println!("\nLedger written to {} ({} entries)", path.display(), entries.len());
+    }
+
+    // Show first use if requested
+    if args.first_seen {
+        println!("\nFirst Seen:");
+        match find_first_seen(&txs) {
+            Some(tx) => {
+                if let Some(height) = tx.status.block_height {
+                    println!("  Block height: {}", height);
+                }
+                if let Some(time) = tx.status.block_time {
+                    let datetime = DateTime::<Utc>::from_timestamp(time as i64, 0)
+                        .unwrap_or_else(Utc::now);
+                    println!("  Block time:   {}", datetime.format("%Y-%m-%d %H:%M:%S UTC"));
+                }
+                println!("  TXID:         {}", tx.txid);
+            }
+            None if txs.is_empty() => println!("  Address has never been used"),
+            None => println!("  Only seen in unconfirmed transactions"),
+        }
+    }
 
-    // Calculate balance using helper function
-    let balance = calculate_balance(&outputs, &spent_outputs);
+    // Show activity heatmap if requested
+    if args.heatmap {
+        let dates = rows
+            .iter()
+            .filter_map(|row| row.tx.status.block_time)
+            .filter_map(|time| DateTime::<Utc>::from_timestamp(time as i64, 0))
+            .map(|datetime| datetime.date_naive());
+        let grid = heatmap::ActivityGrid::from_dates(dates, Utc::now().date_naive());
+
+        println!("\nActivity (past year): {} transactions on {} days\n", grid.total(), grid.active_days());
+        for line in grid.render() {
+            println!("  {}", line);
+        }
+    }
 
-    println!("Balance Summary:");
-    println!("  Confirmed:   {} sats", balance.confirmed);
-    println!("  Unconfirmed: {} sats", balance.unconfirmed);
-    println!("  Total:       {} sats", balance.total());
+    // Show the balance timeline if requested
+    if args.history_chart {
+        println!("\nBalance Timeline ({} transactions):", rows.len());
+        if rows.is_empty() {
+            println!("  No transactions found");
+        } else {
+            for line in timeline::render_timeline(&rows, args.unit) {
+                println!("  {}", line);
+            }
+            if args.chart {
+                println!();
+                for line in timeline::render_chart(&rows, now, args.unit) {
+                    println!("  {}", line);
+                }
+            }
+        }
+    }
 
-    // Convert to BTC
-    println!("  Total:       {:.8} BTC", sats_to_btc(balance.total()));
+    if args.fees {
+        println!("\nFees Paid:");
+        for line in fees::format_summary(&fees::summarize(&rows), args.unit) {
+            println!("  {}", line);
+        }
+    }
 
     // Show transactions if requested
     if args.txs {
-        println!("\nTransaction History ({} transactions):", txs.len());
+        let tip = report_height()
+            .map_err(|e| eprintln!("Warning: Could not fetch the tip height for confirmation counts: {}", e))
+            .ok();
+        let replaceable = utxos::replaceable_pending(&txs);
+        // --min-conf leaves out shallower transactions, though the running
+        // balance still counts them
+        let listed: Vec<&export::HistoryRow> = rows
+            .iter()
+            .filter(|row| match (args.min_conf, tip) {
+                (Some(min_conf), Some(tip)) => utxos::confirmations(row.tx.status.block_height, tip) >= min_conf,
+                _ => true,
+            })
+            .collect();
+        let hidden = rows.len() - listed.len();
+        match args.min_conf {
+            Some(min_conf) if hidden > 0 => println!(
+                "\nTransaction History ({} transactions; {} with under {} confirmations not listed):",
+                listed.len(),
+                hidden,
+                min_conf
+            ),
+            _ => println!("\nTransaction History ({} transactions):", listed.len()),
+        }
 
-        if txs.is_empty() {
+        if rows.is_empty() {
             println!("  No transactions found");
+        } else if listed.is_empty() {
+            println!("  None with {} or more confirmations", args.min_conf.unwrap_or(0));
         } else {
-            for tx in &txs {
+            // The running balance is built oldest first; list newest first
+            for row in listed.iter().rev() {
+                let tx = row.tx;
                 println!("\n  TXID: {}", tx.txid);
                 if tx.status.confirmed {
                     if let Some(height) = tx.status.block_height {
                         println!("  Confirmed at height: {}", height);
                     }
+                    if let Some(tip) = tip {
+                        println!("  Confirmations: {}", utxos::confirmations(tx.status.block_height, tip));
+                    }
+                    if let Some(datetime) = tx.status.block_time.and_then(|time| DateTime::<Utc>::from_timestamp(time as i64, 0)) {
+                        println!("  Block time: {}", datetime.format("%Y-%m-%d %H:%M:%S UTC"));
+                    }
                 } else {
-                    println!("  Status: Unconfirmed");
+                    println!("  Status: Unconfirmed{}", if replaceable.contains(&tx.txid) { " (replaceable: RBF)" } else { "" });
                 }
+                println!("  {}: {}", row.direction.title(), args.unit.format_signed(row.amount));
+                println!("  Balance after: {}", args.unit.format_i64(row.balance));
             }
         }
     }
+
+    if args.watch {
+        // Every poll has to see the mempool as it is now
+        if let Source::Esplora(_, Some(store), _) | Source::Electrum(_, Some(store)) = &mut source {
+            store.set_ttl(Duration::ZERO);
+        }
+        let targets: Vec<QueryTarget> = targets.into_iter().map(|(_, target)| target).collect();
+        let config = watch::WatchConfig {
+            interval: Duration::from_secs(args.interval),
+            beep: args.beep,
+            on_change: args.on_change.clone(),
+            webhook: args.webhook.clone(),
+            exec: args.exec.clone(),
+            proxy,
+            limits: scan_limits(args),
+            concurrency: args.concurrency.into(),
+            mempool: args.mempool_watch,
+            unit: args.unit,
+        };
+        watch::run(&source, &targets, balance, alerts::tx_effects(&txs, &scripts), &config);
+    }
+    if let Some((name, count)) = disagreement {
+        let outputs = if count == 1 { "unspent output" } else { "unspent outputs" };
+        return Err(CheckerError::Unverified(format!("The main backend and {} disagree on {} {}", name, count, outputs)));
+    }
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_args_are_consistent() {
+        use clap::CommandFactory;
+        Args::command().debug_assert();
+    }
+
     #[test]
     fn test_parse_network_testnet() {
-        assert_eq!(parse_network("testnet").unwrap(), Network::Testnet);
+        assert_eq!(parse_network("testnet").unwrap(), Chain::Testnet);
+    }
+
+    #[test]
+    fn test_parse_network_test_chains() {
+        assert_eq!(parse_network("testnet4").unwrap(), Chain::Testnet4);
+        assert_eq!(parse_network("signet").unwrap(), Chain::Signet);
+        assert_eq!(parse_network("regtest").unwrap(), Chain::Regtest);
+    }
+
+    #[test]
+    fn test_parse_verify_with() {
+        assert_eq!(parse_verify_with("electrum").unwrap(), (Backend::Electrum, None));
+        assert_eq!(parse_verify_with("mempool-space").unwrap(), (Backend::MempoolSpace, None));
+        assert_eq!(
+            parse_verify_with("bitcoind=http://127.0.0.1:8332").unwrap(),
+            (Backend::Bitcoind, Some("http://127.0.0.1:8332".to_string()))
+        );
+        assert_eq!(
+            parse_verify_with("https://mempool.space/api").unwrap(),
+            (Backend::Esplora, Some("https://mempool.space/api".to_string()))
+        );
+        assert_eq!(parse_verify_with("tcp://127.0.0.1:50001").unwrap().0, Backend::Electrum);
+        assert!(parse_verify_with("electrum=").is_err());
+        assert!(parse_verify_with("fulcrum").is_err());
     }
 
     #[test]
     fn test_parse_network_bitcoin() {
-        assert_eq!(parse_network("bitcoin").unwrap(), Network::Bitcoin);
+        assert_eq!(parse_network("bitcoin").unwrap(), Chain::Bitcoin);
     }
 
     #[test]
     fn test_parse_network_mainnet() {
-        assert_eq!(parse_network("mainnet").unwrap(), Network::Bitcoin);
+        assert_eq!(parse_network("mainnet").unwrap(), Chain::Bitcoin);
+    }
+
+    #[test]
+    fn test_parse_network_choice() {
+        assert_eq!(parse_network_choice("auto").unwrap(), None);
+        assert_eq!(parse_network_choice("bitcoin").unwrap(), Some(Chain::Bitcoin));
+        assert!(parse_network_choice("testnet3").is_err());
+    }
+
+    #[test]
+    fn test_infer_network() {
+        let infer = |input: &str| infer_network(&query::parse_query_target(input).unwrap());
+        assert_eq!(infer("bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq"), Some(Chain::Bitcoin));
+        assert_eq!(infer("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx"), Some(Chain::Testnet));
+        assert_eq!(infer("bcrt1qw508d6qejxtdg4y5r3zarvary0c5xw7kygt080"), Some(Chain::Regtest));
+        assert_eq!(
+            infer("vpub5Y6cjg78GGuNLsaPhmYsiw4gYX3HoQiRBiSwDaBXKUafCt9bNwWQiitDk5VZ5BVxYnQdwoTyXSs2JHRPAgjAvtbBrf8ZhDYe2jWAqvZVnsc"),
+            Some(Chain::Testnet)
+        );
+        assert_eq!(infer("0014751e76e8199196d454941c45d1b3a323f1433bd6"), None);
     }
 
     #[test]
     fn test_parse_network_invalid() {
         assert!(parse_network("invalid").is_err());
-        assert!(parse_network("regtest").is_err());
+        assert!(parse_network("Regtest").is_err());
         assert!(parse_network("").is_err());
     }
 
     #[test]
     fn test_get_esplora_url_mainnet() {
-        assert_eq!(get_esplora_url(Network::Bitcoin), "https://blockstream.info/api");
+        assert_eq!(get_esplora_url(Chain::Bitcoin), Some("https://blockstream.info/api"));
     }
 
     #[test]
     fn test_get_esplora_url_testnet() {
-        assert_eq!(get_esplora_url(Network::Testnet), "https://blockstream.info/testnet/api");
+        assert_eq!(get_esplora_url(Chain::Testnet), Some("https://blockstream.info/testnet/api"));
+    }
+
+    #[test]
+    fn test_get_esplora_url_other_test_chains() {
+        assert_eq!(get_esplora_url(Chain::Testnet4), Some("https://mempool.space/testnet4/api"));
+        assert_eq!(get_esplora_url(Chain::Signet), Some("https://mempool.space/signet/api"));
+        assert_eq!(get_esplora_url(Chain::Regtest), None);
     }
 
     #[test]
@@ -367,4 +2902,147 @@ mod tests {
         assert_eq!(balance.unconfirmed, 150_000);
         assert_eq!(balance.total(), 350_000);
     }
+
+    fn make_tx(id: u8, block_height: Option<u32>) -> Tx {
+        Tx { status: test_util::status(block_height, None), ..test_util::tx(id, vec![], vec![]) }
+    }
+
+    #[test]
+    fn test_find_first_seen_picks_lowest_height() {
+        let txs = vec![
+            make_tx(0x11, Some(300)),
+            make_tx(0x22, None),
+            make_tx(0x33, Some(120)),
+            make_tx(0x44, Some(250)),
+        ];
+
+        let first = find_first_seen(&txs).unwrap();
+        assert_eq!(first.status.block_height, Some(120));
+    }
+
+    #[test]
+    fn test_find_first_seen_ignores_unconfirmed() {
+        let txs = vec![
+            make_tx(0x22, None),
+        ];
+        assert!(find_first_seen(&txs).is_none());
+        assert!(find_first_seen(&[]).is_none());
+    }
+
+    #[test]
+    fn test_merge_histories_counts_shared_transactions_once() {
+        use bdk::bitcoin::hashes::Hash;
+
+        let a = History {
+            txs: vec![
+                make_tx(0x11, Some(100)),
+                make_tx(0x22, Some(300)),
+            ],
+            scripts: HashSet::from([sha256::Hash::hash(b"a")]),
+            keychains: HashMap::new(),
+            chains: Vec::new(),
+            balance: None,
+        };
+        let b = History {
+            txs: vec![
+                make_tx(0x22, Some(300)),
+                make_tx(0x33, None),
+            ],
+            scripts: HashSet::from([sha256::Hash::hash(b"b")]),
+            keychains: HashMap::new(),
+            chains: Vec::new(),
+            balance: None,
+        };
+
+        let merged = merge_histories(vec![a, b]);
+        let heights: Vec<Option<u32>> = merged.txs.iter().map(|tx| tx.status.block_height).collect();
+        assert_eq!(heights, vec![None, Some(300), Some(100)]);
+        assert_eq!(merged.scripts.len(), 2);
+    }
+
+    #[test]
+    fn test_page_history_keeps_each_transaction_once() {
+        let page = |ids: std::ops::Range<u8>| ids.map(|id| make_tx(id, Some(1_000 - id as u32))).collect::<Vec<_>>();
+        let progress = Progress::new(false);
+
+        // Transaction 26 was mined between the pages, so both list it
+        let mut pages = vec![page(26..51), page(1..27)].into_iter();
+        let txs = page_history(&[], &progress, |_| Ok(pages.next().unwrap_or_default())).unwrap();
+        assert_eq!(txs.len(), 50, "51 listed, 50 unique");
+
+        // A server that ignores last_seen serves the first page again
+        let mut requests = 0;
+        let txs = page_history(&[], &progress, |_| {
+            requests += 1;
+            Ok(page(1..26))
+        })
+        .unwrap();
+        assert_eq!((txs.len(), requests), (25, 2));
+
+        // Paging stops at a settled transaction, which comes from the store
+        let settled = page(20..26);
+        let mut pages = vec![page(1..26)].into_iter();
+        let txs = page_history(&settled, &progress, |_| Ok(pages.next().unwrap_or_default())).unwrap();
+        assert_eq!(txs.len(), 25);
+    }
+
+    #[test]
+    fn test_history_at_replays_up_to_the_height() {
+        use bdk::bitcoin::Script;
+
+        let script: Script = "51".parse().unwrap();
+        let mut funding = make_tx(0x11, Some(100));
+        funding.vout = vec![test_util::output(script.clone(), 5_000)];
+        let mut spending = make_tx(0x22, Some(200));
+        spending.vin = vec![test_util::input(funding.txid, 0, script.clone(), 5_000)];
+        let unconfirmed = make_tx(0x33, None);
+        let history = || History {
+            txs: vec![unconfirmed.clone(), spending.clone(), funding.clone()],
+            scripts: HashSet::from([query::script_hash(&script)]),
+            keychains: HashMap::new(),
+            chains: Vec::new(),
+            balance: None,
+        };
+
+        let at = |height| {
+            let history = history_at(history(), height);
+            (history.txs.len(), tally(&history).0.confirmed)
+        };
+        assert_eq!(at(99), (0, 0));
+        assert_eq!(at(150), (1, 5_000));
+        assert_eq!(at(200), (2, 0));
+    }
+
+    #[test]
+    fn test_chain_balances() {
+        use bdk::bitcoin::Script;
+
+        let scripts: Vec<Script> = ["51", "52", "53"].iter().map(|hex| hex.parse().unwrap()).collect();
+        let hashes: Vec<sha256::Hash> = scripts.iter().map(query::script_hash).collect();
+        let mut tx = make_tx(0x11, Some(100));
+        tx.vout = scripts.iter().zip([1_000, 2_000, 4_000]).map(|(script, value)| test_util::output(script.clone(), value)).collect();
+
+        let mut history = History { txs: vec![tx], scripts: hashes[..2].iter().copied().collect(), keychains: HashMap::new(), chains: Vec::new(), balance: None };
+        assert!(chain_balances(&history).is_empty());
+
+        history.keychains.insert(KeychainKind::Internal, HashSet::from([hashes[1]]));
+        assert_eq!(chain_balances(&history), vec![("Receive", 0, 0), ("Change", 2_000, 1), ("Other inputs", 1_000, 1)]);
+        history.keychains.insert(KeychainKind::External, HashSet::from([hashes[0]]));
+        assert_eq!(chain_balances(&history), vec![("Receive", 1_000, 1), ("Change", 2_000, 1)]);
+    }
+
+    #[test]
+    fn test_balance_table() {
+        let rows = vec![
+            ("tb1qshort".to_string(), BalanceInfo { confirmed: 1_500_000, unconfirmed: 0 }, 2),
+            ("tb1qmuchlongeraddress".to_string(), BalanceInfo { confirmed: 0, unconfirmed: 25_000 }, 1),
+        ];
+        let lines = balance_table(&rows, Unit::Sats);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].starts_with("Address               "));
+        assert!(lines[1].starts_with("tb1qshort             "));
+        assert!(lines[1].ends_with("1,500,000                 0         1,500,000      2"));
+        // Columns line up whatever the input length
+        assert!(lines.iter().all(|line| line.len() == lines[0].len()));
+    }
 }
@@ -0,0 +1,181 @@
+//! Incoming zero-conf payments for `--mempool-watch`: each unconfirmed
+//! transaction paying the checked scripts, with its fee rate and when it's
+//! likely to confirm
+//!
+//! The estimate compares the transaction's own fee rate with the backend's
+//! fee estimates (Esplora's `/fee-estimates`, or Electrum's `estimatefee`
+//! for the same targets): it's the first confirmation target whose estimated
+//! rate the transaction pays. A transaction with unconfirmed parents can
+//! confirm no sooner than they do, which the estimate doesn't see. A
+//! payment its sender can replace (BIP125) is marked, as it may never
+//! confirm at all.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::Txid;
+use bdk::esplora_client::Tx;
+use std::collections::{HashMap, HashSet};
+use units::Unit;
+
+use crate::{export, utxos};
+
+/// Confirmation targets asked of Electrum, in blocks; Esplora reports these
+/// and more
+pub const TARGETS: [u16; 8] = [1, 2, 3, 6, 12, 24, 144, 1008];
+
+/// Estimated fee rate in sat/vB to confirm within each target, by target
+pub type FeeEstimates = Vec<(u16, f64)>;
+
+/// Esplora's `/fee-estimates` map, keyed by target as a string, as a sorted
+/// list; keys that aren't targets are skipped
+pub fn parse_estimates(estimates: &HashMap<String, f64>) -> FeeEstimates {
+    let mut parsed: FeeEstimates = estimates
+        .iter()
+        .filter_map(|(target, rate)| Some((target.parse().ok()?, *rate)))
+        .filter(|(target, _)| *target > 0)
+        .collect();
+    parsed.sort_by_key(|(target, _)| *target);
+    parsed
+}
+
+/// One unconfirmed transaction that adds to the balance
+#[derive(Debug, Clone, PartialEq)]
+pub struct Incoming {
+    pub txid: Txid,
+    /// Net sats it adds to the checked scripts
+    pub amount: u64,
+    pub vsize: u64,
+    /// None when the backend didn't know the fee
+    pub fee_rate: Option<f64>,
+    /// Signals RBF, or spends an output of a transaction that does
+    pub replaceable: bool,
+}
+
+/// Unconfirmed transactions in `txs` with a positive net effect on
+/// `scripts`, newest first as listed
+pub fn incoming(txs: &[Tx], scripts: &HashSet<sha256::Hash>) -> Vec<Incoming> {
+    let amounts: HashMap<Txid, i64> =
+        export::history_rows(txs, scripts).iter().map(|row| (row.tx.txid, row.amount)).collect();
+    let replaceable = utxos::replaceable_pending(txs);
+    txs.iter()
+        .filter(|tx| !tx.status.confirmed)
+        .filter_map(|tx| {
+            let amount = u64::try_from(*amounts.get(&tx.txid)?).ok().filter(|amount| *amount > 0)?;
+            let vsize = (tx.to_tx().weight() as u64).div_ceil(4);
+            // Electrum leaves the fee at 0 when a parent couldn't be resolved
+            let fee_rate = (tx.fee > 0).then(|| tx.fee as f64 / vsize as f64);
+            Some(Incoming { txid: tx.txid, amount, vsize, fee_rate, replaceable: replaceable.contains(&tx.txid) })
+        })
+        .collect()
+}
+
+/// When a fee rate is likely to confirm
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Eta {
+    /// Within this many blocks
+    Within(u16),
+    /// Below the estimate for even the longest target
+    Beyond(u16),
+    Unknown,
+}
+
+pub fn eta(fee_rate: Option<f64>, estimates: &FeeEstimates) -> Eta {
+    let (Some(fee_rate), Some(&(longest, _))) = (fee_rate, estimates.last()) else {
+        return Eta::Unknown;
+    };
+    estimates
+        .iter()
+        .find(|(_, estimate)| fee_rate >= *estimate)
+        .map_or(Eta::Beyond(longest), |(target, _)| Eta::Within(*target))
+}
+
+/// Roughly how long `blocks` take at 10 minutes each
+fn duration(blocks: u16) -> String {
+    let minutes = u32::from(blocks) * 10;
+    match minutes {
+        0..=59 => format!("{} min", minutes),
+        60..=1440 => format!("{} h", minutes.div_ceil(60)),
+        _ => format!("{} days", minutes.div_ceil(1440)),
+    }
+}
+
+impl Eta {
+    pub fn describe(&self) -> String {
+        match self {
+            Eta::Within(1) => "next block (~10 min)".to_string(),
+            Eta::Within(blocks) => format!("within ~{} blocks (~{})", blocks, duration(*blocks)),
+            Eta::Beyond(blocks) => {
+                format!("not within {} blocks at current rates; the sender may need to bump the fee", blocks)
+            }
+            Eta::Unknown => "unknown".to_string(),
+        }
+    }
+}
+
+/// One line per payment: txid, amount in `unit`, fee rate and ETA, and
+/// whether it can be replaced
+pub fn format_payment(payment: &Incoming, estimates: &FeeEstimates, unit: Unit) -> String {
+    let fee_rate = payment.fee_rate.map_or("fee unknown".to_string(), |rate| format!("{:.1} sat/vB", rate));
+    format!(
+        "{}  +{}  {} ({} vB)  ETA: {}{}",
+        payment.txid,
+        unit.format(payment.amount),
+        fee_rate,
+        payment.vsize,
+        eta(payment.fee_rate, estimates).describe(),
+        if payment.replaceable { "  [replaceable: RBF]" } else { "" }
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::Script;
+    use bdk::esplora_client::Vin;
+    use crate::query::script_hash;
+    use crate::test_util::{self, input, output, p2wpkh, script, status, txid};
+
+    fn estimates() -> FeeEstimates {
+        let map: HashMap<String, f64> =
+            [("1", 20.0), ("3", 12.5), ("6", 8.0), ("144", 2.0), ("1008", 1.0)].map(|(k, v)| (k.to_string(), v)).into();
+        parse_estimates(&map)
+    }
+
+    #[test]
+    fn test_eta() {
+        assert_eq!(estimates()[0], (1, 20.0));
+        assert_eq!(eta(Some(25.0), &estimates()), Eta::Within(1));
+        assert_eq!(eta(Some(12.5), &estimates()), Eta::Within(3));
+        assert_eq!(eta(Some(3.0), &estimates()), Eta::Within(144));
+        assert_eq!(eta(Some(0.5), &estimates()), Eta::Beyond(1008));
+        assert_eq!(eta(None, &estimates()), Eta::Unknown);
+        assert_eq!(eta(Some(5.0), &Vec::new()), Eta::Unknown);
+        assert_eq!(Eta::Within(6).describe(), "within ~6 blocks (~1 h)");
+        assert_eq!(Eta::Within(144).describe(), "within ~144 blocks (~24 h)");
+        assert_eq!(Eta::Within(1008).describe(), "within ~1008 blocks (~7 days)");
+    }
+
+    #[test]
+    fn test_incoming() {
+        let ours = p2wpkh();
+        let theirs = script(0);
+        let tx = |id: u8, confirmed: bool, from: &Script, to: &Script| {
+            let vin = Vin {
+                witness: vec![vec![0; 72], vec![0; 33]],
+                sequence: 0xffff_fffd,
+                ..input(txid(0xee), u32::from(id), from.clone(), 50_000)
+            };
+            let status = status(confirmed.then_some(100), None);
+            Tx { status, fee: 1_000, ..test_util::tx(id, vec![vin], vec![output(to.clone(), 49_000)]) }
+        };
+        let scripts = HashSet::from([script_hash(&ours)]);
+        let txs = [tx(3, false, &ours, &theirs), tx(2, false, &theirs, &ours), tx(1, true, &theirs, &ours)];
+
+        // Only the unconfirmed payment in counts; a spend takes from the balance
+        let payments = incoming(&txs, &scripts);
+        assert_eq!(payments.len(), 1);
+        assert_eq!((payments[0].txid, payments[0].amount, payments[0].vsize), (txs[1].txid, 49_000, 110));
+        let line = format_payment(&payments[0], &estimates(), Unit::Sats);
+        assert!(payments[0].replaceable);
+        assert!(line.ends_with("+49,000 sats  9.1 sat/vB (110 vB)  ETA: within ~6 blocks (~1 h)  [replaceable: RBF]"), "{}", line);
+    }
+}
@@ -0,0 +1,113 @@
+//! mempool.space backend for `--backend mempool-space`: balances from the
+//! per-script chain and mempool stats, one request per address
+//!
+//! mempool.space serves the Esplora API, so anything that needs the full
+//! history (wallets, `--txs`, `--utxos`, ...) is fetched exactly as with
+//! `--backend esplora`. When only the balance is asked for, the stats
+//! endpoint already totals what was received and spent, and no transactions
+//! need to be fetched or matched up.
+
+use bdk::bitcoin::hashes::sha256;
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::chain::Chain;
+use crate::BalanceInfo;
+
+/// Returns the mempool.space API URL for the given chain; regtest has none
+pub fn default_url(chain: Chain) -> Option<&'static str> {
+    match chain {
+        Chain::Bitcoin => Some("https://mempool.space/api"),
+        Chain::Testnet => Some("https://mempool.space/testnet/api"),
+        Chain::Testnet4 => Some("https://mempool.space/testnet4/api"),
+        Chain::Signet => Some("https://mempool.space/signet/api"),
+        Chain::Regtest => None,
+    }
+}
+
+/// Totals over the outputs a script received, in confirmed or in mempool
+/// transactions
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub struct TxoStats {
+    pub funded_txo_count: u64,
+    pub funded_txo_sum: u64,
+    pub spent_txo_count: u64,
+    pub spent_txo_sum: u64,
+    pub tx_count: u64,
+}
+
+/// Response of `/scripthash/:hash`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Deserialize)]
+pub struct ScriptStats {
+    pub chain_stats: TxoStats,
+    pub mempool_stats: TxoStats,
+}
+
+impl ScriptStats {
+    /// Balance and unspent output count. Confirmed outputs spent in the
+    /// mempool no longer count as confirmed, as with the full history; the
+    /// stats don't say which outputs a mempool spend used, so any spent
+    /// beyond the confirmed balance come out of the unconfirmed one.
+    pub fn balance(&self) -> (BalanceInfo, usize) {
+        let (chain, mempool) = (&self.chain_stats, &self.mempool_stats);
+        let chain_balance = chain.funded_txo_sum.saturating_sub(chain.spent_txo_sum);
+        let total = (chain_balance + mempool.funded_txo_sum).saturating_sub(mempool.spent_txo_sum);
+        let confirmed = chain_balance.saturating_sub(mempool.spent_txo_sum);
+        let unspent = (chain.funded_txo_count + mempool.funded_txo_count)
+            .saturating_sub(chain.spent_txo_count + mempool.spent_txo_count);
+        (BalanceInfo { confirmed, unconfirmed: total - confirmed }, unspent as usize)
+    }
+}
+
+/// Combined balance of several scripts, counting a script given twice once
+pub fn combined_balance(stats: &[(sha256::Hash, ScriptStats)]) -> (BalanceInfo, usize) {
+    let unique: HashMap<&sha256::Hash, &ScriptStats> = stats.iter().map(|(hash, stats)| (hash, stats)).collect();
+    unique.values().map(|stats| stats.balance()).fold(
+        (BalanceInfo { confirmed: 0, unconfirmed: 0 }, 0),
+        |(total, count), (balance, unspent)| {
+            (
+                BalanceInfo { confirmed: total.confirmed + balance.confirmed, unconfirmed: total.unconfirmed + balance.unconfirmed },
+                count + unspent,
+            )
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::hashes::Hash;
+
+    fn stats(funded: (u64, u64), spent: (u64, u64)) -> TxoStats {
+        TxoStats {
+            funded_txo_count: funded.0,
+            funded_txo_sum: funded.1,
+            spent_txo_count: spent.0,
+            spent_txo_sum: spent.1,
+            tx_count: funded.0 + spent.0,
+        }
+    }
+
+    #[test]
+    fn test_balance_from_stats() {
+        let response = r#"{"scripthash":"ab","chain_stats":{"funded_txo_count":3,"funded_txo_sum":150000,"spent_txo_count":1,"spent_txo_sum":50000,"tx_count":4},
+            "mempool_stats":{"funded_txo_count":1,"funded_txo_sum":20000,"spent_txo_count":0,"spent_txo_sum":0,"tx_count":1}}"#;
+        let parsed: ScriptStats = serde_json::from_str(response).unwrap();
+        assert_eq!(parsed.balance(), (BalanceInfo { confirmed: 100_000, unconfirmed: 20_000 }, 3));
+
+        // Spending a confirmed output in the mempool, with change back
+        let spending = ScriptStats { chain_stats: stats((2, 100_000), (0, 0)), mempool_stats: stats((1, 30_000), (1, 60_000)) };
+        assert_eq!(spending.balance(), (BalanceInfo { confirmed: 40_000, unconfirmed: 30_000 }, 2));
+    }
+
+    #[test]
+    fn test_combined_balance_counts_repeats_once() {
+        let hash = sha256::Hash::hash(b"script");
+        let other = sha256::Hash::hash(b"other");
+        let one = ScriptStats { chain_stats: stats((1, 10_000), (0, 0)), mempool_stats: TxoStats::default() };
+        let two = ScriptStats { chain_stats: TxoStats::default(), mempool_stats: stats((1, 5_000), (0, 0)) };
+        let (balance, unspent) = combined_balance(&[(hash, one), (other, two), (hash, one)]);
+        assert_eq!(balance, BalanceInfo { confirmed: 10_000, unconfirmed: 5_000 });
+        assert_eq!(unspent, 2);
+    }
+}
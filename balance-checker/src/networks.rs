@@ -0,0 +1,109 @@
+//! Cross-network checks for `--networks`: the same inputs looked up on
+//! several chains, for funds whose network is uncertain
+//!
+//! Each input is re-encoded for each chain: an address keeps its script, and
+//! an extended key (or descriptor) its key material, so the same scripts are
+//! looked up everywhere. Test chains share testnet's encodings. A wallet
+//! that derived a different account per network (coin type 1' on the test
+//! chains, as BIP44 asks) has a different account key there, which this
+//! can't find from the mainnet one.
+
+use units::Unit;
+
+use crate::chain::Chain;
+use crate::BalanceInfo;
+
+/// One chain's line of the report
+pub struct Row {
+    pub chain: Chain,
+    /// The balance, unspent output count and transaction count, or why the
+    /// chain couldn't be checked
+    pub result: Result<(BalanceInfo, usize, usize), String>,
+}
+
+/// The chains in the order given, each once
+pub fn dedup(chains: &[Chain]) -> Vec<Chain> {
+    let mut unique = Vec::new();
+    for chain in chains {
+        if !unique.contains(chain) {
+            unique.push(*chain);
+        }
+    }
+    unique
+}
+
+/// A row per chain with amounts in `unit`, then which chains the inputs
+/// were used on
+pub fn format_report(rows: &[Row], unit: Unit) -> Vec<String> {
+    let header = |name: &str| format!("{} ({})", name, unit.label());
+    let mut lines = vec![format!(
+        "{:<8}  {:>20}  {:>20}  {:>20}  {:>5}  {:>12}",
+        "Network",
+        header("Confirmed"),
+        header("Unconfirmed"),
+        header("Total"),
+        "UTXOs",
+        "Transactions"
+    )];
+    for row in rows {
+        lines.push(match &row.result {
+            Ok((balance, utxos, txs)) => format!(
+                "{:<8}  {:>20}  {:>20}  {:>20}  {:>5}  {:>12}",
+                row.chain.name(),
+                unit.number(balance.confirmed),
+                unit.number(balance.unconfirmed),
+                unit.number(balance.total()),
+                utxos,
+                txs
+            ),
+            Err(reason) => format!("{:<8}  could not be checked: {}", row.chain.name(), reason),
+        });
+    }
+
+    let names = |used: &dyn Fn(&(BalanceInfo, usize, usize)) -> bool| {
+        rows.iter()
+            .filter(|row| row.result.as_ref().is_ok_and(used))
+            .map(|row| row.chain.name())
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+    let funded = names(&|(balance, _, _)| balance.total() > 0);
+    let emptied = names(&|(balance, _, txs)| balance.total() == 0 && *txs > 0);
+    if !funded.is_empty() {
+        lines.push(format!("Funds on: {}", funded));
+    }
+    if !emptied.is_empty() {
+        lines.push(format!("Used but empty on: {}", emptied));
+    }
+    if funded.is_empty() && emptied.is_empty() && rows.iter().any(|row| row.result.is_ok()) {
+        lines.push("No history on any network checked".to_string());
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format_report() {
+        let rows = [
+            Row { chain: Chain::Bitcoin, result: Ok((BalanceInfo { confirmed: 0, unconfirmed: 0 }, 0, 0)) },
+            Row { chain: Chain::Testnet, result: Ok((BalanceInfo { confirmed: 150_000, unconfirmed: 5_000 }, 3, 7)) },
+            Row { chain: Chain::Signet, result: Ok((BalanceInfo { confirmed: 0, unconfirmed: 0 }, 0, 2)) },
+            Row { chain: Chain::Testnet4, result: Err("connection refused".to_string()) },
+        ];
+        let lines = format_report(&rows, Unit::Sats);
+        assert_eq!(lines[0], "Network       Confirmed (sats)    Unconfirmed (sats)          Total (sats)  UTXOs  Transactions");
+        assert_eq!(lines[2], "testnet                150,000                 5,000               155,000      3             7");
+        assert_eq!(lines[4], "testnet4  could not be checked: connection refused");
+        assert_eq!(lines[5], "Funds on: testnet");
+        assert_eq!(lines[6], "Used but empty on: signet");
+
+        let unused = format_report(&rows[..1], Unit::Btc);
+        assert_eq!(unused[1], "bitcoin             0.00000000            0.00000000            0.00000000      0             0");
+        assert_eq!(unused[2], "No history on any network checked");
+        assert_eq!(format_report(&rows[3..], Unit::Sats).len(), 2);
+        assert_eq!(dedup(&[Chain::Signet, Chain::Bitcoin, Chain::Signet]), [Chain::Signet, Chain::Bitcoin]);
+    }
+}
@@ -0,0 +1,114 @@
+//! Date filter for `--since` and `--until`: the history listings keep only
+//! transactions confirmed in the period, while balances still cover the
+//! whole history
+//!
+//! A date alone covers the whole day, so `--since 2024-07-01 --until
+//! 2024-09-30` is the third quarter of 2024. Unconfirmed transactions have no
+//! block time and count as happening now.
+
+use bdk::esplora_client::Tx;
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, Utc};
+
+/// Inclusive bounds in UTC; at least one is set
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Period {
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl Period {
+    /// The period between the given times, or None if neither is given
+    pub fn new(since: Option<&str>, until: Option<&str>) -> Result<Option<Self>, String> {
+        let since = since.map(|input| parse_time(input, false)).transpose()?;
+        let until = until.map(|input| parse_time(input, true)).transpose()?;
+        if let (Some(since), Some(until)) = (since, until) {
+            if since > until {
+                return Err(format!("--since ({}) is after --until ({})", format_time(since), format_time(until)));
+            }
+        }
+        Ok((since.is_some() || until.is_some()).then_some(Period { since, until }))
+    }
+
+    /// Whether the transaction was confirmed in the period; `now` stands in
+    /// for the time of unconfirmed ones
+    pub fn contains(&self, tx: &Tx, now: u64) -> bool {
+        let time = tx.status.block_time.unwrap_or(now) as i64;
+        self.since.is_none_or(|since| time >= since.timestamp()) && self.until.is_none_or(|until| time <= until.timestamp())
+    }
+
+    pub fn describe(&self) -> String {
+        match (self.since, self.until) {
+            (Some(since), Some(until)) => format!("{} to {}", format_time(since), format_time(until)),
+            (Some(since), None) => format!("since {}", format_time(since)),
+            (None, Some(until)) => format!("until {}", format_time(until)),
+            (None, None) => "all time".to_string(),
+        }
+    }
+}
+
+fn format_time(time: DateTime<Utc>) -> String {
+    time.format("%Y-%m-%d %H:%M:%S UTC").to_string()
+}
+
+/// Parses a UTC date or time: `2024-07-01`, `2024-07-01 12:00`,
+/// `2024-07-01 12:00:00 UTC`, RFC 3339, or a unix timestamp. A date alone
+/// is the start of the day, or its last second with `end_of_day`.
+fn parse_time(input: &str, end_of_day: bool) -> Result<DateTime<Utc>, String> {
+    let input = input.trim();
+    if let Ok(seconds) = input.parse::<i64>() {
+        return DateTime::from_timestamp(seconds, 0).ok_or_else(|| format!("Timestamp out of range: {}", input));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let naive = input.strip_suffix("UTC").or_else(|| input.strip_suffix('Z')).unwrap_or(input).trim();
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(naive, format) {
+            return Ok(time.and_utc());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(naive, "%Y-%m-%d") {
+        let time = if end_of_day { NaiveTime::from_hms_opt(23, 59, 59) } else { NaiveTime::from_hms_opt(0, 0, 0) };
+        return Ok(date.and_time(time.expect("valid time of day")).and_utc());
+    }
+
+    Err(format!("Could not parse date '{}'. Use e.g. \"2024-07-01\", \"2024-07-01 12:00 UTC\", or a unix timestamp", input))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::status;
+
+    fn tx(block_time: Option<u64>) -> Tx {
+        Tx { status: status(None, block_time), ..crate::test_util::tx(0x11, vec![], vec![]) }
+    }
+
+    #[test]
+    fn test_dates_cover_whole_days() {
+        let quarter = Period::new(Some("2024-07-01"), Some("2024-09-30")).unwrap().unwrap();
+        assert_eq!(quarter.describe(), "2024-07-01 00:00:00 UTC to 2024-09-30 23:59:59 UTC");
+
+        let start = 1_719_792_000; // 2024-07-01 00:00:00 UTC
+        let end = 1_727_740_799; // 2024-09-30 23:59:59 UTC
+        let now = 1_800_000_000;
+        assert!(quarter.contains(&tx(Some(start)), now));
+        assert!(quarter.contains(&tx(Some(end)), now));
+        assert!(!quarter.contains(&tx(Some(start - 1)), now));
+        assert!(!quarter.contains(&tx(Some(end + 1)), now));
+        // Unconfirmed is now, after the quarter
+        assert!(!quarter.contains(&tx(None), now));
+        assert!(Period::new(Some("2024-07-01"), None).unwrap().unwrap().contains(&tx(None), now));
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(Period::new(None, None).unwrap(), None);
+        let since = Period::new(Some("2024-07-01 12:30 UTC"), None).unwrap().unwrap();
+        assert_eq!(since.since.unwrap().timestamp(), 1_719_837_000);
+        assert_eq!(Period::new(None, Some("1719837000")).unwrap().unwrap().until, since.since);
+        assert!(Period::new(Some("2024-10-01"), Some("2024-09-30")).is_err());
+        assert!(Period::new(Some("last quarter"), None).is_err());
+    }
+}
@@ -0,0 +1,260 @@
+//! Proof-of-reserves verification for `verify-por`: checks that whoever
+//! claims each address signed the auditor's message with its key
+//!
+//! Two signature formats are accepted, told apart by their length:
+//!
+//! - BIP-322 "simple" signatures, the signing transaction's witness, for
+//!   P2WPKH and P2TR (key path) addresses.
+//! - Legacy `signmessage` signatures, 65 bytes with a recoverable ECDSA
+//!   signature, for P2PKH addresses and, as Electrum and hardware wallets
+//!   sign them (BIP-137), P2WPKH and P2SH-P2WPKH ones.
+//!
+//! The proofs say nothing about balances; those are looked up on-chain
+//! separately, so a signature only attests what the address holds now.
+
+use base64::prelude::{Engine, BASE64_STANDARD};
+use bdk::bitcoin::blockdata::opcodes;
+use bdk::bitcoin::blockdata::script::Builder;
+use bdk::bitcoin::consensus::deserialize;
+use bdk::bitcoin::hashes::{sha256, Hash, HashEngine};
+use bdk::bitcoin::secp256k1::ecdsa::{RecoverableSignature, RecoveryId};
+use bdk::bitcoin::secp256k1::{Message, Secp256k1, XOnlyPublicKey};
+use bdk::bitcoin::util::address::AddressType;
+use bdk::bitcoin::util::misc::signed_msg_hash;
+use bdk::bitcoin::util::sighash::{Prevouts, SighashCache};
+use bdk::bitcoin::{
+    Address, EcdsaSig, EcdsaSighashType, OutPoint, PackedLockTime, PublicKey, SchnorrSig, SchnorrSighashType, Script,
+    Sequence, Transaction, TxIn, TxOut, Txid, Witness,
+};
+use std::io::{self, BufRead};
+
+/// Tag of the BIP-322 message hash
+const MESSAGE_TAG: &[u8] = b"BIP0322-signed-message";
+
+/// Length of a legacy signature: a header byte and the compact signature
+const LEGACY_LEN: usize = 65;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Scheme {
+    Bip322,
+    Legacy,
+}
+
+impl Scheme {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scheme::Bip322 => "BIP-322",
+            Scheme::Legacy => "legacy",
+        }
+    }
+}
+
+/// Reads `ADDRESS SIGNATURE` pairs, one per line; blank lines and `#`
+/// comments are skipped
+pub fn read_proofs(reader: impl BufRead) -> io::Result<Vec<(String, String)>> {
+    let mut proofs = Vec::new();
+    for (number, line) in reader.lines().enumerate() {
+        let line = line?;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        match trimmed.split_whitespace().collect::<Vec<_>>()[..] {
+            [address, signature] => proofs.push((address.to_string(), signature.to_string())),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("line {}: expected an address and a signature", number + 1),
+                ))
+            }
+        }
+    }
+    Ok(proofs)
+}
+
+/// Checks `signature` of `message` against `address`, returning the format
+/// it was in, or why it doesn't verify
+pub fn verify(address: &Address, message: &str, signature: &str) -> Result<Scheme, String> {
+    let bytes = BASE64_STANDARD.decode(signature.trim()).map_err(|_| "not base64".to_string())?;
+    if bytes.len() == LEGACY_LEN && (27..=42).contains(&bytes[0]) {
+        verify_legacy(address, message, &bytes).map(|()| Scheme::Legacy)
+    } else {
+        verify_bip322(address, message, &bytes).map(|()| Scheme::Bip322)
+    }
+}
+
+fn verify_legacy(address: &Address, message: &str, bytes: &[u8]) -> Result<(), String> {
+    let header = bytes[0] - 27;
+    let recovery_id = RecoveryId::from_i32(i32::from(header % 4)).map_err(|e| e.to_string())?;
+    let signature = RecoverableSignature::from_compact(&bytes[1..], recovery_id).map_err(|e| e.to_string())?;
+    let digest = Message::from_slice(&signed_msg_hash(message)[..]).expect("32 bytes");
+    let key = Secp256k1::verification_only()
+        .recover_ecdsa(&digest, &signature)
+        .map_err(|_| "no key recovers from this signature".to_string())?;
+    // Headers 27-30 are for uncompressed keys
+    let key = PublicKey { compressed: header >= 4, inner: key };
+
+    let signer = match address.address_type() {
+        Some(AddressType::P2pkh) => Some(Address::p2pkh(&key, address.network)),
+        Some(AddressType::P2wpkh) => Address::p2wpkh(&key, address.network).ok(),
+        Some(AddressType::P2sh) => Address::p2shwpkh(&key, address.network).ok(),
+        _ => return Err(format!("legacy signatures don't cover {} addresses", type_name(address))),
+    };
+    match signer {
+        Some(signer) if signer == *address => Ok(()),
+        _ => Err("signed by a different key".to_string()),
+    }
+}
+
+fn verify_bip322(address: &Address, message: &str, bytes: &[u8]) -> Result<(), String> {
+    let witness: Witness = deserialize(bytes).map_err(|_| "neither a legacy signature nor a BIP-322 witness".to_string())?;
+    let script_pubkey = address.script_pubkey();
+    let to_sign = to_sign(&to_spend(&script_pubkey, message), witness.clone());
+    let secp = Secp256k1::verification_only();
+
+    match address.address_type() {
+        Some(AddressType::P2wpkh) => {
+            let [signature, key] = witness.to_vec().try_into().map_err(|_| "expected a signature and a key")?;
+            let signature = EcdsaSig::from_slice(&signature).map_err(|e| e.to_string())?;
+            let key = PublicKey::from_slice(&key).map_err(|e| e.to_string())?;
+            if signature.hash_ty != EcdsaSighashType::All {
+                return Err("BIP-322 signatures must sign with SIGHASH_ALL".to_string());
+            }
+            if key.wpubkey_hash().map(|hash| Script::new_v0_p2wpkh(&hash)) != Some(script_pubkey) {
+                return Err("signed by a different key".to_string());
+            }
+            let sighash = SighashCache::new(&to_sign)
+                .segwit_signature_hash(0, &Script::new_p2pkh(&key.pubkey_hash()), 0, EcdsaSighashType::All)
+                .map_err(|e| e.to_string())?;
+            let digest = Message::from_slice(&sighash[..]).expect("32 bytes");
+            secp.verify_ecdsa(&digest, &signature.sig, &key.inner).map_err(|_| "invalid signature".to_string())
+        }
+        Some(AddressType::P2tr) => {
+            let [signature] = witness.to_vec().try_into().map_err(|_| "expected a single key-path signature")?;
+            let signature = SchnorrSig::from_slice(&signature).map_err(|e| e.to_string())?;
+            if !matches!(signature.hash_ty, SchnorrSighashType::Default | SchnorrSighashType::All) {
+                return Err("BIP-322 signatures must sign with SIGHASH_DEFAULT or SIGHASH_ALL".to_string());
+            }
+            let key = XOnlyPublicKey::from_slice(&script_pubkey[2..]).map_err(|e| e.to_string())?;
+            let spent = [TxOut { value: 0, script_pubkey }];
+            let sighash = SighashCache::new(&to_sign)
+                .taproot_key_spend_signature_hash(0, &Prevouts::All(&spent), signature.hash_ty)
+                .map_err(|e| e.to_string())?;
+            let digest = Message::from_slice(&sighash[..]).expect("32 bytes");
+            secp.verify_schnorr(&signature.sig, &digest, &key).map_err(|_| "invalid signature".to_string())
+        }
+        _ => Err(format!("BIP-322 simple signatures aren't supported for {} addresses", type_name(address))),
+    }
+}
+
+fn type_name(address: &Address) -> String {
+    address.address_type().map_or("non-standard".to_string(), |kind| kind.to_string().to_uppercase())
+}
+
+/// BIP-322's tagged hash of the message
+fn message_hash(message: &str) -> sha256::Hash {
+    let tag = sha256::Hash::hash(MESSAGE_TAG);
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag[..]);
+    engine.input(&tag[..]);
+    engine.input(message.as_bytes());
+    sha256::Hash::from_engine(engine)
+}
+
+/// The virtual transaction paying the address, committing to the message
+fn to_spend(script_pubkey: &Script, message: &str) -> Transaction {
+    Transaction {
+        version: 0,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint { txid: Txid::all_zeros(), vout: u32::MAX },
+            script_sig: Builder::new()
+                .push_opcode(opcodes::OP_FALSE)
+                .push_slice(&message_hash(message)[..])
+                .into_script(),
+            sequence: Sequence::ZERO,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut { value: 0, script_pubkey: script_pubkey.clone() }],
+    }
+}
+
+/// The virtual transaction spending `to_spend`, signed by `witness`
+fn to_sign(to_spend: &Transaction, witness: Witness) -> Transaction {
+    Transaction {
+        version: 0,
+        lock_time: PackedLockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: OutPoint { txid: to_spend.txid(), vout: 0 },
+            script_sig: Script::new(),
+            sequence: Sequence::ZERO,
+            witness,
+        }],
+        output: vec![TxOut { value: 0, script_pubkey: Builder::new().push_opcode(opcodes::all::OP_RETURN).into_script() }],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::secp256k1::SecretKey;
+    use bdk::bitcoin::Network;
+    use std::str::FromStr;
+
+    // Test vectors from BIP-322
+    const P2WPKH: &str = "bc1q9vza2e8x573nczrlzms0wvx3gsqjx7vavgkx0l";
+    const P2TR: &str = "bc1ppv609nr0vr25u07u95waq5lucwfm6tde4nydujnu8npg4q75mr5sxq8lt3";
+
+    #[test]
+    fn test_message_hash() {
+        assert_eq!(
+            message_hash("Hello World").to_string(),
+            "f0eb03b1a75ac6d9847f55c624a99169b5dccba2a31f5b23bea77ba270de0a7a"
+        );
+    }
+
+    #[test]
+    fn test_verify_bip322() {
+        let address = Address::from_str(P2WPKH).unwrap();
+        let empty = "AkcwRAIgM2gBAQqvZX15ZiysmKmQpDrG83avLIT492QBzLnQIxYCIBaTpOaD20qRlEylyxFSeEA2ba9YOixpX8z46TSDtS40ASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        let hello = "AkcwRAIgZRfIY3p7/DoVTty6YZbWS71bc5Vct9p9Fia83eRmw2QCICK/ENGfwLtptFluMGs2KsqoNSk89pO7F29zJLUx9a/sASECx/EgAxlkQpQ9hYjgGu6EBCPMVPwVIVJqO4XCsMvViHI=";
+        assert_eq!(verify(&address, "", empty), Ok(Scheme::Bip322));
+        assert_eq!(verify(&address, "Hello World", hello), Ok(Scheme::Bip322));
+        assert_eq!(verify(&address, "Hello World", empty), Err("invalid signature".to_string()));
+
+        let taproot = Address::from_str(P2TR).unwrap();
+        let signature = "AUHd69PrJQEv+oKTfZ8l+WROBHuy9HKrbFCJu7U1iK2iiEy1vMU5EfMtjc+VSHM7aU0SDbak5IUZRVno2P5mjSafAQ==";
+        assert_eq!(verify(&taproot, "Hello World", signature), Ok(Scheme::Bip322));
+        assert!(verify(&taproot, "Goodbye", signature).is_err());
+    }
+
+    #[test]
+    fn test_verify_legacy() {
+        let secp = Secp256k1::new();
+        let secret = SecretKey::from_slice(&[7; 32]).unwrap();
+        let key = PublicKey::new(secret.public_key(&secp));
+        let digest = Message::from_slice(&signed_msg_hash("reserves at block 800000")[..]).unwrap();
+        let (recovery_id, compact) = secp.sign_ecdsa_recoverable(&digest, &secret).serialize_compact();
+        let sign = |header: u8| {
+            let mut bytes = vec![header + recovery_id.to_i32() as u8];
+            bytes.extend_from_slice(&compact);
+            BASE64_STANDARD.encode(bytes)
+        };
+
+        let p2pkh = Address::p2pkh(&key, Network::Bitcoin);
+        assert_eq!(verify(&p2pkh, "reserves at block 800000", &sign(31)), Ok(Scheme::Legacy));
+        assert_eq!(verify(&p2pkh, "reserves at block 800001", &sign(31)), Err("signed by a different key".to_string()));
+        // BIP-137's segwit header
+        let p2wpkh = Address::p2wpkh(&key, Network::Bitcoin).unwrap();
+        assert_eq!(verify(&p2wpkh, "reserves at block 800000", &sign(39)), Ok(Scheme::Legacy));
+        assert!(verify(&Address::from_str(P2TR).unwrap(), "reserves at block 800000", &sign(31)).is_err());
+    }
+
+    #[test]
+    fn test_read_proofs() {
+        let input = "# custodian cold wallet\nbc1qexample  SIG1=\n\n1Example\tSIG2\n";
+        let proofs = read_proofs(input.as_bytes()).unwrap();
+        assert_eq!(proofs, vec![("bc1qexample".to_string(), "SIG1=".to_string()), ("1Example".to_string(), "SIG2".to_string())]);
+        assert!(read_proofs("bc1qexample\n".as_bytes()).is_err());
+    }
+}
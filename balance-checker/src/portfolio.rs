@@ -0,0 +1,221 @@
+//! Portfolio reports for the `portfolio` subcommand: several wallets, each
+//! one or more addresses, extended keys or descriptors under a label, read
+//! from a TOML or JSON file and reported side by side with a grand total
+//!
+//! ```toml
+//! currency = "eur"
+//!
+//! [[wallets]]
+//! label = "Cold storage"
+//! inputs = ["wpkh([73c5da0a/84'/0'/0']xpub.../<0;1>/*)"]
+//!
+//! [[wallets]]
+//! label = "Donations"
+//! inputs = ["bc1q...", "bc1p..."]
+//! ```
+//!
+//! A path ending in `.json` is read as JSON with the same fields. The grand
+//! total merges every wallet's history, so an input listed in two wallets
+//! counts once there.
+
+use serde::Deserialize;
+use std::collections::HashSet;
+use std::path::Path;
+use units::Unit;
+
+use crate::fiat::{Currency, Quote};
+use crate::BalanceInfo;
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Portfolio {
+    /// Fiat currency of the report, unless `--fiat` names one
+    #[serde(default)]
+    pub currency: Option<String>,
+    pub wallets: Vec<WalletEntry>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WalletEntry {
+    pub label: String,
+    pub inputs: Vec<String>,
+}
+
+impl Portfolio {
+    /// The file's currency, if it names one
+    pub fn currency(&self) -> Result<Option<Currency>, String> {
+        use clap::ValueEnum;
+        self.currency
+            .as_deref()
+            .map(|code| {
+                Currency::from_str(code, true)
+                    .map_err(|_| format!("Unknown currency '{}'; expected usd, eur or gbp", code))
+            })
+            .transpose()
+    }
+}
+
+/// Parses a portfolio file's contents, as JSON if `path` ends in `.json`
+/// and TOML otherwise
+pub fn parse(path: &Path, text: &str) -> Result<Portfolio, String> {
+    let portfolio: Portfolio = if path.extension().is_some_and(|extension| extension.eq_ignore_ascii_case("json")) {
+        serde_json::from_str(text).map_err(|e| e.to_string())?
+    } else {
+        toml::from_str(text).map_err(|e| e.to_string().trim_end().to_string())?
+    };
+    if portfolio.wallets.is_empty() {
+        return Err("No wallets listed".to_string());
+    }
+    let mut labels = HashSet::new();
+    for wallet in &portfolio.wallets {
+        if wallet.inputs.is_empty() {
+            return Err(format!("Wallet '{}' has no inputs", wallet.label));
+        }
+        if !labels.insert(wallet.label.as_str()) {
+            return Err(format!("Wallet '{}' is listed twice", wallet.label));
+        }
+    }
+    portfolio.currency()?;
+    Ok(portfolio)
+}
+
+/// One wallet's line of the report
+pub struct Row<'a> {
+    pub label: &'a str,
+    pub inputs: usize,
+    /// The balance and unspent output count, or why it couldn't be checked
+    pub result: Result<(BalanceInfo, usize), String>,
+}
+
+/// The report's table: a row per wallet and the grand total in `unit`, with
+/// each wallet's share of it and, given a quote, its fiat value
+pub fn format_report(rows: &[Row], total: &BalanceInfo, total_utxos: usize, quote: Option<&Quote>, unit: Unit) -> Vec<String> {
+    let width = rows.iter().map(|row| row.label.len()).max().unwrap_or(0).max("Wallet".len());
+    let fiat_header = quote.map_or(String::new(), |quote| format!("  {:>14}", quote.currency.code()));
+    let fiat = |sats: u64| quote.map_or(String::new(), |quote| format!("  {:>14.2}", quote.value_of(sats)));
+    let header = |name: &str| format!("{} ({})", name, unit.label());
+    let mut lines = vec![format!(
+        "{:<width$}  {:>6}  {:>20}  {:>20}  {:>20}{}  {:>6}  {:>5}",
+        "Wallet",
+        "Inputs",
+        header("Confirmed"),
+        header("Unconfirmed"),
+        header("Total"),
+        fiat_header,
+        "Share",
+        "UTXOs",
+        width = width
+    )];
+    for row in rows {
+        lines.push(match &row.result {
+            Ok((balance, utxos)) => format!(
+                "{:<width$}  {:>6}  {:>20}  {:>20}  {:>20}{}  {:>5.1}%  {:>5}",
+                row.label,
+                row.inputs,
+                unit.number(balance.confirmed),
+                unit.number(balance.unconfirmed),
+                unit.number(balance.total()),
+                fiat(balance.total()),
+                if total.total() == 0 { 0.0 } else { balance.total() as f64 / total.total() as f64 * 100.0 },
+                utxos,
+                width = width
+            ),
+            Err(reason) => format!("{:<width$}  {:>6}  could not be checked: {}", row.label, row.inputs, reason, width = width),
+        });
+    }
+    lines.push(format!(
+        "{:<width$}  {:>6}  {:>20}  {:>20}  {:>20}{}  {:>5.1}%  {:>5}",
+        "Total",
+        rows.iter().map(|row| row.inputs).sum::<usize>(),
+        unit.number(total.confirmed),
+        unit.number(total.unconfirmed),
+        unit.number(total.total()),
+        fiat(total.total()),
+        if total.total() == 0 { 0.0 } else { 100.0 },
+        total_utxos,
+        width = width
+    ));
+
+    let summed: u64 = rows.iter().filter_map(|row| row.result.as_ref().ok()).map(|(balance, _)| balance.total()).sum();
+    if summed != total.total() {
+        lines.push("Note: Some inputs are in more than one wallet; the total counts their funds once".to_string());
+    }
+    if let Some(quote) = quote {
+        lines.push(format!(
+            "Rate: {:.2} {}/BTC ({}, {})",
+            quote.rate,
+            quote.currency.code(),
+            quote.provider.name(),
+            quote.time.format("%Y-%m-%d %H:%M:%S UTC")
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fiat::PriceProvider;
+    use chrono::DateTime;
+
+    const TOML: &str = r#"
+currency = "EUR"
+
+[[wallets]]
+label = "Cold storage"
+inputs = ["wpkh(tpub.../0/*)"]
+
+[[wallets]]
+label = "Donations"
+inputs = ["tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx", "tb1q..."]
+"#;
+
+    #[test]
+    fn test_parse() {
+        let portfolio = parse(Path::new("portfolio.toml"), TOML).unwrap();
+        assert_eq!(portfolio.wallets.len(), 2);
+        assert_eq!(portfolio.wallets[1].inputs.len(), 2);
+        assert_eq!(portfolio.currency(), Ok(Some(Currency::Eur)));
+
+        let json = r#"{ "wallets": [{ "label": "Hot", "inputs": ["tb1q..."] }] }"#;
+        assert_eq!(parse(Path::new("p.JSON"), json).unwrap().currency, None);
+
+        assert!(parse(Path::new("p.toml"), "[[wallets]]\nlabel = \"Hot\"\ninputs = []").unwrap_err().contains("no inputs"));
+        assert!(parse(Path::new("p.toml"), "wallets = []").unwrap_err().contains("No wallets"));
+        assert!(parse(Path::new("p.toml"), "[[wallets]]\nname = \"Hot\"").is_err());
+        assert!(parse(Path::new("p.json"), r#"{ "currency": "jpy", "wallets": [{ "label": "a", "inputs": ["x"] }] }"#).is_err());
+    }
+
+    #[test]
+    fn test_format_report() {
+        let rows = [
+            Row { label: "Cold storage", inputs: 1, result: Ok((BalanceInfo { confirmed: 75_000_000, unconfirmed: 0 }, 3)) },
+            Row { label: "Donations", inputs: 2, result: Ok((BalanceInfo { confirmed: 20_000_000, unconfirmed: 5_000_000 }, 4)) },
+            Row { label: "Hot", inputs: 1, result: Err("connection refused".to_string()) },
+        ];
+        let total = BalanceInfo { confirmed: 95_000_000, unconfirmed: 5_000_000 };
+        let quote = Quote {
+            currency: Currency::Eur,
+            rate: 50_000.0,
+            provider: PriceProvider::Kraken,
+            time: DateTime::from_timestamp(1_700_000_000, 0).unwrap(),
+        };
+        let lines = format_report(&rows, &total, 7, Some(&quote), Unit::Btc);
+
+        assert!(lines[0].starts_with("Wallet        Inputs"));
+        assert!(lines[0].contains("   Total (BTC)             EUR   Share  UTXOs"));
+        assert!(lines[1].ends_with("0.75000000        37500.00   75.0%      3"));
+        assert!(lines[2].contains("  0.20000000            0.05000000"));
+        assert_eq!(lines[3], "Hot                1  could not be checked: connection refused");
+        assert!(lines[4].starts_with("Total              4"));
+        assert!(lines[4].ends_with("1.00000000        50000.00  100.0%      7"));
+        assert_eq!(lines[5], "Rate: 50000.00 EUR/BTC (Kraken, 2023-11-14 22:13:20 UTC)");
+
+        // Without a quote, the fiat column is left out
+        let overlapping = format_report(&rows[..2], &BalanceInfo { confirmed: 80_000_000, unconfirmed: 0 }, 5, None, Unit::Sats);
+        assert!(!overlapping[0].contains("EUR") && overlapping[0].contains("Confirmed (sats)"));
+        assert!(overlapping[3].ends_with("80,000,000  100.0%      5"));
+        assert!(overlapping[4].starts_with("Note: Some inputs are in more than one wallet"));
+    }
+}
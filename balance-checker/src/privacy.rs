@@ -0,0 +1,240 @@
+//! Privacy report for `--privacy-report`: which unspent outputs chain
+//! analysis can already tie together or to their owner, and what to do
+//! about it
+//!
+//! Three common heuristics are checked against the history:
+//!
+//! - Address reuse: an output at an address that received more than once is
+//!   linked to every other payment to it.
+//! - Common-input ownership: inputs spent together are assumed to have one
+//!   owner, so an output at an address that was once spent alongside
+//!   another of the checked addresses is linked to it.
+//! - Round amounts: when a spend paid a round amount (a whole multiple of
+//!   10,000 sats) and its change isn't round, the change is easy to tell
+//!   apart from the payment.
+//!
+//! The score is the share of unspent outputs none of these flag.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::esplora_client::Tx;
+use std::collections::{HashMap, HashSet};
+use units::Unit;
+
+use crate::query::script_hash;
+use crate::reuse;
+use crate::utxos::{self, Utxo};
+
+/// Amounts that are a whole multiple of this many sats (four decimal places
+/// in BTC) count as round
+const ROUND_UNIT: u64 = 10_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Flag {
+    Reused,
+    Linked,
+    RoundChange,
+}
+
+impl Flag {
+    fn name(&self) -> &'static str {
+        match self {
+            Flag::Reused => "reused address",
+            Flag::Linked => "linked by inputs",
+            Flag::RoundChange => "change of a round payment",
+        }
+    }
+
+    fn advice(&self) -> &'static str {
+        match self {
+            Flag::Reused => "Give out a fresh address for every payment; outputs at a reused address are linked already, so spend them together rather than alongside others",
+            Flag::Linked => "Choose inputs with coin control so each spend draws on one address, or on addresses already linked, instead of merging unrelated ones",
+            Flag::RoundChange => "Avoid paying round amounts from this wallet, or pay a little more, so the change looks like a payment too",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Report {
+    /// Each unspent output with what gives it away, largest first
+    pub outputs: Vec<(Utxo, Vec<Flag>)>,
+    /// Past spends that merged inputs from more than one checked address
+    pub merging_spends: usize,
+}
+
+impl Report {
+    /// Share of unspent outputs with no flags, out of 100
+    pub fn score(&self) -> Option<u32> {
+        let clean = self.outputs.iter().filter(|(_, flags)| flags.is_empty()).count();
+        (!self.outputs.is_empty()).then(|| (clean * 100 / self.outputs.len()) as u32)
+    }
+}
+
+fn is_round(value: u64) -> bool {
+    value > 0 && value.is_multiple_of(ROUND_UNIT)
+}
+
+/// Runs the heuristics over the history of `scripts`
+pub fn analyze(txs: &[Tx], scripts: &HashSet<sha256::Hash>) -> Report {
+    let ours = |script: &bdk::bitcoin::Script| scripts.contains(&script_hash(script));
+
+    let (reused, _) = reuse::find_reuse(txs, scripts);
+    let reused: HashSet<sha256::Hash> = reused.iter().map(|reuse| script_hash(&reuse.script)).collect();
+
+    // Addresses spent together, grouped; each maps to its group's first one
+    let mut group: HashMap<sha256::Hash, sha256::Hash> = HashMap::new();
+    let mut merging_spends = 0;
+    let mut round_change: HashSet<(bdk::bitcoin::Txid, u32)> = HashSet::new();
+    for tx in txs {
+        let mut inputs: Vec<sha256::Hash> = tx
+            .vin
+            .iter()
+            .filter_map(|input| input.prevout.as_ref())
+            .filter(|prevout| ours(&prevout.scriptpubkey))
+            .map(|prevout| script_hash(&prevout.scriptpubkey))
+            .collect();
+        if inputs.is_empty() {
+            continue;
+        }
+        inputs.sort();
+        inputs.dedup();
+        if inputs.len() > 1 {
+            merging_spends += 1;
+            let roots: HashSet<sha256::Hash> = inputs.iter().map(|hash| root(&group, *hash)).collect();
+            let target = *roots.iter().min().expect("at least two inputs");
+            for root_hash in group.values_mut().filter(|root_hash| roots.contains(*root_hash)) {
+                *root_hash = target;
+            }
+            for hash in inputs {
+                group.insert(hash, target);
+            }
+        }
+
+        let round_payment = tx.vout.iter().any(|output| !ours(&output.scriptpubkey) && is_round(output.value));
+        if round_payment {
+            for (vout, output) in tx.vout.iter().enumerate() {
+                if ours(&output.scriptpubkey) && !is_round(output.value) {
+                    round_change.insert((tx.txid, vout as u32));
+                }
+            }
+        }
+    }
+
+    let outputs = utxos::unspent_outputs(txs, scripts)
+        .into_iter()
+        .map(|utxo| {
+            let hash = script_hash(&utxo.script);
+            let mut flags = Vec::new();
+            if reused.contains(&hash) {
+                flags.push(Flag::Reused);
+            }
+            if group.contains_key(&hash) {
+                flags.push(Flag::Linked);
+            }
+            if round_change.contains(&(utxo.txid, utxo.vout)) {
+                flags.push(Flag::RoundChange);
+            }
+            (utxo, flags)
+        })
+        .collect();
+    Report { outputs, merging_spends }
+}
+
+/// The group an address belongs to, or itself when it was never merged
+fn root(group: &HashMap<sha256::Hash, sha256::Hash>, hash: sha256::Hash) -> sha256::Hash {
+    group.get(&hash).copied().unwrap_or(hash)
+}
+
+/// A table of the unspent outputs in `unit` and their flags, then the score
+/// and one note per kind of flag found
+pub fn format_report(report: &Report, unit: Unit) -> Vec<String> {
+    let Some(score) = report.score() else {
+        return vec!["No unspent outputs".to_string()];
+    };
+    let mut lines = vec![format!("{:<70}  {:>20}  {}", "Outpoint", format!("Value ({})", unit.label()), "Flags")];
+    for (utxo, flags) in &report.outputs {
+        let names: Vec<&str> = flags.iter().map(Flag::name).collect();
+        lines.push(format!(
+            "{:<70}  {:>20}  {}",
+            format!("{}:{}", utxo.txid, utxo.vout),
+            unit.number(utxo.value),
+            if names.is_empty() { "-".to_string() } else { names.join(", ") }
+        ));
+    }
+
+    let rating = match score {
+        80.. => "good",
+        50..=79 => "fair",
+        _ => "poor",
+    };
+    let clean = report.outputs.iter().filter(|(_, flags)| flags.is_empty()).count();
+    lines.push(format!("Score:       {}/100 ({}; {} of {} UTXOs unflagged)", score, rating, clean, report.outputs.len()));
+    if report.merging_spends > 0 {
+        let spends = if report.merging_spends == 1 { "spend" } else { "spends" };
+        lines.push(format!("Merged:      {} past {} combined inputs from several addresses", report.merging_spends, spends));
+    }
+
+    let mut found: Vec<Flag> = report.outputs.iter().flat_map(|(_, flags)| flags.iter().copied()).collect();
+    found.sort();
+    found.dedup();
+    for flag in found {
+        let count = report.outputs.iter().filter(|(_, flags)| flags.contains(&flag)).count();
+        let outputs = if count == 1 { "UTXO" } else { "UTXOs" };
+        lines.push(format!("Note:        {} {} flagged '{}'. {}", count, outputs, flag.name(), flag.advice()));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{input, output, script, status, txid};
+
+    /// Inputs spend (txid, vout, owner, value); outputs pay (owner, value)
+    fn tx(id: u8, height: u32, inputs: &[(u8, u32, u8, u64)], outputs: &[(u8, u64)]) -> Tx {
+        let vin = inputs.iter().map(|&(prev, vout, owner, value)| input(txid(prev), vout, script(owner), value)).collect();
+        let vout = outputs.iter().map(|&(owner, value)| output(script(owner), value)).collect();
+        Tx { status: status(Some(height), None), ..crate::test_util::tx(id, vin, vout) }
+    }
+
+    #[test]
+    fn test_analyze_flags_each_heuristic() {
+        let scripts: HashSet<sha256::Hash> = [1, 2, 3, 4, 5].iter().map(|&byte| script_hash(&script(byte))).collect();
+        let txs = vec![
+            tx(1, 100, &[], &[(1, 40_123), (2, 30_456), (3, 25_000), (4, 12_345)]),
+            // Merges addresses 1 and 2, pays a round 50,000 and sends change to 5
+            tx(2, 110, &[(1, 0, 1, 40_123), (1, 1, 2, 30_456)], &[(9, 50_000), (5, 20_000 - 421)]),
+            // Address 3 receives again
+            tx(3, 120, &[], &[(3, 7_777)]),
+            // Address 1 receives again after it was spent with 2
+            tx(4, 130, &[], &[(1, 8_000)]),
+        ];
+
+        let report = analyze(&txs, &scripts);
+        assert_eq!(report.merging_spends, 1);
+        let flags: Vec<(u64, Vec<Flag>)> = report.outputs.iter().map(|(utxo, flags)| (utxo.value, flags.clone())).collect();
+        assert_eq!(
+            flags,
+            vec![
+                (25_000, vec![Flag::Reused]),
+                (19_579, vec![Flag::RoundChange]),
+                (12_345, vec![]),
+                (8_000, vec![Flag::Reused, Flag::Linked]),
+                (7_777, vec![Flag::Reused]),
+            ]
+        );
+        assert_eq!(report.score(), Some(20));
+    }
+
+    #[test]
+    fn test_format_report() {
+        let scripts = HashSet::from([script_hash(&script(1))]);
+        let txs = vec![tx(1, 100, &[], &[(1, 12_345)])];
+        let lines = format_report(&analyze(&txs, &scripts), Unit::Sats);
+        assert!(lines[0].contains("Value (sats)"));
+        assert!(lines[1].ends_with("12,345  -"));
+        assert_eq!(lines[2], "Score:       100/100 (good; 1 of 1 UTXOs unflagged)");
+        assert_eq!(lines.len(), 3);
+
+        assert_eq!(format_report(&analyze(&[], &scripts), Unit::Sats), vec!["No unspent outputs"]);
+    }
+}
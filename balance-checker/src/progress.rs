@@ -0,0 +1,146 @@
+//! Status line on stderr while a target's history is fetched: scripts
+//! scanned, transactions and pages fetched, the derivation index a wallet
+//! scan has reached, and how far block filters have been matched or blocks
+//! scanned
+//!
+//! The counters are shared by the `--concurrency` threads. The line is
+//! hidden entirely when stderr is not a terminal so logs and pipes stay
+//! clean.
+
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::IsTerminal;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+const TEMPLATE: &str = "{spinner} {msg} · {elapsed}";
+
+pub struct Progress {
+    bar: ProgressBar,
+    scripts: AtomicU64,
+    txs: AtomicU64,
+    pages: AtomicU64,
+    /// Chain being scanned and the highest index derived on it
+    index: Mutex<Option<(String, u32)>>,
+    /// Height block filters were matched up to, and the tip
+    filters: Mutex<Option<(u32, u32)>>,
+    /// Height blocks were scanned up to, and the tip
+    blocks: Mutex<Option<(u32, u32)>>,
+}
+
+impl Progress {
+    /// A status line, hidden unless `enabled` and stderr is a terminal
+    pub fn new(enabled: bool) -> Self {
+        let bar = if enabled && std::io::stderr().is_terminal() {
+            let bar = ProgressBar::with_draw_target(None, ProgressDrawTarget::stderr());
+            bar.set_style(ProgressStyle::with_template(TEMPLATE).expect("template is valid"));
+            bar.enable_steady_tick(Duration::from_millis(100));
+            bar
+        } else {
+            ProgressBar::hidden()
+        };
+        let progress = Progress {
+            bar,
+            scripts: AtomicU64::new(0),
+            txs: AtomicU64::new(0),
+            pages: AtomicU64::new(0),
+            index: Mutex::new(None),
+            filters: Mutex::new(None),
+            blocks: Mutex::new(None),
+        };
+        progress.bar.set_message(progress.status());
+        progress
+    }
+
+    /// Scripts up to `index` on the chain labelled `chain` are being fetched
+    pub fn derived(&self, chain: &str, index: u32) {
+        *self.index.lock().unwrap() = Some((chain.to_string(), index));
+        self.bar.set_message(self.status());
+    }
+
+    /// Block filters up to `height` of `tip` were matched
+    pub fn filters(&self, height: u32, tip: u32) {
+        *self.filters.lock().unwrap() = Some((height, tip));
+        self.bar.set_message(self.status());
+    }
+
+    /// Blocks up to `height` of `tip` were scanned
+    pub fn blocks(&self, height: u32, tip: u32) {
+        *self.blocks.lock().unwrap() = Some((height, tip));
+        self.bar.set_message(self.status());
+    }
+
+    /// One script's history is complete
+    pub fn script_done(&self) {
+        self.scripts.fetch_add(1, Ordering::Relaxed);
+        self.bar.set_message(self.status());
+    }
+
+    /// A page of `txs` transactions arrived
+    pub fn page(&self, txs: usize) {
+        self.pages.fetch_add(1, Ordering::Relaxed);
+        self.txs.fetch_add(txs as u64, Ordering::Relaxed);
+        self.bar.set_message(self.status());
+    }
+
+    /// Clears the line, so what's printed next starts clean
+    pub fn finish(&self) {
+        self.bar.finish_and_clear();
+    }
+
+    fn status(&self) -> String {
+        let scripts = self.scripts.load(Ordering::Relaxed);
+        let pages = self.pages.load(Ordering::Relaxed);
+        let mut line = format!(
+            "{} {} scanned, {} txs fetched ({} {})",
+            scripts,
+            if scripts == 1 { "script" } else { "scripts" },
+            self.txs.load(Ordering::Relaxed),
+            pages,
+            if pages == 1 { "page" } else { "pages" }
+        );
+        if let Some((chain, index)) = &*self.index.lock().unwrap() {
+            line.push_str(&format!(", {} at index {}", chain, index));
+        }
+        if let Some((height, tip)) = *self.filters.lock().unwrap() {
+            line.push_str(&format!(", block filters to height {} of {}", height, tip));
+        }
+        if let Some((height, tip)) = *self.blocks.lock().unwrap() {
+            line.push_str(&format!(", blocks to height {} of {}", height, tip));
+        }
+        line
+    }
+}
+
+impl Drop for Progress {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_status_line() {
+        let progress = Progress::new(false);
+        assert_eq!(progress.status(), "0 scripts scanned, 0 txs fetched (0 pages)");
+
+        progress.derived("Wpkh receive", 19);
+        progress.page(25);
+        progress.page(3);
+        progress.script_done();
+        assert_eq!(progress.status(), "1 script scanned, 28 txs fetched (2 pages), Wpkh receive at index 19");
+        assert!(progress.bar.is_hidden());
+
+        progress.derived("Wpkh change", 4);
+        assert!(progress.status().ends_with("Wpkh change at index 4"));
+
+        progress.filters(1_000, 2_500);
+        assert!(progress.status().ends_with("Wpkh change at index 4, block filters to height 1000 of 2500"));
+
+        progress.blocks(2_400, 2_500);
+        assert!(progress.status().ends_with("of 2500, blocks to height 2400 of 2500"));
+    }
+}
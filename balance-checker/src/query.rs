@@ -0,0 +1,291 @@
+//! Parsing of the thing being checked: an address, a raw scriptPubKey, an
+//! Electrum-style script hash, a wallet, or a silent payment address
+//!
+//! Esplora indexes history by the SHA256 of the scriptPubKey, so every query
+//! is reduced to that hash (or, for wallets, a set of them) before fetching.
+
+use bdk::bitcoin::hashes::hex::{FromHex, ToHex};
+use bdk::bitcoin::hashes::{sha256, Hash};
+use bdk::bitcoin::util::address;
+use bdk::bitcoin::{Address, Network, Script};
+use std::str::FromStr;
+
+use crate::silentpayments::{self, Receiver};
+use crate::wallet::{self, Wallet};
+
+/// Length of a script hash in hex characters
+const SCRIPT_HASH_HEX_LEN: usize = 64;
+
+/// Prefixes naming what a hex input is, for when its length can't
+const SCRIPT_PREFIX: &str = "script:";
+const SCRIPT_HASH_PREFIX: &str = "scripthash:";
+
+/// What the user asked to check
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum QueryTarget {
+    Address(Address),
+    Script(Script),
+    /// SHA256 of the scriptPubKey, stored in forward byte order
+    ScriptHash(sha256::Hash),
+    /// Scripts derived from descriptors, found by scanning
+    Wallet(Wallet),
+    /// Outputs paid to a silent payment address, found by scanning blocks
+    SilentPayment(Box<Receiver>),
+}
+
+impl QueryTarget {
+    /// The hash of the target's scriptPubKey; wallets have many
+    pub fn script_hash(&self) -> Option<sha256::Hash> {
+        match self {
+            QueryTarget::Address(address) => Some(script_hash(&address.script_pubkey())),
+            QueryTarget::Script(script) => Some(script_hash(script)),
+            QueryTarget::ScriptHash(hash) => Some(*hash),
+            QueryTarget::Wallet(_) | QueryTarget::SilentPayment(_) => None,
+        }
+    }
+
+    /// The scriptPubKey itself, unknown when only its hash was given
+    pub fn script_pubkey(&self) -> Option<Script> {
+        match self {
+            QueryTarget::Address(address) => Some(address.script_pubkey()),
+            QueryTarget::Script(script) => Some(script.clone()),
+            QueryTarget::ScriptHash(_) | QueryTarget::Wallet(_) | QueryTarget::SilentPayment(_) => None,
+        }
+    }
+
+    /// The target on another network: addresses and extended keys encoded
+    /// for it, for the same scripts. Scripts and script hashes are the same
+    /// everywhere.
+    pub fn for_network(&self, network: Network) -> Result<QueryTarget, String> {
+        match self {
+            QueryTarget::Address(address) => {
+                Ok(QueryTarget::Address(Address { payload: address.payload.clone(), network }))
+            }
+            QueryTarget::Script(_) | QueryTarget::ScriptHash(_) => Ok(self.clone()),
+            QueryTarget::Wallet(wallet) => Ok(QueryTarget::Wallet(wallet.for_network(network))),
+            QueryTarget::SilentPayment(_) => {
+                Err("A silent payment address is scanned for with its scan key on one network only".to_string())
+            }
+        }
+    }
+
+    /// Short description of the input kind for display
+    pub fn kind(&self) -> &'static str {
+        match self {
+            QueryTarget::Address(_) => "address",
+            QueryTarget::Script(_) => "raw script",
+            QueryTarget::ScriptHash(_) => "script hash",
+            QueryTarget::Wallet(wallet) => wallet.kind,
+            QueryTarget::SilentPayment(_) => "silent payment address",
+        }
+    }
+}
+
+/// Classifies the input as an address, an output descriptor, an extended
+/// public key, a silent payment address, a script hash (64 hex chars,
+/// Electrum byte order) or a raw scriptPubKey (any other even-length hex).
+/// 64 hex chars that also read as a script are refused unless prefixed with
+/// `script:` or `scripthash:`.
+pub fn parse_query_target(input: &str) -> Result<QueryTarget, String> {
+    if let Some(hex) = input.strip_prefix(SCRIPT_PREFIX) {
+        return parse_script(hex).map(QueryTarget::Script);
+    }
+    if let Some(hex) = input.strip_prefix(SCRIPT_HASH_PREFIX) {
+        return electrum_script_hash(hex).map(QueryTarget::ScriptHash);
+    }
+    let address_error = match Address::from_str(input) {
+        Ok(address) => return Ok(QueryTarget::Address(address)),
+        Err(e) => e,
+    };
+    if input.contains('(') {
+        return wallet::parse_descriptor(input).map(QueryTarget::Wallet);
+    }
+    if let Some(wallet) = wallet::parse_extended_key(input) {
+        return wallet.map(QueryTarget::Wallet);
+    }
+    if let Some(address) = silentpayments::parse_address(input) {
+        return address.map(|address| QueryTarget::SilentPayment(Box::new(Receiver::new(address))));
+    }
+    // Never echoed, in case it's real
+    if let Some(secret) = secret_kind(input) {
+        return Err(format!(
+            "That input looks like {}, which can spend the funds. Only public data is needed: did you mean to pass \
+             an xpub or a descriptor? Keep secrets out of command lines and shell history.",
+            secret
+        ));
+    }
+
+    let is_hex = !input.is_empty() && input.chars().all(|c| c.is_ascii_hexdigit());
+    if is_hex && is_public_key(input) {
+        let descriptor = if input.len() == 66 { "wpkh" } else { "pkh" };
+        return Err(format!(
+            "'{}' is a public key, not an address or script. Did you mean to pass an xpub, or a descriptor such as {}({})?",
+            input, descriptor, input
+        ));
+    }
+    if !is_hex && looks_like_address(input) {
+        // The outer error would only name the encoding
+        let reason = match address_error {
+            address::Error::Base58(e) => e.to_string(),
+            address::Error::Bech32(e) => e.to_string(),
+            e => e.to_string(),
+        };
+        return Err(format!("Invalid address '{}': {}. Check it for typos", input, reason));
+    }
+    if !is_hex || !input.len().is_multiple_of(2) {
+        return Err(format!(
+            "Invalid input: '{}'. Expected an address, a descriptor, an extended public key, a scriptPubKey in hex, or a script hash",
+            input
+        ));
+    }
+
+    if input.len() != SCRIPT_HASH_HEX_LEN {
+        return parse_script(input).map(QueryTarget::Script);
+    }
+    // A 32-byte script is nonstandard but possible; most hashes don't decode
+    // as one, since a push soon runs past the end
+    let is_script = parse_script(input).is_ok_and(|script| script.instructions().all(|instruction| instruction.is_ok()));
+    if is_script {
+        return Err(format!(
+            "'{}' could be a script hash or a 32-byte scriptPubKey. Prefix it with '{}' or '{}' to say which",
+            input, SCRIPT_HASH_PREFIX, SCRIPT_PREFIX
+        ));
+    }
+    electrum_script_hash(input).map(QueryTarget::ScriptHash)
+}
+
+fn parse_script(hex: &str) -> Result<Script, String> {
+    Script::from_str(hex).map_err(|e| format!("Invalid script hex '{}': {}", hex, e))
+}
+
+/// What kind of private key or seed the input looks like, if any
+fn secret_kind(input: &str) -> Option<&'static str> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    if words.len() >= 12 && words.iter().all(|word| word.chars().all(|c| c.is_ascii_alphabetic())) {
+        return Some("a seed phrase");
+    }
+    let is_base58 = input.chars().all(|c| c.is_ascii_alphanumeric() && !"0OIl".contains(c));
+    if !is_base58 {
+        return None;
+    }
+    if input.len() > 100 && input.get(1..4).is_some_and(|version| version == "prv") {
+        return Some("an extended private key");
+    }
+    match (input.len(), input.chars().next()) {
+        (51, Some('5' | '9')) | (52, Some('K' | 'L' | 'c')) => Some("a private key (WIF)"),
+        _ => None,
+    }
+}
+
+/// A compressed (66 hex chars) or uncompressed (130) public key
+fn is_public_key(hex: &str) -> bool {
+    match hex.len() {
+        66 => hex.starts_with("02") || hex.starts_with("03"),
+        130 => hex.starts_with("04"),
+        _ => false,
+    }
+}
+
+/// Whether a string that didn't parse was probably meant as an address: a
+/// bech32 prefix, or a base58 string of an address's length and lead
+fn looks_like_address(input: &str) -> bool {
+    let lower = input.to_ascii_lowercase();
+    if ["bc1", "tb1", "bcrt1"].iter().any(|prefix| lower.starts_with(prefix)) {
+        return true;
+    }
+    (25..=35).contains(&input.len())
+        && input.starts_with(['1', '3', 'm', 'n', '2'])
+        && input.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Hashes a scriptPubKey the way Esplora indexes it
+pub fn script_hash(script: &Script) -> sha256::Hash {
+    sha256::Hash::hash(script.as_bytes())
+}
+
+/// Hex form of a script hash as used in Esplora `/scripthash/` URLs
+pub fn esplora_script_hash(hash: &sha256::Hash) -> String {
+    hash.into_inner().to_hex()
+}
+
+/// Parses an Electrum-style script hash, which is byte-reversed
+fn electrum_script_hash(input: &str) -> Result<sha256::Hash, String> {
+    let mut bytes = Vec::<u8>::from_hex(input)
+        .map_err(|e| format!("Invalid script hash '{}': {}", input, e))?;
+    bytes.reverse();
+    sha256::Hash::from_slice(&bytes).map_err(|e| format!("Invalid script hash '{}': {}", input, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // scriptPubKey of tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx
+    const SCRIPT_HEX: &str = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+    const ESPLORA_HASH: &str = "8838f796bf4970b148779c05b74b8c49515b322d04035f7faa5d9b2375df2396";
+    const ELECTRUM_HASH: &str = "9623df75239b5daa7f5f03042d325b51498c4bb7059c7748b17049bf96f73888";
+
+    #[test]
+    fn test_parse_address() {
+        let target = parse_query_target("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap();
+        assert_eq!(target.kind(), "address");
+        assert_eq!(esplora_script_hash(&target.script_hash().unwrap()), ESPLORA_HASH);
+    }
+
+    #[test]
+    fn test_parse_raw_script() {
+        let target = parse_query_target(SCRIPT_HEX).unwrap();
+        assert_eq!(target.kind(), "raw script");
+        assert_eq!(esplora_script_hash(&target.script_hash().unwrap()), ESPLORA_HASH);
+        assert_eq!(target.script_pubkey().unwrap().to_hex(), SCRIPT_HEX);
+    }
+
+    #[test]
+    fn test_parse_electrum_script_hash() {
+        let target = parse_query_target(ELECTRUM_HASH).unwrap();
+        assert_eq!(target.kind(), "script hash");
+        assert_eq!(esplora_script_hash(&target.script_hash().unwrap()), ESPLORA_HASH);
+        assert!(target.script_pubkey().is_none());
+    }
+
+    #[test]
+    fn test_parse_ambiguous_script_hash() {
+        // OP_RETURN and a 30-byte push: a well-formed 32-byte script
+        let hex = format!("6a1e{}", "ab".repeat(30));
+        let error = parse_query_target(&hex).unwrap_err();
+        assert!(error.contains("could be a script hash or a 32-byte scriptPubKey"), "{}", error);
+
+        let script = parse_query_target(&format!("script:{}", hex)).unwrap();
+        assert_eq!(script.script_pubkey().unwrap().to_hex(), hex);
+        let hash = parse_query_target(&format!("scripthash:{}", hex)).unwrap();
+        assert_eq!(hash.kind(), "script hash");
+
+        let prefixed = parse_query_target(&format!("script:{}", SCRIPT_HEX)).unwrap();
+        assert_eq!(prefixed.script_pubkey().unwrap().to_hex(), SCRIPT_HEX);
+        assert!(parse_query_target(&format!("scripthash:{}", SCRIPT_HEX)).is_err());
+    }
+
+    #[test]
+    fn test_parse_explains_near_misses() {
+        // One character changed from tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx
+        let typo = parse_query_target("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsy").unwrap_err();
+        assert!(typo.starts_with("Invalid address 'tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsy': "), "{}", typo);
+        assert!(typo.ends_with("Check it for typos"));
+
+        let key = "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798";
+        assert!(parse_query_target(key).unwrap_err().contains(&format!("wpkh({})", key)));
+
+        let wif = "cVt4o7BGAig1UXywgGSmARhxMdzP5qvQsxKkSsc1XEkw3tDTQFpy";
+        let secret = parse_query_target(wif).unwrap_err();
+        assert!(secret.contains("a private key (WIF)") && !secret.contains(wif));
+        let seed = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+        assert!(parse_query_target(seed).unwrap_err().contains("a seed phrase"));
+    }
+
+    #[test]
+    fn test_parse_invalid() {
+        assert!(parse_query_target("").is_err());
+        assert!(parse_query_target("not-an-address").is_err());
+        assert!(parse_query_target("abc").is_err()); // odd-length hex
+    }
+}
@@ -0,0 +1,195 @@
+//! Retries and rate limiting for server requests, so long scans against
+//! public servers survive the odd 429 or timeout
+//!
+//! A request that fails in a way another try could fix (a timeout, a
+//! dropped connection, a 429 or 5xx status) is tried again up to
+//! `--retries` times, waiting 1s, 2s, 4s, ... in between, or as long as a
+//! 429's `Retry-After` asks. Anything else, such as a 404 or a malformed
+//! response, fails at once. `--rate-limit` spaces requests out to at most N
+//! per second across all `--concurrency` threads.
+//!
+//! The Electrum client reconnects and backs off by itself, so for Electrum
+//! only the rate limit is applied here and `--retries` is handed to the
+//! client.
+
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Wait before the first retry, doubled for each one after
+const BASE_DELAY: Duration = Duration::from_secs(1);
+
+/// Longest wait between tries, however many have failed or whatever a
+/// server's `Retry-After` says
+const MAX_DELAY: Duration = Duration::from_secs(60);
+
+/// Why a request failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Failure {
+    /// Worth trying again, after `retry_after` if the server said so
+    Transient { error: String, retry_after: Option<Duration> },
+    Permanent(String),
+}
+
+pub struct RetryPolicy {
+    retries: u32,
+    base_delay: Duration,
+    /// Time between requests with --rate-limit
+    interval: Option<Duration>,
+    /// When the next request may start
+    next_slot: Mutex<Instant>,
+}
+
+impl RetryPolicy {
+    /// Up to `retries` retries per request, and at most `rate_limit`
+    /// requests per second if given
+    pub fn new(retries: u32, rate_limit: Option<u32>) -> Self {
+        RetryPolicy {
+            retries,
+            base_delay: BASE_DELAY,
+            interval: rate_limit.map(|per_second| Duration::from_secs(1) / per_second),
+            next_slot: Mutex::new(Instant::now()),
+        }
+    }
+
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Makes the request, waiting for the rate limit first, and tries again
+    /// after transient failures while retries remain
+    pub fn call<T>(&self, mut request: impl FnMut() -> Result<T, Failure>) -> Result<T, String> {
+        let mut attempt = 0;
+        loop {
+            self.throttle();
+            let (error, retry_after) = match request() {
+                Ok(value) => return Ok(value),
+                Err(Failure::Permanent(error)) => return Err(error),
+                Err(Failure::Transient { error, retry_after }) => (error, retry_after),
+            };
+            if attempt == self.retries {
+                return Err(match self.retries {
+                    0 => error,
+                    1 => format!("{} (after 1 retry)", error),
+                    retries => format!("{} (after {} retries)", error, retries),
+                });
+            }
+            attempt += 1;
+            let delay = retry_after.unwrap_or_else(|| self.backoff(attempt)).min(MAX_DELAY);
+            eprintln!("Warning: {}; retrying in {}s ({} of {})", error, delay.as_secs(), attempt, self.retries);
+            thread::sleep(delay);
+        }
+    }
+
+    /// Waits until the rate limit allows another request
+    pub fn throttle(&self) {
+        let Some(interval) = self.interval else {
+            return;
+        };
+        let wait = {
+            let mut next_slot = self.next_slot.lock().unwrap();
+            let now = Instant::now();
+            let slot = (*next_slot).max(now);
+            *next_slot = slot + interval;
+            slot - now
+        };
+        if !wait.is_zero() {
+            thread::sleep(wait);
+        }
+    }
+
+    /// Wait before the given retry, counting from 1
+    fn backoff(&self, attempt: u32) -> Duration {
+        self.base_delay.saturating_mul(1 << (attempt - 1).min(16)).min(MAX_DELAY)
+    }
+}
+
+/// Sorts a failed HTTP request into what's worth retrying
+pub fn http_failure(error: ureq::Error) -> Failure {
+    let message = error.to_string();
+    match error {
+        ureq::Error::Status(429, response) => {
+            let retry_after = response.header("Retry-After").and_then(|secs| secs.trim().parse().ok());
+            Failure::Transient { error: message, retry_after: retry_after.map(Duration::from_secs) }
+        }
+        ureq::Error::Status(500..=599, _) => Failure::Transient { error: message, retry_after: None },
+        ureq::Error::Status(..) => Failure::Permanent(message),
+        ureq::Error::Transport(transport) => match transport.kind() {
+            ureq::ErrorKind::Dns | ureq::ErrorKind::ConnectionFailed | ureq::ErrorKind::Io | ureq::ErrorKind::ProxyConnect => {
+                Failure::Transient { error: message, retry_after: None }
+            }
+            _ => Failure::Permanent(message),
+        },
+    }
+}
+
+/// Sorts a failure to read a response body: a cut-off body is worth
+/// retrying, one that isn't what was asked for isn't
+pub fn body_failure(error: std::io::Error) -> Failure {
+    match error.kind() {
+        std::io::ErrorKind::InvalidData => Failure::Permanent(error.to_string()),
+        _ => Failure::Transient { error: error.to_string(), retry_after: None },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::Cell;
+
+    fn policy(retries: u32) -> RetryPolicy {
+        RetryPolicy { base_delay: Duration::ZERO, ..RetryPolicy::new(retries, None) }
+    }
+
+    fn transient(error: &str) -> Failure {
+        Failure::Transient { error: error.to_string(), retry_after: None }
+    }
+
+    #[test]
+    fn test_call_retries_transient_failures() {
+        let tries = Cell::new(0);
+        let result = policy(3).call(|| {
+            tries.set(tries.get() + 1);
+            if tries.get() < 3 { Err(transient("timed out")) } else { Ok(tries.get()) }
+        });
+        assert_eq!(result, Ok(3));
+
+        tries.set(0);
+        let result: Result<(), String> = policy(2).call(|| {
+            tries.set(tries.get() + 1);
+            Err(transient("status code 429"))
+        });
+        assert_eq!(result.unwrap_err(), "status code 429 (after 2 retries)");
+        assert_eq!(tries.get(), 3);
+    }
+
+    #[test]
+    fn test_call_gives_up_on_permanent_failures() {
+        let tries = Cell::new(0);
+        let result: Result<(), String> = policy(3).call(|| {
+            tries.set(tries.get() + 1);
+            Err(Failure::Permanent("status code 404".to_string()))
+        });
+        assert_eq!(result.unwrap_err(), "status code 404");
+        assert_eq!(tries.get(), 1);
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_the_cap() {
+        let policy = RetryPolicy::new(10, None);
+        assert_eq!(policy.backoff(1), Duration::from_secs(1));
+        assert_eq!(policy.backoff(3), Duration::from_secs(4));
+        assert_eq!(policy.backoff(10), MAX_DELAY);
+    }
+
+    #[test]
+    fn test_throttle_spaces_requests() {
+        let policy = RetryPolicy::new(0, Some(50));
+        let start = Instant::now();
+        for _ in 0..6 {
+            policy.throttle();
+        }
+        // The first request goes at once, then one every 20ms
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+}
@@ -0,0 +1,141 @@
+//! Address reuse report for wallet scans: which of the wallet's addresses
+//! received funds in more than one transaction
+//!
+//! Every payment to a reused address is linked to the others on-chain, so
+//! reuse is the easiest privacy leak to spot and to stop. Several outputs to
+//! the same address in one transaction count as one receipt.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::Script;
+use bdk::esplora_client::Tx;
+use std::collections::{HashMap, HashSet};
+use units::Unit;
+
+use crate::query::script_hash;
+
+/// Receipts listed per address before the rest are summed up
+const MAX_LISTED: usize = 10;
+
+/// A script that received funds more than once
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Reuse {
+    pub script: Script,
+    /// Amount received in each transaction, oldest first
+    pub receipts: Vec<u64>,
+}
+
+impl Reuse {
+    pub fn total(&self) -> u64 {
+        self.receipts.iter().sum()
+    }
+}
+
+/// Scripts among `scripts` paid by more than one transaction, most reused
+/// first, and how many of `scripts` received anything at all
+pub fn find_reuse(txs: &[Tx], scripts: &HashSet<sha256::Hash>) -> (Vec<Reuse>, usize) {
+    let mut ordered: Vec<&Tx> = txs.iter().collect();
+    ordered.sort_by_key(|tx| tx.status.block_height.unwrap_or(u32::MAX));
+
+    let mut received: HashMap<sha256::Hash, Reuse> = HashMap::new();
+    for tx in ordered {
+        let mut paid: Vec<(sha256::Hash, &Script, u64)> = Vec::new();
+        for output in &tx.vout {
+            let hash = script_hash(&output.scriptpubkey);
+            if !scripts.contains(&hash) {
+                continue;
+            }
+            match paid.iter_mut().find(|(seen, _, _)| *seen == hash) {
+                Some((_, _, value)) => *value += output.value,
+                None => paid.push((hash, &output.scriptpubkey, output.value)),
+            }
+        }
+        for (hash, script, value) in paid {
+            received
+                .entry(hash)
+                .or_insert_with(|| Reuse { script: script.clone(), receipts: Vec::new() })
+                .receipts
+                .push(value);
+        }
+    }
+
+    let used = received.len();
+    let mut reused: Vec<Reuse> = received.into_values().filter(|reuse| reuse.receipts.len() > 1).collect();
+    reused.sort_by(|a, b| {
+        b.receipts.len().cmp(&a.receipts.len()).then(b.total().cmp(&a.total())).then(a.script.cmp(&b.script))
+    });
+    (reused, used)
+}
+
+/// One line per reused address, given its display name; long runs of
+/// receipts are cut short
+pub fn format_reuse(reused: &[Reuse], name: impl Fn(&Script) -> String, unit: Unit) -> Vec<String> {
+    let names: Vec<String> = reused.iter().map(|reuse| name(&reuse.script)).collect();
+    let width = names.iter().map(String::len).max().unwrap_or(0);
+    reused
+        .iter()
+        .zip(names)
+        .map(|(reuse, name)| {
+            let mut amounts: Vec<String> = reuse.receipts.iter().take(MAX_LISTED).map(|&value| unit.number(value)).collect();
+            if reuse.receipts.len() > MAX_LISTED {
+                amounts.push(format!("and {} more", reuse.receipts.len() - MAX_LISTED));
+            }
+            format!(
+                "{:<width$}  {:>3} times  {} ({})",
+                name,
+                reuse.receipts.len(),
+                unit.format(reuse.total()),
+                amounts.join(", "),
+                width = width
+            )
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{output, script, status};
+
+    /// Outputs pay (owner, value)
+    fn tx(id: u8, height: Option<u32>, outputs: &[(u8, u64)]) -> Tx {
+        let vout = outputs.iter().map(|&(owner, value)| output(script(owner), value)).collect();
+        Tx { status: status(height, None), ..crate::test_util::tx(id, vec![], vout) }
+    }
+
+    #[test]
+    fn test_find_reuse() {
+        let scripts: HashSet<sha256::Hash> = [1, 2, 3].iter().map(|&byte| script_hash(&script(byte))).collect();
+        // Newest first, as Esplora lists them
+        let txs = vec![
+            tx(4, None, &[(1, 7_000)]),
+            tx(3, Some(30), &[(2, 5_000), (9, 1_000)]),
+            // Two outputs to one address in one transaction are one receipt
+            tx(2, Some(20), &[(1, 2_000), (1, 1_000), (2, 4_000)]),
+            tx(1, Some(10), &[(1, 10_000), (3, 500)]),
+        ];
+
+        let (reused, used) = find_reuse(&txs, &scripts);
+        assert_eq!(used, 3);
+        assert_eq!(
+            reused,
+            vec![
+                Reuse { script: script(1), receipts: vec![10_000, 3_000, 7_000] },
+                Reuse { script: script(2), receipts: vec![4_000, 5_000] },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_reuse() {
+        let reused = [
+            Reuse { script: script(1), receipts: vec![1_000; 12] },
+            Reuse { script: script(2), receipts: vec![4_000, 5_000] },
+        ];
+        let name = |candidate: &Script| if *candidate == script(1) { "tb1qlong".to_string() } else { "tb1q".to_string() };
+        let lines = format_reuse(&reused, name, Unit::Sats);
+        assert!(lines[0].starts_with("tb1qlong   12 times  12,000 sats (1,000, 1,000,"));
+        assert!(lines[0].ends_with("1,000, and 2 more)"));
+        assert_eq!(lines[1], "tb1q        2 times  9,000 sats (4,000, 5,000)");
+        assert_eq!(format_reuse(&reused, name, Unit::Mbtc)[1], "tb1q        2 times  0.09000 mBTC (0.04000, 0.05000)");
+    }
+}
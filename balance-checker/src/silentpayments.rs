@@ -0,0 +1,508 @@
+//! Silent payments (BIP 352) for `sp1...` inputs: every transaction of the
+//! scanned blocks is checked for taproot outputs paying the address
+//!
+//! A silent payment output's key is derived from the sender's input keys
+//! and the address's scan key, so no index can look it up by script. With
+//! the scan private key (`--sp-scan-key`), each transaction's eligible input
+//! keys are summed and the output keys the sender would have made are
+//! compared with its taproot outputs, including payments to the change
+//! label (m = 0). Spends of the outputs found are picked up from later
+//! blocks, so the balance is what's left of them. Only confirmed
+//! transactions are seen: the mempool isn't scanned.
+
+use bdk::bitcoin::bech32::{self, FromBase32, ToBase32, Variant};
+use bdk::bitcoin::blockdata::script::Instruction;
+use bdk::bitcoin::consensus::encode::serialize;
+use bdk::bitcoin::hashes::hex::FromHex;
+use bdk::bitcoin::hashes::{hash160, sha256, Hash, HashEngine};
+use bdk::bitcoin::secp256k1::{All, Parity, PublicKey, Scalar, Secp256k1, SecretKey, XOnlyPublicKey};
+use bdk::bitcoin::{Network, OutPoint, PrivateKey, Script};
+use bdk::esplora_client::{Tx, Vin};
+use std::collections::HashSet;
+use std::fmt;
+
+use crate::progress::Progress;
+use crate::query::script_hash;
+
+/// Blocks scanned back from the tip without a `--birthday`: about a day
+pub const DEFAULT_BLOCKS: u32 = 144;
+
+/// Bytes of the two keys at the start of an address's data
+const ADDRESS_KEYS_LEN: usize = 66;
+
+/// BIP 341's provably unspendable point H, the internal key of outputs that
+/// can only be spent by script; such inputs don't count
+const NUMS_H: &str = "50929b74c1a04954b78b4b6035e97a5e078a5a0f28ec96d547bfee9ace803ac0";
+
+/// A silent payment address: the receiver's scan and spend public keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SpAddress {
+    pub scan: PublicKey,
+    pub spend: PublicKey,
+    /// Bitcoin for `sp`, Testnet for `tsp` (every test network), Regtest for `sprt`
+    pub network: Network,
+}
+
+impl fmt::Display for SpAddress {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let hrp = match self.network {
+            Network::Bitcoin => "sp",
+            Network::Regtest => "sprt",
+            _ => "tsp",
+        };
+        let mut data = vec![bech32::u5::try_from_u8(0).expect("0 fits in 5 bits")];
+        data.extend([self.scan.serialize(), self.spend.serialize()].concat().to_base32());
+        let encoded = bech32::encode(hrp, data, Variant::Bech32m).map_err(|_| fmt::Error)?;
+        f.write_str(&encoded)
+    }
+}
+
+/// The address, if the input has a silent payment prefix
+pub fn parse_address(input: &str) -> Option<Result<SpAddress, String>> {
+    let lower = input.to_ascii_lowercase();
+    let network = if lower.starts_with("sprt1") {
+        Network::Regtest
+    } else if lower.starts_with("sp1") {
+        Network::Bitcoin
+    } else if lower.starts_with("tsp1") {
+        Network::Testnet
+    } else {
+        return None;
+    };
+    let invalid = |reason: String| format!("Invalid silent payment address '{}': {}. Check it for typos", input, reason);
+    Some((|| {
+        let (_, data, variant) = bech32::decode(input).map_err(|e| invalid(e.to_string()))?;
+        if variant != Variant::Bech32m {
+            return Err(invalid("it should be encoded with bech32m".to_string()));
+        }
+        let (version, payload) = data.split_first().ok_or_else(|| invalid("no data".to_string()))?;
+        // Later versions keep the two keys first and may add more after them
+        if version.to_u8() == 31 {
+            return Err(invalid("version 31 is not supported".to_string()));
+        }
+        let bytes = Vec::<u8>::from_base32(payload).map_err(|e| invalid(e.to_string()))?;
+        if bytes.len() < ADDRESS_KEYS_LEN || (version.to_u8() == 0 && bytes.len() != ADDRESS_KEYS_LEN) {
+            return Err(invalid(format!("expected {} bytes of keys, found {}", ADDRESS_KEYS_LEN, bytes.len())));
+        }
+        let key = |bytes: &[u8]| PublicKey::from_slice(bytes).map_err(|e| invalid(e.to_string()));
+        Ok(SpAddress { scan: key(&bytes[..33])?, spend: key(&bytes[33..ADDRESS_KEYS_LEN])?, network })
+    })())
+}
+
+/// A silent payment address to scan for, with the scan key once it's given
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Receiver {
+    pub address: SpAddress,
+    scan_key: Option<SecretKey>,
+    /// First block height to scan
+    birthday: Option<u32>,
+}
+
+impl Receiver {
+    pub fn new(address: SpAddress) -> Self {
+        Receiver { address, scan_key: None, birthday: None }
+    }
+
+    /// Adds the scan private key, as 64 hex characters or WIF, after
+    /// checking it belongs to the address
+    pub fn with_scan_key(mut self, key: &str, birthday: Option<u32>) -> Result<Self, String> {
+        let key = key.trim();
+        let secret = match <[u8; 32]>::from_hex(key) {
+            Ok(bytes) => SecretKey::from_slice(&bytes).ok(),
+            Err(_) => PrivateKey::from_wif(key).ok().map(|key| key.inner),
+        }
+        // Never echoed, since it's a secret
+        .ok_or_else(|| "Invalid --sp-scan-key: expected a private key as 64 hex characters or WIF".to_string())?;
+        if PublicKey::from_secret_key(&Secp256k1::new(), &secret) != self.address.scan {
+            return Err("--sp-scan-key is not the scan key of this silent payment address".to_string());
+        }
+        self.scan_key = Some(secret);
+        self.birthday = birthday;
+        Ok(self)
+    }
+
+    /// The first height to scan: the birthday, or a day's worth of blocks
+    /// back from the tip. A birthday above the tip has no blocks to scan.
+    pub fn start_height(&self, tip: u32) -> Result<u32, String> {
+        match self.birthday {
+            Some(birthday) if birthday > tip => Err(format!("--birthday {} is above the tip ({})", birthday, tip)),
+            Some(birthday) => Ok(birthday),
+            None => Ok(tip.saturating_sub(DEFAULT_BLOCKS - 1)),
+        }
+    }
+
+    fn keys(&self) -> Result<ScanKeys, String> {
+        let scan_key = self.scan_key.ok_or_else(|| {
+            format!("Scanning for payments to {} needs its scan private key; give it with --sp-scan-key", self.address)
+        })?;
+        let secp = Secp256k1::new();
+        let label = tagged_hash("BIP0352/Label", &[&scan_key.secret_bytes(), &0u32.to_be_bytes()]);
+        let label = SecretKey::from_slice(&label).map_err(|e| e.to_string())?;
+        Ok(ScanKeys { change_label: PublicKey::from_secret_key(&secp, &label), scan_key, spend: self.address.spend, secp })
+    }
+}
+
+struct ScanKeys {
+    scan_key: SecretKey,
+    spend: PublicKey,
+    /// The change label's point, added to the spend key for change outputs
+    change_label: PublicKey,
+    secp: Secp256k1<All>,
+}
+
+/// Every transaction from `from` to `tip` that pays the receiver or spends
+/// what it was paid, newest first, and the hashes of the scripts paid.
+/// `block_txs` returns a block's transactions with their prevouts.
+pub fn scan(
+    receiver: &Receiver,
+    from: u32,
+    tip: u32,
+    mut block_txs: impl FnMut(u32) -> Result<Vec<Tx>, String>,
+    progress: &Progress,
+) -> Result<(Vec<Tx>, HashSet<sha256::Hash>), String> {
+    let keys = receiver.keys()?;
+    let mut found: HashSet<OutPoint> = HashSet::new();
+    let mut scripts = HashSet::new();
+    let mut txs = Vec::new();
+    for height in from..=tip {
+        for tx in block_txs(height)? {
+            let spends = tx.vin.iter().any(|input| found.contains(&OutPoint::new(input.txid, input.vout)));
+            let received = received_outputs(&tx, &keys);
+            for &vout in &received {
+                found.insert(OutPoint::new(tx.txid, vout));
+                scripts.insert(script_hash(&tx.vout[vout as usize].scriptpubkey));
+            }
+            if spends || !received.is_empty() {
+                txs.push(tx);
+            }
+        }
+        progress.blocks(height, tip);
+    }
+    txs.reverse();
+    Ok((txs, scripts))
+}
+
+/// Indexes of the outputs of `tx` paying the receiver
+fn received_outputs(tx: &Tx, keys: &ScanKeys) -> Vec<u32> {
+    let taproot: Vec<(u32, &[u8])> = tx
+        .vout
+        .iter()
+        .enumerate()
+        .filter(|(_, output)| output.scriptpubkey.is_v1_p2tr())
+        .map(|(vout, output)| (vout as u32, &output.scriptpubkey.as_bytes()[2..]))
+        .collect();
+    if taproot.is_empty() || tx.vin.iter().any(spends_future_segwit) {
+        return Vec::new();
+    }
+    let input_keys: Vec<PublicKey> = tx.vin.iter().filter_map(input_public_key).collect();
+    let Ok(sum) = PublicKey::combine_keys(&input_keys.iter().collect::<Vec<_>>()) else {
+        // No eligible inputs, or keys that cancel out
+        return Vec::new();
+    };
+    let Some(lowest) = tx.vin.iter().map(|input| serialize(&OutPoint::new(input.txid, input.vout))).min() else {
+        return Vec::new();
+    };
+    let input_hash = tagged_hash("BIP0352/Inputs", &[&lowest, &sum.serialize()]);
+    let Some(shared_secret) = Scalar::from_be_bytes(input_hash)
+        .ok()
+        .and_then(|input_hash| keys.scan_key.mul_tweak(&input_hash).ok())
+        .and_then(|tweak| sum.mul_tweak(&keys.secp, &Scalar::from(tweak)).ok())
+    else {
+        return Vec::new();
+    };
+
+    // Outputs are numbered k = 0, 1, ... in whatever order the sender put them
+    let mut received = Vec::new();
+    for k in 0u32.. {
+        let tweak = tagged_hash("BIP0352/SharedSecret", &[&shared_secret.serialize(), &k.to_be_bytes()]);
+        let Some(output_key) =
+            Scalar::from_be_bytes(tweak).ok().and_then(|tweak| keys.spend.add_exp_tweak(&keys.secp, &tweak).ok())
+        else {
+            break;
+        };
+        let candidates = [Some(output_key), output_key.combine(&keys.change_label).ok()];
+        let matched = taproot.iter().find(|(vout, key)| {
+            !received.contains(vout)
+                && candidates.iter().flatten().any(|candidate| candidate.x_only_public_key().0.serialize() == **key)
+        });
+        match matched {
+            Some(&(vout, _)) => received.push(vout),
+            None => break,
+        }
+    }
+    received.sort_unstable();
+    received
+}
+
+/// Whether the input spends a segwit output of a version above 1, which
+/// makes the whole transaction ineligible
+fn spends_future_segwit(input: &Vin) -> bool {
+    input
+        .prevout
+        .as_ref()
+        .and_then(|prevout| prevout.scriptpubkey.witness_version())
+        .is_some_and(|version| version.to_num() > 1)
+}
+
+/// The public key an input contributes, if it's an eligible kind: taproot
+/// (but not script-only), P2WPKH, P2SH-P2WPKH or P2PKH with a compressed key
+fn input_public_key(input: &Vin) -> Option<PublicKey> {
+    let script = &input.prevout.as_ref()?.scriptpubkey;
+    if script.is_v1_p2tr() {
+        let mut witness = &input.witness[..];
+        if witness.len() > 1 && witness.last()?.first() == Some(&0x50) {
+            // Annex
+            witness = &witness[..witness.len() - 1];
+        }
+        if witness.len() > 1 {
+            // Script path: the control block holds the internal key
+            let internal_key = witness.last()?.get(1..33)?;
+            if internal_key == <[u8; 32]>::from_hex(NUMS_H).expect("valid hex") {
+                return None;
+            }
+        }
+        let key = XOnlyPublicKey::from_slice(&script.as_bytes()[2..]).ok()?;
+        return Some(key.public_key(Parity::Even));
+    }
+    if script.is_v0_p2wpkh() {
+        return compressed_key(input.witness.last()?);
+    }
+    if script.is_p2sh() {
+        let redeem_script = pushes(&input.scriptsig).last().map(|bytes| Script::from(bytes.to_vec()))?;
+        return redeem_script.is_v0_p2wpkh().then(|| compressed_key(input.witness.last()?)).flatten();
+    }
+    if script.is_p2pkh() {
+        // The key is the last push hashing to the script's, wherever the
+        // rest of the scriptSig puts it
+        let hash = &script.as_bytes()[3..23];
+        return pushes(&input.scriptsig)
+            .into_iter()
+            .rev()
+            .find(|bytes| bytes.len() == 33 && hash160::Hash::hash(bytes)[..] == *hash)
+            .and_then(compressed_key);
+    }
+    None
+}
+
+fn compressed_key(bytes: &[u8]) -> Option<PublicKey> {
+    if bytes.len() != 33 {
+        return None;
+    }
+    PublicKey::from_slice(bytes).ok()
+}
+
+fn pushes(script: &Script) -> Vec<&[u8]> {
+    script
+        .instructions()
+        .filter_map(|instruction| match instruction {
+            Ok(Instruction::PushBytes(bytes)) => Some(bytes),
+            _ => None,
+        })
+        .collect()
+}
+
+/// BIP 340 tagged hash of the parts, concatenated
+fn tagged_hash(tag: &str, parts: &[&[u8]]) -> [u8; 32] {
+    let tag = sha256::Hash::hash(tag.as_bytes());
+    let mut engine = sha256::Hash::engine();
+    engine.input(&tag);
+    engine.input(&tag);
+    for part in parts {
+        engine.input(part);
+    }
+    sha256::Hash::from_engine(engine).into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::Txid;
+    use bdk::esplora_client::{PrevOut, TxStatus};
+    use std::collections::HashMap;
+    use std::str::FromStr;
+
+    use crate::test_util::{self, input, output, status, txid};
+
+    fn secret(byte: u8) -> SecretKey {
+        SecretKey::from_slice(&[byte; 32]).unwrap()
+    }
+
+    fn public(byte: u8) -> PublicKey {
+        PublicKey::from_secret_key(&Secp256k1::new(), &secret(byte))
+    }
+
+    fn address() -> SpAddress {
+        SpAddress { scan: public(1), spend: public(2), network: Network::Bitcoin }
+    }
+
+    fn receiver() -> Receiver {
+        Receiver::new(address()).with_scan_key(&"01".repeat(32), None).unwrap()
+    }
+
+    fn taproot(key: &PublicKey) -> Script {
+        Script::from([&[0x51, 0x20][..], &key.x_only_public_key().0.serialize()].concat())
+    }
+
+    fn p2wpkh_input(spent: OutPoint, key: &PublicKey) -> Vin {
+        let hash = hash160::Hash::hash(&key.serialize());
+        let script = Script::from([&[0x00, 0x14][..], &hash[..]].concat());
+        Vin {
+            witness: vec![vec![0x30; 71], key.serialize().to_vec()],
+            sequence: 0xffff_fffd,
+            ..input(spent.txid, spent.vout, script, 50_000)
+        }
+    }
+
+    /// Pays 10,000 sats to each of `outputs`
+    fn tx(id: u8, vin: Vec<Vin>, outputs: Vec<Script>) -> Tx {
+        let vout = outputs.into_iter().map(|script| output(script, 10_000)).collect();
+        Tx { status: TxStatus { confirmed: true, ..status(None, None) }, fee: 500, ..test_util::tx(id, vin, vout) }
+    }
+
+    /// What a sender spending `spent` with `sender` makes for output k,
+    /// plus `label` if given, following BIP 352 from the sender's side
+    fn sender_output(spent: OutPoint, sender: &SecretKey, k: u32, label: Option<&PublicKey>) -> Script {
+        let secp = Secp256k1::new();
+        let sum = PublicKey::from_secret_key(&secp, sender);
+        let input_hash = tagged_hash("BIP0352/Inputs", &[&serialize(&spent), &sum.serialize()]);
+        let tweak = sender.mul_tweak(&Scalar::from_be_bytes(input_hash).unwrap()).unwrap();
+        let shared_secret = address().scan.mul_tweak(&secp, &Scalar::from(tweak)).unwrap();
+        let t_k = tagged_hash("BIP0352/SharedSecret", &[&shared_secret.serialize(), &k.to_be_bytes()]);
+        let spend = match label {
+            Some(label) => address().spend.combine(label).unwrap(),
+            None => address().spend,
+        };
+        taproot(&spend.add_exp_tweak(&secp, &Scalar::from_be_bytes(t_k).unwrap()).unwrap())
+    }
+
+    #[test]
+    fn test_address_round_trip() {
+        let encoded = address().to_string();
+        assert!(encoded.starts_with("sp1q"));
+        assert_eq!(parse_address(&encoded).unwrap().unwrap(), address());
+
+        let testnet = SpAddress { network: Network::Signet, ..address() }.to_string();
+        assert!(testnet.starts_with("tsp1q"));
+        assert_eq!(parse_address(&testnet).unwrap().unwrap().network, Network::Testnet);
+
+        let typo = format!("{}{}", &encoded[..encoded.len() - 1], if encoded.ends_with('q') { 'p' } else { 'q' });
+        assert!(parse_address(&typo).unwrap().unwrap_err().contains("Check it for typos"));
+        assert!(parse_address("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4").is_none());
+
+        // BIP 352 test vector: the address of these scan and spend keys
+        let key = |hex: &str| PublicKey::from_secret_key(&Secp256k1::new(), &SecretKey::from_str(hex).unwrap());
+        let vector = SpAddress {
+            scan: key("0f694e068028a717f8af6b9411f9a133dd3565258714cc226594b34db90c1f2c"),
+            spend: key("9d6ad855ce3417ef84e836892e5a56392bfba05fa5d97ccea30e266f540e08b3"),
+            network: Network::Bitcoin,
+        };
+        assert_eq!(
+            vector.to_string(),
+            "sp1qqgste7k9hx0qftg6qmwlkqtwuy6cycyavzmzj85c6qdfhjdpdjtdgqjuexzk6murw56suy3e0rd2cgqvycxttddwsvgxe2usfpxumr70xc9pkqwv"
+        );
+    }
+
+    #[test]
+    fn test_bip352_simple_send_two_inputs() {
+        // BIP 352 send and receive test vector "Simple send: two inputs"
+        let secp = Secp256k1::new();
+        let key = |hex: &str| SecretKey::from_str(hex).unwrap();
+        let receiver = Receiver::new(SpAddress {
+            scan: PublicKey::from_secret_key(&secp, &key("0f694e068028a717f8af6b9411f9a133dd3565258714cc226594b34db90c1f2c")),
+            spend: PublicKey::from_secret_key(&secp, &key("9d6ad855ce3417ef84e836892e5a56392bfba05fa5d97ccea30e266f540e08b3")),
+            network: Network::Bitcoin,
+        })
+        .with_scan_key("0f694e068028a717f8af6b9411f9a133dd3565258714cc226594b34db90c1f2c", None)
+        .unwrap();
+        let input = |txid: &str, private_key: &str| {
+            let spent = OutPoint::new(Txid::from_str(txid).unwrap(), 0);
+            p2wpkh_input(spent, &PublicKey::from_secret_key(&secp, &key(private_key)))
+        };
+        let output = XOnlyPublicKey::from_str("3e9fce73d4e77a4809908e3c3a2e54ee147b9312dc5044a193d1fc85de46e3c1").unwrap();
+        let paid = tx(
+            1,
+            vec![
+                input("f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16", "eadc78165ff1f8ea94ad7cfdc54990738a4c53f6e0507b42154201b8e5dff3b1"),
+                input("a1075db55d416d3ca199f55b6084e2115b9345e16c5cf302fc80e9d5fbf5d48d", "93f5ed907ad5b2bdbbdcb5d9116ebc0a4e1f92f910d5260237fa45a9408aad16"),
+            ],
+            vec![taproot(&output.public_key(Parity::Even))],
+        );
+        assert_eq!(received_outputs(&paid, &receiver.keys().unwrap()), vec![0]);
+    }
+
+    #[test]
+    fn test_scan_key_must_match_the_address() {
+        assert!(Receiver::new(address()).with_scan_key(&"02".repeat(32), None).unwrap_err().contains("not the scan key"));
+        let wif = PrivateKey::new(secret(1), Network::Bitcoin).to_wif();
+        assert!(Receiver::new(address()).with_scan_key(&wif, None).is_ok());
+        let secret_input = Receiver::new(address()).with_scan_key("zz", None).unwrap_err();
+        assert!(!secret_input.contains("zz"));
+        assert!(Receiver::new(address()).keys().err().unwrap().contains("--sp-scan-key"));
+        assert_eq!(receiver().start_height(1_000), Ok(857));
+        assert_eq!(receiver().start_height(100), Ok(0));
+    }
+
+    #[test]
+    fn test_birthday_above_the_tip_is_refused() {
+        let receiver = Receiver::new(address()).with_scan_key(&"01".repeat(32), Some(900)).unwrap();
+        assert_eq!(receiver.start_height(900), Ok(900));
+        assert_eq!(receiver.start_height(899).unwrap_err(), "--birthday 900 is above the tip (899)");
+    }
+
+    #[test]
+    fn test_finds_payments_their_change_and_spends() {
+        let sender = secret(7);
+        let spent = OutPoint::new(txid(0xa0), 3);
+        // Two payments in one transaction, after an output that isn't ours
+        let paid = tx(
+            1,
+            vec![p2wpkh_input(spent, &public(7))],
+            vec![taproot(&public(9)), sender_output(spent, &sender, 1, None), sender_output(spent, &sender, 0, None)],
+        );
+        // The receiver's own change, to the change label
+        let change_spent = OutPoint::new(txid(0xb0), 0);
+        let label = PublicKey::from_secret_key(
+            &Secp256k1::new(),
+            &SecretKey::from_slice(&tagged_hash("BIP0352/Label", &[&secret(1).secret_bytes(), &0u32.to_be_bytes()])).unwrap(),
+        );
+        let change = tx(2, vec![p2wpkh_input(change_spent, &public(8))], vec![sender_output(change_spent, &secret(8), 0, Some(&label))]);
+        let unrelated = tx(3, vec![p2wpkh_input(OutPoint::new(txid(0xc0), 0), &public(7))], vec![taproot(&public(10))]);
+        let spend = tx(4, vec![p2wpkh_input(OutPoint::new(txid(1), 1), &public(11))], vec![taproot(&public(12))]);
+
+        let blocks = HashMap::from([(100, vec![paid, unrelated]), (101, vec![]), (102, vec![change, spend])]);
+        let (txs, scripts) = scan(&receiver(), 100, 102, |height| Ok(blocks[&height].clone()), &Progress::new(false)).unwrap();
+        let ids: Vec<Txid> = txs.iter().map(|tx| tx.txid).collect();
+        assert_eq!(ids, vec![txid(4), txid(2), txid(1)]);
+        assert_eq!(scripts.len(), 3);
+        assert_eq!(received_outputs(&txs[2], &receiver().keys().unwrap()), vec![1, 2]);
+    }
+
+    #[test]
+    fn test_input_public_keys() {
+        let key = public(5);
+        let mut input = p2wpkh_input(OutPoint::new(txid(1), 0), &key);
+        assert_eq!(input_public_key(&input), Some(key));
+        input.witness = vec![vec![0x30; 71], vec![0x04; 65]];
+        assert_eq!(input_public_key(&input), None, "uncompressed keys don't count");
+
+        // P2PKH, with the key found by its hash
+        let hash = hash160::Hash::hash(&key.serialize());
+        let p2pkh = Script::from([&[0x76, 0xa9, 0x14][..], &hash[..], &[0x88, 0xac]].concat());
+        let scriptsig = Script::from([&[71][..], &[0x30; 71], &[33], &key.serialize()].concat());
+        let input = Vin { prevout: Some(PrevOut { value: 1, scriptpubkey: p2pkh }), scriptsig, witness: vec![], ..input };
+        assert_eq!(input_public_key(&input), Some(key));
+
+        // Taproot key path counts with the even key; script-only doesn't
+        let output_key = taproot(&key);
+        let input = Vin {
+            prevout: Some(PrevOut { value: 1, scriptpubkey: output_key }),
+            scriptsig: Script::new(),
+            witness: vec![vec![0x01; 64]],
+            ..input
+        };
+        assert_eq!(input_public_key(&input).unwrap().x_only_public_key().0, key.x_only_public_key().0);
+        let control_block = [&[0xc0][..], &<[u8; 32]>::from_hex(NUMS_H).unwrap()].concat();
+        let script_path = Vin { witness: vec![vec![0x01; 64], vec![0x51], control_block], ..input };
+        assert_eq!(input_public_key(&script_path), None);
+    }
+}
@@ -0,0 +1,198 @@
+//! Offline simulated chain for workshops and demos
+//!
+//! Builds a fake history of blocks and transactions that pay to and spend from
+//! the target script, shaped exactly like Esplora's responses, so the real
+//! balance and history code runs without any network access.
+
+use bdk::bitcoin::hashes::{sha256d, Hash};
+use bdk::bitcoin::{BlockHash, Script, Txid, WPubkeyHash};
+use bdk::esplora_client::{PrevOut, Tx, TxStatus, Vin, Vout};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+
+/// Seconds between simulated blocks
+const BLOCK_INTERVAL_SECS: u64 = 600;
+
+/// Range of amounts received by the wallet, in sats
+const RECEIVE_RANGE_SATS: (u64, u64) = (10_000, 5_000_000);
+
+/// Flat fee paid by every simulated transaction
+const FEE_SATS: u64 = 500;
+
+/// Chance that a transaction spends from the wallet when it has coins
+const SPEND_PROBABILITY: f64 = 0.4;
+
+/// Shape of the simulated chain
+#[derive(Debug, Clone)]
+pub struct SimConfig {
+    /// Number of blocks; the tip is at this height
+    pub blocks: u32,
+    /// Number of transactions touching the wallet
+    pub txs: usize,
+    /// Same seed, same chain
+    pub seed: u64,
+}
+
+/// A simulated chain tip and the wallet's history, newest first like Esplora
+#[derive(Debug)]
+pub struct SimChain {
+    pub tip_height: u32,
+    pub txs: Vec<Tx>,
+}
+
+/// Generates a history of receives and spends for `wallet`. Heights are
+/// spread randomly over the chain and the newest transaction is left in the
+/// mempool so the unconfirmed balance has something to show.
+pub fn build_chain(wallet: &Script, config: &SimConfig, now: u64) -> Result<SimChain, String> {
+    if config.blocks == 0 {
+        return Err("A simulated chain needs at least one block".to_string());
+    }
+
+    let mut rng = StdRng::seed_from_u64(config.seed);
+    let counterparty = Script::new_v0_p2wpkh(&WPubkeyHash::hash(b"simulated counterparty"));
+
+    let mut heights: Vec<u32> = (0..config.txs).map(|_| rng.gen_range(1..=config.blocks)).collect();
+    heights.sort_unstable();
+
+    let mut utxos: Vec<(Txid, u32, u64)> = Vec::new();
+    let mut txs = Vec::with_capacity(config.txs);
+
+    for (index, &height) in heights.iter().enumerate() {
+        let txid = fake_txid(config.seed, index);
+        let spend = !utxos.is_empty() && rng.gen_bool(SPEND_PROBABILITY);
+
+        let (vin, vout) = if spend {
+            let (prev_txid, prev_vout, value) = utxos.swap_remove(rng.gen_range(0..utxos.len()));
+            let payment = rng.gen_range(1..=value - FEE_SATS);
+            let change = value - FEE_SATS - payment;
+
+            let mut vout = vec![output(&counterparty, payment)];
+            if change > 0 {
+                // Change too small to pay the fee stays put as dust
+                if change > FEE_SATS {
+                    utxos.push((txid, 1, change));
+                }
+                vout.push(output(wallet, change));
+            }
+            (input(prev_txid, prev_vout, wallet, value), vout)
+        } else {
+            let amount = rng.gen_range(RECEIVE_RANGE_SATS.0..=RECEIVE_RANGE_SATS.1);
+            let change = rng.gen_range(RECEIVE_RANGE_SATS.0..=RECEIVE_RANGE_SATS.1);
+            utxos.push((txid, 0, amount));
+
+            let funding = fake_txid(config.seed ^ u64::MAX, index);
+            (
+                input(funding, 0, &counterparty, amount + change + FEE_SATS),
+                vec![output(wallet, amount), output(&counterparty, change)],
+            )
+        };
+
+        let in_mempool = config.txs > 1 && index == config.txs - 1;
+        let status = if in_mempool {
+            TxStatus { confirmed: false, block_height: None, block_hash: None, block_time: None }
+        } else {
+            TxStatus {
+                confirmed: true,
+                block_height: Some(height),
+                block_hash: Some(BlockHash::from_hash(sha256d::Hash::hash(&height.to_le_bytes()))),
+                block_time: Some(now.saturating_sub(u64::from(config.blocks - height) * BLOCK_INTERVAL_SECS)),
+            }
+        };
+
+        txs.push(Tx { txid, version: 2, locktime: 0, vin: vec![vin], vout, status, fee: FEE_SATS });
+    }
+
+    txs.reverse();
+    Ok(SimChain { tip_height: config.blocks, txs })
+}
+
+fn fake_txid(seed: u64, index: usize) -> Txid {
+    let mut bytes = seed.to_le_bytes().to_vec();
+    bytes.extend_from_slice(&(index as u64).to_le_bytes());
+    Txid::from_hash(sha256d::Hash::hash(&bytes))
+}
+
+fn input(txid: Txid, vout: u32, script: &Script, value: u64) -> Vin {
+    Vin {
+        txid,
+        vout,
+        prevout: Some(PrevOut { value, scriptpubkey: script.clone() }),
+        scriptsig: Script::new(),
+        witness: Vec::new(),
+        sequence: 0xffff_fffd,
+        is_coinbase: false,
+    }
+}
+
+fn output(script: &Script, value: u64) -> Vout {
+    Vout { value, scriptpubkey: script.clone() }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn wallet() -> Script {
+        Script::new_v0_p2wpkh(&WPubkeyHash::hash(b"wallet"))
+    }
+
+    fn config(txs: usize) -> SimConfig {
+        SimConfig { blocks: 500, txs, seed: 7 }
+    }
+
+    #[test]
+    fn test_chain_is_deterministic_and_newest_first() {
+        let a = build_chain(&wallet(), &config(20), 1_700_000_000).unwrap();
+        let b = build_chain(&wallet(), &config(20), 1_700_000_000).unwrap();
+        assert_eq!(a.txs.len(), 20);
+        assert_eq!(a.txs.iter().map(|tx| tx.txid).collect::<Vec<_>>(), b.txs.iter().map(|tx| tx.txid).collect::<Vec<_>>());
+
+        // Esplora order: mempool first, then descending height
+        assert!(!a.txs[0].status.confirmed);
+        let heights: Vec<u32> = a.txs.iter().filter_map(|tx| tx.status.block_height).collect();
+        assert!(heights.windows(2).all(|w| w[0] >= w[1]));
+        assert!(heights.iter().all(|&h| h >= 1 && h <= a.tip_height));
+    }
+
+    #[test]
+    fn test_every_tx_touches_the_wallet_and_balances() {
+        let wallet = wallet();
+        let chain = build_chain(&wallet, &config(30), 1_700_000_000).unwrap();
+
+        for tx in &chain.txs {
+            let pays_wallet = tx.vout.iter().any(|o| o.scriptpubkey == wallet);
+            let spends_wallet = tx.vin.iter().any(|i| i.prevout.as_ref().unwrap().scriptpubkey == wallet);
+            assert!(pays_wallet || spends_wallet);
+
+            let inputs: u64 = tx.vin.iter().map(|i| i.prevout.as_ref().unwrap().value).sum();
+            let outputs: u64 = tx.vout.iter().map(|o| o.value).sum();
+            assert_eq!(inputs, outputs + tx.fee);
+        }
+    }
+
+    #[test]
+    fn test_spends_only_existing_wallet_outputs() {
+        let wallet = wallet();
+        let chain = build_chain(&wallet, &config(40), 1_700_000_000).unwrap();
+        let created: Vec<(Txid, u32)> = chain
+            .txs
+            .iter()
+            .flat_map(|tx| tx.vout.iter().enumerate().filter(|(_, o)| o.scriptpubkey == wallet).map(move |(i, _)| (tx.txid, i as u32)))
+            .collect();
+
+        for input in chain.txs.iter().flat_map(|tx| &tx.vin) {
+            if input.prevout.as_ref().unwrap().scriptpubkey == wallet {
+                assert!(created.contains(&(input.txid, input.vout)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_single_tx_is_confirmed_and_empty_chain_rejected() {
+        let chain = build_chain(&wallet(), &config(1), 1_700_000_000).unwrap();
+        assert!(chain.txs[0].status.confirmed);
+
+        let empty = SimConfig { blocks: 0, txs: 1, seed: 0 };
+        assert!(build_chain(&wallet(), &empty, 0).is_err());
+    }
+}
@@ -0,0 +1,210 @@
+//! What it takes to spend each unspent output, for the `--utxos` table: a
+//! single key, an M-of-N multisig, or some other script
+//!
+//! A descriptor says exactly what its scripts need. For an address, the
+//! script type is all there is until a spend reveals the script behind it:
+//! P2PKH and P2WPKH need one key, but a P2SH, P2WSH or P2TR script could be
+//! anything, and none of it shows the checked keys can sign. Those outputs
+//! are counted so the caller can warn about them.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::Script;
+use bdk::descriptor::{Descriptor, DescriptorPublicKey};
+use bdk::esplora_client::Tx;
+use bdk::miniscript::policy::{Liftable, Semantic};
+use bdk::miniscript::MiniscriptKey;
+use std::collections::{HashMap, HashSet};
+
+use crate::query::script_hash;
+use crate::timelocks::{self, Condition};
+use crate::utxos::Utxo;
+use crate::wallet::DerivedScript;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Requirement {
+    SingleKey,
+    Multisig(usize, usize),
+    /// Any other policy, with how many keys it involves
+    Script { keys: usize, timelock: bool },
+    /// A taproot output with script paths: its key, or any of its leaves
+    KeyOrScriptPath { keys: usize, timelock: bool },
+    Unknown,
+}
+
+/// Where a requirement comes from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Basis {
+    Descriptor,
+    /// The script an earlier spend from the address revealed
+    RevealedScript,
+    /// Only the address's script type, which proves nothing
+    ScriptType,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Spendability {
+    pub requirement: Requirement,
+    pub basis: Basis,
+}
+
+impl Spendability {
+    pub fn describe(&self) -> String {
+        match self.requirement {
+            Requirement::SingleKey => "single key".to_string(),
+            Requirement::Multisig(k, n) => format!("{}-of-{} multisig", k, n),
+            Requirement::Script { keys, timelock } => format!("script, {}", keys_and_timelock(keys, timelock)),
+            Requirement::KeyOrScriptPath { keys, timelock } => {
+                format!("key path or script path, {}", keys_and_timelock(keys, timelock))
+            }
+            Requirement::Unknown => "unknown script".to_string(),
+        }
+    }
+}
+
+fn keys_and_timelock(keys: usize, timelock: bool) -> String {
+    format!("{} {}{}", keys, if keys == 1 { "key" } else { "keys" }, if timelock { ", timelock" } else { "" })
+}
+
+impl Requirement {
+    /// What a descriptor's scripts need. Lifting a taproot descriptor with
+    /// script paths makes a 1-of-N of its key and leaves, which isn't a
+    /// multisig, so those are told apart first.
+    pub fn from_descriptor(descriptor: &Descriptor<DescriptorPublicKey>) -> Option<Self> {
+        let policy = descriptor.lift().ok()?;
+        match descriptor {
+            Descriptor::Tr(tr) if tr.taptree().is_some() => {
+                let mut keys = HashSet::new();
+                collect_keys(&policy, &mut keys);
+                Some(Requirement::KeyOrScriptPath { keys: keys.len(), timelock: Condition::from_policy(&policy).has_timelock() })
+            }
+            _ => Some(Requirement::from_policy(&policy)),
+        }
+    }
+
+    pub fn from_policy<Pk: MiniscriptKey>(policy: &Semantic<Pk>) -> Self {
+        match policy {
+            Semantic::Key(_) => Requirement::SingleKey,
+            Semantic::Threshold(k, subs) if subs.iter().all(|sub| matches!(sub, Semantic::Key(_))) => {
+                if subs.len() == 1 {
+                    Requirement::SingleKey
+                } else {
+                    Requirement::Multisig(*k, subs.len())
+                }
+            }
+            _ => {
+                let mut keys = HashSet::new();
+                collect_keys(policy, &mut keys);
+                Requirement::Script { keys: keys.len(), timelock: Condition::from_policy(policy).has_timelock() }
+            }
+        }
+    }
+
+    /// All an address says: P2PK, P2PKH and P2WPKH pay a single key
+    fn from_script_type(script: &Script) -> Self {
+        if script.is_p2pk() || script.is_p2pkh() || script.is_v0_p2wpkh() {
+            Requirement::SingleKey
+        } else {
+            Requirement::Unknown
+        }
+    }
+}
+
+/// Distinct keys of a policy; a key can appear on several paths
+fn collect_keys<Pk: MiniscriptKey>(policy: &Semantic<Pk>, keys: &mut HashSet<String>) {
+    match policy {
+        Semantic::Key(key) => {
+            keys.insert(key.to_string());
+        }
+        Semantic::Threshold(_, subs) => subs.iter().for_each(|sub| collect_keys(sub, keys)),
+        _ => {}
+    }
+}
+
+/// Requirements of the wallet's `derived` scripts, then of scripts an input
+/// in `txs` revealed
+pub fn known(
+    derived: &HashMap<sha256::Hash, DerivedScript>,
+    txs: &[Tx],
+    scripts: &HashSet<sha256::Hash>,
+) -> HashMap<sha256::Hash, Spendability> {
+    // Worked out once per descriptor rather than per script
+    let mut requirements: HashMap<usize, Option<Requirement>> = HashMap::new();
+    let mut known = HashMap::new();
+    for (hash, script) in derived {
+        let requirement =
+            *requirements.entry(script.position).or_insert_with(|| Requirement::from_descriptor(&script.entry.descriptor));
+        if let Some(requirement) = requirement {
+            known.insert(*hash, Spendability { requirement, basis: Basis::Descriptor });
+        }
+    }
+
+    for (hash, policy) in timelocks::revealed_policies(txs, scripts) {
+        known
+            .entry(hash)
+            .or_insert(Spendability { requirement: Requirement::from_policy(&policy), basis: Basis::RevealedScript });
+    }
+    known
+}
+
+/// The known requirement of the output's script, or what its type implies
+pub fn classify(utxo: &Utxo, known: &HashMap<sha256::Hash, Spendability>) -> Spendability {
+    known.get(&script_hash(&utxo.script)).copied().unwrap_or(Spendability {
+        requirement: Requirement::from_script_type(&utxo.script),
+        basis: Basis::ScriptType,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    use crate::test_util::{p2wpkh, utxo};
+    use crate::wallet::{derive_scripts, WalletDescriptor};
+
+    const KEY: &str = "tpubD6NzVbkrYhZ4WaWSyoBvQwbpLkojyoTZPRsgXELWz3Popb3qkjcJyJUGLnL4qHHoQvao8ESaAstxYSnhyswJ76uZPStJRJCTKvosUCJZL5B";
+    const OTHER: &str = "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp";
+
+    fn requirement(descriptor: &str) -> Requirement {
+        let descriptor = descriptor.replace("KEY", &format!("{}/0/*", KEY)).replace("OTHER", &format!("{}/0/*", OTHER));
+        Requirement::from_descriptor(&Descriptor::<DescriptorPublicKey>::from_str(&descriptor).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_descriptor_requirements() {
+        assert_eq!(requirement("wpkh(KEY)"), Requirement::SingleKey);
+        assert_eq!(requirement("sh(wpkh(KEY))"), Requirement::SingleKey);
+        assert_eq!(requirement("tr(KEY)"), Requirement::SingleKey);
+        assert_eq!(requirement("wsh(sortedmulti(1,KEY,OTHER))"), Requirement::Multisig(1, 2));
+        assert_eq!(
+            requirement("wsh(or_d(pk(KEY),and_v(v:pk(OTHER),older(144))))"),
+            Requirement::Script { keys: 2, timelock: true }
+        );
+        assert_eq!(requirement("tr(KEY,pk(OTHER))"), Requirement::KeyOrScriptPath { keys: 2, timelock: false });
+        assert_eq!(
+            Spendability { requirement: requirement("tr(KEY,pk(OTHER))"), basis: Basis::Descriptor }.describe(),
+            "key path or script path, 2 keys"
+        );
+        assert_eq!(Spendability { requirement: Requirement::Multisig(2, 3), basis: Basis::Descriptor }.describe(), "2-of-3 multisig");
+        assert_eq!(
+            Spendability { requirement: Requirement::Script { keys: 1, timelock: true }, basis: Basis::Descriptor }.describe(),
+            "script, 1 key, timelock"
+        );
+    }
+
+    #[test]
+    fn test_classify() {
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&format!("wsh(multi(2,{}/0/*,{}/0/*))", KEY, OTHER)).unwrap();
+        let entry = WalletDescriptor { label: "Wsh receive".to_string(), descriptor: descriptor.clone(), keychain: None };
+        let known = known(&derive_scripts(&[(&entry, 3)]), &[], &HashSet::new());
+        let utxo = |script: Script| utxo(0x11, 1_000, None, script);
+
+        let ours = classify(&utxo(descriptor.at_derivation_index(2).script_pubkey()), &known);
+        assert_eq!(ours, Spendability { requirement: Requirement::Multisig(2, 2), basis: Basis::Descriptor });
+        // Past the scanned indexes, it's just a P2WSH output
+        let unknown = classify(&utxo(descriptor.at_derivation_index(3).script_pubkey()), &known);
+        assert_eq!(unknown, Spendability { requirement: Requirement::Unknown, basis: Basis::ScriptType });
+        let p2wpkh = classify(&utxo(p2wpkh()), &known);
+        assert_eq!(p2wpkh, Spendability { requirement: Requirement::SingleKey, basis: Basis::ScriptType });
+    }
+}
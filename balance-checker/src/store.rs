@@ -0,0 +1,542 @@
+//! Local wallet store, kept per network under
+//! `~/.local/share/bdk-experiments/balance-checker/<network>` (or
+//! `$XDG_DATA_HOME`), so repeat Esplora and Electrum checks only fetch what's new
+//!
+//! Each checked descriptor gets BDK's SQLite wallet database, named by the
+//! descriptor's checksum; an address or script is kept as its `addr()` or
+//! `raw()` descriptor. The database holds the descriptor checksum, each
+//! derived script with its index, the transactions with their fees and
+//! block times, the outputs they spend and create, and the sync checkpoint:
+//! the tip and time of the last sync. A script given only by its hash has
+//! no descriptor, so its history isn't stored.
+//!
+//! Transactions that had [`SETTLED_DEPTH`] confirmations at the checkpoint
+//! are settled: the next run pages through Esplora's newest-first history
+//! only until it reaches one, and takes the rest from the store; Electrum
+//! lists every txid at once, and only those not settled are downloaded.
+//! Anything newer, including unconfirmed transactions, is fetched again once
+//! the checkpoint is older than the TTL (`--cache-ttl`) or the tip has
+//! moved, so a shallow reorg or a dropped mempool transaction doesn't
+//! linger. Within the TTL a rerun takes each stored script's whole history
+//! from the store.
+//!
+//! The reports need every input's spent output, so those are stored as
+//! spent UTXOs even when another wallet owned them; BDK leaves spent
+//! outputs out of balances, so they don't count as the wallet's funds.
+//!
+//! Descriptors checked over Esplora are synced by BDK itself instead (see
+//! [`crate::sync`]), each into its own `<checksum>-synced` database with
+//! the same layout. Within the TTL of its last sync at this tip, a rerun
+//! reads it without syncing again.
+//!
+//! `--rescan-from HEIGHT` cuts every stored wallet back to what was known
+//! below that block, for a deeper reorg than the settled depth allows for or
+//! a store that went wrong.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::{OutPoint, Script, TxOut, Txid};
+use bdk::database::{BatchDatabase, BatchOperations, Database, SqliteDatabase, SyncTime};
+use bdk::descriptor::calc_checksum;
+use bdk::esplora_client::{PrevOut, Tx, TxStatus, Vin, Vout};
+use bdk::{BlockTime, KeychainKind, LocalUtxo, TransactionDetails};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::chain::Chain;
+use crate::query::script_hash;
+
+/// Directory in `$XDG_DATA_HOME` shared by the workspace's tools
+const SHARED_DIR: &str = "bdk-experiments";
+/// Directory inside the shared one; one directory of wallet databases per
+/// network, beside the label database
+const STORE_DIR: &str = "balance-checker";
+
+const DATABASE_EXTENSION: &str = "sqlite";
+
+/// Added to the checksum in the name of a database BDK's wallet sync keeps
+const SYNCED_SUFFIX: &str = "-synced";
+
+/// Confirmations after which a transaction isn't expected to be reorged out
+pub const SETTLED_DEPTH: u32 = 6;
+
+/// The network's directory of wallet databases, stamped with this run's tip
+pub struct StoreDir {
+    path: PathBuf,
+    /// Chain tip when this run started, saved as every wallet's checkpoint
+    tip_height: u32,
+    /// How long a checkpoint at the same tip is used whole, unconfirmed
+    /// transactions included
+    ttl: Duration,
+}
+
+impl StoreDir {
+    pub fn open(path: &Path, tip_height: u32, ttl: Duration) -> Result<Self, String> {
+        fs::create_dir_all(path).map_err(|e| format!("Could not open history store {}: {}", path.display(), e))?;
+        Ok(StoreDir { path: path.to_path_buf(), tip_height, ttl })
+    }
+
+    /// Stops using whole stored histories, e.g. while watching for changes
+    pub fn set_ttl(&mut self, ttl: Duration) {
+        self.ttl = ttl;
+    }
+
+    /// The database of `descriptor`, whose scripts are on `keychain`. One
+    /// that can't be opened only warns, since the store is a cache.
+    pub fn wallet(&self, descriptor: &str, keychain: KeychainKind) -> Option<HistoryStore> {
+        let body = descriptor.split('#').next().unwrap_or(descriptor);
+        let checksum = calc_checksum(body).ok()?;
+        let path = self.path.join(&checksum).with_extension(DATABASE_EXTENSION);
+        match HistoryStore::open(&path, &checksum, keychain, self.tip_height, self.ttl) {
+            Ok(store) => Some(store),
+            Err(e) => {
+                eprintln!("Warning: {}; fetching full histories", e);
+                None
+            }
+        }
+    }
+
+    /// The database BDK's wallet sync keeps for `descriptor`, and whether
+    /// its last sync is at this run's tip and younger than the TTL. One that
+    /// can't be opened only warns, since the store is a cache.
+    pub fn synced_wallet(&self, descriptor: &str) -> Option<(SqliteDatabase, bool)> {
+        let body = descriptor.split('#').next().unwrap_or(descriptor);
+        let checksum = calc_checksum(body).ok()?;
+        let path = self.path.join(format!("{}{}", checksum, SYNCED_SUFFIX)).with_extension(DATABASE_EXTENSION);
+        let opened = open_database(&path).and_then(|db| {
+            let sync = db.get_sync_time().map_err(|e| store_error(&path, e))?.map(|sync| sync.block_time);
+            let fresh = sync.is_some_and(|sync| {
+                sync.height == self.tip_height && now().saturating_sub(sync.timestamp) < self.ttl.as_secs()
+            });
+            Ok((db, fresh))
+        });
+        match opened {
+            Ok(opened) => Some(opened),
+            Err(e) => {
+                eprintln!("Warning: {}; syncing without the store", e);
+                None
+            }
+        }
+    }
+
+    /// Drops what every stored wallet says about block `height` and above,
+    /// as if each was last synced at the block below it, and returns how
+    /// many wallets that changed. The next lookup of each fetches everything
+    /// from `SETTLED_DEPTH` blocks below `height` again.
+    pub fn discard_from(&self, height: u32) -> Result<usize, String> {
+        let entries = fs::read_dir(&self.path).map_err(|e| format!("Could not read the history store: {}", e))?;
+        let mut discarded = 0;
+        for entry in entries {
+            let path = entry.map_err(|e| format!("Could not read the history store: {}", e))?.path();
+            if path.extension().is_none_or(|extension| extension != DATABASE_EXTENSION) {
+                continue;
+            }
+            let mut db = open_database(&path)?;
+            let synced_at = db.get_sync_time().map_err(|e| store_error(&path, e))?;
+            if synced_at.is_none_or(|sync| sync.block_time.height < height) {
+                continue;
+            }
+            let below = height.saturating_sub(1);
+            let txs = db.iter_txs(false).map_err(|e| store_error(&path, e))?;
+            let dropped: HashSet<Txid> = txs
+                .iter()
+                .filter(|details| details.confirmation_time.as_ref().is_none_or(|time| time.height >= height))
+                .map(|details| details.txid)
+                .collect();
+            let utxos = db.iter_utxos().map_err(|e| store_error(&path, e))?;
+            let mut batch = db.begin_batch();
+            delete_txs(&mut batch, &dropped, &utxos).map_err(|e| store_error(&path, e))?;
+            batch
+                .set_sync_time(SyncTime { block_time: BlockTime { height: below, timestamp: 0 } })
+                .map_err(|e| store_error(&path, e))?;
+            db.commit_batch(batch).map_err(|e| store_error(&path, e))?;
+            discarded += 1;
+        }
+        Ok(discarded)
+    }
+}
+
+/// What's been read from one wallet's database, kept up to date as it's saved
+struct Stored {
+    db: SqliteDatabase,
+    txs: HashMap<Txid, Tx>,
+    /// Transactions paying or spending each stored script
+    by_script: HashMap<sha256::Hash, HashSet<Txid>>,
+    /// Every stored script
+    scripts: HashSet<sha256::Hash>,
+}
+
+/// One descriptor's wallet database
+pub struct HistoryStore {
+    stored: Mutex<Stored>,
+    keychain: KeychainKind,
+    /// Tip of this run, saved as the checkpoint
+    tip_height: u32,
+    /// Height of the checkpoint when opened
+    synced_at: Option<u32>,
+    /// The checkpoint is at this run's tip and younger than the TTL
+    fresh: bool,
+}
+
+impl HistoryStore {
+    fn open(path: &Path, checksum: &str, keychain: KeychainKind, tip_height: u32, ttl: Duration) -> Result<Self, String> {
+        let mut db = open_database(path)?;
+        db.check_descriptor_checksum(keychain, checksum.as_bytes()).map_err(|e| store_error(path, e))?;
+        let sync = db.get_sync_time().map_err(|e| store_error(path, e))?.map(|sync| sync.block_time);
+        let fresh = sync.as_ref().is_some_and(|sync| {
+            sync.height == tip_height && now().saturating_sub(sync.timestamp) < ttl.as_secs()
+        });
+
+        let scripts: HashSet<sha256::Hash> =
+            db.iter_script_pubkeys(Some(keychain)).map_err(|e| store_error(path, e))?.iter().map(script_hash).collect();
+        let prevouts: HashMap<OutPoint, TxOut> = db
+            .iter_utxos()
+            .map_err(|e| store_error(path, e))?
+            .into_iter()
+            .map(|utxo| (utxo.outpoint, utxo.txout))
+            .collect();
+        let mut stored = Stored { db, txs: HashMap::new(), by_script: HashMap::new(), scripts };
+        let details = stored.db.iter_txs(true).map_err(|e| store_error(path, e))?;
+        for details in details {
+            if let Some(tx) = from_details(&details, &prevouts) {
+                stored.index(tx);
+            }
+        }
+        Ok(HistoryStore { stored: Mutex::new(stored), keychain, tip_height, synced_at: sync.map(|sync| sync.height), fresh })
+    }
+
+    /// The script's whole stored history, if it was stored at a checkpoint
+    /// at the current tip less than the TTL ago
+    pub fn fresh(&self, script_hash: &sha256::Hash) -> Option<Vec<Tx>> {
+        let stored = self.stored.lock().expect("no lookup panics while holding the store");
+        (self.fresh && stored.scripts.contains(script_hash)).then(|| stored.history(script_hash).cloned().collect())
+    }
+
+    /// Stored transactions of the script that were settled at the checkpoint
+    pub fn settled(&self, script_hash: &sha256::Hash) -> Vec<Tx> {
+        let Some(synced_at) = self.synced_at else {
+            return Vec::new();
+        };
+        let stored = self.stored.lock().expect("no lookup panics while holding the store");
+        stored
+            .history(script_hash)
+            .filter(|tx| tx.status.block_height.is_some_and(|height| synced_at.saturating_sub(height) + 1 >= SETTLED_DEPTH))
+            .cloned()
+            .collect()
+    }
+
+    /// Saves the full history of `script`, derived at `index`, and moves the
+    /// checkpoint to this run's tip. Failures only warn, since the store is
+    /// a cache and the lookup itself succeeded.
+    pub fn save(&self, script: &Script, index: u32, txs: &[Tx]) {
+        let mut stored = self.stored.lock().expect("no lookup panics while holding the store");
+        if let Err(e) = stored.save(script, self.keychain, index, txs, self.tip_height) {
+            eprintln!("Warning: Could not save history of {} to the history store: {}", script_hash(script), e);
+        }
+    }
+}
+
+impl Stored {
+    fn history<'a>(&'a self, script_hash: &sha256::Hash) -> impl Iterator<Item = &'a Tx> {
+        self.by_script.get(script_hash).into_iter().flatten().filter_map(|txid| self.txs.get(txid))
+    }
+
+    /// Adds `tx` to the scripts it pays or spends
+    fn index(&mut self, tx: Tx) {
+        let touched = tx
+            .vout
+            .iter()
+            .map(|output| &output.scriptpubkey)
+            .chain(tx.vin.iter().filter_map(|input| input.prevout.as_ref().map(|prevout| &prevout.scriptpubkey)))
+            .map(script_hash)
+            .filter(|hash| self.scripts.contains(hash))
+            .collect::<Vec<_>>();
+        for hash in touched {
+            self.by_script.entry(hash).or_default().insert(tx.txid);
+        }
+        self.txs.insert(tx.txid, tx);
+    }
+
+    fn save(&mut self, script: &Script, keychain: KeychainKind, index: u32, txs: &[Tx], tip_height: u32) -> Result<(), String> {
+        // BDK keys each transaction by the txid of its contents
+        if let Some(tx) = txs.iter().find(|tx| tx.to_tx().txid() != tx.txid) {
+            return Err(format!("the server's transaction {} doesn't match its txid", tx.txid));
+        }
+        self.write(script, keychain, index, txs, tip_height).map_err(|e| e.to_string())
+    }
+
+    fn write(&mut self, script: &Script, keychain: KeychainKind, index: u32, txs: &[Tx], tip_height: u32) -> Result<(), bdk::Error> {
+        let hash = script_hash(script);
+        self.scripts.insert(hash);
+        let current: HashSet<Txid> = txs.iter().map(|tx| tx.txid).collect();
+        let spent: HashSet<OutPoint> =
+            txs.iter().flat_map(|tx| tx.vin.iter().map(|input| OutPoint::new(input.txid, input.vout))).collect();
+
+        let mut batch = self.db.begin_batch();
+        batch.set_script_pubkey(script, keychain, index)?;
+        if self.db.get_last_index(keychain)?.is_none_or(|last| last < index) {
+            batch.set_last_index(keychain, index)?;
+        }
+        // Gone from the history since it was stored: dropped from the
+        // mempool, or reorged out
+        let gone: HashSet<Txid> = self.history(&hash).map(|tx| tx.txid).filter(|txid| !current.contains(txid)).collect();
+        delete_txs(&mut batch, &gone, &self.db.iter_utxos()?)?;
+        for tx in txs {
+            batch.set_tx(&self.details(tx))?;
+            for input in &tx.vin {
+                if let Some(prevout) = &input.prevout {
+                    let txout = TxOut { value: prevout.value, script_pubkey: prevout.scriptpubkey.clone() };
+                    let outpoint = OutPoint::new(input.txid, input.vout);
+                    batch.set_utxo(&LocalUtxo { outpoint, txout, keychain, is_spent: true })?;
+                }
+            }
+            for (vout, output) in tx.vout.iter().enumerate().filter(|(_, output)| output.scriptpubkey == *script) {
+                let outpoint = OutPoint::new(tx.txid, vout as u32);
+                let txout = TxOut { value: output.value, script_pubkey: output.scriptpubkey.clone() };
+                batch.set_utxo(&LocalUtxo { outpoint, txout, keychain, is_spent: spent.contains(&outpoint) })?;
+            }
+        }
+        batch.set_sync_time(SyncTime { block_time: BlockTime { height: tip_height, timestamp: now() } })?;
+        self.db.commit_batch(batch)?;
+
+        for txid in gone {
+            self.txs.remove(&txid);
+            self.by_script.values_mut().for_each(|txids| {
+                txids.remove(&txid);
+            });
+        }
+        for tx in txs {
+            self.index(tx.clone());
+        }
+        Ok(())
+    }
+
+    /// BDK's record of `tx`: what it paid to and spent from the stored
+    /// scripts, its fee, and its block
+    fn details(&self, tx: &Tx) -> TransactionDetails {
+        let received =
+            tx.vout.iter().filter(|output| self.scripts.contains(&script_hash(&output.scriptpubkey))).map(|output| output.value).sum();
+        let sent = tx
+            .vin
+            .iter()
+            .filter_map(|input| input.prevout.as_ref())
+            .filter(|prevout| self.scripts.contains(&script_hash(&prevout.scriptpubkey)))
+            .map(|prevout| prevout.value)
+            .sum();
+        TransactionDetails {
+            transaction: Some(tx.to_tx()),
+            txid: tx.txid,
+            received,
+            sent,
+            fee: Some(tx.fee),
+            confirmation_time: tx
+                .status
+                .block_height
+                .map(|height| BlockTime { height, timestamp: tx.status.block_time.unwrap_or(0) }),
+        }
+    }
+}
+
+/// Deletes the transactions and the outputs they created
+fn delete_txs(batch: &mut impl BatchOperations, txids: &HashSet<Txid>, utxos: &[LocalUtxo]) -> Result<(), bdk::Error> {
+    for txid in txids {
+        batch.del_tx(txid, true)?;
+    }
+    for utxo in utxos.iter().filter(|utxo| txids.contains(&utxo.outpoint.txid)) {
+        batch.del_utxo(&utxo.outpoint)?;
+    }
+    Ok(())
+}
+
+/// The transaction as Esplora serves it, from BDK's record and the stored
+/// outputs its inputs spend; the block hash isn't kept
+pub fn from_details(details: &TransactionDetails, prevouts: &HashMap<OutPoint, TxOut>) -> Option<Tx> {
+    let tx = details.transaction.as_ref()?;
+    let vin = tx
+        .input
+        .iter()
+        .map(|input| Vin {
+            txid: input.previous_output.txid,
+            vout: input.previous_output.vout,
+            prevout: prevouts
+                .get(&input.previous_output)
+                .map(|txout| PrevOut { value: txout.value, scriptpubkey: txout.script_pubkey.clone() }),
+            scriptsig: input.script_sig.clone(),
+            witness: input.witness.to_vec(),
+            sequence: input.sequence.0,
+            is_coinbase: input.previous_output.is_null(),
+        })
+        .collect();
+    Some(Tx {
+        txid: details.txid,
+        version: tx.version,
+        locktime: tx.lock_time.0,
+        vin,
+        vout: tx.output.iter().map(|output| Vout { value: output.value, scriptpubkey: output.script_pubkey.clone() }).collect(),
+        status: TxStatus {
+            confirmed: details.confirmation_time.is_some(),
+            block_height: details.confirmation_time.as_ref().map(|time| time.height),
+            block_hash: None,
+            block_time: details.confirmation_time.as_ref().map(|time| time.timestamp),
+        },
+        fee: details.fee.unwrap_or(0),
+    })
+}
+
+/// Opens or creates the database at `path`. BDK panics on a file SQLite
+/// can't open, so it's checked first.
+fn open_database(path: &Path) -> Result<SqliteDatabase, String> {
+    fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(false)
+        .open(path)
+        .map_err(|e| format!("Could not open history store {}: {}", path.display(), e))?;
+    Ok(SqliteDatabase::new(path))
+}
+
+fn store_error(path: &Path, e: bdk::Error) -> String {
+    format!("History store {} failed: {}", path.display(), e)
+}
+
+/// Where the history store for `chain` lives
+pub fn default_path(chain: Chain) -> Option<PathBuf> {
+    Some(data_dir()?.join(chain.name()))
+}
+
+/// The checker's own directory inside [`shared_dir`]
+pub fn data_dir() -> Option<PathBuf> {
+    Some(shared_dir()?.join(STORE_DIR))
+}
+
+/// The tools' directory in `$XDG_DATA_HOME` if set, otherwise in
+/// `~/.local/share`
+pub fn shared_dir() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+    Some(base.join(SHARED_DIR))
+}
+
+/// Seconds since the Unix epoch
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::hashes::hex::ToHex;
+    use bdk::bitcoin::hashes::Hash;
+    use bdk::bitcoin::BlockHash;
+
+    use crate::test_util::{input, output, p2wpkh, status, txid, P2WPKH as OURS};
+
+    fn temporary_dir(name: &str, tip_height: u32, ttl: Duration) -> StoreDir {
+        let path = std::env::temp_dir().join(format!("balance-checker-store-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&path);
+        StoreDir::open(&path, tip_height, ttl).unwrap()
+    }
+
+    fn descriptor() -> String {
+        format!("raw({})", OURS)
+    }
+
+    /// BDK keys raw transactions by their computed txid, so it's the real one
+    fn tx(id: u8, height: Option<u32>) -> Tx {
+        let vin = Vin {
+            witness: vec![vec![0x30, 0x44], vec![0x02]],
+            sequence: 0xffff_fffd,
+            ..input(txid(0xee), u32::from(id), p2wpkh(), 20_000)
+        };
+        let status = TxStatus {
+            block_hash: height.map(|_| BlockHash::all_zeros()),
+            ..status(height, height.map(|height| 1_700_000_000 + u64::from(height) * 600))
+        };
+        let mut tx = Tx { status, fee: 1_000, ..crate::test_util::tx(0, vec![vin], vec![output(p2wpkh(), 19_000)]) };
+        tx.txid = tx.to_tx().txid();
+        tx
+    }
+
+    fn save(dir: &StoreDir, txs: &[Tx]) {
+        let store = dir.wallet(&descriptor(), KeychainKind::External).unwrap();
+        store.save(&p2wpkh(), 0, txs);
+    }
+
+    fn hash() -> sha256::Hash {
+        script_hash(&p2wpkh())
+    }
+
+    #[test]
+    fn test_tx_round_trips_through_the_database() {
+        let dir = temporary_dir("round-trip", 110, Duration::from_secs(60));
+        let original = tx(1, Some(100));
+        save(&dir, std::slice::from_ref(&original));
+
+        let stored = dir.wallet(&descriptor(), KeychainKind::External).unwrap().fresh(&hash()).unwrap();
+        assert_eq!(stored[0].txid, original.txid);
+        assert_eq!(stored[0].vin, original.vin);
+        assert_eq!(stored[0].vout, original.vout);
+        assert_eq!(stored[0].status, TxStatus { block_hash: None, ..original.status });
+        assert_eq!(stored[0].fee, original.fee);
+        // The database is BDK's own: the script, its output and the checkpoint
+        let db = SqliteDatabase::new(dir.path.join(calc_checksum(&descriptor()).unwrap()).with_extension("sqlite"));
+        assert_eq!(db.iter_script_pubkeys(None).unwrap()[0].to_hex(), OURS);
+        assert_eq!(db.get_tx(&original.txid, false).unwrap().unwrap().received, 19_000);
+        assert_eq!(db.get_sync_time().unwrap().unwrap().block_time.height, 110);
+        assert!(!db.get_utxo(&OutPoint::new(original.txid, 0)).unwrap().unwrap().is_spent);
+    }
+
+    #[test]
+    fn test_settled_keeps_only_deep_transactions() {
+        let dir = temporary_dir("settled", 110, Duration::ZERO);
+        assert!(dir.wallet(&descriptor(), KeychainKind::External).unwrap().settled(&hash()).is_empty());
+
+        // Six confirmations at 105, five at 106
+        save(&dir, &[tx(3, None), tx(2, Some(106)), tx(1, Some(105))]);
+        let settled: Vec<Option<u32>> =
+            dir.wallet(&descriptor(), KeychainKind::External).unwrap().settled(&hash()).iter().map(|tx| tx.status.block_height).collect();
+        assert_eq!(settled, vec![Some(105)]);
+    }
+
+    #[test]
+    fn test_fresh_history_is_used_whole_until_the_tip_moves() {
+        let mut dir = temporary_dir("fresh", 110, Duration::ZERO);
+        save(&dir, &[tx(2, None), tx(1, Some(105))]);
+        // With no TTL, nothing is fresh
+        assert!(dir.wallet(&descriptor(), KeychainKind::External).unwrap().fresh(&hash()).is_none());
+
+        dir.set_ttl(Duration::from_secs(60));
+        assert_eq!(dir.wallet(&descriptor(), KeychainKind::External).unwrap().fresh(&hash()).unwrap().len(), 2);
+
+        // A new block makes the unconfirmed part stale; a transaction that
+        // left the history is dropped when it's saved again
+        let moved = StoreDir { tip_height: 111, ..dir };
+        let store = moved.wallet(&descriptor(), KeychainKind::External).unwrap();
+        assert!(store.fresh(&hash()).is_none());
+        assert_eq!(store.settled(&hash()).len(), 1);
+        store.save(&p2wpkh(), 0, &[tx(1, Some(105))]);
+        assert_eq!(moved.wallet(&descriptor(), KeychainKind::External).unwrap().fresh(&hash()).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_discard_from_forgets_blocks_at_and_above_the_height() {
+        let dir = temporary_dir("discard", 200, Duration::from_secs(60));
+        save(&dir, &[tx(4, None), tx(3, Some(190)), tx(2, Some(160)), tx(1, Some(150))]);
+        let early = StoreDir { tip_height: 140, ttl: Duration::ZERO, path: dir.path.clone() };
+        let other = "addr(tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx)";
+        early.wallet(other, KeychainKind::External).unwrap().save(&p2wpkh(), 0, &[tx(1, Some(130))]);
+
+        // Synced below the height, the other wallet is left as it was
+        assert_eq!(dir.discard_from(158), Ok(1));
+        let store = dir.wallet(&descriptor(), KeychainKind::External).unwrap();
+        assert!(store.fresh(&hash()).is_none());
+        // Six confirmations at 157 settles 152 and below
+        assert_eq!(store.settled(&hash()).iter().map(|tx| tx.status.block_height).collect::<Vec<_>>(), vec![Some(150)]);
+        assert_eq!(early.wallet(other, KeychainKind::External).unwrap().settled(&hash()).len(), 1);
+    }
+}
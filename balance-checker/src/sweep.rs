@@ -0,0 +1,194 @@
+//! Sweep estimate for `--sweep-estimate`: the size and fee of one
+//! transaction spending every unspent output to a single output, and what
+//! is left after the fee
+//!
+//! Inputs from a descriptor are sized by the largest satisfaction its
+//! miniscript allows (73-byte signatures), so the fee is an upper bound by a
+//! byte or so per signature. Inputs known only by address are sized from
+//! their script type as `--consolidate-advice` does. The output is of the
+//! wallet's own type: the first descriptor's, or else the largest output's.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::Script;
+use std::collections::HashMap;
+use units::Unit;
+
+use crate::consolidate;
+use crate::query::script_hash;
+use crate::utxos::{script_type, Utxo};
+use crate::wallet::DerivedScript;
+
+/// Outpoint, sequence and the scriptSig length, in weight units; the
+/// satisfaction weight covers the rest
+const INPUT_BASE_WEIGHT: u64 = (32 + 4 + 4) * 4;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sweep {
+    pub fee_rate: f64,
+    pub inputs: usize,
+    pub unconfirmed: usize,
+    pub value: u64,
+    /// Inputs sized from the descriptor rather than guessed from the type
+    pub exact_inputs: usize,
+    pub weight: u64,
+    pub vsize: u64,
+    pub fee: u64,
+    pub destination_type: &'static str,
+    /// Outputs whose own input fee is at least their value, and their total
+    pub uneconomical: usize,
+    pub uneconomical_value: u64,
+    pub uneconomical_fee: u64,
+}
+
+impl Sweep {
+    /// What the single output receives; None when the fee eats it all
+    pub fn net(&self) -> Option<u64> {
+        self.value.checked_sub(self.fee).filter(|net| *net > 0)
+    }
+}
+
+/// Input weights of the wallet's `derived` scripts, by script hash
+pub fn input_weights(derived: &HashMap<sha256::Hash, DerivedScript>) -> HashMap<sha256::Hash, u64> {
+    derived
+        .iter()
+        .filter_map(|(hash, script)| {
+            let satisfaction = script.descriptor.max_satisfaction_weight().ok()?;
+            Some((*hash, INPUT_BASE_WEIGHT + satisfaction as u64))
+        })
+        .collect()
+}
+
+/// Sizes the sweep of `utxos` into one output paying `destination`
+pub fn estimate(utxos: &[Utxo], weights: &HashMap<sha256::Hash, u64>, destination: &Script, fee_rate: f64) -> Sweep {
+    let input_weight = |utxo: &Utxo| weights.get(&script_hash(&utxo.script)).copied();
+    let weight_of = |utxo: &Utxo| input_weight(utxo).unwrap_or_else(|| consolidate::input_weight(&utxo.script));
+    let segwit = utxos.iter().any(|utxo| !utxo.script.is_p2pkh() && !utxo.script.is_p2pk());
+    let weight = consolidate::BASE_WEIGHT
+        + consolidate::count_weight(utxos.len())
+        + if segwit { consolidate::SEGWIT_WEIGHT } else { 0 }
+        + utxos.iter().map(weight_of).sum::<u64>()
+        + consolidate::output_weight(destination);
+    let vsize = weight.div_ceil(4);
+    let uneconomical: Vec<(&Utxo, u64)> = utxos
+        .iter()
+        .map(|utxo| (utxo, consolidate::fee(weight_of(utxo), fee_rate)))
+        .filter(|(utxo, fee)| utxo.value <= *fee)
+        .collect();
+
+    Sweep {
+        fee_rate,
+        inputs: utxos.len(),
+        unconfirmed: utxos.iter().filter(|utxo| utxo.height.is_none()).count(),
+        value: utxos.iter().map(|utxo| utxo.value).sum(),
+        exact_inputs: utxos.iter().filter(|utxo| input_weight(utxo).is_some()).count(),
+        weight,
+        vsize,
+        fee: (vsize as f64 * fee_rate).ceil() as u64,
+        destination_type: script_type(destination),
+        uneconomical: uneconomical.len(),
+        uneconomical_value: uneconomical.iter().map(|(utxo, _)| utxo.value).sum(),
+        uneconomical_fee: uneconomical.iter().map(|(_, fee)| fee).sum(),
+    }
+}
+
+/// Lines of the `Sweep Estimate` section, amounts in `unit`
+pub fn format_sweep(sweep: &Sweep, unit: Unit) -> Vec<String> {
+    let outputs = if sweep.inputs == 1 { "UTXO" } else { "UTXOs" };
+    let mut lines = vec![
+        format!("Inputs:      {} {}, {}", sweep.inputs, outputs, unit.format(sweep.value)),
+        format!("Output:      one {}", sweep.destination_type),
+        format!("Size:        {} vB ({} WU)", sweep.vsize, sweep.weight),
+        format!(
+            "Fee:         {} at {} sat/vB ({:.2}% of the amount)",
+            unit.format(sweep.fee),
+            sweep.fee_rate,
+            sweep.fee as f64 / sweep.value as f64 * 100.0
+        ),
+    ];
+    match sweep.net() {
+        Some(net) => lines.push(format!("Net:         {}", unit.format(net))),
+        None => lines.push("Net:         nothing; the fee is more than the outputs are worth".to_string()),
+    }
+    if sweep.uneconomical > 0 {
+        let outputs = if sweep.uneconomical == 1 { "UTXO costs" } else { "UTXOs cost" };
+        lines.push(format!(
+            "Note: {} {} more to spend than {} worth ({} for {} of fees); leaving them out nets {} more",
+            sweep.uneconomical,
+            outputs,
+            if sweep.uneconomical == 1 { "it's" } else { "they're" },
+            unit.format(sweep.uneconomical_value),
+            unit.format(sweep.uneconomical_fee),
+            unit.format(sweep.uneconomical_fee - sweep.uneconomical_value)
+        ));
+    }
+    if sweep.unconfirmed > 0 {
+        lines.push(format!(
+            "Note: {} of the inputs {} unconfirmed, so the sweep can't confirm before {}",
+            sweep.unconfirmed,
+            if sweep.unconfirmed == 1 { "is" } else { "are" },
+            if sweep.unconfirmed == 1 { "it does" } else { "they do" }
+        ));
+    }
+    if sweep.exact_inputs < sweep.inputs {
+        lines.push(format!(
+            "Note: {} of the inputs {} sized from their script type, assuming single-key spends (2-of-3 multisig for P2WSH)",
+            sweep.inputs - sweep.exact_inputs,
+            if sweep.inputs - sweep.exact_inputs == 1 { "is" } else { "are" }
+        ));
+    }
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::descriptor::{Descriptor, DescriptorPublicKey};
+    use std::str::FromStr;
+
+    use crate::test_util::utxo;
+    use crate::wallet::{derive_scripts, WalletDescriptor};
+
+    const KEY: &str = "tpubD6NzVbkrYhZ4WaWSyoBvQwbpLkojyoTZPRsgXELWz3Popb3qkjcJyJUGLnL4qHHoQvao8ESaAstxYSnhyswJ76uZPStJRJCTKvosUCJZL5B";
+    const OTHER: &str = "tpubD6NzVbkrYhZ4XgiXtGrdW5XDAPFCL9h7we1vwNCpn8tGbBcgfVYjXyhWo4E1xkh56hjod1RhGjxbaTLV3X4FyWuejifB9jusQ46QzG87VKp";
+
+    fn weights(descriptor: &Descriptor<DescriptorPublicKey>, count: u32) -> HashMap<sha256::Hash, u64> {
+        let entry = WalletDescriptor { label: "Receive".to_string(), descriptor: descriptor.clone(), keychain: None };
+        input_weights(&derive_scripts(&[(&entry, count)]))
+    }
+
+    #[test]
+    fn test_wpkh_sweep() {
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&format!("wpkh({}/0/*)", KEY)).unwrap();
+        let weights = weights(&descriptor, 2);
+        let script = |index| descriptor.at_derivation_index(index).script_pubkey();
+        let utxos = [utxo(1, 100_000, Some(100), script(0)), utxo(2, 50_000, Some(100), script(1)), utxo(3, 100, Some(100), script(1))];
+        let sweep = estimate(&utxos, &weights, &script(0), 2.0);
+
+        // 10.5 vB overhead, three 68 vB inputs, a 31 vB output
+        assert_eq!((sweep.weight, sweep.vsize, sweep.fee), (982, 246, 492));
+        assert_eq!(sweep.exact_inputs, 3);
+        assert_eq!(sweep.net(), Some(149_608));
+        let lines = format_sweep(&sweep, Unit::Sats);
+        assert_eq!(lines[0], "Inputs:      3 UTXOs, 150,100 sats");
+        assert_eq!(lines[1], "Output:      one P2WPKH");
+        assert_eq!(lines[5], "Note: 1 UTXO costs more to spend than it's worth (100 sats for 136 sats of fees); leaving them out nets 36 sats more");
+    }
+
+    #[test]
+    fn test_multisig_inputs_from_the_descriptor() {
+        let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&format!("wsh(multi(2,{}/0/*,{}/0/*))", KEY, OTHER)).unwrap();
+        let weights = weights(&descriptor, 1);
+        let script = descriptor.at_derivation_index(0).script_pubkey();
+        let sweep = estimate(&[utxo(1, 10_000, Some(100), script.clone())], &weights, &script, 1.0);
+        // A 384 WU input: the dummy, two 73-byte signatures and the 71-byte
+        // witness script, with their lengths; and a 43 vB output
+        assert_eq!((sweep.weight, sweep.vsize), (598, 150));
+        assert_eq!(sweep.exact_inputs, 1);
+
+        // Known only by address, it's guessed as 2-of-3
+        let guessed = estimate(&[utxo(1, 10_000, Some(100), script.clone())], &HashMap::new(), &script, 1.0);
+        assert!(guessed.vsize > sweep.vsize);
+        assert!(format_sweep(&guessed, Unit::Sats).last().unwrap().starts_with("Note: 1 of the inputs is sized from their script type"));
+        assert_eq!(estimate(&[utxo(1, 100, Some(100), script.clone())], &weights, &script, 1.0).net(), None);
+    }
+}
@@ -0,0 +1,148 @@
+//! Wallet histories on the Esplora backends through BDK's own wallet sync
+//!
+//! Each descriptor of a wallet is opened as a watch-only [`bdk::Wallet`] on
+//! its database in the history store (in memory with `--no-store`), brought
+//! up to date with [`bdk::Wallet::sync`], and read back: its transactions,
+//! the outputs it owns, and its balance. BDK derives scripts 100 at a time
+//! and lists the history of every derived one, deriving more until
+//! `--gap-limit` in a row are unused. Transactions it already holds aren't
+//! downloaded again; one no longer listed is dropped, as after a reorg, and
+//! of two conflicting unconfirmed ones only the higher fee is kept.
+//!
+//! A plain address or script has no descriptor BDK can sync, and BDK can't
+//! stop at `--scan-limit`, so those are still looked up script by script.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::{Network, OutPoint, Script, TxOut};
+use bdk::blockchain::esplora::{EsploraBlockchain, EsploraError};
+use bdk::database::{AnyDatabase, Database, MemoryDatabase};
+use bdk::{Balance, KeychainKind, SyncOptions};
+use std::collections::{HashMap, HashSet};
+
+use crate::progress::Progress;
+use crate::query::script_hash;
+use crate::retry::{self, Failure, RetryPolicy};
+use crate::store::{self, StoreDir};
+use crate::wallet::{self, ChainUsage, ScanResult, Wallet};
+
+/// Syncs every descriptor of `wallet` through `blockchain`'s client, up to
+/// `gap_limit` unused scripts in a row, and returns what the synced wallets
+/// hold with the balance BDK gives them
+pub fn sync(
+    blockchain: &EsploraBlockchain,
+    retry: &RetryPolicy,
+    store: Option<&StoreDir>,
+    wallet: &Wallet,
+    gap_limit: u32,
+    concurrency: usize,
+    progress: &Progress,
+) -> Result<(ScanResult, Balance), String> {
+    // BDK only checks the keys against the network and encodes addresses
+    // with it, and neither matters here
+    let network = wallet.network.unwrap_or(Network::Bitcoin);
+    let client = EsploraBlockchain::from_client((**blockchain).clone(), gap_limit as usize)
+        .with_concurrency(concurrency.min(u8::MAX.into()) as u8);
+
+    let mut details = Vec::new();
+    // Outputs any of the wallet's descriptors own, spent or not
+    let mut owned: HashMap<OutPoint, TxOut> = HashMap::new();
+    let mut derived: Vec<Vec<Script>> = Vec::new();
+    let mut balance = Balance::default();
+    for entry in &wallet.descriptors {
+        let descriptor = entry.descriptor.to_string();
+        let (database, fresh): (AnyDatabase, bool) = match store.and_then(|store| store.synced_wallet(&descriptor)) {
+            Some((database, fresh)) => (database.into(), fresh),
+            None => (MemoryDatabase::new().into(), false),
+        };
+        let synced = bdk::Wallet::new(descriptor.as_str(), None, network, database)
+            .map_err(|e| format!("Could not open {} as a BDK wallet: {}", entry.label, e))?;
+        progress.derived(&entry.label, 0);
+        if !fresh {
+            retry.call(|| synced.sync(&client, SyncOptions::default()).map_err(sync_failure))?;
+        }
+
+        balance = balance + synced.get_balance().map_err(|e| e.to_string())?;
+        details.extend(synced.list_transactions(true).map_err(|e| e.to_string())?);
+        let database = synced.database();
+        owned.extend(database.iter_utxos().map_err(|e| e.to_string())?.into_iter().map(|utxo| (utxo.outpoint, utxo.txout)));
+        // The scripts BDK derived and listed, in index order
+        let count = database.iter_script_pubkeys(Some(KeychainKind::External)).map_err(|e| e.to_string())?.len().max(1);
+        let scripts: Vec<Script> =
+            (0..count as u32).map(|index| entry.descriptor.at_derivation_index(index).script_pubkey()).collect();
+        progress.derived(&entry.label, count as u32 - 1);
+        derived.push(scripts);
+    }
+
+    let txs = wallet::merge_txs(details.iter().filter_map(|details| store::from_details(details, &owned)));
+    let paid: HashSet<sha256::Hash> =
+        txs.iter().flat_map(|tx| &tx.vout).map(|output| script_hash(&output.scriptpubkey)).collect();
+    let mut scripts = HashSet::new();
+    let mut keychains: HashMap<KeychainKind, HashSet<sha256::Hash>> = HashMap::new();
+    let mut chains = Vec::new();
+    for (entry, derived) in wallet.descriptors.iter().zip(&derived) {
+        let hashes: Vec<sha256::Hash> = derived.iter().map(script_hash).collect();
+        if let Some(keychain) = entry.keychain {
+            keychains.entry(keychain).or_default().extend(&hashes);
+        }
+        chains.push(chain_usage(&entry.label, &hashes, &paid));
+        scripts.extend(hashes);
+    }
+    Ok((ScanResult { txs, scripts, keychains, chains }, balance))
+}
+
+/// How far a descriptor's scripts, in index order, were paid
+fn chain_usage(label: &str, scripts: &[sha256::Hash], paid: &HashSet<sha256::Hash>) -> ChainUsage {
+    let used: Vec<u32> = (0..).zip(scripts).filter(|(_, hash)| paid.contains(*hash)).map(|(index, _)| index).collect();
+    let next_index = used.last().map_or(0, |index| index + 1);
+    ChainUsage {
+        label: label.to_string(),
+        scanned: scripts.len() as u32,
+        used: used.len() as u32,
+        next_index,
+        unused: (0..next_index).filter(|index| !used.contains(index)).collect(),
+        limited: false,
+    }
+}
+
+/// Sorts a failed sync like a failed request. BDK writes what it found only
+/// once the whole sync has succeeded, so a sync is simply tried again.
+fn sync_failure(error: bdk::Error) -> Failure {
+    let bdk::Error::Esplora(error) = error else {
+        return Failure::Permanent(error.to_string());
+    };
+    match *error {
+        EsploraError::Ureq(error) => retry::http_failure(error),
+        EsploraError::UreqTransport(transport) => retry::http_failure(transport.into()),
+        EsploraError::HttpResponse(status @ (429 | 500..=599)) => {
+            Failure::Transient { error: format!("HTTP status {}", status), retry_after: None }
+        }
+        EsploraError::Io(error) => retry::body_failure(error),
+        error => Failure::Permanent(error.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::hashes::Hash;
+
+    #[test]
+    fn test_chain_usage() {
+        let scripts: Vec<sha256::Hash> = (0..6u8).map(|index| sha256::Hash::hash(&[index])).collect();
+        let paid = HashSet::from([scripts[1], scripts[3]]);
+        assert_eq!(
+            chain_usage("BIP84 receive", &scripts, &paid),
+            ChainUsage { label: "BIP84 receive".to_string(), scanned: 6, used: 2, next_index: 4, unused: vec![0, 2], limited: false }
+        );
+        assert_eq!(chain_usage("BIP84 change", &scripts, &HashSet::new()).next_index, 0);
+    }
+
+    #[test]
+    fn test_sync_failures() {
+        let esplora = |error: EsploraError| sync_failure(bdk::Error::Esplora(Box::new(error)));
+        assert!(matches!(esplora(EsploraError::HttpResponse(503)), Failure::Transient { .. }));
+        assert!(matches!(esplora(EsploraError::HttpResponse(429)), Failure::Transient { .. }));
+        assert!(matches!(esplora(EsploraError::HttpResponse(404)), Failure::Permanent(_)));
+        assert!(matches!(sync_failure(bdk::Error::Generic("bad".to_string())), Failure::Permanent(_)));
+    }
+}
@@ -0,0 +1,81 @@
+//! Builders for the transactions and outputs the unit tests check
+//!
+//! Each builder fills in what the tests don't care about; a test that does
+//! care overrides the field with struct update syntax, e.g.
+//! `Tx { fee: 500, ..tx(1, vec![], vec![]) }`.
+
+use bdk::bitcoin::{OutPoint, PackedLockTime, Script, Sequence, Transaction, TxIn, TxOut, Txid, Witness};
+use bdk::esplora_client::{PrevOut, Tx, TxStatus, Vin, Vout};
+use std::str::FromStr;
+
+use crate::utxos::Utxo;
+
+/// scriptPubKey of tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx
+pub const P2WPKH: &str = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+
+/// The txid with every byte `id`
+pub fn txid(id: u8) -> Txid {
+    Txid::from_str(&format!("{:02x}", id).repeat(32)).unwrap()
+}
+
+/// A P2WPKH script whose key hash is `owner` repeated, one per owner
+pub fn script(owner: u8) -> Script {
+    Script::from(vec![0x00, 0x14].into_iter().chain([owner; 20]).collect::<Vec<u8>>())
+}
+
+/// The script of [`P2WPKH`]
+pub fn p2wpkh() -> Script {
+    Script::from_str(P2WPKH).unwrap()
+}
+
+/// Confirmed when it has a height or a block time
+pub fn status(height: Option<u32>, block_time: Option<u64>) -> TxStatus {
+    TxStatus { confirmed: height.is_some() || block_time.is_some(), block_height: height, block_hash: None, block_time }
+}
+
+/// An input spending output `vout` of `prev`, which paid `value` to `script`
+pub fn input(prev: Txid, vout: u32, script: Script, value: u64) -> Vin {
+    Vin {
+        txid: prev,
+        vout,
+        prevout: Some(PrevOut { value, scriptpubkey: script }),
+        scriptsig: Script::new(),
+        witness: vec![],
+        sequence: u32::MAX,
+        is_coinbase: false,
+    }
+}
+
+pub fn output(script: Script, value: u64) -> Vout {
+    Vout { value, scriptpubkey: script }
+}
+
+/// Transaction `id`, unconfirmed and paying no fee
+pub fn tx(id: u8, vin: Vec<Vin>, vout: Vec<Vout>) -> Tx {
+    Tx { txid: txid(id), version: 2, locktime: 0, vin, vout, status: status(None, None), fee: 0 }
+}
+
+/// Output 0 of transaction `id`
+pub fn utxo(id: u8, value: u64, height: Option<u32>, script: Script) -> Utxo {
+    Utxo { txid: txid(id), vout: 0, value, height, script }
+}
+
+/// A raw transaction spending `inputs` (a coinbase-like null input when
+/// there are none) to `outputs`
+pub fn transaction(inputs: &[OutPoint], outputs: &[(Script, u64)]) -> Transaction {
+    let inputs = if inputs.is_empty() { &[OutPoint::null()][..] } else { inputs };
+    Transaction {
+        version: 2,
+        lock_time: PackedLockTime(0),
+        input: inputs
+            .iter()
+            .map(|&previous_output| TxIn {
+                previous_output,
+                script_sig: Script::new(),
+                sequence: Sequence::MAX,
+                witness: Witness::new(),
+            })
+            .collect(),
+        output: outputs.iter().map(|(script, value)| TxOut { value: *value, script_pubkey: script.clone() }).collect(),
+    }
+}
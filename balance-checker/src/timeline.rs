@@ -0,0 +1,160 @@
+//! Balance timeline for `--history-chart`: the balance after each
+//! transaction, oldest first, and with `--chart` a step chart of it over time
+
+use chrono::DateTime;
+use units::Unit;
+
+use crate::export::HistoryRow;
+
+/// Chart size in characters, not counting the axes and labels
+const CHART_WIDTH: usize = 60;
+const CHART_HEIGHT: usize = 10;
+
+/// A header and one line per transaction with its change and the balance
+/// after it, in `unit`
+pub fn render_timeline(rows: &[HistoryRow], unit: Unit) -> Vec<String> {
+    let mut lines = vec![format!(
+        "{:<11}  {:>8}  {:>20}  {:>20}  {}",
+        "Date",
+        "Height",
+        format!("Change ({})", unit.label()),
+        format!("Balance ({})", unit.label()),
+        "TXID"
+    )];
+    lines.extend(rows.iter().map(|row| {
+        let date = row
+            .tx
+            .status
+            .block_time
+            .and_then(|time| DateTime::from_timestamp(time as i64, 0))
+            .map_or("unconfirmed".to_string(), |datetime| datetime.format("%Y-%m-%d").to_string());
+        let height = row.tx.status.block_height.map_or("-".to_string(), |height| height.to_string());
+        format!(
+            "{:<11}  {:>8}  {:>20}  {:>20}  {}",
+            date,
+            height,
+            unit.signed_number(row.amount),
+            unit.number_i64(row.balance),
+            row.tx.txid
+        )
+    }));
+    lines
+}
+
+/// Step chart of the balance from the first transaction to `now`, with
+/// unconfirmed transactions drawn at `now` and the scale in `unit`
+pub fn render_chart(rows: &[HistoryRow], now: u64, unit: Unit) -> Vec<String> {
+    let points: Vec<(u64, i64)> = rows
+        .iter()
+        .map(|row| (row.tx.status.block_time.unwrap_or(now).min(now), row.balance))
+        .collect();
+    let Some(&(start, _)) = points.iter().min_by_key(|(time, _)| *time) else {
+        return vec!["No transactions to chart".to_string()];
+    };
+    let max = points.iter().map(|&(_, balance)| balance).max().unwrap_or(0).max(1);
+
+    // Balance at the end of each column's slice of time
+    let columns: Vec<i64> = (0..CHART_WIDTH)
+        .map(|column| {
+            let time = start + (now - start) * (column as u64 + 1) / CHART_WIDTH as u64;
+            points.iter().rev().find(|&&(at, _)| at <= time).map_or(0, |&(_, balance)| balance)
+        })
+        .collect();
+
+    let top_label = unit.format(max as u64);
+    let label_width = top_label.len();
+    let mut lines = Vec::new();
+    for level in (1..=CHART_HEIGHT).rev() {
+        let row: String = columns
+            .iter()
+            .map(|&balance| if bar_height(balance, max) >= level { '█' } else { ' ' })
+            .collect();
+        let label = if level == CHART_HEIGHT { top_label.as_str() } else { "" };
+        lines.push(format!("{:>label_width$} │{}", label, row));
+    }
+    lines.push(format!("{:>label_width$} └{}", "0", "─".repeat(CHART_WIDTH)));
+
+    let date = |time: u64| {
+        DateTime::from_timestamp(time as i64, 0).map_or(String::new(), |datetime| datetime.format("%Y-%m-%d").to_string())
+    };
+    lines.push(format!(
+        "{:label_width$}  {}{:>width$}",
+        "",
+        date(start),
+        date(now),
+        width = CHART_WIDTH - date(start).len()
+    ));
+    lines
+}
+
+/// Rows filled for a balance, scaled to the largest; any positive balance
+/// gets at least one so small amounts still show
+fn bar_height(balance: i64, max: i64) -> usize {
+    if balance <= 0 {
+        return 0;
+    }
+    ((balance * CHART_HEIGHT as i64 + max - 1) / max) as usize
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::history_rows;
+    use crate::test_util::{output, status, tx};
+    use bdk::bitcoin::Script;
+    use bdk::esplora_client::Tx;
+    use std::collections::HashSet;
+
+    const DAY: u64 = 86_400;
+    const START: u64 = 1_700_006_400; // 2023-11-15
+
+    fn receive(id: u8, time: Option<u64>, value: u64) -> Tx {
+        let height = time.map(|time| ((time - START) / 600) as u32);
+        Tx { status: status(height, time), ..tx(id, vec![], vec![output(Script::new(), value)]) }
+    }
+
+    fn txs() -> Vec<Tx> {
+        vec![receive(2, None, 50_000), receive(1, Some(START), 150_000)]
+    }
+
+    fn scripts() -> HashSet<bdk::bitcoin::hashes::sha256::Hash> {
+        HashSet::from([crate::query::script_hash(&Script::new())])
+    }
+
+    #[test]
+    fn test_render_timeline() {
+        let txs = txs();
+        let lines = render_timeline(&history_rows(&txs, &scripts()), Unit::Sats);
+        assert_eq!(lines.len(), 3);
+        assert!(lines[0].contains("Change (sats)"));
+        assert!(lines[1].starts_with("2023-11-15          0              +150,000               150,000  0101"));
+        assert!(lines[2].starts_with("unconfirmed         -               +50,000               200,000  0202"));
+
+        let lines = render_timeline(&history_rows(&txs, &scripts()), Unit::Btc);
+        assert!(lines[0].contains("Balance (BTC)"));
+        assert!(lines[2].contains(&format!("+0.00050000  {:>20}", "0.00200000")));
+    }
+
+    #[test]
+    fn test_render_chart() {
+        let txs = txs();
+        let lines = render_chart(&history_rows(&txs, &scripts()), START + 10 * DAY, Unit::Btc);
+
+        // Ten levels, the axis, and the dates
+        assert_eq!(lines.len(), CHART_HEIGHT + 2);
+        assert!(lines[0].starts_with("0.00200000 BTC │"));
+        // The unconfirmed receive lifts only the last column to the top
+        assert!(lines[0].ends_with(" █"));
+        assert!(lines[CHART_HEIGHT - 1].ends_with(&"█".repeat(CHART_WIDTH)));
+        assert!(lines[CHART_HEIGHT + 1].trim_start().starts_with("2023-11-15"));
+        assert!(lines[CHART_HEIGHT + 1].ends_with("2023-11-25"));
+    }
+
+    #[test]
+    fn test_bar_height() {
+        assert_eq!(bar_height(0, 100), 0);
+        assert_eq!(bar_height(1, 100), 1);
+        assert_eq!(bar_height(50, 100), 5);
+        assert_eq!(bar_height(100, 100), CHART_HEIGHT);
+    }
+}
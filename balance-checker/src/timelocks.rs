@@ -0,0 +1,400 @@
+//! Timelocked outputs: unspent outputs that no spending path can spend yet
+//! because of a CLTV (`after`) or CSV (`older`) condition, and when they
+//! unlock
+//!
+//! Spending conditions come from the descriptors being checked, or, for
+//! plain addresses, from a P2WSH or P2SH script an earlier spend from the
+//! same address revealed (if it parses as miniscript). Taproot script
+//! spends reveal only one leaf, so they're not used. An output with any
+//! path open now, e.g. a key that can always spend, isn't locked. Hash
+//! preimages are assumed to be known. Times are compared with the clock
+//! rather than the chain's median time, so they're a few minutes off.
+
+use bdk::bitcoin::blockdata::script::Instruction;
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::{PublicKey, Script};
+use bdk::esplora_client::Tx;
+use bdk::miniscript::policy::{Liftable, Semantic};
+use bdk::miniscript::{Legacy, Miniscript, MiniscriptKey, Segwitv0};
+use chrono::{DateTime, Utc};
+use std::collections::{HashMap, HashSet};
+use units::Unit;
+
+use crate::query::script_hash;
+use crate::utxos::Utxo;
+use crate::wallet::DerivedScript;
+
+/// Lock times below this are block heights, from it on Unix times
+const LOCKTIME_THRESHOLD: u32 = 500_000_000;
+/// Relative lock in units of 512 seconds rather than blocks
+const SEQUENCE_TYPE_FLAG: u32 = 1 << 22;
+const SEQUENCE_VALUE_MASK: u32 = 0xffff;
+/// For estimating when a height is reached, and the reverse
+const BLOCK_INTERVAL_SECS: u64 = 600;
+
+/// What spending an output takes, as far as time goes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Condition {
+    /// Keys or hashes, with no timelock
+    Always,
+    Never,
+    /// CLTV: a block height or Unix time
+    After(u32),
+    /// CSV: an nSequence-style relative lock
+    Older(u32),
+    Threshold(usize, Vec<Condition>),
+}
+
+/// A moment a spend could be mined at: the block's height and time, and
+/// when the output being spent confirmed
+#[derive(Debug, Clone, Copy)]
+struct Point {
+    height: u32,
+    time: u64,
+    confirmed_height: u32,
+    confirmed_time: u64,
+}
+
+impl Condition {
+    pub fn from_policy<Pk: MiniscriptKey>(policy: &Semantic<Pk>) -> Self {
+        match policy {
+            Semantic::Unsatisfiable => Condition::Never,
+            Semantic::After(lock_time) => Condition::After(lock_time.0),
+            Semantic::Older(sequence) => Condition::Older(sequence.to_consensus_u32()),
+            Semantic::Threshold(k, subs) => Condition::Threshold(*k, subs.iter().map(Condition::from_policy).collect()),
+            _ => Condition::Always,
+        }
+    }
+
+    pub fn has_timelock(&self) -> bool {
+        match self {
+            Condition::After(_) | Condition::Older(_) => true,
+            Condition::Threshold(_, subs) => subs.iter().any(Condition::has_timelock),
+            Condition::Always | Condition::Never => false,
+        }
+    }
+
+    /// A spend's lock time is either a height or a time, and so is each
+    /// input's relative lock, so a path can't mix them
+    fn satisfied(&self, point: &Point, time_lock: bool, time_age: bool) -> bool {
+        match *self {
+            Condition::Always => true,
+            Condition::Never => false,
+            // The spend's nLockTime is at least the value and below the block's
+            Condition::After(value) if value < LOCKTIME_THRESHOLD => !time_lock && value < point.height,
+            Condition::After(value) => time_lock && u64::from(value) < point.time,
+            Condition::Older(value) if value & SEQUENCE_TYPE_FLAG != 0 => {
+                let needed = u64::from(value & SEQUENCE_VALUE_MASK) * 512;
+                time_age && point.time.saturating_sub(point.confirmed_time) >= needed
+            }
+            Condition::Older(value) => {
+                !time_age && point.height.saturating_sub(point.confirmed_height) >= value & SEQUENCE_VALUE_MASK
+            }
+            Condition::Threshold(k, ref subs) => {
+                subs.iter().filter(|sub| sub.satisfied(point, time_lock, time_age)).count() >= k
+            }
+        }
+    }
+
+    fn spendable(&self, point: &Point) -> bool {
+        [(false, false), (false, true), (true, false), (true, true)]
+            .into_iter()
+            .any(|(time_lock, time_age)| self.satisfied(point, time_lock, time_age))
+    }
+
+    /// Heights and times at which a lock opens, given when the output
+    /// confirmed
+    fn openings(&self, confirmed_height: u32, confirmed_time: u64, openings: &mut Vec<Unlock>) {
+        match *self {
+            Condition::After(value) if value < LOCKTIME_THRESHOLD => openings.push(Unlock::Height(value + 1)),
+            Condition::After(value) => openings.push(Unlock::Time(u64::from(value) + 1)),
+            Condition::Older(value) if value & SEQUENCE_TYPE_FLAG != 0 => {
+                openings.push(Unlock::Time(confirmed_time + u64::from(value & SEQUENCE_VALUE_MASK) * 512))
+            }
+            Condition::Older(value) => openings.push(Unlock::Height(confirmed_height + (value & SEQUENCE_VALUE_MASK))),
+            Condition::Threshold(_, ref subs) => {
+                subs.iter().for_each(|sub| sub.openings(confirmed_height, confirmed_time, openings))
+            }
+            Condition::Always | Condition::Never => {}
+        }
+    }
+}
+
+/// When a locked output can first be spent
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unlock {
+    /// The first block a spend can be mined in
+    Height(u32),
+    /// Unix time after which a spend can be mined
+    Time(u64),
+    /// No path opens on its own, e.g. one mixing height and time locks
+    Never,
+}
+
+/// An output's conditions and where they came from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Spending {
+    pub condition: Condition,
+    /// The descriptor's label, or that the script was revealed by a spend
+    pub source: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LockedOutput {
+    pub utxo: Utxo,
+    pub unlock: Unlock,
+    pub source: String,
+}
+
+/// Conditions of every checked script that has a timelock: the wallet's
+/// `derived` scripts, then scripts an input in `txs` revealed
+pub fn spending_conditions(
+    derived: &HashMap<sha256::Hash, DerivedScript>,
+    txs: &[Tx],
+    scripts: &HashSet<sha256::Hash>,
+) -> HashMap<sha256::Hash, Spending> {
+    // Lifted once per descriptor rather than per script
+    let mut lifted: HashMap<usize, Option<Condition>> = HashMap::new();
+    let mut conditions = HashMap::new();
+    for (hash, script) in derived {
+        let condition = lifted.entry(script.position).or_insert_with(|| {
+            let condition = Condition::from_policy(&script.entry.descriptor.lift().ok()?);
+            condition.has_timelock().then_some(condition)
+        });
+        if let Some(condition) = condition {
+            conditions.insert(*hash, Spending { condition: condition.clone(), source: script.entry.label.clone() });
+        }
+    }
+
+    for (hash, policy) in revealed_policies(txs, scripts) {
+        let condition = Condition::from_policy(&policy);
+        if !conditions.contains_key(&hash) && condition.has_timelock() {
+            conditions.insert(hash, Spending { condition, source: "script revealed by an earlier spend".to_string() });
+        }
+    }
+    conditions
+}
+
+/// Policies of the checked `scripts` that an input in `txs` revealed, each
+/// script once
+pub fn revealed_policies(txs: &[Tx], scripts: &HashSet<sha256::Hash>) -> HashMap<sha256::Hash, Semantic<PublicKey>> {
+    let mut policies = HashMap::new();
+    for input in txs.iter().flat_map(|tx| &tx.vin) {
+        let Some(prevout) = &input.prevout else { continue };
+        let hash = script_hash(&prevout.scriptpubkey);
+        if !scripts.contains(&hash) || policies.contains_key(&hash) {
+            continue;
+        }
+        if let Some(policy) = revealed_policy(&prevout.scriptpubkey, &input.scriptsig, &input.witness) {
+            policies.insert(hash, policy);
+        }
+    }
+    policies
+}
+
+/// Policy of the script a P2WSH, P2SH-P2WSH or P2SH spend revealed, if it
+/// parses as miniscript; a P2SH-P2WPKH spend reveals a single key
+pub fn revealed_policy(script_pubkey: &Script, scriptsig: &Script, witness: &[Vec<u8>]) -> Option<Semantic<PublicKey>> {
+    let witness_script = || witness.last().map(|bytes| Script::from(bytes.clone()));
+    let segwit = |script: Script| Miniscript::<PublicKey, Segwitv0>::parse_insane(&script).ok()?.lift().ok();
+    if script_pubkey.is_v0_p2wsh() {
+        let script = witness_script()?;
+        return (Script::new_v0_p2wsh(&script.wscript_hash()) == *script_pubkey).then(|| segwit(script)).flatten();
+    }
+    if script_pubkey.is_p2sh() {
+        let redeem_script = scriptsig
+            .instructions()
+            .filter_map(|instruction| match instruction {
+                Ok(Instruction::PushBytes(bytes)) => Some(Script::from(bytes.to_vec())),
+                _ => None,
+            })
+            .last()?;
+        if Script::new_p2sh(&redeem_script.script_hash()) != *script_pubkey {
+            return None;
+        }
+        if redeem_script.is_v0_p2wsh() {
+            let script = witness_script()?;
+            return (Script::new_v0_p2wsh(&script.wscript_hash()) == redeem_script).then(|| segwit(script)).flatten();
+        }
+        if redeem_script.is_v0_p2wpkh() {
+            let key = PublicKey::from_slice(witness.last()?).ok()?;
+            return Some(Semantic::Key(key));
+        }
+        return Miniscript::<PublicKey, Legacy>::parse_insane(&redeem_script).ok()?.lift().ok();
+    }
+    None
+}
+
+/// The outputs that can't be spent in the next block, with when they can.
+/// `block_times` gives the time of the block each output confirmed in; an
+/// unconfirmed one is treated as confirming in the next block.
+pub fn locked_outputs(
+    utxos: &[Utxo],
+    conditions: &HashMap<sha256::Hash, Spending>,
+    block_time: impl Fn(&Utxo) -> Option<u64>,
+    tip_height: u32,
+    now: u64,
+) -> Vec<LockedOutput> {
+    let mut locked: Vec<LockedOutput> = utxos
+        .iter()
+        .filter_map(|utxo| {
+            let spending = conditions.get(&script_hash(&utxo.script))?;
+            let confirmed_height = utxo.height.unwrap_or(tip_height + 1);
+            let confirmed_time = block_time(utxo).unwrap_or(now);
+            let point = |height: u32, time: u64| Point { height, time, confirmed_height, confirmed_time };
+            if spending.condition.spendable(&point(tip_height + 1, now)) {
+                return None;
+            }
+
+            // Try each opening in order of its estimated time
+            let mut openings = Vec::new();
+            spending.condition.openings(confirmed_height, confirmed_time, &mut openings);
+            let mut candidates: Vec<(Point, Unlock)> = openings
+                .into_iter()
+                .map(|unlock| match unlock {
+                    Unlock::Height(height) => {
+                        let blocks = u64::from(height.saturating_sub(tip_height + 1));
+                        (point(height, now + blocks * BLOCK_INTERVAL_SECS), unlock)
+                    }
+                    Unlock::Time(time) => {
+                        let blocks = time.saturating_sub(now).div_ceil(BLOCK_INTERVAL_SECS) as u32;
+                        (point(tip_height + 1 + blocks, time), unlock)
+                    }
+                    Unlock::Never => unreachable!("openings are heights or times"),
+                })
+                .collect();
+            candidates.sort_by_key(|(point, _)| point.time);
+            let unlock = candidates
+                .into_iter()
+                .find(|(point, _)| spending.condition.spendable(point))
+                .map_or(Unlock::Never, |(_, unlock)| unlock);
+            Some(LockedOutput { utxo: utxo.clone(), unlock, source: spending.source.clone() })
+        })
+        .collect();
+    locked.sort_by_key(|output| match output.unlock {
+        Unlock::Height(height) => (0, u64::from(height)),
+        Unlock::Time(time) => (1, time),
+        Unlock::Never => (2, 0),
+    });
+    locked
+}
+
+/// Lines of the `Timelocked Outputs` section, with values in `unit`
+pub fn format_report(locked: &[LockedOutput], tip_height: u32, now: u64, unit: Unit) -> Vec<String> {
+    locked
+        .iter()
+        .map(|output| {
+            let unconfirmed = if output.utxo.height.is_none() { ", if it confirms in the next block" } else { "" };
+            let unlock = match output.unlock {
+                Unlock::Height(height) => {
+                    let blocks = height - tip_height;
+                    format!("spendable from block {} (in {} blocks, ~{}{})", height, blocks, duration(u64::from(blocks) * BLOCK_INTERVAL_SECS), unconfirmed)
+                }
+                Unlock::Time(time) => {
+                    let date = DateTime::<Utc>::from_timestamp(time as i64, 0)
+                        .map_or_else(|| time.to_string(), |date| date.format("%Y-%m-%d %H:%M UTC").to_string());
+                    format!("spendable after {} (in ~{}{})", date, duration(time.saturating_sub(now)), unconfirmed)
+                }
+                Unlock::Never => "no spending path opens on its own".to_string(),
+            };
+            format!("{}:{}  {}  {}  [{}]", output.utxo.txid, output.utxo.vout, unit.format(output.utxo.value), unlock, output.source)
+        })
+        .collect()
+}
+
+/// Rough length of a wait, in the largest fitting unit
+fn duration(secs: u64) -> String {
+    match secs {
+        0..=5_399 => format!("{} minutes", secs.div_ceil(60)),
+        5_400..=129_599 => format!("{} hours", (secs + 1_800) / 3_600),
+        _ => format!("{} days", (secs + 43_200) / 86_400),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::descriptor::{Descriptor, DescriptorPublicKey};
+    use bdk::esplora_client::Vin;
+    use std::str::FromStr;
+
+    use crate::test_util::{input, output, status, tx, txid};
+    use crate::wallet::{derive_scripts, WalletDescriptor};
+
+    const KEY: &str = "[73c5da0a/84'/1'/0']tpubDC8msFGeGuwnKG9Upg7DM2b4DaRqg3CUZa5g8v2SRQ6K4NSkxUgd7HsL2XVWbVm39yBA4LAxysQAm397zwQSQoQgewGiYZqrA9DsP4zbQ1M";
+    const OTHER: &str = "tpubD6NzVbkrYhZ4WaWSyoBvQwbpLkojyoTZPRsgXELWz3Popb3qkjcJyJUGLnL4qHHoQvao8ESaAstxYSnhyswJ76uZPStJRJCTKvosUCJZL5B";
+    const NOW: u64 = 1_780_000_000;
+
+    fn descriptor(miniscript: &str) -> Descriptor<DescriptorPublicKey> {
+        let text = miniscript.replace("KEY", &format!("{}/0/*", KEY)).replace("OTHER", &format!("{}/0/*", OTHER));
+        Descriptor::from_str(&text).unwrap()
+    }
+
+    fn utxo(script: Script, height: Option<u32>) -> Utxo {
+        crate::test_util::utxo(0x11, 50_000, height, script)
+    }
+
+    fn locked(miniscript: &str, height: Option<u32>, tip: u32) -> Vec<LockedOutput> {
+        let entry = WalletDescriptor { label: "Wsh receive".to_string(), descriptor: descriptor(miniscript), keychain: None };
+        let conditions = spending_conditions(&derive_scripts(&[(&entry, 5)]), &[], &HashSet::new());
+        let descriptor = entry.descriptor;
+        let script = descriptor.at_derivation_index(3).script_pubkey();
+        let block_time = |utxo: &Utxo| utxo.height.map(|height| NOW - u64::from(tip - height) * 600);
+        locked_outputs(&[utxo(script, height)], &conditions, block_time, tip, NOW)
+    }
+
+    #[test]
+    fn test_descriptor_locks() {
+        // CLTV: spendable in the block after the lock height
+        let cltv = "wsh(and_v(v:pk(KEY),after(900000)))";
+        let outputs = locked(cltv, Some(880_000), 899_000);
+        assert_eq!(outputs[0].unlock, Unlock::Height(900_001));
+        assert_eq!(outputs[0].source, "Wsh receive");
+        assert!(locked(cltv, Some(880_000), 900_000).is_empty());
+
+        // CSV counts from the block it confirmed in
+        let csv = "wsh(and_v(v:pk(KEY),older(144)))";
+        assert_eq!(locked(csv, Some(1_000), 1_050)[0].unlock, Unlock::Height(1_144));
+        assert!(locked(csv, Some(1_000), 1_143).is_empty());
+        assert_eq!(locked(csv, None, 1_050)[0].unlock, Unlock::Height(1_195));
+
+        // A key that can always spend leaves nothing locked
+        assert!(locked("wsh(or_d(pk(KEY),and_v(v:pk(OTHER),older(144))))", Some(1_000), 1_050).is_empty());
+        // Both locks on one path
+        let both = locked("wsh(and_v(v:pk(KEY),and_v(v:after(2000),older(100))))", Some(1_950), 1_960);
+        assert_eq!(both[0].unlock, Unlock::Height(2_050));
+        // Time locks
+        let time = locked("wsh(and_v(v:pk(KEY),after(1790000000)))", Some(1_000), 1_050);
+        assert_eq!(time[0].unlock, Unlock::Time(1_790_000_001));
+        // No lock at all isn't reported
+        assert!(locked("wsh(pk(KEY))", Some(1_000), 1_050).is_empty());
+    }
+
+    #[test]
+    fn test_script_revealed_by_a_spend() {
+        let descriptor = descriptor("wsh(and_v(v:pk(KEY),older(10)))");
+        let definite = descriptor.at_derivation_index(0);
+        let script_pubkey = definite.script_pubkey();
+        let witness_script = definite.explicit_script().unwrap();
+        let input = Vin {
+            witness: vec![vec![0x30; 71], witness_script.to_bytes()],
+            sequence: 10,
+            ..input(txid(0x33), 0, script_pubkey.clone(), 10_000)
+        };
+        let spend = Tx { status: status(Some(990), None), fee: 1_000, ..tx(0x22, vec![input], vec![output(Script::new(), 9_000)]) };
+        let scripts = HashSet::from([script_hash(&script_pubkey)]);
+        let conditions = spending_conditions(&HashMap::new(), &[spend], &scripts);
+        let spending = &conditions[&script_hash(&script_pubkey)];
+        assert_eq!(spending.source, "script revealed by an earlier spend");
+
+        let outputs = locked_outputs(&[utxo(script_pubkey, Some(995))], &conditions, |_| None, 1_000, NOW);
+        assert_eq!(outputs[0].unlock, Unlock::Height(1_005));
+        assert_eq!(
+            format_report(&outputs, 1_000, NOW, Unit::Sats),
+            vec![format!(
+                "{}:0  50,000 sats  spendable from block 1005 (in 5 blocks, ~50 minutes)  [script revealed by an earlier spend]",
+                "11".repeat(32)
+            )]
+        );
+    }
+}
@@ -0,0 +1,441 @@
+//! Unspent output listing for `--utxos`: every coin the checked scripts can
+//! still spend, largest first, with its confirmations and age
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::{Script, Txid};
+use bdk::esplora_client::Tx;
+use std::collections::{HashMap, HashSet};
+use units::Unit;
+
+use crate::query::script_hash;
+
+/// One unspent output
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Utxo {
+    pub txid: Txid,
+    pub vout: u32,
+    pub value: u64,
+    /// None while unconfirmed
+    pub height: Option<u32>,
+    pub script: Script,
+}
+
+impl Utxo {
+    /// Blocks mined since the one that confirmed it
+    pub fn age(&self, tip_height: u32) -> Option<u32> {
+        self.height.map(|height| tip_height.saturating_sub(height))
+    }
+}
+
+/// Confirmations of whatever was mined at `height` with the chain at
+/// `tip_height`: 1 in its own block, 0 while unconfirmed
+pub fn confirmations(height: Option<u32>, tip_height: u32) -> u32 {
+    height.map_or(0, |height| tip_height.saturating_sub(height) + 1)
+}
+
+/// Outputs to `scripts` that no transaction in `txs` spends, largest first
+pub fn unspent_outputs(txs: &[Tx], scripts: &HashSet<sha256::Hash>) -> Vec<Utxo> {
+    let spent: HashSet<(Txid, u32)> = txs
+        .iter()
+        .flat_map(|tx| &tx.vin)
+        .filter(|input| input.prevout.as_ref().is_some_and(|prevout| scripts.contains(&script_hash(&prevout.scriptpubkey))))
+        .map(|input| (input.txid, input.vout))
+        .collect();
+
+    let mut utxos: Vec<Utxo> = txs
+        .iter()
+        .flat_map(|tx| {
+            tx.vout.iter().enumerate().map(move |(vout, output)| Utxo {
+                txid: tx.txid,
+                vout: vout as u32,
+                value: output.value,
+                height: tx.status.block_height,
+                script: output.scriptpubkey.clone(),
+            })
+        })
+        .filter(|utxo| scripts.contains(&script_hash(&utxo.script)) && !spent.contains(&(utxo.txid, utxo.vout)))
+        .collect();
+    // Ties keep a stable order: oldest first
+    utxos.sort_by_key(|utxo| (std::cmp::Reverse(utxo.value), utxo.height.unwrap_or(u32::MAX), utxo.txid, utxo.vout));
+    utxos
+}
+
+/// Blocks a coinbase output has to wait before it can be spent
+pub const COINBASE_MATURITY: u32 = 100;
+
+/// Transactions in `txs` that are a block's coinbase
+pub fn coinbase_txids(txs: &[Tx]) -> HashSet<Txid> {
+    txs.iter().filter(|tx| tx.vin.iter().any(|input| input.is_coinbase)).map(|tx| tx.txid).collect()
+}
+
+/// Unspent value by whether it can be spent yet, for `--min-conf`,
+/// `--dust-limit` and coinbase outputs; the amounts add up to the balance
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct BalanceSplit {
+    pub min_conf: Option<u32>,
+    pub dust_limit: Option<u64>,
+    /// At least the required confirmations (one by default)
+    pub spendable: u64,
+    /// Confirmed, but not deeply enough yet
+    pub pending: u64,
+    pub unconfirmed: u64,
+    /// Outputs under the dust limit, however confirmed
+    pub dust: u64,
+    pub dust_count: usize,
+    /// Outputs of the `coinbase` transactions fewer than
+    /// `COINBASE_MATURITY` blocks old, as Bitcoin Core counts them
+    pub immature: u64,
+    pub immature_count: usize,
+}
+
+/// Splits the outputs' value at `min_conf` confirmations, setting aside
+/// immature outputs of the `coinbase` transactions, then outputs worth less
+/// than `dust_limit`
+pub fn split_balance(
+    utxos: &[Utxo],
+    tip_height: u32,
+    coinbase: &HashSet<Txid>,
+    min_conf: Option<u32>,
+    dust_limit: Option<u64>,
+) -> BalanceSplit {
+    let mut split = BalanceSplit { min_conf, dust_limit, ..Default::default() };
+    for utxo in utxos {
+        if coinbase.contains(&utxo.txid) && utxo.age(tip_height).is_none_or(|age| age < COINBASE_MATURITY) {
+            split.immature += utxo.value;
+            split.immature_count += 1;
+            continue;
+        }
+        if dust_limit.is_some_and(|limit| utxo.value < limit) {
+            split.dust += utxo.value;
+            split.dust_count += 1;
+            continue;
+        }
+        match confirmations(utxo.height, tip_height) {
+            0 => split.unconfirmed += utxo.value,
+            confirmations if confirmations >= min_conf.unwrap_or(1) => split.spendable += utxo.value,
+            _ => split.pending += utxo.value,
+        }
+    }
+    split
+}
+
+/// Unconfirmed value by who sent it, as Bitcoin Core splits its pending
+/// balance into trusted and untrusted
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PendingSplit {
+    /// From someone else's transactions, only safe to count once confirmed
+    pub incoming: u64,
+    /// Change from transactions the checked scripts paid for in full
+    pub change: u64,
+    /// The part of `incoming` its sender can replace (BIP125), and in how
+    /// many transactions
+    pub replaceable: u64,
+    pub replaceable_txs: usize,
+}
+
+impl PendingSplit {
+    /// Unconfirmed value that doesn't signal RBF: change, and incoming funds
+    /// that don't opt in. Since Bitcoin Core 28 nodes relay replacements of
+    /// any transaction by default (full RBF), so the sender can still
+    /// replace these.
+    pub fn non_signalling(&self) -> u64 {
+        self.change + self.incoming - self.replaceable
+    }
+}
+
+/// Unconfirmed transactions that spend only the checked scripts' outputs,
+/// each of them confirmed or itself from such a transaction. Nobody else
+/// can double-spend these, so their outputs are as good as confirmed.
+pub fn trusted_pending(txs: &[Tx], scripts: &HashSet<sha256::Hash>) -> HashSet<Txid> {
+    fn trusted(tx: &Tx, by_id: &HashMap<Txid, &Tx>, scripts: &HashSet<sha256::Hash>, memo: &mut HashMap<Txid, bool>) -> bool {
+        if tx.status.confirmed {
+            return true;
+        }
+        if let Some(&known) = memo.get(&tx.txid) {
+            return known;
+        }
+        let ours = !tx.vin.is_empty()
+            && tx.vin.iter().all(|input| {
+                input.prevout.as_ref().is_some_and(|prevout| scripts.contains(&script_hash(&prevout.scriptpubkey)))
+                    && by_id.get(&input.txid).is_some_and(|parent| trusted(parent, by_id, scripts, memo))
+            });
+        memo.insert(tx.txid, ours);
+        ours
+    }
+
+    let by_id: HashMap<Txid, &Tx> = txs.iter().map(|tx| (tx.txid, tx)).collect();
+    let mut memo = HashMap::new();
+    txs.iter().filter(|tx| !tx.status.confirmed && trusted(tx, &by_id, scripts, &mut memo)).map(|tx| tx.txid).collect()
+}
+
+/// Whether the transaction signals BIP125 opt-in replaceability, with an
+/// input sequence number below 0xfffffffe
+pub fn signals_rbf(tx: &Tx) -> bool {
+    tx.vin.iter().any(|input| input.sequence < 0xffff_fffe)
+}
+
+/// Unconfirmed transactions that can be replaced: those signalling RBF, and
+/// those spending an output of one, which its replacement would evict too.
+/// Parents that aren't in `txs` can't be checked.
+pub fn replaceable_pending(txs: &[Tx]) -> HashSet<Txid> {
+    fn replaceable(tx: &Tx, by_id: &HashMap<Txid, &Tx>, memo: &mut HashMap<Txid, bool>) -> bool {
+        if tx.status.confirmed {
+            return false;
+        }
+        if let Some(&known) = memo.get(&tx.txid) {
+            return known;
+        }
+        // Settles cycles, which valid transactions can't form anyway
+        memo.insert(tx.txid, false);
+        let result = signals_rbf(tx)
+            || tx.vin.iter().any(|input| by_id.get(&input.txid).is_some_and(|parent| replaceable(parent, by_id, memo)));
+        memo.insert(tx.txid, result);
+        result
+    }
+
+    let by_id: HashMap<Txid, &Tx> = txs.iter().map(|tx| (tx.txid, tx)).collect();
+    let mut memo = HashMap::new();
+    txs.iter().filter(|tx| replaceable(tx, &by_id, &mut memo)).map(|tx| tx.txid).collect()
+}
+
+/// Splits the unconfirmed outputs' value by whether their transaction is in
+/// `trusted`, and incoming value by whether it's in `replaceable`, leaving
+/// out outputs under `dust_limit` as `split_balance` does
+pub fn split_pending(
+    utxos: &[Utxo],
+    trusted: &HashSet<Txid>,
+    replaceable: &HashSet<Txid>,
+    dust_limit: Option<u64>,
+) -> PendingSplit {
+    let mut split = PendingSplit::default();
+    let mut replaceable_txs = HashSet::new();
+    for utxo in utxos.iter().filter(|utxo| utxo.height.is_none() && dust_limit.is_none_or(|limit| utxo.value >= limit)) {
+        if trusted.contains(&utxo.txid) {
+            split.change += utxo.value;
+        } else {
+            split.incoming += utxo.value;
+            if replaceable.contains(&utxo.txid) {
+                split.replaceable += utxo.value;
+                replaceable_txs.insert(utxo.txid);
+            }
+        }
+    }
+    split.replaceable_txs = replaceable_txs.len();
+    split
+}
+
+/// Standard name of an output script's type
+pub fn script_type(script: &Script) -> &'static str {
+    if script.is_p2pkh() {
+        "P2PKH"
+    } else if script.is_p2sh() {
+        "P2SH"
+    } else if script.is_v0_p2wpkh() {
+        "P2WPKH"
+    } else if script.is_v0_p2wsh() {
+        "P2WSH"
+    } else if script.is_v1_p2tr() {
+        "P2TR"
+    } else if script.is_p2pk() {
+        "P2PK"
+    } else {
+        "UNKNOWN"
+    }
+}
+
+/// A header and one line per output, values in `unit`; `requirements` says
+/// what spending each takes, and `labels` holds each output's address label.
+/// The Label column is left out when none has one.
+pub fn format_table(
+    utxos: &[Utxo],
+    tip_height: u32,
+    requirements: &[String],
+    labels: &[Option<String>],
+    unit: Unit,
+) -> Vec<String> {
+    let labelled = labels.iter().any(Option::is_some);
+    let label_column = |label: &str| if labelled { format!("  {}", label) } else { String::new() };
+    let width = requirements.iter().map(String::len).chain(["Requires".len()]).max().unwrap_or(0);
+    let mut lines = vec![format!(
+        "{:<70}  {:>20}  {:>13}  {:<7}  {:<width$}  {:>6}{}",
+        "Outpoint",
+        format!("Value ({})", unit.label()),
+        "Confirmations",
+        "Type",
+        "Requires",
+        "Age",
+        label_column("Label"),
+        width = width
+    )];
+    lines.extend(utxos.iter().enumerate().map(|(i, utxo)| {
+        let age = utxo.age(tip_height).map_or("-".to_string(), |age| age.to_string());
+        format!(
+            "{:<70}  {:>20}  {:>13}  {:<7}  {:<width$}  {:>6}{}",
+            format!("{}:{}", utxo.txid, utxo.vout),
+            unit.number(utxo.value),
+            confirmations(utxo.height, tip_height),
+            script_type(&utxo.script),
+            requirements.get(i).map_or("-", String::as_str),
+            age,
+            label_column(labels.get(i).and_then(Option::as_deref).unwrap_or("-")),
+            width = width
+        )
+    }));
+    lines
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bdk::bitcoin::hashes::Hash;
+    use bdk::esplora_client::Vin;
+    use std::str::FromStr;
+
+    use crate::test_util::{self, input, output, p2wpkh, status, txid, utxo};
+
+    /// Pays `values` to our script, spending output `vout` of `prev` from it
+    fn tx(id: u8, height: Option<u32>, spends: Option<(u8, u32)>, values: &[u64]) -> Tx {
+        let vin = spends.map(|(prev, vout)| input(txid(prev), vout, p2wpkh(), 0)).into_iter().collect();
+        let vout = values.iter().map(|&value| output(p2wpkh(), value)).collect();
+        Tx { status: status(height, None), ..test_util::tx(id, vin, vout) }
+    }
+
+    #[test]
+    fn test_unspent_outputs_sorted_by_value() {
+        let scripts = HashSet::from([script_hash(&p2wpkh())]);
+        let txs = vec![
+            tx(3, None, Some((1, 0)), &[40_000]),
+            tx(2, Some(105), None, &[70_000]),
+            tx(1, Some(100), None, &[50_000, 20_000]),
+        ];
+
+        let utxos = unspent_outputs(&txs, &scripts);
+        let outpoints: Vec<(Txid, u32)> = utxos.iter().map(|utxo| (utxo.txid, utxo.vout)).collect();
+        assert_eq!(outpoints, vec![(txid(2), 0), (txid(3), 0), (txid(1), 1)]);
+
+        assert_eq!(confirmations(utxos[0].height, 110), 6);
+        assert_eq!(utxos[0].age(110), Some(5));
+        assert_eq!(confirmations(utxos[1].height, 110), 0);
+        assert_eq!(utxos[1].age(110), None);
+    }
+
+    #[test]
+    fn test_confirmations() {
+        assert_eq!(confirmations(Some(990), 1_000), 11);
+        assert_eq!(confirmations(Some(990), 990), 1);
+        assert_eq!(confirmations(None, 1_000), 0);
+    }
+
+    #[test]
+    fn test_split_balance_by_confirmations() {
+        let utxos = [utxo(1, 1_000, Some(100), Script::new()), utxo(1, 2_000, Some(105), Script::new()), utxo(1, 4_000, Some(110), Script::new()), utxo(1, 8_000, None, Script::new())];
+
+        // At tip 110: 11, 6, and 1 confirmations
+        let split = split_balance(&utxos, 110, &HashSet::new(), Some(6), None);
+        assert_eq!((split.spendable, split.pending, split.unconfirmed, split.dust), (3_000, 4_000, 8_000, 0));
+        assert_eq!(split_balance(&utxos, 110, &HashSet::new(), None, None).pending, 0);
+    }
+
+    #[test]
+    fn test_split_balance_sets_dust_aside() {
+        let utxos = [utxo(1, 100_000, Some(100), Script::new()), utxo(1, 300, Some(100), Script::new()), utxo(1, 500, None, Script::new()), utxo(1, 546, None, Script::new())];
+        let split = split_balance(&utxos, 110, &HashSet::new(), None, Some(546));
+        assert_eq!((split.spendable, split.unconfirmed), (100_000, 546));
+        assert_eq!((split.dust, split.dust_count), (800, 2));
+    }
+
+    #[test]
+    fn test_split_balance_sets_immature_coinbase_aside() {
+        let mut reward = tx(5, Some(1_000), None, &[312_500_000]);
+        reward.vin = vec![Vin { prevout: None, is_coinbase: true, ..input(Txid::all_zeros(), u32::MAX, Script::new(), 0) }];
+        let txs = vec![reward, tx(1, Some(1_000), None, &[50_000])];
+        let coinbase = coinbase_txids(&txs);
+        assert_eq!(coinbase, HashSet::from([txid(5)]));
+
+        let scripts = HashSet::from([script_hash(&p2wpkh())]);
+        let utxos = unspent_outputs(&txs, &scripts);
+        // 100 confirmations at 1099; Bitcoin Core waits for 101
+        let split = split_balance(&utxos, 1_099, &coinbase, None, None);
+        assert_eq!((split.spendable, split.immature, split.immature_count), (50_000, 312_500_000, 1));
+        let split = split_balance(&utxos, 1_100, &coinbase, None, None);
+        assert_eq!((split.spendable, split.immature), (312_550_000, 0));
+    }
+
+    #[test]
+    fn test_trusted_pending_needs_every_input_ours() {
+        let scripts = HashSet::from([script_hash(&p2wpkh())]);
+        let mut from_others = tx(4, None, Some((9, 0)), &[30_000]);
+        from_others.vin[0].prevout.as_mut().unwrap().scriptpubkey = Script::from_str("6a0102").unwrap();
+        let txs = vec![
+            tx(1, Some(100), None, &[50_000]),
+            // Change from spending the confirmed output, then change of that change
+            tx(2, None, Some((1, 0)), &[20_000]),
+            tx(3, None, Some((2, 0)), &[10_000]),
+            from_others,
+            // Spending an untrusted output is no better than receiving it
+            tx(5, None, Some((4, 0)), &[25_000]),
+        ];
+
+        let trusted = trusted_pending(&txs, &scripts);
+        assert_eq!(trusted, HashSet::from([txid(2), txid(3)]));
+
+        let split = split_pending(&unspent_outputs(&txs, &scripts), &trusted, &HashSet::new(), None);
+        assert_eq!(split, PendingSplit { incoming: 25_000, change: 10_000, replaceable: 0, replaceable_txs: 0 });
+        assert_eq!(split_pending(&unspent_outputs(&txs, &scripts), &trusted, &HashSet::new(), Some(20_000)).change, 0);
+    }
+
+    #[test]
+    fn test_replaceable_pending_follows_descendants() {
+        let scripts = HashSet::from([script_hash(&p2wpkh())]);
+        let from_others = |id: u8, sequence: u32| {
+            let mut tx = tx(id, None, Some((id + 10, 0)), &[30_000]);
+            tx.vin[0].prevout.as_mut().unwrap().scriptpubkey = Script::from_str("6a0102").unwrap();
+            tx.vin[0].sequence = sequence;
+            tx
+        };
+        let mut confirmed = from_others(1, 0xffff_fffd);
+        confirmed.status = status(Some(100), None);
+        let txs = vec![
+            confirmed,
+            from_others(2, 0xffff_fffd),
+            // Final sequence numbers, so only replaceable through its parent
+            tx(3, None, Some((2, 0)), &[20_000]),
+            from_others(4, 0xffff_fffe),
+        ];
+
+        let replaceable = replaceable_pending(&txs);
+        assert_eq!(replaceable, HashSet::from([txid(2), txid(3)]));
+
+        let split = split_pending(&unspent_outputs(&txs, &scripts), &trusted_pending(&txs, &scripts), &replaceable, None);
+        assert_eq!(split, PendingSplit { incoming: 50_000, change: 0, replaceable: 20_000, replaceable_txs: 1 });
+        assert_eq!(split.non_signalling(), 30_000);
+    }
+
+    #[test]
+    fn test_script_type() {
+        assert_eq!(script_type(&p2wpkh()), "P2WPKH");
+        let p2tr = Script::from_str(&format!("5120{}", "11".repeat(32))).unwrap();
+        assert_eq!(script_type(&p2tr), "P2TR");
+        assert_eq!(script_type(&Script::from_str("6a0102").unwrap()), "UNKNOWN");
+    }
+
+    #[test]
+    fn test_format_table() {
+        let utxo = Utxo { vout: 2, ..utxo(1, 1_500_000, None, p2wpkh()) };
+        let single = ["single key".to_string()];
+        let lines = format_table(std::slice::from_ref(&utxo), 800_000, &single, &[None], Unit::Sats);
+        assert!(lines[1].starts_with(&format!("{}:2", txid(1))));
+        assert!(lines[1].ends_with("1,500,000              0  P2WPKH   single key       -"));
+        let in_btc = format_table(std::slice::from_ref(&utxo), 800_000, &single, &[None], Unit::Btc);
+        assert!(in_btc[0].contains("     Value (BTC)"));
+        assert!(in_btc[1].contains("      0.01500000              0"));
+        assert_eq!(lines[0].len(), lines[1].len());
+
+        let requirements = ["2-of-3 multisig".to_string(), "single key".to_string()];
+        let labelled =
+            format_table(&[utxo.clone(), utxo], 800_000, &requirements, &[Some("cold storage".to_string()), None], Unit::Sats);
+        assert!(labelled[0].ends_with("Requires            Age  Label"));
+        assert!(labelled[1].ends_with("P2WPKH   2-of-3 multisig       -  cold storage"));
+        assert!(labelled[2].ends_with("P2WPKH   single key            -  -"));
+    }
+}
@@ -0,0 +1,770 @@
+//! Wallets given as output descriptors, extended public keys, or multisig
+//! cosigner keys, and scanning the scripts they derive
+//!
+//! Descriptors are parsed with BDK's miniscript. A `#` checksum, when given,
+//! is checked first, so a mistyped descriptor fails before any lookup. For
+//! an account-level extended key the standard descriptors are built
+//! instead: `ypub`/`zpub` (and testnet `upub`/`vpub`) name their script
+//! type, so only that descriptor is built. A plain `xpub`/`tpub` doesn't,
+//! so BIP44, BIP49, BIP84, and BIP86 are all scanned. Each descriptor has a
+//! receive chain (`/0/*`) and a change chain (`/1/*`), scanned until
+//! `gap_limit` consecutive scripts have no history, or until `scan_limit`
+//! scripts when one is set.
+
+use bdk::bitcoin::hashes::sha256;
+use bdk::bitcoin::util::base58;
+use bdk::bitcoin::util::bip32::{ChildNumber, ExtendedPubKey};
+use bdk::bitcoin::{Network, Script, Txid};
+use bdk::descriptor::{calc_checksum, Descriptor, DescriptorPublicKey, DescriptorXKey, Wildcard};
+use bdk::esplora_client::Tx;
+use bdk::miniscript::{translate_hash_clone, DefiniteDescriptorKey, ForEachKey, TranslatePk, Translator};
+use bdk::KeychainKind;
+use std::collections::{HashMap, HashSet};
+use std::convert::Infallible;
+use std::str::FromStr;
+
+use crate::concurrent;
+use crate::progress::Progress;
+use crate::query::script_hash;
+
+/// Unused scripts in a row after which a chain is considered exhausted
+pub const DEFAULT_GAP_LIMIT: u32 = 20;
+/// Indexes of a ranged descriptor to derive when the scan didn't count them
+pub const UNSCANNED_INDEXES: u32 = 1_000;
+
+/// How far each chain of a wallet is scanned
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ScanLimits {
+    pub gap_limit: u32,
+    /// Most scripts derived from each chain, however recently one was used
+    pub scan_limit: Option<u32>,
+}
+
+impl Default for ScanLimits {
+    fn default() -> Self {
+        ScanLimits { gap_limit: DEFAULT_GAP_LIMIT, scan_limit: None }
+    }
+}
+
+/// Version bytes of the standard `xpub`/`tpub` encodings
+const XPUB_VERSION: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TPUB_VERSION: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+
+/// Standard single-key account layouts
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Purpose {
+    /// Legacy P2PKH
+    Bip44,
+    /// P2WPKH nested in P2SH
+    Bip49,
+    /// Native segwit P2WPKH
+    Bip84,
+    /// Taproot key path
+    Bip86,
+}
+
+impl Purpose {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Purpose::Bip44 => "BIP44",
+            Purpose::Bip49 => "BIP49",
+            Purpose::Bip84 => "BIP84",
+            Purpose::Bip86 => "BIP86",
+        }
+    }
+
+    /// Descriptor for one chain of an account key
+    fn descriptor(&self, xpub: &ExtendedPubKey, chain: u32) -> String {
+        match self {
+            Purpose::Bip44 => format!("pkh({}/{}/*)", xpub, chain),
+            Purpose::Bip49 => format!("sh(wpkh({}/{}/*))", xpub, chain),
+            Purpose::Bip84 => format!("wpkh({}/{}/*)", xpub, chain),
+            Purpose::Bip86 => format!("tr({}/{}/*)", xpub, chain),
+        }
+    }
+}
+
+/// One descriptor of a wallet, named for display
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WalletDescriptor {
+    pub label: String,
+    pub descriptor: Descriptor<DescriptorPublicKey>,
+    /// Receive or change chain, when the descriptor is one of a pair
+    pub keychain: Option<KeychainKind>,
+}
+
+/// The descriptors making up a wallet
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Wallet {
+    pub descriptors: Vec<WalletDescriptor>,
+    /// Implied by the extended keys; None when the wallet uses only single keys
+    pub network: Option<Network>,
+    /// What the user gave, for display
+    pub kind: &'static str,
+}
+
+impl Wallet {
+    /// The same wallet with its extended keys encoded for `network`, to look
+    /// for it on another chain. The keys derive the same scripts; only their
+    /// version bytes change. Single keys are the same everywhere.
+    pub fn for_network(&self, network: Network) -> Wallet {
+        let descriptors = self
+            .descriptors
+            .iter()
+            .map(|entry| WalletDescriptor {
+                descriptor: entry.descriptor.translate_pk(&mut NetworkEncoder(network)).unwrap_or_else(|never| match never {}),
+                ..entry.clone()
+            })
+            .collect();
+        Wallet { descriptors, network: self.network.map(|_| network), kind: self.kind }
+    }
+}
+
+/// Re-encodes extended keys for a network, for [`Wallet::for_network`]
+struct NetworkEncoder(Network);
+
+impl Translator<DescriptorPublicKey, DescriptorPublicKey, Infallible> for NetworkEncoder {
+    fn pk(&mut self, key: &DescriptorPublicKey) -> Result<DescriptorPublicKey, Infallible> {
+        Ok(match key {
+            DescriptorPublicKey::XPub(xkey) => DescriptorPublicKey::XPub(DescriptorXKey {
+                xkey: ExtendedPubKey { network: self.0, ..xkey.xkey },
+                ..xkey.clone()
+            }),
+            DescriptorPublicKey::Single(_) => key.clone(),
+        })
+    }
+
+    translate_hash_clone!(DescriptorPublicKey, DescriptorPublicKey, Infallible);
+}
+
+/// Parses an output descriptor such as `wpkh([d34db33f/84'/0'/0']xpub.../0/*)`.
+/// A receive (`/0/*`) or change (`/1/*`) descriptor brings its other chain
+/// along, and a multipath `<0;1>/*` descriptor is expanded into both.
+pub fn parse_descriptor(input: &str) -> Result<Wallet, String> {
+    let input = input.trim();
+    with_checksum(input)?;
+    let chains = if input.contains('<') {
+        let [receive, change] = expand_multipath(input)?;
+        vec![(Some(KeychainKind::External), receive), (Some(KeychainKind::Internal), change)]
+    } else {
+        vec![(None, input.to_string())]
+    };
+
+    let mut descriptors = Vec::new();
+    let mut networks = Vec::new();
+    for (keychain, text) in chains {
+        let (descriptor, network) = parse_one_descriptor(&text)?;
+        networks.extend(network);
+        descriptors.push((keychain, descriptor));
+    }
+    if let [(None, descriptor)] = &descriptors[..] {
+        if let Some((keychain, other)) = other_chain(descriptor) {
+            let given = descriptor.clone();
+            descriptors = match keychain {
+                KeychainKind::Internal => vec![(Some(KeychainKind::External), given), (Some(keychain), other)],
+                KeychainKind::External => vec![(Some(keychain), other), (Some(KeychainKind::Internal), given)],
+            };
+        }
+    }
+
+    Ok(Wallet {
+        descriptors: descriptors
+            .into_iter()
+            .map(|(keychain, descriptor)| {
+                let name = format!("{:?}", descriptor.desc_type());
+                let label = match keychain {
+                    Some(KeychainKind::External) => format!("{} receive", name),
+                    Some(KeychainKind::Internal) => format!("{} change", name),
+                    None => name,
+                };
+                WalletDescriptor { label, descriptor, keychain }
+            })
+            .collect(),
+        network: networks.first().copied(),
+        kind: "descriptor",
+    })
+}
+
+/// The descriptor as written with its `#` checksum, after checking the
+/// checksum it was given, if any. A multipath descriptor's checksum covers
+/// the multipath form.
+pub fn with_checksum(input: &str) -> Result<String, String> {
+    let input = input.trim();
+    let (body, given) = match input.rsplit_once('#') {
+        Some((body, given)) => (body, Some(given)),
+        None => (input, None),
+    };
+    let expected = calc_checksum(body).map_err(|e| format!("Invalid descriptor: {}", e))?;
+    if let Some(given) = given.filter(|given| *given != expected) {
+        return Err(format!(
+            "Invalid descriptor: the checksum is #{}, but the descriptor as written has checksum #{}. A typo in either \
+             makes them differ, so copy the descriptor again rather than changing its checksum",
+            given, expected
+        ));
+    }
+    Ok(format!("{}#{}", body, expected))
+}
+
+/// Parses one descriptor and checks its keys, returning the network they
+/// name, if any
+fn parse_one_descriptor(input: &str) -> Result<(Descriptor<DescriptorPublicKey>, Option<Network>), String> {
+    let descriptor = Descriptor::<DescriptorPublicKey>::from_str(input)
+        .map_err(|e| {
+            // Checksum errors already say so
+            let message = e.to_string();
+            if message.starts_with("Invalid descriptor") { message } else { format!("Invalid descriptor: {}", message) }
+        })?;
+
+    // Public keys can't derive hardened children
+    let hardened = descriptor.for_any_key(|key| match key {
+        DescriptorPublicKey::XPub(xkey) => {
+            xkey.wildcard == Wildcard::Hardened || xkey.derivation_path.into_iter().any(|step| step.is_hardened())
+        }
+        DescriptorPublicKey::Single(_) => false,
+    });
+    if hardened {
+        return Err("Invalid descriptor: hardened derivation after an xpub needs the private key".to_string());
+    }
+
+    let mut networks = Vec::new();
+    descriptor.for_each_key(|key| {
+        if let DescriptorPublicKey::XPub(xkey) = key {
+            networks.push(xkey.xkey.network);
+        }
+        true
+    });
+    networks.dedup();
+    if networks.len() > 1 {
+        return Err("Invalid descriptor: it mixes mainnet and testnet keys".to_string());
+    }
+
+    Ok((descriptor, networks.first().copied()))
+}
+
+/// Splits a BIP389 multipath descriptor into its receive and change
+/// descriptors, which miniscript can parse. The checksum, which covers the
+/// multipath form, has already been checked.
+fn expand_multipath(input: &str) -> Result<[String; 2], String> {
+    let mut rest = input.split('#').next().unwrap_or(input);
+
+    let mut chains = [String::new(), String::new()];
+    while let Some(start) = rest.find('<') {
+        let end = rest[start..].find('>').ok_or("Invalid descriptor: '<' without a closing '>'")? + start;
+        let [receive, change] = rest[start + 1..end].split(';').collect::<Vec<_>>()[..] else {
+            return Err("Invalid descriptor: only two-path <receive;change> keys are supported".to_string());
+        };
+        for (chain, step) in chains.iter_mut().zip([receive, change]) {
+            chain.push_str(&rest[..start]);
+            chain.push_str(step);
+        }
+        rest = &rest[end + 1..];
+    }
+    for chain in &mut chains {
+        chain.push_str(rest);
+    }
+    Ok(chains)
+}
+
+/// When every extended key ends in `/0/*` or every one in `/1/*`, the
+/// descriptor for the other chain and which chain that is
+fn other_chain(descriptor: &Descriptor<DescriptorPublicKey>) -> Option<(KeychainKind, Descriptor<DescriptorPublicKey>)> {
+    let mut chains = HashSet::new();
+    let standard = descriptor.for_each_key(|key| match key {
+        DescriptorPublicKey::XPub(xkey) => match xkey.derivation_path.into_iter().last() {
+            Some(ChildNumber::Normal { index }) if *index <= 1 && xkey.wildcard == Wildcard::Unhardened => {
+                chains.insert(*index);
+                true
+            }
+            _ => false,
+        },
+        DescriptorPublicKey::Single(_) => true,
+    });
+    let [chain] = chains.into_iter().collect::<Vec<_>>()[..] else { return None };
+    if !standard {
+        return None;
+    }
+
+    // Only key paths end in a wildcard, so this changes nothing else
+    let text = descriptor.to_string();
+    let text = text.split('#').next().unwrap_or(&text);
+    let other = text.replace(&format!("/{}/*", chain), &format!("/{}/*", 1 - chain));
+    let keychain = if chain == 0 { KeychainKind::Internal } else { KeychainKind::External };
+    Descriptor::from_str(&other).ok().map(|other| (keychain, other))
+}
+
+/// Parses an account-level extended public key, or returns None if the
+/// input doesn't look like one
+pub fn parse_extended_key(input: &str) -> Option<Result<Wallet, String>> {
+    let (version, network, purposes): (_, _, &[Purpose]) = match input.get(..4)? {
+        "xpub" => (XPUB_VERSION, Network::Bitcoin, &[Purpose::Bip44, Purpose::Bip49, Purpose::Bip84, Purpose::Bip86]),
+        "ypub" => (XPUB_VERSION, Network::Bitcoin, &[Purpose::Bip49]),
+        "zpub" => (XPUB_VERSION, Network::Bitcoin, &[Purpose::Bip84]),
+        "tpub" => (TPUB_VERSION, Network::Testnet, &[Purpose::Bip44, Purpose::Bip49, Purpose::Bip84, Purpose::Bip86]),
+        "upub" => (TPUB_VERSION, Network::Testnet, &[Purpose::Bip49]),
+        "vpub" => (TPUB_VERSION, Network::Testnet, &[Purpose::Bip84]),
+        _ => return None,
+    };
+
+    Some(decode_extended_key(input, version).map(|xpub| {
+        let descriptors = purposes
+            .iter()
+            .flat_map(|purpose| {
+                [(0, "receive", KeychainKind::External), (1, "change", KeychainKind::Internal)].map(
+                    |(chain, name, keychain)| WalletDescriptor {
+                        label: format!("{} {}", purpose.label(), name),
+                        descriptor: Descriptor::from_str(&purpose.descriptor(&xpub, chain))
+                            .expect("standard descriptors are valid"),
+                        keychain: Some(keychain),
+                    },
+                )
+            })
+            .collect();
+        Wallet { descriptors, network: Some(network), kind: "extended public key" }
+    }))
+}
+
+/// Builds an M-of-N wallet from cosigner account keys, with receive and
+/// change chains. Keys are sorted per script like `sortedmulti`. SLIP-132
+/// `Ypub`/`Upub` keys select P2SH-wrapped P2WSH; otherwise native P2WSH.
+pub fn multisig(threshold: usize, keys: &[String]) -> Result<Wallet, String> {
+    if threshold == 0 || threshold > keys.len() {
+        return Err(format!("Invalid multisig: threshold {} must be between 1 and the {} keys given", threshold, keys.len()));
+    }
+
+    let mut nested = None;
+    let mut network = None;
+    let mut xpubs = Vec::new();
+    for key in keys {
+        let (version, key_network, wants_nested) = match key.get(..4) {
+            Some("xpub" | "ypub" | "zpub") => (XPUB_VERSION, Network::Bitcoin, None),
+            Some("Ypub") => (XPUB_VERSION, Network::Bitcoin, Some(true)),
+            Some("Zpub") => (XPUB_VERSION, Network::Bitcoin, Some(false)),
+            Some("tpub" | "upub" | "vpub") => (TPUB_VERSION, Network::Testnet, None),
+            Some("Upub") => (TPUB_VERSION, Network::Testnet, Some(true)),
+            Some("Vpub") => (TPUB_VERSION, Network::Testnet, Some(false)),
+            _ => return Err(format!("Invalid multisig key '{}': expected an extended public key", key)),
+        };
+        if wants_nested.is_some() && nested.is_some() && wants_nested != nested {
+            return Err("Invalid multisig: keys mix nested (Ypub) and native (Zpub) segwit".to_string());
+        }
+        nested = nested.or(wants_nested);
+        if network.replace(key_network).is_some_and(|previous| previous != key_network) {
+            return Err("Invalid multisig: keys mix mainnet and testnet".to_string());
+        }
+        xpubs.push(decode_extended_key(key, version)?);
+    }
+
+    let descriptors = [(0, "receive", KeychainKind::External), (1, "change", KeychainKind::Internal)]
+        .into_iter()
+        .map(|(chain, name, keychain)| {
+            let keys: Vec<String> = xpubs.iter().map(|xpub| format!("{}/{}/*", xpub, chain)).collect();
+            let script = format!("wsh(sortedmulti({},{}))", threshold, keys.join(","));
+            let script = if nested == Some(true) { format!("sh({})", script) } else { script };
+            Ok(WalletDescriptor {
+                label: format!("{}-of-{} {}", threshold, keys.len(), name),
+                descriptor: Descriptor::from_str(&script).map_err(|e| format!("Invalid multisig: {}", e))?,
+                keychain: Some(keychain),
+            })
+        })
+        .collect::<Result<_, String>>()?;
+    Ok(Wallet { descriptors, network, kind: "multisig" })
+}
+
+/// Decodes a SLIP-132 key by swapping its version bytes for the standard ones
+fn decode_extended_key(input: &str, version: [u8; 4]) -> Result<ExtendedPubKey, String> {
+    let mut data = base58::from_check(input).map_err(|e| format!("Invalid extended public key: {}", e))?;
+    if data.len() != 78 {
+        return Err(format!("Invalid extended public key: expected 78 bytes, got {}", data.len()));
+    }
+    data[..4].copy_from_slice(&version);
+    ExtendedPubKey::decode(&data).map_err(|e| format!("Invalid extended public key: {}", e))
+}
+
+/// How much of one descriptor was scanned
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainUsage {
+    pub label: String,
+    /// Scripts derived and queried
+    pub scanned: u32,
+    /// Scripts with any history
+    pub used: u32,
+    /// Index just past the last script with history; the next fresh one
+    pub next_index: u32,
+    /// Indexes below `next_index` without history, in order
+    pub unused: Vec<u32>,
+    /// Stopped at the scan limit before reaching the gap limit, so later
+    /// scripts may have history too
+    pub limited: bool,
+}
+
+/// Everything found for a wallet
+#[derive(Debug)]
+pub struct ScanResult {
+    /// Every transaction touching the wallet, newest first like Esplora
+    pub txs: Vec<Tx>,
+    /// Hashes of every derived script, used or not
+    pub scripts: HashSet<sha256::Hash>,
+    /// The same hashes by receive or change chain, where known
+    pub keychains: HashMap<KeychainKind, HashSet<sha256::Hash>>,
+    pub chains: Vec<ChainUsage>,
+}
+
+/// Derives each descriptor's scripts in order, fetching their history with
+/// `history` (given the descriptor's position, the derivation index and the
+/// script), until `gap_limit` unused scripts in a row, `scan_limit`
+/// scripts, or the single script of a descriptor without a wildcard
+///
+/// Up to `concurrency` scripts are looked up at once. Scripts fetched past
+/// the end of the gap are ignored, so the result is the same as a scan one
+/// script at a time. Each window's last index is reported to `progress`.
+pub fn scan(
+    wallet: &Wallet,
+    limits: ScanLimits,
+    concurrency: usize,
+    progress: &Progress,
+    history: impl Fn(usize, u32, &Script) -> Result<Vec<Tx>, String> + Sync,
+) -> Result<ScanResult, String> {
+    let mut txs = Vec::new();
+    let mut scripts = HashSet::new();
+    let mut keychains: HashMap<KeychainKind, HashSet<sha256::Hash>> = HashMap::new();
+    let mut chains = Vec::new();
+
+    for (position, entry) in wallet.descriptors.iter().enumerate() {
+        let mut usage =
+            ChainUsage { label: entry.label.clone(), scanned: 0, used: 0, next_index: 0, unused: Vec::new(), limited: false };
+        // Unused indexes since the last used one, which are only holes if
+        // another one is used after them
+        let mut gap_indexes = Vec::new();
+        let mut gap = 0;
+        let mut index = 0;
+        'chain: loop {
+            // No more than could still be needed if none of them are used
+            let count = if entry.descriptor.has_wildcard() {
+                let remaining = limits.scan_limit.map_or(u32::MAX, |limit| limit - index);
+                (concurrency as u32).min(limits.gap_limit - gap).min(remaining).max(1)
+            } else {
+                1
+            };
+            if entry.descriptor.has_wildcard() {
+                progress.derived(&entry.label, index + count - 1);
+            }
+            let window: Vec<(u32, Script)> =
+                (index..index + count).map(|i| (i, entry.descriptor.at_derivation_index(i).script_pubkey())).collect();
+            let results = concurrent::map(&window, concurrency, |(i, script)| history(position, *i, script));
+
+            for ((i, script), found) in window.iter().zip(results) {
+                scripts.insert(script_hash(script));
+                if let Some(keychain) = entry.keychain {
+                    keychains.entry(keychain).or_default().insert(script_hash(script));
+                }
+                let found = found?;
+
+                usage.scanned += 1;
+                if found.is_empty() {
+                    gap += 1;
+                    gap_indexes.push(*i);
+                } else {
+                    usage.used += 1;
+                    usage.next_index = usage.scanned;
+                    usage.unused.append(&mut gap_indexes);
+                    gap = 0;
+                }
+                txs.extend(found);
+
+                if !entry.descriptor.has_wildcard() || gap >= limits.gap_limit {
+                    break 'chain;
+                }
+                if limits.scan_limit.is_some_and(|limit| usage.scanned >= limit) {
+                    usage.limited = true;
+                    break 'chain;
+                }
+            }
+            index += count;
+        }
+        chains.push(usage);
+    }
+
+    Ok(ScanResult { txs: merge_txs(txs), scripts, keychains, chains })
+}
+
+/// The transactions of several scripts' histories, each once (one
+/// transaction can pay several of a wallet's scripts), unconfirmed first
+/// and then by height descending, as Esplora lists them
+pub fn merge_txs(txs: impl IntoIterator<Item = Tx>) -> Vec<Tx> {
+    let mut seen: HashSet<Txid> = HashSet::new();
+    let mut merged: Vec<Tx> = txs.into_iter().filter(|tx| seen.insert(tx.txid)).collect();
+    merged.sort_by_key(|tx| std::cmp::Reverse(tx.status.block_height.unwrap_or(u32::MAX)));
+    merged
+}
+
+/// One script of a wallet: the descriptor it came from and its index
+#[derive(Debug, Clone)]
+pub struct DerivedScript<'a> {
+    /// Where the descriptor is in the list given to [`derive_scripts`]
+    pub position: usize,
+    pub entry: &'a WalletDescriptor,
+    pub index: u32,
+    /// The descriptor derived at `index`
+    pub descriptor: Descriptor<DefiniteDescriptorKey>,
+}
+
+/// The scripts of `descriptors`, each derived to its count of indexes (a
+/// descriptor without a wildcard has its one script), by script hash; a
+/// script two descriptors derive is kept for the first
+pub fn derive_scripts<'a>(descriptors: &[(&'a WalletDescriptor, u32)]) -> HashMap<sha256::Hash, DerivedScript<'a>> {
+    let mut scripts = HashMap::new();
+    for (position, &(entry, count)) in descriptors.iter().enumerate() {
+        let count = if entry.descriptor.has_wildcard() { count } else { 1 };
+        for index in 0..count {
+            let descriptor = entry.descriptor.at_derivation_index(index);
+            scripts
+                .entry(script_hash(&descriptor.script_pubkey()))
+                .or_insert(DerivedScript { position, entry, index, descriptor });
+        }
+    }
+    scripts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::status;
+    use bdk::bitcoin::Address;
+
+    // BIP84 test vector account key
+    const ZPUB: &str = "zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYs";
+    // The same key in standard encoding
+    const ZPUB_AS_XPUB: &str = "xpub6CatWdiZiodmUeTDp8LT5or8nmbKNcuyvz7WyksVFkKB4RHwCD3XyuvPEbvqAQY3rAPshWcMLoP2fMFMKHPJ4ZeZXYVUhLv1VMrjPC7PW6V";
+    // BIP86 test vector account key
+    const XPUB: &str = "xpub6BgBgsespWvERF3LHQu6CnqdvfEvtMcQjYrcRzx53QJjSxarj2afYWcLteoGVky7D3UKDP9QyrLprQ3VCECoY49yfdDEHGCtMMj92pReUsQ";
+
+    fn address(entry: &WalletDescriptor, index: u32) -> String {
+        let script = entry.descriptor.at_derivation_index(index).script_pubkey();
+        Address::from_script(&script, Network::Bitcoin).unwrap().to_string()
+    }
+
+    fn tx(id: u8, height: Option<u32>) -> Tx {
+        Tx { status: status(height, None), ..crate::test_util::tx(id, vec![], vec![]) }
+    }
+
+    #[test]
+    fn test_zpub_derives_bip84_addresses() {
+        let wallet = parse_extended_key(ZPUB).unwrap().unwrap();
+        assert_eq!(wallet.network, Some(Network::Bitcoin));
+        assert_eq!(wallet.descriptors.len(), 2);
+        assert_eq!(wallet.descriptors[0].label, "BIP84 receive");
+        assert_eq!(address(&wallet.descriptors[0], 0), "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+        assert_eq!(address(&wallet.descriptors[1], 0), "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el");
+    }
+
+    #[test]
+    fn test_xpub_scans_every_purpose() {
+        let wallet = parse_extended_key(XPUB).unwrap().unwrap();
+        let labels: Vec<&str> = wallet.descriptors.iter().map(|d| d.label.as_str()).collect();
+        assert_eq!(labels[0], "BIP44 receive");
+        assert_eq!(labels[7], "BIP86 change");
+        assert_eq!(
+            address(&wallet.descriptors[6], 0),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+    }
+
+    #[test]
+    fn test_for_network_keeps_the_scripts() {
+        let wallet = parse_extended_key(ZPUB).unwrap().unwrap();
+        let testnet = wallet.for_network(Network::Testnet);
+        assert_eq!(testnet.network, Some(Network::Testnet));
+        assert!(testnet.descriptors[0].descriptor.to_string().starts_with("wpkh(tpub"));
+        assert_eq!(testnet.descriptors[1].label, "BIP84 change");
+        for (mainnet, testnet) in wallet.descriptors.iter().zip(&testnet.descriptors) {
+            assert_eq!(mainnet.descriptor.at_derivation_index(3).script_pubkey(), testnet.descriptor.at_derivation_index(3).script_pubkey());
+        }
+        assert_eq!(testnet.for_network(Network::Bitcoin), wallet);
+    }
+
+    #[test]
+    fn test_parse_extended_key_rejects_other_input() {
+        assert!(parse_extended_key("bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu").is_none());
+        assert!(parse_extended_key("zpub6rFR7y4Q2AijBEqTUquhVz398htDFrtymD9xYYfG1m4wAcvPhXNfE3EfH1r1ADqtfSdVCToUG868RvUUkgDKf31mGDtKsAYz2oz2AGutZYt")
+            .unwrap()
+            .is_err());
+    }
+
+    #[test]
+    fn test_parse_descriptor() {
+        let descriptor = format!("wpkh([73c5da0a/84'/0'/0']{}/0/*)", ZPUB_AS_XPUB);
+        let wallet = parse_descriptor(&descriptor).unwrap();
+        assert_eq!(wallet.network, Some(Network::Bitcoin));
+        assert_eq!(wallet.descriptors[0].label, "Wpkh receive");
+        assert_eq!(address(&wallet.descriptors[0], 0), "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+        // The change chain comes along, with the same key origin
+        assert_eq!(wallet.descriptors[1].label, "Wpkh change");
+        assert_eq!(wallet.descriptors[1].keychain, Some(KeychainKind::Internal));
+        assert_eq!(address(&wallet.descriptors[1], 0), "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el");
+        assert!(wallet.descriptors[1].descriptor.to_string().starts_with("wpkh([73c5da0a/84'/0'/0']"));
+
+        // Given the change chain, the receive chain is still listed first
+        let change = parse_descriptor(&format!("wpkh({}/1/*)", ZPUB_AS_XPUB)).unwrap();
+        assert_eq!(address(&change.descriptors[0], 0), "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+
+        // Other paths are scanned as given
+        let other = parse_descriptor(&format!("wpkh({}/7/*)", ZPUB_AS_XPUB)).unwrap();
+        assert_eq!(other.descriptors.len(), 1);
+        assert_eq!(other.descriptors[0].label, "Wpkh");
+
+        // A single-key descriptor derives one script and implies no network
+        let single = parse_descriptor(
+            "wsh(sortedmulti(1,0330d54fd0dd420a6e5f8d3624f5f3482cae350f79d5f0753bf5beef9c2d91af3c,03d01115d548e7561b15c38f004d734633687cf4419620095bc5b0f47070afe85a))",
+        )
+        .unwrap();
+        assert_eq!(single.network, None);
+        assert!(!single.descriptors[0].descriptor.has_wildcard());
+    }
+
+    #[test]
+    fn test_parse_descriptor_checksum() {
+        let descriptor = format!("wpkh({}/0/*)", ZPUB_AS_XPUB);
+        let checksum = bdk::descriptor::calc_checksum(&descriptor).unwrap();
+        assert!(parse_descriptor(&format!("{}#{}", descriptor, checksum)).is_ok());
+        assert!(parse_descriptor(&format!("{}#qqqqqqqq", descriptor)).is_err());
+        assert!(parse_descriptor(&format!("wpkh({}/0h/*)", ZPUB_AS_XPUB)).is_err());
+        assert!(parse_descriptor("wpkh(not-a-key)").is_err());
+    }
+
+    #[test]
+    fn test_with_checksum() {
+        let descriptor = format!("wpkh({}/0/*)", ZPUB_AS_XPUB);
+        let checksum = calc_checksum(&descriptor).unwrap();
+        let expected = format!("{}#{}", descriptor, checksum);
+        assert_eq!(with_checksum(&descriptor).unwrap(), expected);
+        assert_eq!(with_checksum(&format!(" {} ", expected)).unwrap(), expected);
+
+        let error = with_checksum(&format!("{}#qqqqqqqq", descriptor)).unwrap_err();
+        assert!(error.starts_with(&format!("Invalid descriptor: the checksum is #qqqqqqqq, but the descriptor as written has checksum #{}.", checksum)), "{}", error);
+        // A typo in the descriptor changes its checksum
+        let typo = expected.replacen("wpkh(", "wsh(", 1);
+        assert!(with_checksum(&typo).is_err());
+        assert!(parse_descriptor(&typo).unwrap_err().contains("the checksum is"));
+        assert!(with_checksum("wpkh(é)").is_err());
+    }
+
+    #[test]
+    fn test_parse_multipath_descriptor() {
+        let descriptor = format!("wpkh({}/<0;1>/*)", ZPUB_AS_XPUB);
+        let wallet = parse_descriptor(&descriptor).unwrap();
+        let labels: Vec<&str> = wallet.descriptors.iter().map(|d| d.label.as_str()).collect();
+        assert_eq!(labels, vec!["Wpkh receive", "Wpkh change"]);
+        assert_eq!(address(&wallet.descriptors[1], 0), "bc1q8c6fshw2dlwun7ekn9qwf37cu2rn755upcp6el");
+
+        // The checksum is of the multipath form
+        let checksum = calc_checksum(&descriptor).unwrap();
+        assert!(parse_descriptor(&format!("{}#{}", descriptor, checksum)).is_ok());
+        assert!(parse_descriptor(&format!("{}#qqqqqqqq", descriptor)).is_err());
+        assert!(parse_descriptor(&format!("wpkh({}/<0;1;2>/*)", ZPUB_AS_XPUB)).is_err());
+        assert!(parse_descriptor(&format!("wpkh({}/<0;1/*)", ZPUB_AS_XPUB)).is_err());
+    }
+
+    /// `key` re-encoded with other SLIP-132 version bytes
+    fn with_version(key: &str, version: [u8; 4]) -> String {
+        let mut data = base58::from_check(key).unwrap();
+        data[..4].copy_from_slice(&version);
+        base58::check_encode_slice(&data)
+    }
+
+    #[test]
+    fn test_multisig_descriptors() {
+        let keys = [XPUB.to_string(), ZPUB_AS_XPUB.to_string()];
+        let wallet = multisig(2, &keys).unwrap();
+        let expected = format!("wsh(sortedmulti(2,{}/0/*,{}/0/*))", XPUB, ZPUB_AS_XPUB);
+        assert_eq!(wallet.descriptors[0].descriptor, Descriptor::from_str(&expected).unwrap());
+        assert_eq!(wallet.descriptors[1].label, "2-of-2 change");
+        assert_eq!(wallet.network, Some(Network::Bitcoin));
+
+        // Ypub cosigner keys mean P2SH-wrapped P2WSH
+        let ypubs = keys.map(|key| with_version(&key, [0x02, 0x95, 0xb4, 0x3f]));
+        let nested = multisig(1, &ypubs).unwrap();
+        assert!(nested.descriptors[0].descriptor.to_string().starts_with("sh(wsh(sortedmulti(1,"));
+    }
+
+    #[test]
+    fn test_multisig_rejects_bad_input() {
+        let keys = [XPUB.to_string(), ZPUB_AS_XPUB.to_string()];
+        assert!(multisig(0, &keys).is_err());
+        assert!(multisig(3, &keys).is_err());
+        assert!(multisig(1, &[XPUB.to_string(), "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu".to_string()]).is_err());
+        let testnet = with_version(ZPUB_AS_XPUB, TPUB_VERSION);
+        assert!(multisig(1, &[XPUB.to_string(), testnet]).is_err());
+    }
+
+    #[test]
+    fn test_derive_scripts() {
+        let wallet = parse_extended_key(ZPUB).unwrap().unwrap();
+        let single = parse_descriptor("wpkh(02e6642fd69bd211f93f7f1f36ca51a26a5290eb2dd1b0d8279a87bb0d480c8443)").unwrap();
+        let chains = [(&wallet.descriptors[0], 3), (&wallet.descriptors[1], 2), (&single.descriptors[0], 5)];
+        let derived = derive_scripts(&chains);
+
+        // Three receive, two change and the one script without a wildcard
+        assert_eq!(derived.len(), 6);
+        let script = wallet.descriptors[1].descriptor.at_derivation_index(1).script_pubkey();
+        let change = &derived[&script_hash(&script)];
+        assert_eq!((change.position, change.index), (1, 1));
+        assert_eq!(change.descriptor.script_pubkey(), script);
+    }
+
+    #[test]
+    fn test_scan_stops_after_gap_limit() {
+        let wallet = parse_extended_key(ZPUB).unwrap().unwrap();
+        let receive = &wallet.descriptors[0].descriptor;
+        let used = [0, 3].map(|i| receive.at_derivation_index(i).script_pubkey());
+
+        // Looking scripts up together must not change what is found
+        for concurrency in [1, 3, 8] {
+            let limits = ScanLimits { gap_limit: 5, scan_limit: None };
+            let result = scan(&wallet, limits, concurrency, &Progress::new(false), |_, _, script| {
+                Ok(match used.iter().position(|u| u == script) {
+                    Some(0) => vec![tx(1, Some(100)), tx(2, None)],
+                    // The same transaction paid both addresses
+                    Some(_) => vec![tx(1, Some(100)), tx(3, Some(200))],
+                    None => vec![],
+                })
+            })
+            .unwrap();
+
+            // Receive: 0..=3 plus five unused; change: five unused
+            assert_eq!(
+                result.chains[0],
+                ChainUsage { label: "BIP84 receive".to_string(), scanned: 9, used: 2, next_index: 4, unused: vec![1, 2], limited: false }
+            );
+            assert_eq!(result.chains[1].scanned, 5);
+            assert_eq!(result.scripts.len(), 14);
+            assert_eq!(result.keychains[&KeychainKind::Internal].len(), 5);
+            let heights: Vec<Option<u32>> = result.txs.iter().map(|tx| tx.status.block_height).collect();
+            assert_eq!(heights, vec![None, Some(200), Some(100)]);
+        }
+    }
+
+    #[test]
+    fn test_scan_stops_at_scan_limit() {
+        let wallet = parse_extended_key(ZPUB).unwrap().unwrap();
+        let receive = &wallet.descriptors[0].descriptor;
+        let used: Vec<Script> = (0..40).step_by(10).map(|i| receive.at_derivation_index(i).script_pubkey()).collect();
+
+        for concurrency in [1, 4] {
+            // Every tenth receive script is used, so the gap is never reached
+            let limits = ScanLimits { gap_limit: 15, scan_limit: Some(25) };
+            let result = scan(&wallet, limits, concurrency, &Progress::new(false), |_, _, script| {
+                Ok(if used.contains(script) { vec![tx(1, Some(100))] } else { vec![] })
+            })
+            .unwrap();
+            let unused: Vec<u32> = (1..20).filter(|i| i % 10 != 0).collect();
+            assert_eq!(
+                result.chains[0],
+                ChainUsage { label: "BIP84 receive".to_string(), scanned: 25, used: 3, next_index: 21, unused, limited: true }
+            );
+            assert_eq!(
+                result.chains[1],
+                ChainUsage { label: "BIP84 change".to_string(), scanned: 15, used: 0, next_index: 0, unused: vec![], limited: false }
+            );
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Watch-only wallets saved with `--save-wallet NAME` and opened with
+//! `--wallet NAME`, one JSON file each in
+//! `~/.local/share/bdk-experiments/wallets` (or `$XDG_DATA_HOME`), where
+//! the workspace's other tools can open them too
+//!
+//! A file holds the wallet's public descriptors with their checksums, the
+//! network they're used on, and what the last check found: the tip it was
+//! synced at and the balance. Every check of a saved wallet brings that state
+//! up to date. It's only shown, never used to shorten a scan: the history
+//! store keeps its own checkpoint for each descriptor and skips settled
+//! history below it (see [`crate::store`]).
+
+use bdk::descriptor::{Descriptor, DescriptorPublicKey};
+use bdk::KeychainKind;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use crate::chain::Chain;
+use crate::store;
+use crate::wallet::{Wallet, WalletDescriptor};
+
+const WALLETS_DIR: &str = "wallets";
+const MAX_NAME_LENGTH: usize = 64;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedWallet {
+    pub name: String,
+    /// `bitcoin`, `testnet`, `testnet4`, `signet` or `regtest`
+    pub network: String,
+    pub descriptors: Vec<SavedDescriptor>,
+    /// None until the wallet was first checked
+    pub sync: Option<SyncState>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SavedDescriptor {
+    pub label: String,
+    /// `receive` or `change`, when the descriptor is one of a pair
+    pub keychain: Option<String>,
+    pub descriptor: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SyncState {
+    pub tip_height: u32,
+    /// RFC 3339
+    pub synced_at: String,
+    pub confirmed: u64,
+    pub unconfirmed: u64,
+}
+
+impl SavedWallet {
+    pub fn new(name: &str, chain: Chain, wallet: &Wallet) -> Result<Self, String> {
+        let descriptors = wallet
+            .descriptors
+            .iter()
+            .map(|entry| SavedDescriptor {
+                label: entry.label.clone(),
+                keychain: entry.keychain.map(|keychain| keychain_name(keychain).to_string()),
+                descriptor: entry.descriptor.to_string(),
+            })
+            .collect();
+        Ok(SavedWallet { name: validate_name(name)?.to_string(), network: chain.name().to_string(), descriptors, sync: None })
+    }
+
+    pub fn chain(&self) -> Result<Chain, String> {
+        Chain::ALL
+            .into_iter()
+            .find(|chain| chain.name() == self.network)
+            .ok_or_else(|| format!("Wallet {} names an unknown network '{}'", self.name, self.network))
+    }
+
+    /// The descriptors to scan, checked against their checksums
+    pub fn wallet(&self) -> Result<Wallet, String> {
+        let descriptors = self
+            .descriptors
+            .iter()
+            .map(|saved| {
+                let descriptor = Descriptor::<DescriptorPublicKey>::from_str(&saved.descriptor)
+                    .map_err(|e| format!("Wallet {} has an invalid descriptor: {}", self.name, e))?;
+                let keychain = match saved.keychain.as_deref() {
+                    None => None,
+                    Some("receive") => Some(KeychainKind::External),
+                    Some("change") => Some(KeychainKind::Internal),
+                    Some(other) => return Err(format!("Wallet {} names an unknown keychain '{}'", self.name, other)),
+                };
+                Ok(WalletDescriptor { label: saved.label.clone(), descriptor, keychain })
+            })
+            .collect::<Result<Vec<_>, String>>()?;
+        if descriptors.is_empty() {
+            return Err(format!("Wallet {} has no descriptors", self.name));
+        }
+        Ok(Wallet { descriptors, network: Some(self.chain()?.network()), kind: "saved wallet" })
+    }
+
+    /// Whether `other` watches the same scripts on the same network
+    pub fn same_wallet(&self, other: &SavedWallet) -> bool {
+        let descriptors = |wallet: &SavedWallet| -> Vec<String> {
+            wallet.descriptors.iter().map(|saved| saved.descriptor.clone()).collect()
+        };
+        self.network == other.network && descriptors(self) == descriptors(other)
+    }
+}
+
+fn keychain_name(keychain: KeychainKind) -> &'static str {
+    match keychain {
+        KeychainKind::External => "receive",
+        KeychainKind::Internal => "change",
+    }
+}
+
+pub fn default_dir() -> Option<PathBuf> {
+    Some(store::shared_dir()?.join(WALLETS_DIR))
+}
+
+/// Names become file names, so they're kept to a safe set of characters
+pub fn validate_name(name: &str) -> Result<&str, String> {
+    let valid = !name.is_empty()
+        && name.len() <= MAX_NAME_LENGTH
+        && !name.starts_with('.')
+        && name.chars().all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'));
+    if !valid {
+        return Err(format!(
+            "Invalid wallet name '{}': use up to {} letters, digits, '-', '_' or '.', not starting with '.'",
+            name, MAX_NAME_LENGTH
+        ));
+    }
+    Ok(name)
+}
+
+fn path(dir: &Path, name: &str) -> Result<PathBuf, String> {
+    Ok(dir.join(format!("{}.json", validate_name(name)?)))
+}
+
+/// The saved wallet, or None if there's none by that name
+pub fn load(dir: &Path, name: &str) -> Result<Option<SavedWallet>, String> {
+    let path = path(dir, name)?;
+    let text = match fs::read_to_string(&path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(format!("Could not read {}: {}", path.display(), e)),
+    };
+    let wallet: SavedWallet =
+        serde_json::from_str(&text).map_err(|e| format!("Could not read {}: {}", path.display(), e))?;
+    if wallet.name != name {
+        return Err(format!("{} holds wallet '{}', not '{}'", path.display(), wallet.name, name));
+    }
+    Ok(Some(wallet))
+}
+
+/// Writes the wallet through a temporary file, so a reader never sees half
+/// of it. Returns where it was written.
+pub fn save(dir: &Path, wallet: &SavedWallet) -> Result<PathBuf, String> {
+    let path = path(dir, &wallet.name)?;
+    fs::create_dir_all(dir).map_err(|e| format!("Could not create {}: {}", dir.display(), e))?;
+    let partial = path.with_extension("json.partial");
+    let text = serde_json::to_string_pretty(wallet).expect("saved wallets serialize");
+    fs::write(&partial, text + "\n")
+        .and_then(|()| fs::rename(&partial, &path))
+        .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+    Ok(path)
+}
+
+/// Every readable saved wallet, by name; unreadable files only warn
+pub fn list(dir: &Path) -> Result<Vec<SavedWallet>, String> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(format!("Could not read {}: {}", dir.display(), e)),
+    };
+    let mut wallets = Vec::new();
+    for entry in entries.filter_map(Result::ok) {
+        let file_name = entry.file_name().to_string_lossy().into_owned();
+        let Some(name) = file_name.strip_suffix(".json") else { continue };
+        match load(dir, name) {
+            Ok(Some(wallet)) => wallets.push(wallet),
+            Ok(None) => {}
+            Err(e) => eprintln!("Warning: {}", e),
+        }
+    }
+    wallets.sort_by(|a, b| a.name.cmp(&b.name));
+    Ok(wallets)
+}
+
+/// Whether there was a wallet to remove
+pub fn remove(dir: &Path, name: &str) -> Result<bool, String> {
+    let path = path(dir, name)?;
+    match fs::remove_file(&path) {
+        Ok(()) => Ok(true),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(false),
+        Err(e) => Err(format!("Could not remove {}: {}", path.display(), e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::wallet::parse_descriptor;
+
+    const DESCRIPTOR: &str = "wpkh([73c5da0a/84'/1'/0']tpubDC8msFGeGuwnKG9Upg7DM2b4DaRqg3CUZa5g8v2SRQ6K4NSkxUgd7HsL2XVWbVm39yBA4LAxysQAm397zwQSQoQgewGiYZqrA9DsP4zbQ1M/0/*)";
+
+    fn temporary_dir(test: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("balance-checker-{}-{}", test, rand::random::<u64>()));
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_save_and_reopen() {
+        let dir = temporary_dir("wallets");
+        let wallet = parse_descriptor(DESCRIPTOR).unwrap();
+        let mut saved = SavedWallet::new("savings", Chain::Testnet4, &wallet).unwrap();
+        saved.sync = Some(SyncState {
+            tip_height: 80_000,
+            synced_at: "2026-10-16T12:00:00Z".to_string(),
+            confirmed: 1_000,
+            unconfirmed: 0,
+        });
+        save(&dir, &saved).unwrap();
+
+        let reopened = load(&dir, "savings").unwrap().unwrap();
+        assert_eq!(reopened, saved);
+        assert_eq!(reopened.chain().unwrap(), Chain::Testnet4);
+        let opened = reopened.wallet().unwrap();
+        assert_eq!(opened.descriptors, wallet.descriptors);
+        assert_eq!(opened.kind, "saved wallet");
+        assert!(reopened.descriptors[0].descriptor.contains('#'), "saved with its checksum");
+        assert_eq!(reopened.descriptors[1].keychain.as_deref(), Some("change"));
+
+        assert_eq!(list(&dir).unwrap().len(), 1);
+        assert!(load(&dir, "other").unwrap().is_none());
+        assert!(remove(&dir, "savings").unwrap());
+        assert!(!remove(&dir, "savings").unwrap());
+        assert!(list(&dir).unwrap().is_empty());
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_same_wallet_compares_descriptors_and_network() {
+        let wallet = parse_descriptor(DESCRIPTOR).unwrap();
+        let saved = SavedWallet::new("a", Chain::Testnet, &wallet).unwrap();
+        assert!(saved.same_wallet(&SavedWallet::new("b", Chain::Testnet, &wallet).unwrap()));
+        assert!(!saved.same_wallet(&SavedWallet::new("a", Chain::Signet, &wallet).unwrap()));
+        let other = parse_descriptor(&DESCRIPTOR.replace("/0/*", "/5/*")).unwrap();
+        assert!(!saved.same_wallet(&SavedWallet::new("a", Chain::Testnet, &other).unwrap()));
+    }
+
+    #[test]
+    fn test_names_are_safe_file_names() {
+        assert!(validate_name("cold-storage_2024.v2").is_ok());
+        for name in ["", "../escape", ".hidden", "a/b", "with space", &"x".repeat(65)] {
+            assert!(validate_name(name).is_err(), "{:?}", name);
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! Watch mode for `--watch`: re-checks the balance every `--interval`
+//! seconds after the first report and prints a line whenever the confirmed
+//! or unconfirmed amount changes, e.g. while waiting for a payment
+//!
+//! A change can also ring the terminal bell (`--beep`) or run a shell
+//! command (`--on-change`), which gets the new balance in `BALANCE_*`
+//! environment variables. `--webhook` and `--exec` send a JSON description
+//! of the change, transactions included (see `alerts`). With
+//! `--mempool-watch`, each new incoming mempool payment also gets a line with
+//! its fee rate and confirmation estimate. A failed poll is reported and
+//! retried at the next interval rather than ending the watch.
+
+use chrono::Utc;
+use std::io::{self, Write};
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+use units::Unit;
+
+use crate::alerts::{self, TxEffects};
+use crate::mempool::{self, Incoming};
+use crate::proxy::Proxy;
+use crate::query::QueryTarget;
+use crate::wallet::ScanLimits;
+use crate::{fee_estimates, fetch_target, merge_histories, tally, tip_height, BalanceInfo, FetchOptions, Source};
+
+pub struct WatchConfig {
+    pub interval: Duration,
+    pub beep: bool,
+    pub on_change: Option<String>,
+    pub webhook: Option<String>,
+    pub exec: Option<String>,
+    /// For the webhook
+    pub proxy: Option<Proxy>,
+    pub limits: ScanLimits,
+    pub concurrency: usize,
+    /// Report incoming mempool payments as they're first seen
+    pub mempool: bool,
+    /// For amounts in the printed lines; alerts carry sats
+    pub unit: Unit,
+}
+
+/// Polls until the process is stopped, starting from the balance and
+/// transactions already shown
+pub fn run(source: &Source, targets: &[QueryTarget], mut balance: BalanceInfo, mut effects: TxEffects, config: &WatchConfig) -> ! {
+    println!("\nWatching for balance changes every {}s (Ctrl-C to stop)...", config.interval.as_secs());
+    loop {
+        thread::sleep(config.interval);
+        let now = Utc::now().format("%Y-%m-%d %H:%M:%S UTC");
+        let (current, current_effects, incoming) = match check(source, targets, config.limits, config.concurrency) {
+            Ok(checked) => checked,
+            Err(e) => {
+                eprintln!("[{}] Warning: {}; retrying in {}s", now, e, config.interval.as_secs());
+                continue;
+            }
+        };
+        if config.mempool {
+            report_incoming(source, &incoming, &effects, &now.to_string(), config.unit);
+        }
+        let Some(change) = describe_change(&balance, &current, config.unit) else {
+            effects = current_effects;
+            continue;
+        };
+
+        println!("[{}] {}", now, change);
+        if config.beep {
+            print!("\x07");
+            let _ = io::stdout().flush();
+        }
+        if let Some(command) = &config.on_change {
+            run_command(command, &balance, &current);
+        }
+        if config.webhook.is_some() || config.exec.is_some() {
+            let changes = alerts::tx_changes(&effects, &current_effects);
+            let tip = tip_height(source).ok();
+            let payload = alerts::payload(&change, &balance, &current, &changes, tip, Utc::now());
+            if let Some(url) = &config.webhook {
+                if let Err(e) = alerts::post_webhook(url, &payload, config.proxy.as_ref()) {
+                    eprintln!("Warning: Could not send the --webhook alert: {}", e);
+                }
+            }
+            if let Some(command) = &config.exec {
+                if let Err(e) = alerts::run_exec(command, &payload, &command_env(&balance, &current)) {
+                    eprintln!("Warning: --exec command failed: {}", e);
+                }
+            }
+        }
+        balance = current;
+        effects = current_effects;
+    }
+}
+
+fn check(
+    source: &Source,
+    targets: &[QueryTarget],
+    limits: ScanLimits,
+    concurrency: usize,
+) -> Result<(BalanceInfo, TxEffects, Vec<Incoming>), String> {
+    let histories = targets
+        .iter()
+        .map(|target| fetch_target(source, target, FetchOptions::quiet(limits, concurrency)))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| e.to_string())?;
+    let history = merge_histories(histories);
+    Ok((
+        tally(&history).0,
+        alerts::tx_effects(&history.txs, &history.scripts),
+        mempool::incoming(&history.txs, &history.scripts),
+    ))
+}
+
+/// Prints the incoming payments the previous poll didn't see, with their
+/// confirmation estimates
+fn report_incoming(source: &Source, incoming: &[Incoming], previous: &TxEffects, now: &str, unit: Unit) {
+    let new: Vec<&Incoming> = incoming.iter().filter(|payment| !previous.contains_key(&payment.txid)).collect();
+    if new.is_empty() {
+        return;
+    }
+    let estimates = fee_estimates(source).unwrap_or_else(|e| {
+        eprintln!("[{}] Warning: Could not fetch fee estimates: {}", now, e);
+        Vec::new()
+    });
+    for payment in new {
+        println!("[{}] Incoming payment: {}", now, mempool::format_payment(payment, &estimates, unit));
+    }
+}
+
+/// One line describing what changed in `unit`, or None if nothing did
+fn describe_change(previous: &BalanceInfo, current: &BalanceInfo, unit: Unit) -> Option<String> {
+    if previous == current {
+        return None;
+    }
+    let part = |name: &str, before: u64, after: u64| {
+        if before == after {
+            format!("{} {}", name, unit.number(after))
+        } else {
+            let change = unit.signed_number(after as i64 - before as i64);
+            format!("{} {} -> {} ({})", name, unit.number(before), unit.number(after), change)
+        }
+    };
+    Some(format!(
+        "Balance changed: {}, {}; total {}",
+        part("confirmed", previous.confirmed, current.confirmed),
+        part("unconfirmed", previous.unconfirmed, current.unconfirmed),
+        unit.format(current.total())
+    ))
+}
+
+/// Variables describing the change, for the `--on-change` command
+fn command_env(previous: &BalanceInfo, current: &BalanceInfo) -> Vec<(&'static str, String)> {
+    vec![
+        ("BALANCE_CONFIRMED", current.confirmed.to_string()),
+        ("BALANCE_UNCONFIRMED", current.unconfirmed.to_string()),
+        ("BALANCE_TOTAL", current.total().to_string()),
+        ("BALANCE_CHANGE", (current.total() as i64 - previous.total() as i64).to_string()),
+    ]
+}
+
+/// The command, to be run through the platform's shell
+pub fn shell(command: &str) -> Command {
+    let mut shell = if cfg!(windows) {
+        let mut shell = Command::new("cmd");
+        shell.arg("/C");
+        shell
+    } else {
+        let mut shell = Command::new("sh");
+        shell.arg("-c");
+        shell
+    };
+    shell.arg(command);
+    shell
+}
+
+/// Runs the command through the shell and waits for it; failures only warn
+fn run_command(command: &str, previous: &BalanceInfo, current: &BalanceInfo) {
+    match shell(command).envs(command_env(previous, current)).status() {
+        Ok(status) if !status.success() => eprintln!("Warning: --on-change command exited with {}", status),
+        Ok(_) => {}
+        Err(e) => eprintln!("Warning: Could not run --on-change command: {}", e),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_describe_change() {
+        let before = BalanceInfo { confirmed: 100_000, unconfirmed: 0 };
+        assert_eq!(describe_change(&before, &BalanceInfo { confirmed: 100_000, unconfirmed: 0 }, Unit::Sats), None);
+
+        let incoming = BalanceInfo { confirmed: 100_000, unconfirmed: 50_000 };
+        assert_eq!(
+            describe_change(&before, &incoming, Unit::Sats).unwrap(),
+            "Balance changed: confirmed 100,000, unconfirmed 0 -> 50,000 (+50,000); total 150,000 sats"
+        );
+
+        let confirmed = BalanceInfo { confirmed: 150_000, unconfirmed: 0 };
+        assert_eq!(
+            describe_change(&incoming, &confirmed, Unit::Sats).unwrap(),
+            "Balance changed: confirmed 100,000 -> 150,000 (+50,000), unconfirmed 50,000 -> 0 (-50,000); total 150,000 sats"
+        );
+        assert_eq!(
+            describe_change(&incoming, &confirmed, Unit::Btc).unwrap(),
+            "Balance changed: confirmed 0.00100000 -> 0.00150000 (+0.00050000), unconfirmed 0.00050000 -> 0.00000000 (-0.00050000); total 0.00150000 BTC"
+        );
+    }
+
+    #[test]
+    fn test_command_env() {
+        let env = command_env(
+            &BalanceInfo { confirmed: 100_000, unconfirmed: 0 },
+            &BalanceInfo { confirmed: 60_000, unconfirmed: 0 },
+        );
+        assert!(env.contains(&("BALANCE_TOTAL", "60000".to_string())));
+        assert!(env.contains(&("BALANCE_CHANGE", "-40000".to_string())));
+    }
+}
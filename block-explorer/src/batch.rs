@@ -0,0 +1,232 @@
+//! Batch queries: one block identifier per input line, one summary record per
+//! block on stdout
+
+use crate::error::ExplorerError;
+use crate::esplora::EsploraClient;
+use crate::headers;
+use crate::progress::Progress;
+use crate::work::{block_work, format_chainwork, zero_work};
+use crate::{parse_block_identifier, BlockIdentifier, BlockInfo, OutputFormat};
+use serde::Serialize;
+use std::io::{self, BufRead};
+
+/// Condensed, machine-friendly view of a block
+#[derive(Debug, PartialEq, Serialize)]
+pub struct BlockSummary {
+    pub height: u32,
+    pub hash: String,
+    pub timestamp: u64,
+    pub tx_count: usize,
+    pub size: usize,
+    pub weight: usize,
+    pub difficulty: f64,
+    /// Expected hashes to mine this block, in decimal
+    pub work: String,
+    /// Work summed over the range so far (hex), only in range mode. A block
+    /// that couldn't be fetched leaves it out of every later record, as the
+    /// sum would be short.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cumulative_work: Option<String>,
+}
+
+impl From<&BlockInfo> for BlockSummary {
+    fn from(block: &BlockInfo) -> Self {
+        BlockSummary {
+            height: block.height,
+            hash: block.id.clone(),
+            timestamp: block.timestamp,
+            tx_count: block.tx_count,
+            size: block.size,
+            weight: block.weight,
+            difficulty: block.difficulty,
+            work: block_work(block.bits).to_string(),
+            cumulative_work: None,
+        }
+    }
+}
+
+/// A failed lookup, reported in place of a summary so line counts match input
+#[derive(Debug, Serialize)]
+struct BlockError<'a> {
+    input: &'a str,
+    error: String,
+}
+
+/// Reads one identifier per line, skipping blank lines and `#` comments
+pub fn read_identifiers(reader: impl BufRead) -> io::Result<Vec<String>> {
+    let mut identifiers = Vec::new();
+    for line in reader.lines() {
+        let line = line?;
+        let trimmed = line.trim();
+        if !trimmed.is_empty() && !trimmed.starts_with('#') {
+            identifiers.push(trimmed.to_string());
+        }
+    }
+    Ok(identifiers)
+}
+
+/// Resolves a height, hash, or hash prefix and fetches the block, recording
+/// it in the header store; offline, only the store is consulted
+pub fn query_block(client: &EsploraClient, input: &str) -> Result<BlockInfo, ExplorerError> {
+    let identifier = parse_block_identifier(input);
+    if client.is_offline() {
+        return headers::require(client.headers())?.lookup(&identifier);
+    }
+
+    let hash = match identifier {
+        BlockIdentifier::Height(height) => client
+            .get_text(&format!("/block-height/{}", height))
+            .map_err(|e| ExplorerError::from_request("Could not fetch block hash", e))?,
+        BlockIdentifier::Hash(hash) => hash,
+        BlockIdentifier::HashPrefix(prefix) => headers::require(client.headers())?.resolve_prefix(&prefix)?.id,
+    };
+
+    let block = client
+        .get_json(&format!("/block/{}", hash))
+        .map_err(|e| ExplorerError::from_request("Could not fetch block", e))?;
+    if let Some(headers) = client.headers() {
+        headers.insert(&block);
+    }
+    Ok(block)
+}
+
+/// Queries every identifier and prints one record per block; in range mode
+/// each record also carries the work accumulated since the first block, up
+/// to the first block that fails. Failed lookups don't stop the batch, but
+/// the first one is returned so the exit code reflects it.
+pub fn run(
+    client: &EsploraClient,
+    identifiers: &[String],
+    format: OutputFormat,
+    range_mode: bool,
+    show_progress: bool,
+) -> Result<(), ExplorerError> {
+    if format == OutputFormat::Text {
+        let mut header = "height\thash\ttimestamp\ttx_count\tsize\tweight\tdifficulty\twork".to_string();
+        if range_mode {
+            header.push_str("\tcumulative_work");
+        }
+        println!("{}", header);
+    }
+
+    let progress = Progress::new(client, identifiers.len() as u64, "blocks", show_progress);
+    // None once a block is missing from the sum
+    let mut cumulative = Some(zero_work());
+    let mut first_error = None;
+    for input in identifiers {
+        match query_block(client, input) {
+            Ok(block) => {
+                let mut summary = BlockSummary::from(&block);
+                if range_mode {
+                    cumulative = cumulative.map(|work| work + block_work(block.bits));
+                    summary.cumulative_work = cumulative.map(format_chainwork);
+                }
+                progress.suspend(|| match format {
+                    OutputFormat::Text => println!("{}", format_text_record(&summary, range_mode)),
+                    OutputFormat::Json => println!("{}", to_json_line(&summary)),
+                });
+            }
+            Err(err) => {
+                progress.suspend(|| {
+                    match format {
+                        OutputFormat::Text => eprintln!("Warning: {}: {}", input, err),
+                        OutputFormat::Json => {
+                            println!("{}", to_json_line(&BlockError { input, error: err.to_string() }))
+                        }
+                    }
+                    if range_mode && cumulative.is_some() {
+                        eprintln!("Warning: cumulative work is left out from block {} on, as its work is unknown", input);
+                    }
+                });
+                cumulative = None;
+                first_error.get_or_insert(err);
+            }
+        }
+        progress.tick();
+    }
+
+    first_error.map_or(Ok(()), Err)
+}
+
+/// Tab-separated record matching the header printed by [`run`]; in range
+/// mode a missing cumulative work is `-`
+fn format_text_record(summary: &BlockSummary, range_mode: bool) -> String {
+    let mut record = format!(
+        "{}\t{}\t{}\t{}\t{}\t{}\t{:.2}\t{}",
+        summary.height,
+        summary.hash,
+        summary.timestamp,
+        summary.tx_count,
+        summary.size,
+        summary.weight,
+        summary.difficulty,
+        summary.work
+    );
+    if range_mode {
+        record.push('\t');
+        record.push_str(summary.cumulative_work.as_deref().unwrap_or("-"));
+    }
+    record
+}
+
+fn to_json_line(value: &impl Serialize) -> String {
+    serde_json::to_string(value).expect("summary records always serialize")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_summary() -> BlockSummary {
+        BlockSummary {
+            height: 0,
+            hash: "000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943".to_string(),
+            timestamp: 1296688602,
+            tx_count: 1,
+            size: 285,
+            weight: 1140,
+            difficulty: 1.0,
+            work: "4295032833".to_string(),
+            cumulative_work: None,
+        }
+    }
+
+    #[test]
+    fn test_read_identifiers_skips_blanks_and_comments() {
+        let input = "800000\n\n# genesis\n  0  \n0000000000000093bcb68c03a9a168ae252572d348a2eaeba2cdf9231d73206f\n";
+        let identifiers = read_identifiers(input.as_bytes()).unwrap();
+        assert_eq!(
+            identifiers,
+            vec![
+                "800000",
+                "0",
+                "0000000000000093bcb68c03a9a168ae252572d348a2eaeba2cdf9231d73206f",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_format_text_record() {
+        assert_eq!(
+            format_text_record(&sample_summary(), false),
+            "0\t000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943\t1296688602\t1\t285\t1140\t1.00\t4295032833"
+        );
+
+        let mut summary = sample_summary();
+        summary.cumulative_work = Some("100010001".to_string());
+        assert!(format_text_record(&summary, true).ends_with("\t4295032833\t100010001"));
+
+        summary.cumulative_work = None;
+        assert!(format_text_record(&summary, true).ends_with("\t4295032833\t-"));
+    }
+
+    #[test]
+    fn test_json_line_is_single_line() {
+        let line = to_json_line(&sample_summary());
+        assert!(!line.contains('\n'));
+        assert!(line.starts_with("{\"height\":0,\"hash\":\"000000000933"));
+
+        let error = to_json_line(&BlockError { input: "abc", error: "not found".to_string() });
+        assert_eq!(error, r#"{"input":"abc","error":"not found"}"#);
+    }
+}
@@ -0,0 +1,141 @@
+//! Backend capability discovery
+//!
+//! Esplora-compatible servers don't all expose the same endpoints: the
+//! blockstream.info instances have no block audit API, and self-hosted
+//! deployments sometimes disable fee estimation. Probing once up front lets
+//! commands print a clear "not supported" message instead of failing halfway
+//! through with an HTTP error.
+
+use crate::esplora::EsploraClient;
+
+/// Optional backend endpoints that some commands depend on
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Capability {
+    FeeEstimates,
+    Outspends,
+    BlockAudit,
+}
+
+impl Capability {
+    /// Human-readable name used in status lines and error messages
+    pub fn name(&self) -> &'static str {
+        match self {
+            Capability::FeeEstimates => "fee estimates",
+            Capability::Outspends => "output spend status",
+            Capability::BlockAudit => "block audit",
+        }
+    }
+}
+
+/// The set of optional endpoints a backend answered successfully
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct Capabilities {
+    pub fee_estimates: bool,
+    pub outspends: bool,
+    pub block_audit: bool,
+}
+
+impl Capabilities {
+    /// Probes the `wanted` endpoints using a known block hash and, for
+    /// outspends, one of its txids; the others are left unsupported
+    pub fn probe(client: &EsploraClient, block_hash: &str, txid: impl FnOnce() -> String, wanted: &[Capability]) -> Self {
+        Capabilities {
+            fee_estimates: wanted.contains(&Capability::FeeEstimates) && client.probe(&fee_estimates_path()),
+            outspends: wanted.contains(&Capability::Outspends) && client.probe(&outspends_path(&txid())),
+            block_audit: wanted.contains(&Capability::BlockAudit) && client.probe(&audit_summary_path(block_hash)),
+        }
+    }
+
+    /// Returns whether the backend supports the given capability
+    pub fn supports(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::FeeEstimates => self.fee_estimates,
+            Capability::Outspends => self.outspends,
+            Capability::BlockAudit => self.block_audit,
+        }
+    }
+
+    /// Returns an explanatory error if the capability is missing
+    pub fn require(&self, capability: Capability) -> Result<(), String> {
+        if self.supports(capability) {
+            Ok(())
+        } else {
+            Err(format!("{} is not supported by this backend", capitalize(capability.name())))
+        }
+    }
+
+    /// Formats a one-line summary of the `probed` capabilities, such as
+    /// "fee estimates ✓, block audit ✗"
+    pub fn summary(&self, probed: &[Capability]) -> String {
+        probed
+            .iter()
+            .map(|cap| format!("{} {}", cap.name(), if self.supports(*cap) { "✓" } else { "✗" }))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+}
+
+/// Path of the fee estimates endpoint
+pub fn fee_estimates_path() -> String {
+    "/fee-estimates".to_string()
+}
+
+/// Path of the per-output spend status endpoint for a transaction
+pub fn outspends_path(txid: &str) -> String {
+    format!("/tx/{}/outspends", txid)
+}
+
+/// Path of the block audit summary endpoint (mempool.space extension)
+pub fn audit_summary_path(block_hash: &str) -> String {
+    format!("/v1/block/{}/audit-summary", block_hash)
+}
+
+fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_endpoint_paths() {
+        assert_eq!(fee_estimates_path(), "/fee-estimates");
+        assert_eq!(outspends_path("abc"), "/tx/abc/outspends");
+        assert_eq!(audit_summary_path("00ff"), "/v1/block/00ff/audit-summary");
+    }
+
+    #[test]
+    fn test_supports_and_require() {
+        let caps = Capabilities {
+            fee_estimates: true,
+            outspends: false,
+            block_audit: false,
+        };
+        assert!(caps.supports(Capability::FeeEstimates));
+        assert!(!caps.supports(Capability::Outspends));
+        assert!(caps.require(Capability::FeeEstimates).is_ok());
+        assert_eq!(
+            caps.require(Capability::BlockAudit).unwrap_err(),
+            "Block audit is not supported by this backend"
+        );
+    }
+
+    #[test]
+    fn test_summary() {
+        let caps = Capabilities {
+            fee_estimates: true,
+            outspends: true,
+            block_audit: false,
+        };
+        assert_eq!(
+            caps.summary(&[Capability::FeeEstimates, Capability::Outspends, Capability::BlockAudit]),
+            "fee estimates ✓, output spend status ✓, block audit ✗"
+        );
+        assert_eq!(caps.summary(&[Capability::BlockAudit]), "block audit ✗");
+    }
+}
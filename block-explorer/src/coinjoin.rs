@@ -0,0 +1,106 @@
+//! Heuristic CoinJoin detection for `--stats`
+//!
+//! CoinJoins give many participants outputs of one shared value so they
+//! can't be told apart. Whirlpool mixes are exactly five inputs to five
+//! equal outputs in fixed pool sizes; Wasabi rounds have dozens of inputs
+//! and at least ten equal outputs; JoinMarket-style joins have a few equal
+//! outputs, at least as many inputs, and at most one change output per
+//! participant. Batched exchange payouts also repeat values but are usually
+//! funded by one or two inputs, which the input checks rule out.
+
+use crate::Transaction;
+use std::collections::HashMap;
+
+/// Whirlpool pool denominations, in sats
+const WHIRLPOOL_POOLS: [u64; 4] = [100_000, 1_000_000, 5_000_000, 50_000_000];
+
+/// Outputs below this are ignored when looking for the shared value
+const DUST_LIMIT: u64 = 546;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CoinjoinKind {
+    Whirlpool,
+    Wasabi,
+    /// JoinMarket and other equal-output joins
+    Generic,
+}
+
+impl CoinjoinKind {
+    pub fn label(&self) -> &'static str {
+        match self {
+            CoinjoinKind::Whirlpool => "Whirlpool",
+            CoinjoinKind::Wasabi => "Wasabi",
+            CoinjoinKind::Generic => "equal-output",
+        }
+    }
+}
+
+/// The most common non-dust output value and how many outputs share it
+fn most_common_value(tx: &Transaction) -> Option<(u64, usize)> {
+    let mut counts: HashMap<u64, usize> = HashMap::new();
+    for output in tx.vout.iter().filter(|output| output.value >= DUST_LIMIT) {
+        *counts.entry(output.value).or_default() += 1;
+    }
+    // Ties go to the larger value, so the result doesn't depend on hash order
+    counts.into_iter().max_by_key(|&(value, count)| (count, value))
+}
+
+pub fn classify(tx: &Transaction) -> Option<CoinjoinKind> {
+    if tx.vin.iter().any(|input| input.is_coinbase) {
+        return None;
+    }
+    let (value, equal) = most_common_value(tx)?;
+    let (inputs, outputs) = (tx.vin.len(), tx.vout.len());
+
+    if inputs == 5 && outputs == 5 && equal == 5 && WHIRLPOOL_POOLS.contains(&value) {
+        Some(CoinjoinKind::Whirlpool)
+    } else if inputs >= 20 && equal >= 10 {
+        Some(CoinjoinKind::Wasabi)
+    } else if equal >= 3 && inputs >= equal && outputs <= equal * 2 + 1 {
+        Some(CoinjoinKind::Generic)
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, input, output};
+
+    fn tx(inputs: usize, values: &[u64]) -> Transaction {
+        let vin = (0..inputs).map(|i| input(&format!("prev{}", i), None)).collect();
+        let vout = values.iter().map(|&value| output("", value)).collect();
+        Transaction { version: 1, ..test_util::tx("t", vin, vout) }
+    }
+
+    #[test]
+    fn test_whirlpool() {
+        assert_eq!(classify(&tx(5, &[1_000_000; 5])), Some(CoinjoinKind::Whirlpool));
+        // Five equal outputs outside the pool sizes are still a generic join
+        assert_eq!(classify(&tx(5, &[1_234_567; 5])), Some(CoinjoinKind::Generic));
+    }
+
+    #[test]
+    fn test_wasabi() {
+        let mut values = vec![10_000_000; 40];
+        values.extend([3_141_592, 2_718_281, 1_414_213]);
+        assert_eq!(classify(&tx(45, &values)), Some(CoinjoinKind::Wasabi));
+    }
+
+    #[test]
+    fn test_generic_join_with_change() {
+        let values = [500_000, 500_000, 500_000, 120_000, 80_000, 33_000];
+        assert_eq!(classify(&tx(4, &values)), Some(CoinjoinKind::Generic));
+    }
+
+    #[test]
+    fn test_ordinary_transactions() {
+        // A batched payout funded by one input
+        assert_eq!(classify(&tx(1, &[50_000, 50_000, 50_000, 50_000, 912_345])), None);
+        assert_eq!(classify(&tx(2, &[10_000, 25_000])), None);
+        // Equal dust outputs don't count
+        assert_eq!(classify(&tx(4, &[330, 330, 330, 10_000])), None);
+        assert_eq!(classify(&tx(1, &[])), None);
+    }
+}
@@ -0,0 +1,96 @@
+//! ANSI colorization of terminal output
+//!
+//! Colors are only emitted when stdout is a terminal (or `--color always` is
+//! given), so piped output stays plain text.
+
+use clap::ValueEnum;
+use std::fmt::Display;
+use std::io::IsTerminal;
+
+const GREEN: &str = "\x1b[32m";
+const YELLOW: &str = "\x1b[33m";
+const DIM: &str = "\x1b[2m";
+const RESET: &str = "\x1b[0m";
+
+/// When to colorize output
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ColorChoice {
+    Auto,
+    Always,
+    Never,
+}
+
+impl ColorChoice {
+    /// Resolves the choice against the environment: `auto` enables color only
+    /// on a TTY and honours the NO_COLOR convention
+    fn enabled(self) -> bool {
+        match self {
+            ColorChoice::Always => true,
+            ColorChoice::Never => false,
+            ColorChoice::Auto => {
+                std::env::var_os("NO_COLOR").is_none() && std::io::stdout().is_terminal()
+            }
+        }
+    }
+}
+
+/// Applies a consistent color scheme to displayed values
+#[derive(Debug, Clone, Copy)]
+pub struct Palette {
+    enabled: bool,
+}
+
+impl Palette {
+    pub fn new(choice: ColorChoice) -> Self {
+        Palette { enabled: choice.enabled() }
+    }
+
+    /// A palette that never emits escape codes
+    pub fn plain() -> Self {
+        Palette { enabled: false }
+    }
+
+    /// Numeric values and amounts (green)
+    pub fn value(&self, text: impl Display) -> String {
+        self.paint(GREEN, text)
+    }
+
+    /// Block hashes, txids and merkle roots (dimmed)
+    pub fn hash(&self, text: impl Display) -> String {
+        self.paint(DIM, text)
+    }
+
+    /// Warnings and notes (yellow)
+    pub fn warning(&self, text: impl Display) -> String {
+        self.paint(YELLOW, text)
+    }
+
+    fn paint(&self, code: &str, text: impl Display) -> String {
+        if self.enabled {
+            format!("{}{}{}", code, text, RESET)
+        } else {
+            text.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_palette_disabled_is_plain() {
+        let palette = Palette::new(ColorChoice::Never);
+        assert_eq!(palette.value(42), "42");
+        assert_eq!(palette.hash("abc"), "abc");
+        assert_eq!(palette.warning("careful"), "careful");
+    }
+
+    #[test]
+    fn test_palette_enabled_wraps_in_escape_codes() {
+        let palette = Palette::new(ColorChoice::Always);
+        assert_eq!(palette.value(42), "\x1b[32m42\x1b[0m");
+        assert_eq!(palette.hash("abc"), "\x1b[2mabc\x1b[0m");
+        assert_eq!(palette.warning("careful"), "\x1b[33mcareful\x1b[0m");
+    }
+}
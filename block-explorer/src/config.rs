@@ -0,0 +1,124 @@
+//! Defaults read from `~/.config/bdk-experiments/block-explorer.toml`
+//!
+//! Every key is optional and command-line flags always win. Example:
+//!
+//! ```toml
+//! network = "bitcoin"
+//! esplora_url = ["https://blockstream.info/api", "https://mempool.space/api"]
+//! proxy = "http://127.0.0.1:8080"
+//! limit = 25
+//! format = "json"
+//! rps = 2.0
+//! max_requests = 500
+//! ```
+
+use crate::OutputFormat;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+
+/// File name inside the shared config directory
+const CONFIG_FILE: &str = "bdk-experiments/block-explorer.toml";
+
+#[derive(Debug, Default, PartialEq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub network: Option<String>,
+    /// A single URL or a list tried in order, like repeated `--esplora-url`
+    pub esplora_url: Option<OneOrMany>,
+    pub proxy: Option<String>,
+    pub limit: Option<usize>,
+    pub format: Option<OutputFormat>,
+    pub rps: Option<f64>,
+    pub max_requests: Option<u64>,
+}
+
+#[derive(Debug, PartialEq, Deserialize)]
+#[serde(untagged)]
+pub enum OneOrMany {
+    One(String),
+    Many(Vec<String>),
+}
+
+impl Config {
+    /// Configured Esplora endpoints, empty when none are set
+    pub fn esplora_urls(&self) -> Vec<String> {
+        match &self.esplora_url {
+            Some(OneOrMany::One(url)) => vec![url.clone()],
+            Some(OneOrMany::Many(urls)) => urls.clone(),
+            None => Vec::new(),
+        }
+    }
+}
+
+/// `$XDG_CONFIG_HOME` if set, otherwise `~/.config`
+pub fn default_path() -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_CONFIG_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))?;
+    Some(base.join(CONFIG_FILE))
+}
+
+/// Loads the config file; a missing file at the default location is not an error
+pub fn load(path: &Path, required: bool) -> Result<Config, String> {
+    match std::fs::read_to_string(path) {
+        Ok(contents) => parse(&contents).map_err(|e| format!("{}: {}", path.display(), e)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound && !required => Ok(Config::default()),
+        Err(e) => Err(format!("Could not read config {}: {}", path.display(), e)),
+    }
+}
+
+fn parse(contents: &str) -> Result<Config, String> {
+    toml::from_str(contents).map_err(|e| e.message().to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_full_config() {
+        let config = parse(
+            r#"
+            network = "bitcoin"
+            esplora_url = ["https://a.example/api", "https://b.example/api"]
+            proxy = "http://127.0.0.1:8080"
+            limit = 25
+            format = "json"
+            rps = 2.5
+            max_requests = 500
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.network.as_deref(), Some("bitcoin"));
+        assert_eq!(config.esplora_urls(), vec!["https://a.example/api", "https://b.example/api"]);
+        assert_eq!(config.proxy.as_deref(), Some("http://127.0.0.1:8080"));
+        assert_eq!(config.limit, Some(25));
+        assert_eq!(config.format, Some(OutputFormat::Json));
+        assert_eq!(config.rps, Some(2.5));
+        assert_eq!(config.max_requests, Some(500));
+    }
+
+    #[test]
+    fn test_parse_single_url_and_empty_file() {
+        let config = parse(r#"esplora_url = "https://a.example/api""#).unwrap();
+        assert_eq!(config.esplora_urls(), vec!["https://a.example/api"]);
+
+        assert_eq!(parse("").unwrap(), Config::default());
+        assert!(Config::default().esplora_urls().is_empty());
+    }
+
+    #[test]
+    fn test_parse_rejects_typos() {
+        assert!(parse(r#"netwrok = "bitcoin""#).is_err());
+        assert!(parse(r#"format = "yaml""#).is_err());
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let path = Path::new("/nonexistent/block-explorer.toml");
+        assert_eq!(load(path, false).unwrap(), Config::default());
+        assert!(load(path, true).is_err());
+    }
+}
@@ -0,0 +1,111 @@
+//! Error taxonomy and process exit codes
+//!
+//! Each failure class gets its own exit code so scripts can tell "that block
+//! doesn't exist" apart from "the API is down".
+
+use crate::esplora::RequestError;
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExplorerError {
+    /// Bad command-line input or input file
+    InvalidArgument(String),
+    /// The backend has no such block
+    NotFound(String),
+    /// The backend could not be reached or returned a server error
+    Network(String),
+    /// The backend's response could not be decoded
+    Parse(String),
+    /// The backend's data failed a local consistency check
+    Verification(String),
+    /// The `--max-requests` budget ran out before the work was done
+    Budget(String),
+}
+
+impl ExplorerError {
+    /// Process exit code; 2 matches clap's own usage errors
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            ExplorerError::InvalidArgument(_) => 2,
+            ExplorerError::NotFound(_) => 3,
+            ExplorerError::Network(_) => 4,
+            ExplorerError::Parse(_) => 5,
+            ExplorerError::Verification(_) => 6,
+            ExplorerError::Budget(_) => 7,
+        }
+    }
+
+    /// Classifies a request error, prefixing it with what was being fetched
+    pub fn from_request(context: &str, err: RequestError) -> Self {
+        let message = format!("{}: {}", context, err);
+        match err {
+            RequestError::Status { code: 404, .. } => ExplorerError::NotFound(message),
+            // Esplora answers 400 for malformed hashes and heights
+            RequestError::Status { code: 400, .. } => ExplorerError::InvalidArgument(message),
+            RequestError::Status { .. } | RequestError::Transport(_) => ExplorerError::Network(message),
+            RequestError::Parse(_) => ExplorerError::Parse(message),
+            RequestError::Budget { .. } => ExplorerError::Budget(message),
+            RequestError::Offline => ExplorerError::InvalidArgument(message),
+        }
+    }
+}
+
+impl fmt::Display for ExplorerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExplorerError::InvalidArgument(msg)
+            | ExplorerError::NotFound(msg)
+            | ExplorerError::Network(msg)
+            | ExplorerError::Parse(msg)
+            | ExplorerError::Verification(msg)
+            | ExplorerError::Budget(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn status(code: u16) -> RequestError {
+        RequestError::Status { url: "https://x/block/abc".to_string(), code }
+    }
+
+    #[test]
+    fn test_from_request_classification() {
+        assert_eq!(
+            ExplorerError::from_request("Error fetching block", status(404)),
+            ExplorerError::NotFound("Error fetching block: https://x/block/abc: status code 404".to_string())
+        );
+        assert!(matches!(
+            ExplorerError::from_request("ctx", status(400)),
+            ExplorerError::InvalidArgument(_)
+        ));
+        assert!(matches!(ExplorerError::from_request("ctx", status(503)), ExplorerError::Network(_)));
+        assert!(matches!(
+            ExplorerError::from_request("ctx", RequestError::Transport("refused".to_string())),
+            ExplorerError::Network(_)
+        ));
+        assert!(matches!(
+            ExplorerError::from_request("ctx", RequestError::Parse("eof".to_string())),
+            ExplorerError::Parse(_)
+        ));
+        assert!(matches!(
+            ExplorerError::from_request("ctx", RequestError::Budget { limit: 5 }),
+            ExplorerError::Budget(_)
+        ));
+    }
+
+    #[test]
+    fn test_exit_codes_are_distinct() {
+        let codes = [
+            ExplorerError::InvalidArgument(String::new()).exit_code(),
+            ExplorerError::NotFound(String::new()).exit_code(),
+            ExplorerError::Network(String::new()).exit_code(),
+            ExplorerError::Parse(String::new()).exit_code(),
+            ExplorerError::Verification(String::new()).exit_code(),
+            ExplorerError::Budget(String::new()).exit_code(),
+        ];
+        assert_eq!(codes, [2, 3, 4, 5, 6, 7]);
+    }
+}
@@ -0,0 +1,244 @@
+//! Blocking Esplora HTTP client with endpoint failover
+//!
+//! Endpoints are tried in the order given. When one fails to respond, times
+//! out, or returns a server error, the request is retried against the next
+//! endpoint, and the one that answered becomes the first choice for later
+//! requests. Client errors such as 404 are returned as-is, since another
+//! server would give the same answer.
+//!
+//! Every attempt, retries included, goes through the client's
+//! [`Scheduler`], which paces requests and enforces the request budget.
+//! An offline client refuses every request and leaves lookups to its
+//! [`HeaderStore`].
+
+use crate::headers::HeaderStore;
+use crate::ratelimit::Scheduler;
+use serde::de::DeserializeOwned;
+use std::cell::Cell;
+use std::fmt;
+use std::time::Duration;
+
+/// Per-request timeout before an endpoint is considered unresponsive
+const REQUEST_TIMEOUT_SECS: u64 = 30;
+
+/// Errors returned by [`EsploraClient`] requests
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestError {
+    /// The server answered with a non-success HTTP status
+    Status { url: String, code: u16 },
+    /// No response could be obtained (connection failure, timeout)
+    Transport(String),
+    /// The response body could not be read or decoded
+    Parse(String),
+    /// The `--max-requests` budget was spent before this request
+    Budget { limit: u64 },
+    /// `--offline` is set, so nothing is sent
+    Offline,
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestError::Status { url, code } => write!(f, "{}: status code {}", url, code),
+            RequestError::Transport(msg) => write!(f, "{}", msg),
+            RequestError::Parse(msg) => write!(f, "invalid response: {}", msg),
+            RequestError::Budget { limit } => {
+                write!(f, "request budget of {} exhausted (raise --max-requests to allow more)", limit)
+            }
+            RequestError::Offline => write!(f, "needs the network, but --offline is set"),
+        }
+    }
+}
+
+pub struct EsploraClient {
+    agent: ureq::Agent,
+    endpoints: Vec<String>,
+    active: Cell<usize>,
+    verbose: bool,
+    scheduler: Scheduler,
+    headers: Option<HeaderStore>,
+    offline: bool,
+}
+
+impl EsploraClient {
+    /// Creates a client for one or more endpoints, in order of preference
+    pub fn new(endpoints: &[String], verbose: bool) -> Self {
+        assert!(!endpoints.is_empty(), "at least one Esplora endpoint is required");
+
+        EsploraClient {
+            agent: ureq::AgentBuilder::new()
+                .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+                .build(),
+            endpoints: endpoints.iter().map(|e| normalize_endpoint(e)).collect(),
+            active: Cell::new(0),
+            verbose,
+            scheduler: Scheduler::default(),
+            headers: None,
+            offline: false,
+        }
+    }
+
+    /// Paces requests and caps how many are sent
+    pub fn with_scheduler(mut self, scheduler: Scheduler) -> Self {
+        self.scheduler = scheduler;
+        self
+    }
+
+    /// Records fetched blocks in, and resolves hash prefixes from, a local store
+    pub fn with_header_store(mut self, headers: HeaderStore) -> Self {
+        self.headers = Some(headers);
+        self
+    }
+
+    /// Refuses all requests, for `--offline`
+    pub fn offline(mut self) -> Self {
+        self.offline = true;
+        self
+    }
+
+    pub fn headers(&self) -> Option<&HeaderStore> {
+        self.headers.as_ref()
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Routes every request through an HTTP proxy
+    pub fn with_proxy(mut self, proxy: &str) -> Result<Self, String> {
+        let proxy = ureq::Proxy::new(proxy).map_err(|e| format!("Invalid proxy '{}': {}", proxy, e))?;
+        self.agent = ureq::AgentBuilder::new()
+            .timeout(Duration::from_secs(REQUEST_TIMEOUT_SECS))
+            .proxy(proxy)
+            .build();
+        Ok(self)
+    }
+
+    /// The endpoint that will be tried first for the next request
+    pub fn active_url(&self) -> &str {
+        &self.endpoints[self.active.get()]
+    }
+
+    /// All configured endpoints, in order of preference
+    pub fn endpoints(&self) -> &[String] {
+        &self.endpoints
+    }
+
+    /// HTTP requests sent so far, failover retries included
+    pub fn requests_sent(&self) -> u64 {
+        self.scheduler.sent()
+    }
+
+    /// Fetches a plain-text response body
+    pub fn get_text(&self, path: &str) -> Result<String, RequestError> {
+        self.call(path)?
+            .into_string()
+            .map(|body| body.trim().to_string())
+            .map_err(|e| RequestError::Parse(e.to_string()))
+    }
+
+    /// Fetches and decodes a JSON response body
+    pub fn get_json<T: DeserializeOwned>(&self, path: &str) -> Result<T, RequestError> {
+        self.call(path)?
+            .into_json()
+            .map_err(|e| RequestError::Parse(e.to_string()))
+    }
+
+    /// Returns whether the endpoint answers successfully, without failover
+    pub fn probe(&self, path: &str) -> bool {
+        if self.offline || self.scheduler.acquire().is_err() {
+            return false;
+        }
+        let url = format!("{}{}", self.active_url(), path);
+        self.agent.get(&url).call().is_ok()
+    }
+
+    fn call(&self, path: &str) -> Result<ureq::Response, RequestError> {
+        if self.offline {
+            return Err(RequestError::Offline);
+        }
+        let mut last_error = None;
+
+        let order = failover_order(self.active.get(), self.endpoints.len());
+        for (attempt, &index) in order.iter().enumerate() {
+            let endpoint = &self.endpoints[index];
+            let url = format!("{}{}", endpoint, path);
+
+            self.scheduler.acquire().map_err(|limit| RequestError::Budget { limit })?;
+            match self.agent.get(&url).call() {
+                Ok(response) => {
+                    if self.verbose {
+                        eprintln!("[{}] GET {}", endpoint, path);
+                    }
+                    self.active.set(index);
+                    return Ok(response);
+                }
+                Err(ureq::Error::Status(code, _)) if !is_retryable_status(code) => {
+                    return Err(RequestError::Status { url, code });
+                }
+                Err(err) => {
+                    let err = match err {
+                        ureq::Error::Status(code, _) => RequestError::Status { url, code },
+                        ureq::Error::Transport(t) => RequestError::Transport(t.to_string()),
+                    };
+                    if attempt + 1 < order.len() {
+                        eprintln!("Warning: {} failed ({}), trying next endpoint", endpoint, err);
+                    }
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        Err(last_error.expect("at least one endpoint was tried"))
+    }
+}
+
+/// Server-side and rate-limit statuses that another endpoint might not share
+fn is_retryable_status(code: u16) -> bool {
+    code == 429 || code >= 500
+}
+
+/// Endpoint indexes to try, starting from the active one and wrapping around
+fn failover_order(active: usize, len: usize) -> Vec<usize> {
+    (0..len).map(|offset| (active + offset) % len).collect()
+}
+
+/// Strips trailing slashes so paths can be appended directly
+fn normalize_endpoint(endpoint: &str) -> String {
+    endpoint.trim().trim_end_matches('/').to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_retryable_status() {
+        assert!(is_retryable_status(500));
+        assert!(is_retryable_status(503));
+        assert!(is_retryable_status(429));
+        assert!(!is_retryable_status(404));
+        assert!(!is_retryable_status(400));
+    }
+
+    #[test]
+    fn test_failover_order() {
+        assert_eq!(failover_order(0, 3), vec![0, 1, 2]);
+        assert_eq!(failover_order(1, 3), vec![1, 2, 0]);
+        assert_eq!(failover_order(0, 1), vec![0]);
+    }
+
+    #[test]
+    fn test_normalize_endpoint() {
+        assert_eq!(normalize_endpoint("https://mempool.space/api/"), "https://mempool.space/api");
+        assert_eq!(normalize_endpoint(" https://blockstream.info/api "), "https://blockstream.info/api");
+    }
+
+    #[test]
+    fn test_request_error_display() {
+        let err = RequestError::Status { url: "https://x/block/abc".to_string(), code: 404 };
+        assert_eq!(err.to_string(), "https://x/block/abc: status code 404");
+        assert_eq!(RequestError::Parse("eof".to_string()).to_string(), "invalid response: eof");
+        assert!(RequestError::Budget { limit: 100 }.to_string().starts_with("request budget of 100 exhausted"));
+    }
+}
@@ -0,0 +1,223 @@
+//! Shareable block reports written by `--report md|html <PATH>`: the block
+//! summary, fee-rate percentiles, and the listed transactions as a document
+
+use clap::ValueEnum;
+use std::fmt::Write;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    #[value(name = "md", alias = "markdown")]
+    Markdown,
+    Html,
+}
+
+impl ExportFormat {
+    pub fn parse(name: &str) -> Result<Self, String> {
+        ExportFormat::from_str(name, true)
+            .map_err(|_| format!("Invalid report format: '{}'. Use 'md' or 'html'", name))
+    }
+}
+
+/// One row of the transaction table
+#[derive(Debug, Clone, PartialEq)]
+pub struct TxRow {
+    pub index: usize,
+    pub txid: String,
+    pub inputs: usize,
+    pub outputs: usize,
+    pub weight: usize,
+    pub total_out: u64,
+    pub fee: Option<u64>,
+    pub fee_rate: Option<f64>,
+    pub scripts: String,
+}
+
+/// Everything the report shows, gathered while the terminal view is printed
+#[derive(Debug, Default)]
+pub struct BlockDocument {
+    pub title: String,
+    /// (label, value) pairs of the block summary
+    pub fields: Vec<(&'static str, String)>,
+    /// (percentile, sat/vB); empty when fee stats were unavailable
+    pub fee_percentiles: Vec<(u32, f64)>,
+    pub txs: Vec<TxRow>,
+    pub tx_count: usize,
+}
+
+const TX_COLUMNS: [&str; 8] = ["#", "TXID", "Inputs", "Outputs", "Weight (WU)", "Total Out (sats)", "Fee (sats)", "Fee Rate (sat/vB)"];
+
+impl BlockDocument {
+    pub fn render(&self, format: ExportFormat) -> String {
+        match format {
+            ExportFormat::Markdown => self.render_markdown(),
+            ExportFormat::Html => self.render_html(),
+        }
+    }
+
+    fn render_markdown(&self) -> String {
+        let mut doc = format!("# {}\n\n## Block\n\n| Field | Value |\n|---|---|\n", self.title);
+        for (label, value) in &self.fields {
+            let _ = writeln!(doc, "| {} | {} |", escape_markdown_cell(label), escape_markdown_cell(value));
+        }
+
+        if !self.fee_percentiles.is_empty() {
+            doc.push_str("\n## Fee rates\n\n| Percentile | sat/vB |\n|---|---|\n");
+            for (percentile, rate) in &self.fee_percentiles {
+                let _ = writeln!(doc, "| p{} | {:.1} |", percentile, rate);
+            }
+        }
+
+        if !self.txs.is_empty() {
+            let _ = writeln!(doc, "\n## Transactions ({} of {})\n", self.txs.len(), self.tx_count);
+            let _ = writeln!(doc, "| {} | Scripts |", TX_COLUMNS.join(" | "));
+            let _ = writeln!(doc, "|{}", "---|".repeat(TX_COLUMNS.len() + 1));
+            for tx in &self.txs {
+                let cells: Vec<String> = tx_cells(tx).iter().map(|cell| escape_markdown_cell(cell)).collect();
+                let _ = writeln!(doc, "| {} | `{}` | {} |", cells[0], cells[1], cells[2..].join(" | "));
+            }
+        }
+        doc
+    }
+
+    fn render_html(&self) -> String {
+        let title = escape_html(&self.title);
+        let mut doc = format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{}</title>\n\
+             <style>body{{font-family:sans-serif}}table{{border-collapse:collapse}}\
+             td,th{{border:1px solid #ccc;padding:2px 8px;text-align:left}}code{{font-size:90%}}</style>\n\
+             </head>\n<body>\n<h1>{}</h1>\n<h2>Block</h2>\n<table>\n",
+            title, title
+        );
+        for (label, value) in &self.fields {
+            let _ = writeln!(doc, "<tr><th>{}</th><td>{}</td></tr>", escape_html(label), escape_html(value));
+        }
+        doc.push_str("</table>\n");
+
+        if !self.fee_percentiles.is_empty() {
+            doc.push_str("<h2>Fee rates</h2>\n<table>\n<tr><th>Percentile</th><th>sat/vB</th></tr>\n");
+            for (percentile, rate) in &self.fee_percentiles {
+                let _ = writeln!(doc, "<tr><td>p{}</td><td>{:.1}</td></tr>", percentile, rate);
+            }
+            doc.push_str("</table>\n");
+        }
+
+        if !self.txs.is_empty() {
+            let _ = writeln!(doc, "<h2>Transactions ({} of {})</h2>\n<table>", self.txs.len(), self.tx_count);
+            let header: String = TX_COLUMNS.iter().chain(["Scripts"].iter()).map(|c| format!("<th>{}</th>", c)).collect();
+            let _ = writeln!(doc, "<tr>{}</tr>", header);
+            for tx in &self.txs {
+                let cells: String = tx_cells(tx)
+                    .iter()
+                    .enumerate()
+                    .map(|(i, cell)| match i {
+                        1 => format!("<td><code>{}</code></td>", escape_html(cell)),
+                        _ => format!("<td>{}</td>", escape_html(cell)),
+                    })
+                    .collect();
+                let _ = writeln!(doc, "<tr>{}</tr>", cells);
+            }
+            doc.push_str("</table>\n");
+        }
+
+        doc.push_str("</body>\n</html>\n");
+        doc
+    }
+}
+
+/// Cell text for a transaction row, in [`TX_COLUMNS`] order plus scripts
+fn tx_cells(tx: &TxRow) -> Vec<String> {
+    vec![
+        tx.index.to_string(),
+        tx.txid.clone(),
+        tx.inputs.to_string(),
+        tx.outputs.to_string(),
+        tx.weight.to_string(),
+        tx.total_out.to_string(),
+        tx.fee.map_or("–".to_string(), |fee| fee.to_string()),
+        tx.fee_rate.map_or("–".to_string(), |rate| format!("{:.1}", rate)),
+        tx.scripts.clone(),
+    ]
+}
+
+/// Keeps a value in its table cell: a `|` would end the cell and a newline
+/// the row
+fn escape_markdown_cell(text: &str) -> String {
+    text.replace('\\', "\\\\").replace('|', "\\|").replace(['\n', '\r'], " ")
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_document() -> BlockDocument {
+        BlockDocument {
+            title: "Block 0".to_string(),
+            fields: vec![("Height", "0".to_string()), ("Timestamp", "2009-01-03 18:15:05 UTC".to_string())],
+            fee_percentiles: vec![(50, 12.5)],
+            txs: vec![TxRow {
+                index: 1,
+                txid: "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string(),
+                inputs: 1,
+                outputs: 1,
+                weight: 816,
+                total_out: 5_000_000_000,
+                fee: None,
+                fee_rate: None,
+                scripts: "in: 1×COINBASE, out: 1×P2PK".to_string(),
+            }],
+            tx_count: 1,
+        }
+    }
+
+    #[test]
+    fn test_parse_export_format() {
+        assert_eq!(ExportFormat::parse("md").unwrap(), ExportFormat::Markdown);
+        assert_eq!(ExportFormat::parse("markdown").unwrap(), ExportFormat::Markdown);
+        assert_eq!(ExportFormat::parse("HTML").unwrap(), ExportFormat::Html);
+        assert!(ExportFormat::parse("pdf").is_err());
+    }
+
+    #[test]
+    fn test_render_markdown() {
+        let doc = sample_document().render(ExportFormat::Markdown);
+        assert!(doc.starts_with("# Block 0\n"));
+        assert!(doc.contains("| Height | 0 |\n"));
+        assert!(doc.contains("| p50 | 12.5 |\n"));
+        assert!(doc.contains("## Transactions (1 of 1)"));
+        assert!(doc.contains("| 1 | `4a5e1e4b"));
+        assert!(doc.contains("| 5000000000 | – | – | in: 1×COINBASE, out: 1×P2PK |\n"));
+
+        // Header and separator rows have the same number of columns
+        let table: Vec<&str> = doc.lines().filter(|l| l.starts_with("| #") || l.starts_with("|---|---|---")).collect();
+        assert_eq!(table[0].matches('|').count(), table[1].matches('|').count());
+    }
+
+    #[test]
+    fn test_render_markdown_escapes_cells() {
+        let mut document = sample_document();
+        document.fields.push(("Note", "a | b\nc\\".to_string()));
+        document.txs[0].scripts = "in: 1×P2WPKH | out: 2×P2TR".to_string();
+        let doc = document.render(ExportFormat::Markdown);
+        assert!(doc.contains("| Note | a \\| b c\\\\ |\n"));
+        assert!(doc.contains("| in: 1×P2WPKH \\| out: 2×P2TR |\n"));
+    }
+
+    #[test]
+    fn test_render_html_escapes() {
+        let mut document = sample_document();
+        document.fields.push(("Note", "<script>&".to_string()));
+        let doc = document.render(ExportFormat::Html);
+        assert!(doc.starts_with("<!DOCTYPE html>"));
+        assert!(doc.contains("<title>Block 0</title>"));
+        assert!(doc.contains("<td>&lt;script&gt;&amp;</td>"));
+        assert!(doc.contains("<td><code>4a5e1e4b"));
+        assert!(doc.trim_end().ends_with("</html>"));
+    }
+}
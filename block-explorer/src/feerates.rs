@@ -0,0 +1,82 @@
+//! Fee-rate percentiles of the transactions confirmed in a block
+
+/// Percentiles reported, in ascending order
+pub const PERCENTILES: [u32; 5] = [10, 25, 50, 75, 90];
+
+/// Fee rate in sat/vB, with virtual size rounded up as consensus does
+pub fn fee_rate(fee: u64, weight: usize) -> f64 {
+    let vsize = weight.div_ceil(4).max(1);
+    fee as f64 / vsize as f64
+}
+
+/// Sorted fee rates of a block's non-coinbase transactions
+#[derive(Debug, PartialEq)]
+pub struct FeeRateDistribution {
+    rates: Vec<f64>,
+}
+
+impl FeeRateDistribution {
+    pub fn from_rates(rates: impl IntoIterator<Item = f64>) -> Self {
+        let mut rates: Vec<f64> = rates.into_iter().collect();
+        rates.sort_by(f64::total_cmp);
+        FeeRateDistribution { rates }
+    }
+
+    /// Number of transactions counted
+    pub fn len(&self) -> usize {
+        self.rates.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.rates.is_empty()
+    }
+
+    /// Nearest-rank percentile, or None for a block with only a coinbase
+    pub fn percentile(&self, p: u32) -> Option<f64> {
+        if self.rates.is_empty() {
+            return None;
+        }
+        let rank = (p as usize * self.rates.len()).div_ceil(100).max(1);
+        Some(self.rates[rank - 1])
+    }
+
+    /// (percentile, rate) for every entry in [`PERCENTILES`]
+    pub fn rows(&self) -> Vec<(u32, f64)> {
+        PERCENTILES
+            .iter()
+            .filter_map(|&p| self.percentile(p).map(|rate| (p, rate)))
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fee_rate_rounds_vsize_up() {
+        assert_eq!(fee_rate(1_000, 400), 10.0);
+        // 561 WU is 140.25 vB, which counts as 141
+        assert_eq!(fee_rate(1_410, 561), 10.0);
+        assert_eq!(fee_rate(0, 0), 0.0);
+    }
+
+    #[test]
+    fn test_percentiles_nearest_rank() {
+        let distribution = FeeRateDistribution::from_rates((1..=10).rev().map(f64::from));
+        assert_eq!(distribution.len(), 10);
+        assert_eq!(distribution.percentile(10), Some(1.0));
+        assert_eq!(distribution.percentile(25), Some(3.0));
+        assert_eq!(distribution.percentile(50), Some(5.0));
+        assert_eq!(distribution.percentile(90), Some(9.0));
+        assert_eq!(distribution.percentile(100), Some(10.0));
+    }
+
+    #[test]
+    fn test_empty_distribution() {
+        let distribution = FeeRateDistribution::from_rates(vec![]);
+        assert!(distribution.is_empty());
+        assert_eq!(distribution.percentile(50), None);
+        assert!(distribution.rows().is_empty());
+    }
+}
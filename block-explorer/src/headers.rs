@@ -0,0 +1,184 @@
+//! Local header store, kept per network under
+//! `~/.local/share/bdk-experiments/block-explorer/headers/<network>` (or
+//! `$XDG_DATA_HOME`)
+//!
+//! Every block summary fetched from Esplora is appended, keyed by hash with
+//! a height index beside it. The store resolves hash prefixes, which Esplora
+//! can't search, and with `--offline` answers header-level queries (height
+//! and hash lookups, timestamps, difficulty) without any requests. A reorg
+//! simply overwrites the height index; stale blocks stay findable by hash.
+
+use crate::error::ExplorerError;
+use crate::{BlockIdentifier, BlockInfo};
+use std::path::{Path, PathBuf};
+
+/// Directory inside the shared data directory; one database per network
+const STORE_DIR: &str = "bdk-experiments/block-explorer/headers";
+
+const BY_HASH: &str = "by_hash";
+const BY_HEIGHT: &str = "by_height";
+
+pub struct HeaderStore {
+    by_hash: sled::Tree,
+    by_height: sled::Tree,
+}
+
+impl HeaderStore {
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let db = sled::open(path).map_err(|e| format!("Could not open header store {}: {}", path.display(), e))?;
+        let tree = |name| db.open_tree(name).map_err(|e| format!("Could not open header store {}: {}", path.display(), e));
+        Ok(HeaderStore { by_hash: tree(BY_HASH)?, by_height: tree(BY_HEIGHT)? })
+    }
+
+    /// Records a fetched block. Failures only warn, since the store is a
+    /// cache and the query itself succeeded.
+    pub fn insert(&self, block: &BlockInfo) {
+        let record = serde_json::to_vec(block).expect("block summaries always serialize");
+        let result = self
+            .by_hash
+            .insert(block.id.as_bytes(), record)
+            .and_then(|_| self.by_height.insert(block.height.to_be_bytes(), block.id.as_bytes()));
+        if let Err(e) = result {
+            eprintln!("Warning: Could not save block {} to the header store: {}", block.id, e);
+        }
+    }
+
+    pub fn by_hash(&self, hash: &str) -> Option<BlockInfo> {
+        let record = self.by_hash.get(hash.to_lowercase().as_bytes()).ok()??;
+        serde_json::from_slice(&record).ok()
+    }
+
+    pub fn by_height(&self, height: u32) -> Option<BlockInfo> {
+        let hash = self.by_height.get(height.to_be_bytes()).ok()??;
+        self.by_hash(std::str::from_utf8(&hash).ok()?)
+    }
+
+    /// A stored block by height, hash, or hash prefix
+    pub fn lookup(&self, identifier: &BlockIdentifier) -> Result<BlockInfo, ExplorerError> {
+        let missing = |what: String| {
+            ExplorerError::NotFound(format!("Block {} is not in the local header store; query it once online", what))
+        };
+        match identifier {
+            BlockIdentifier::Height(height) => {
+                self.by_height(*height).ok_or_else(|| missing(format!("at height {}", height)))
+            }
+            BlockIdentifier::Hash(hash) => self.by_hash(hash).ok_or_else(|| missing(hash.clone())),
+            BlockIdentifier::HashPrefix(prefix) => self.resolve_prefix(prefix),
+        }
+    }
+
+    /// The one stored block whose hash starts with `prefix`
+    pub fn resolve_prefix(&self, prefix: &str) -> Result<BlockInfo, ExplorerError> {
+        let mut matches = self.by_hash.scan_prefix(prefix.to_lowercase().as_bytes()).values();
+        let first = matches.next().and_then(Result::ok).ok_or_else(|| {
+            ExplorerError::NotFound(format!(
+                "No block in the local header store starts with '{}'. Query it once by height or full hash so it is recorded",
+                prefix
+            ))
+        })?;
+        if matches.next().is_some() {
+            return Err(ExplorerError::InvalidArgument(format!(
+                "Block hash prefix '{}' is ambiguous in the local header store; use more characters",
+                prefix
+            )));
+        }
+        serde_json::from_slice(&first)
+            .map_err(|e| ExplorerError::Parse(format!("Corrupt header store record: {}", e)))
+    }
+
+    /// Highest stored height
+    pub fn tip(&self) -> Option<u32> {
+        let (key, _) = self.by_height.last().ok()??;
+        Some(u32::from_be_bytes(key.as_ref().try_into().ok()?))
+    }
+}
+
+/// The store, or an error explaining that a query needs one
+pub fn require(store: Option<&HeaderStore>) -> Result<&HeaderStore, ExplorerError> {
+    store.ok_or_else(|| {
+        ExplorerError::InvalidArgument(
+            "This query needs the local header store, which is unavailable (see the warning above)".to_string(),
+        )
+    })
+}
+
+/// `$XDG_DATA_HOME` if set, otherwise `~/.local/share`
+pub fn default_path(network: &str) -> Option<PathBuf> {
+    let base = std::env::var_os("XDG_DATA_HOME")
+        .filter(|dir| !dir.is_empty())
+        .map(PathBuf::from)
+        .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))?;
+    Some(base.join(STORE_DIR).join(network))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn block(height: u32, id: &str) -> BlockInfo {
+        BlockInfo {
+            id: id.to_string(),
+            height,
+            version: 1,
+            timestamp: 1_231_006_505 + u64::from(height) * 600,
+            tx_count: 1,
+            size: 285,
+            weight: 1140,
+            merkle_root: "4a5e1e4b".to_string(),
+            previousblockhash: None,
+            mediantime: 0,
+            nonce: 0,
+            bits: 486604799,
+            difficulty: 1.0,
+        }
+    }
+
+    fn temporary_store() -> HeaderStore {
+        let db = sled::Config::new().temporary(true).open().unwrap();
+        HeaderStore { by_hash: db.open_tree(BY_HASH).unwrap(), by_height: db.open_tree(BY_HEIGHT).unwrap() }
+    }
+
+    #[test]
+    fn test_lookups_by_height_and_hash() {
+        let store = temporary_store();
+        assert_eq!(store.tip(), None);
+        store.insert(&block(0, "000000000019d6689c"));
+        store.insert(&block(2, "000000006a625f06"));
+
+        assert_eq!(store.by_height(0).unwrap().id, "000000000019d6689c");
+        assert_eq!(store.by_hash("000000006A625F06").unwrap().height, 2);
+        assert!(store.by_height(1).is_none());
+        assert_eq!(store.tip(), Some(2));
+    }
+
+    #[test]
+    fn test_reorg_overwrites_height_index() {
+        let store = temporary_store();
+        store.insert(&block(5, "00000000aaaa"));
+        store.insert(&block(5, "00000000bbbb"));
+        assert_eq!(store.by_height(5).unwrap().id, "00000000bbbb");
+        assert_eq!(store.by_hash("00000000aaaa").unwrap().height, 5);
+    }
+
+    #[test]
+    fn test_resolve_prefix() {
+        let store = temporary_store();
+        store.insert(&block(1, "00000000839a8e68"));
+        store.insert(&block(2, "000000006a625f06"));
+
+        assert_eq!(store.resolve_prefix("0000000083").unwrap().height, 1);
+        assert!(matches!(store.resolve_prefix("00000000"), Err(ExplorerError::InvalidArgument(_))));
+        assert!(matches!(store.resolve_prefix("ffff"), Err(ExplorerError::NotFound(_))));
+    }
+
+    #[test]
+    fn test_lookup_identifiers() {
+        let store = temporary_store();
+        store.insert(&block(7, "00000000839a8e68"));
+
+        assert_eq!(store.lookup(&BlockIdentifier::Height(7)).unwrap().id, "00000000839a8e68");
+        assert_eq!(store.lookup(&BlockIdentifier::Hash("00000000839a8e68".to_string())).unwrap().height, 7);
+        assert_eq!(store.lookup(&BlockIdentifier::HashPrefix("0000000083".to_string())).unwrap().height, 7);
+        assert!(matches!(store.lookup(&BlockIdentifier::Height(8)), Err(ExplorerError::NotFound(_))));
+    }
+}
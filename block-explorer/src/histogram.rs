@@ -0,0 +1,127 @@
+//! Output value distribution for a block
+
+/// Outputs below this value are treated as dust (standard P2PKH dust limit)
+pub const DUST_LIMIT_SATS: u64 = 546;
+
+/// Bucket labels and exclusive upper bounds in sats; the last bucket is open-ended
+const BUCKETS: [(&str, u64); 7] = [
+    ("dust (<546 sat)", DUST_LIMIT_SATS),
+    ("<0.001 BTC", 100_000),
+    ("0.001–0.01 BTC", 1_000_000),
+    ("0.01–0.1 BTC", 10_000_000),
+    ("0.1–1 BTC", 100_000_000),
+    ("1–10 BTC", 1_000_000_000),
+    (">10 BTC", u64::MAX),
+];
+
+/// ASCII names for the buckets above, in the same order, for plain output
+pub const BUCKET_KEYS: [&str; BUCKETS.len()] = [
+    "dust",
+    "lt_0.001_btc",
+    "0.001_to_0.01_btc",
+    "0.01_to_0.1_btc",
+    "0.1_to_1_btc",
+    "1_to_10_btc",
+    "gt_10_btc",
+];
+
+/// Width of the longest bar in characters
+const BAR_WIDTH: usize = 30;
+
+/// Counts of outputs per value bucket
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ValueHistogram {
+    counts: [u64; BUCKETS.len()],
+}
+
+impl ValueHistogram {
+    pub fn from_values(values: impl IntoIterator<Item = u64>) -> Self {
+        let mut histogram = ValueHistogram::default();
+        for value in values {
+            histogram.counts[bucket_index(value)] += 1;
+        }
+        histogram
+    }
+
+    /// Total number of outputs counted
+    pub fn total(&self) -> u64 {
+        self.counts.iter().sum()
+    }
+
+    /// (label, count, percentage) for every bucket, in ascending value order
+    pub fn rows(&self) -> Vec<(&'static str, u64, f64)> {
+        let total = self.total();
+        BUCKETS
+            .iter()
+            .zip(self.counts.iter())
+            .map(|((label, _), &count)| (*label, count, percentage(count, total)))
+            .collect()
+    }
+
+    /// Renders a bar proportional to the largest bucket
+    pub fn bar(&self, count: u64) -> String {
+        let max = self.counts.iter().copied().max().unwrap_or(0);
+        if max == 0 {
+            return String::new();
+        }
+        let len = ((count as f64 / max as f64) * BAR_WIDTH as f64).round() as usize;
+        "█".repeat(len)
+    }
+}
+
+fn bucket_index(value: u64) -> usize {
+    BUCKETS
+        .iter()
+        .position(|(_, upper)| value < *upper)
+        .unwrap_or(BUCKETS.len() - 1)
+}
+
+fn percentage(count: u64, total: u64) -> f64 {
+    if total == 0 {
+        0.0
+    } else {
+        count as f64 * 100.0 / total as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bucket_boundaries() {
+        assert_eq!(bucket_index(0), 0);
+        assert_eq!(bucket_index(545), 0);
+        assert_eq!(bucket_index(546), 1);
+        assert_eq!(bucket_index(99_999), 1);
+        assert_eq!(bucket_index(100_000), 2);
+        assert_eq!(bucket_index(99_999_999), 4);
+        assert_eq!(bucket_index(100_000_000), 5);
+        assert_eq!(bucket_index(1_000_000_000), 6);
+        assert_eq!(bucket_index(u64::MAX), 6);
+    }
+
+    #[test]
+    fn test_histogram_rows() {
+        let histogram = ValueHistogram::from_values(vec![100, 1_000, 50_000, 5_000_000_000]);
+        assert_eq!(histogram.total(), 4);
+
+        let rows = histogram.rows();
+        assert_eq!(rows.len(), 7);
+        assert_eq!(rows[0], ("dust (<546 sat)", 1, 25.0));
+        assert_eq!(rows[1], ("<0.001 BTC", 2, 50.0));
+        assert_eq!(rows[6], (">10 BTC", 1, 25.0));
+    }
+
+    #[test]
+    fn test_histogram_bar() {
+        let histogram = ValueHistogram::from_values(vec![1_000, 1_000, 200_000]);
+        assert_eq!(histogram.bar(2).chars().count(), BAR_WIDTH);
+        assert_eq!(histogram.bar(1).chars().count(), BAR_WIDTH / 2);
+        assert_eq!(histogram.bar(0), "");
+
+        let empty = ValueHistogram::from_values(vec![]);
+        assert_eq!(empty.bar(0), "");
+        assert_eq!(empty.rows()[0].2, 0.0);
+    }
+}
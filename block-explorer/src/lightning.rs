@@ -0,0 +1,157 @@
+//! Rough Lightning channel heuristics for `--lightning`
+//!
+//! Pre-taproot channels are funded to a 2-of-2 multisig P2WSH output. Closing
+//! one reveals the witness script, and BOLT 3 commitment transactions (force
+//! closes) hide the commitment number in a locktime with upper byte 0x20 and
+//! an input sequence with upper byte 0x80. Opens can't be confirmed until the
+//! output is spent, so a transaction with one P2WSH output and at most one
+//! other (change) output is only a candidate. Taproot channels look like any
+//! other key-path spend and are not detected.
+
+use crate::scripts::{self, ScriptType};
+use crate::{Input, Transaction};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChannelEvent {
+    Open,
+    CooperativeClose,
+    ForceClose,
+}
+
+impl ChannelEvent {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ChannelEvent::Open => "possible channel open",
+            ChannelEvent::CooperativeClose => "cooperative channel close",
+            ChannelEvent::ForceClose => "force close (commitment transaction)",
+        }
+    }
+
+    /// Short name for plain output
+    pub fn key(&self) -> &'static str {
+        match self {
+            ChannelEvent::Open => "open",
+            ChannelEvent::CooperativeClose => "coop_close",
+            ChannelEvent::ForceClose => "force_close",
+        }
+    }
+}
+
+/// Per-block counts of each event
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct ChannelCounts {
+    pub opens: usize,
+    pub cooperative_closes: usize,
+    pub force_closes: usize,
+}
+
+impl ChannelCounts {
+    pub fn record(&mut self, event: ChannelEvent) {
+        match event {
+            ChannelEvent::Open => self.opens += 1,
+            ChannelEvent::CooperativeClose => self.cooperative_closes += 1,
+            ChannelEvent::ForceClose => self.force_closes += 1,
+        }
+    }
+}
+
+/// Classifies a transaction, checking closes before opens since a splice
+/// or channel-to-channel payment can look like both
+pub fn classify(tx: &Transaction) -> Option<ChannelEvent> {
+    if tx.vin.iter().any(|input| input.is_coinbase) {
+        return None;
+    }
+
+    if let Some(input) = tx.vin.iter().find(|input| spends_two_of_two(input)) {
+        return Some(if is_commitment(tx.locktime, input.sequence) {
+            ChannelEvent::ForceClose
+        } else {
+            ChannelEvent::CooperativeClose
+        });
+    }
+
+    let p2wsh_outputs = tx
+        .vout
+        .iter()
+        .filter(|output| scripts::classify(&output.scriptpubkey) == ScriptType::P2wsh)
+        .count();
+    (p2wsh_outputs == 1 && tx.vout.len() <= 2).then_some(ChannelEvent::Open)
+}
+
+/// A P2WSH spend whose witness script is a bare 2-of-2 multisig
+fn spends_two_of_two(input: &Input) -> bool {
+    input.witness.len() == 4 && input.witness[0].is_empty() && is_two_of_two_script(&input.witness[3])
+}
+
+/// `OP_2 <33-byte key> <33-byte key> OP_2 OP_CHECKMULTISIG`
+fn is_two_of_two_script(script_hex: &str) -> bool {
+    script_hex.len() == 142
+        && script_hex.starts_with("5221")
+        && &script_hex[70..72] == "21"
+        && script_hex.ends_with("52ae")
+}
+
+/// BOLT 3 commitment transaction markers in locktime and sequence
+fn is_commitment(locktime: u32, sequence: u32) -> bool {
+    locktime >> 24 == 0x20 && sequence >> 24 == 0x80
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, input, output};
+
+    const KEY: &str = "0330d54fd0dd420a6e5f8d3624f5f3482cae350f79d5f0753bf5beef9c2d91af3c";
+    const P2WSH: &str = "00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262";
+    const P2WPKH: &str = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+
+    fn multisig_script() -> String {
+        format!("5221{}21{}52ae", KEY, KEY)
+    }
+
+    fn tx(witness: Vec<String>, sequence: u32, locktime: u32, outputs: &[&str]) -> Transaction {
+        let vin = vec![Input { sequence, witness, ..input("prev", None) }];
+        let vout = outputs.iter().map(|script| output(script, 1_000)).collect();
+        Transaction { locktime, ..test_util::tx("t", vin, vout) }
+    }
+
+    #[test]
+    fn test_is_two_of_two_script() {
+        assert!(is_two_of_two_script(&multisig_script()));
+        assert!(!is_two_of_two_script(&format!("5321{}21{}52ae", KEY, KEY)));
+        assert!(!is_two_of_two_script(P2WSH));
+    }
+
+    #[test]
+    fn test_classify_closes() {
+        let witness = vec![String::new(), "30440220".to_string(), "30440220".to_string(), multisig_script()];
+
+        let coop = tx(witness.clone(), 0xffff_ffff, 0, &[P2WPKH, P2WPKH]);
+        assert_eq!(classify(&coop), Some(ChannelEvent::CooperativeClose));
+
+        let force = tx(witness, 0x8012_3456, 0x2034_5678, &[P2WSH, P2WPKH, P2WSH]);
+        assert_eq!(classify(&force), Some(ChannelEvent::ForceClose));
+    }
+
+    #[test]
+    fn test_classify_opens_and_ordinary_payments() {
+        let single_sig = vec!["30440220".to_string(), KEY.to_string()];
+        assert_eq!(classify(&tx(single_sig.clone(), 0xffff_fffd, 0, &[P2WSH, P2WPKH])), Some(ChannelEvent::Open));
+        assert_eq!(classify(&tx(single_sig.clone(), 0xffff_fffd, 0, &[P2WPKH, P2WPKH])), None);
+        // Batched payouts to several P2WSH outputs aren't single channel opens
+        assert_eq!(classify(&tx(single_sig.clone(), 0xffff_fffd, 0, &[P2WSH, P2WSH, P2WPKH])), None);
+
+        let mut coinbase = tx(single_sig, 0xffff_ffff, 0, &[P2WSH]);
+        coinbase.vin[0].is_coinbase = true;
+        assert_eq!(classify(&coinbase), None);
+    }
+
+    #[test]
+    fn test_counts() {
+        let mut counts = ChannelCounts::default();
+        counts.record(ChannelEvent::Open);
+        counts.record(ChannelEvent::Open);
+        counts.record(ChannelEvent::ForceClose);
+        assert_eq!(counts, ChannelCounts { opens: 2, cooperative_closes: 0, force_closes: 1 });
+    }
+}
@@ -1,28 +1,220 @@
-use clap::Parser;
+mod batch;
+mod capabilities;
+mod coinjoin;
+mod color;
+mod config;
+mod error;
+mod esplora;
+mod export;
+mod feerates;
+mod headers;
+mod histogram;
+mod lightning;
+mod mining;
+mod output;
+mod progress;
+mod ratelimit;
+mod scripts;
+mod search;
+mod stream;
+#[cfg(test)]
+mod test_util;
+mod timesearch;
+mod verify;
+mod witness;
+mod work;
+
+use capabilities::{Capabilities, Capability};
+use color::{ColorChoice, Palette};
+use config::Config;
+use error::ExplorerError;
+use esplora::{EsploraClient, RequestError};
+use headers::HeaderStore;
+use export::{BlockDocument, ExportFormat, TxRow};
+use feerates::FeeRateDistribution;
+use histogram::ValueHistogram;
+use output::{plain_row, Report};
+use progress::Progress;
+use clap::{Parser, Subcommand, ValueEnum};
 use chrono::{DateTime, Utc};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufReader, IsTerminal};
+use std::ops::Range;
+use std::path::PathBuf;
+use units::Unit;
 
 #[derive(Parser, Debug)]
 #[command(name = "block-explorer")]
 #[command(about = "Explore Bitcoin blocks by height or hash", long_about = None)]
+#[command(subcommand_negates_reqs = true)]
 struct Args {
-    /// Block height or block hash to query
-    block: String,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Block height, block hash, or inclusive height range (e.g. 800000..800010)
+    #[arg(required_unless_present_any = ["stdin", "input_file"])]
+    block: Option<String>,
+
+    /// Read block heights/hashes from stdin, one per line
+    #[arg(long, conflicts_with_all = ["block", "input_file"])]
+    stdin: bool,
+
+    /// Read block heights/hashes from a file, one per line
+    #[arg(long, value_name = "PATH", conflicts_with = "block")]
+    input_file: Option<PathBuf>,
 
-    /// Network (testnet or bitcoin)
-    #[arg(short, long, default_value = "testnet")]
-    network: String,
+    /// Output format; json prints a single block's report as one object, and
+    /// batches and ranges as one summary record per line (NDJSON) [default: text]
+    #[arg(long, value_enum, global = true)]
+    format: Option<OutputFormat>,
+
+    /// Network (testnet or bitcoin) [default: testnet]
+    #[arg(short, long, global = true)]
+    network: Option<String>,
 
     /// Show transactions in the block
     #[arg(short, long)]
     txs: bool,
 
-    /// Limit number of transactions to display (default: 10)
-    #[arg(short, long, default_value = "10")]
-    limit: usize,
+    /// Limit number of transactions to display [default: 10]
+    #[arg(short, long)]
+    limit: Option<usize>,
+
+    /// Skip this many transactions before listing
+    #[arg(long, default_value = "0")]
+    offset: usize,
+
+    /// List every transaction from the offset on, ignoring the limit
+    #[arg(long, conflicts_with = "limit")]
+    all: bool,
+
+    /// Show how many outputs of each listed transaction have been spent
+    #[arg(long)]
+    outspends: bool,
+
+    /// Show the block audit summary (mempool.space backends only)
+    #[arg(long)]
+    audit: bool,
+
+    /// Recompute the merkle root from the block's txids and compare it to the header
+    #[arg(long)]
+    verify: bool,
+
+    /// Show current fee estimates for common confirmation targets
+    #[arg(long)]
+    fee_estimates: bool,
+
+    /// Show the distribution of output values across the whole block
+    #[arg(long)]
+    value_histogram: bool,
+
+    /// Show fee-rate percentiles of the transactions in the block
+    #[arg(long)]
+    fee_percentiles: bool,
+
+    /// Decode each listed input's witness stack: signatures, keys, scripts, and taproot details
+    #[arg(long)]
+    witness: bool,
+
+    /// Show block-wide totals and likely CoinJoin transactions
+    #[arg(long)]
+    stats: bool,
+
+    /// Flag likely Lightning channel opens and closes, with per-block counts
+    #[arg(long)]
+    lightning: bool,
+
+    /// List every transaction in the block or height range that pays to or spends from this address
+    #[arg(long, value_name = "ADDRESS", requires = "block", conflicts_with_all = ["stdin", "input_file"])]
+    search_address: Option<String>,
+
+    /// Aggregate blocks won, subsidy, fees, and fullness per miner over the block or height range
+    #[arg(long, requires = "block", conflicts_with_all = ["stdin", "input_file", "search_address"])]
+    mining_stats: bool,
+
+    /// Also write the block summary, fee stats, and with --txs the listed
+    /// transactions to a Markdown (md) or HTML (html) file
+    #[arg(
+        long = "report",
+        num_args = 2,
+        value_names = ["FORMAT", "PATH"],
+        conflicts_with_all = ["stdin", "input_file", "search_address", "mining_stats"]
+    )]
+    export: Option<Vec<String>>,
+
+    /// Plain `key: value` and tab-separated output without box drawing or symbols
+    #[arg(long, conflicts_with = "format", global = true)]
+    plain: bool,
+
+    /// Unit for displayed amounts [default: sats, or btc for --mining-stats]
+    #[arg(long, value_enum, global = true)]
+    unit: Option<Unit>,
+
+    /// When to colorize output
+    #[arg(long, value_enum, default_value = "auto", global = true)]
+    color: ColorChoice,
+
+    /// Esplora API URL; repeat to add fallbacks tried in order on failure
+    #[arg(long = "esplora-url", value_name = "URL", global = true)]
+    esplora_urls: Vec<String>,
+
+    /// Send requests through an HTTP proxy (e.g. http://127.0.0.1:8080)
+    #[arg(long, value_name = "URL", global = true)]
+    proxy: Option<String>,
+
+    /// Stop after this many HTTP requests (exit code 7) instead of hammering the backend
+    #[arg(long, value_name = "N", global = true)]
+    max_requests: Option<u64>,
+
+    /// Send at most this many requests per second
+    #[arg(long, value_name = "RATE", global = true)]
+    rps: Option<f64>,
+
+    /// Read defaults from this file instead of ~/.config/bdk-experiments/block-explorer.toml
+    #[arg(long, value_name = "PATH", global = true)]
+    config: Option<PathBuf>,
+
+    /// Report which endpoint served each request
+    #[arg(short, long, global = true)]
+    verbose: bool,
+
+    /// Answer header-level queries from the local header store, sending no requests
+    #[arg(long, global = true)]
+    offline: bool,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Find the first block mined at or after a time (UTC)
+    At {
+        /// e.g. "2024-04-20 00:00 UTC", 2024-04-20, RFC 3339, or a unix timestamp
+        time: String,
+    },
+    /// Fee-rate percentiles of a block's transactions
+    Fees {
+        #[command(subcommand)]
+        query: FeesQuery,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum FeesQuery {
+    /// The 10th/25th/50th/75th/90th percentile fee rates of the block at a height
+    At { height: u32 },
+}
+
+/// How results are written to stdout
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum OutputFormat {
+    Text,
+    Json,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
 struct BlockInfo {
     id: String,
     height: u32,
@@ -60,11 +252,17 @@ struct Transaction {
 struct Input {
     txid: String,
     vout: u32,
+    /// The output being spent; absent for coinbase inputs
+    #[serde(default)]
+    prevout: Option<Output>,
     #[serde(default)]
     is_coinbase: bool,
     scriptsig: String,
     #[serde(default)]
     sequence: u32,
+    /// Witness stack items, hex encoded
+    #[serde(default)]
+    witness: Vec<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -72,6 +270,8 @@ struct Input {
 struct Output {
     value: u64,
     scriptpubkey: String,
+    #[serde(default)]
+    scriptpubkey_address: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
@@ -82,6 +282,33 @@ struct TxStatus {
     block_height: Option<u32>,
 }
 
+/// Esplora's /block/:hash/status response
+#[derive(Debug, Deserialize)]
+struct BlockStatus {
+    in_best_chain: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct Outspend {
+    spent: bool,
+}
+
+#[derive(Debug, Deserialize)]
+struct AuditSummary {
+    #[serde(rename = "matchRate", default)]
+    match_rate: Option<f64>,
+    #[serde(rename = "missingTxs", default)]
+    missing_txs: Vec<String>,
+    #[serde(rename = "addedTxs", default)]
+    added_txs: Vec<String>,
+}
+
+/// Transactions listed by --txs when neither --limit nor the config sets one
+const DEFAULT_TX_LIMIT: usize = 10;
+
+/// Confirmation targets (in blocks) shown by --fee-estimates
+const FEE_ESTIMATE_TARGETS: [u32; 4] = [1, 3, 6, 144];
+
 /// Validates and normalizes the network name
 fn validate_network(network: &str) -> Result<&'static str, String> {
     match network {
@@ -100,8 +327,20 @@ fn get_esplora_url(network: &str) -> &'static str {
     }
 }
 
-/// Determines if the input is a block height (number) or hash
+/// Length of a full block hash in hex characters
+const BLOCK_HASH_HEX_LEN: usize = 64;
+
+/// Shortest hash prefix accepted in place of a full block hash
+const MIN_HASH_PREFIX_LEN: usize = 12;
+
+/// Determines if the input is a block height (number), hash, or hash prefix
 fn parse_block_identifier(input: &str) -> BlockIdentifier {
+    // Checked before heights so zero-padded prefixes aren't read as numbers
+    let is_hex = input.chars().all(|c| c.is_ascii_hexdigit());
+    if is_hex && (MIN_HASH_PREFIX_LEN..BLOCK_HASH_HEX_LEN).contains(&input.len()) {
+        return BlockIdentifier::HashPrefix(input.to_lowercase());
+    }
+
     if let Ok(height) = input.parse::<u32>() {
         BlockIdentifier::Height(height)
     } else {
@@ -109,11 +348,30 @@ fn parse_block_identifier(input: &str) -> BlockIdentifier {
     }
 }
 
-/// Represents either a block height or hash
+/// Parses an inclusive height range such as `800000..800010`
+fn parse_height_range(input: &str) -> Result<Option<(u32, u32)>, String> {
+    let Some((start, end)) = input.split_once("..") else {
+        return Ok(None);
+    };
+
+    let parse = |s: &str| {
+        s.trim()
+            .parse::<u32>()
+            .map_err(|_| format!("Invalid height range: '{}'. Use START..END, e.g. 800000..800010", input))
+    };
+    let (start, end) = (parse(start)?, parse(end)?);
+    if start > end {
+        return Err(format!("Invalid height range: '{}'. START must not exceed END", input));
+    }
+    Ok(Some((start, end)))
+}
+
+/// Represents a block height, hash, or unique hash prefix
 #[derive(Debug, PartialEq, Eq)]
 enum BlockIdentifier {
     Height(u32),
     Hash(String),
+    HashPrefix(String),
 }
 
 /// Formats a network name for display
@@ -130,204 +388,828 @@ fn is_coinbase_tx(tx: &Transaction) -> bool {
     tx.vin.iter().any(|input| input.is_coinbase)
 }
 
+/// Block-wide figures gathered in one pass over every transaction
+#[derive(Debug, Default)]
+struct BlockStats {
+    /// Every output value, for the value histogram
+    values: Vec<u64>,
+    /// Fee rate of every transaction with a known fee
+    rates: Vec<f64>,
+    channels: lightning::ChannelCounts,
+    coinjoins: Vec<(String, coinjoin::CoinjoinKind)>,
+    inputs: usize,
+    outputs: usize,
+    total_out: u64,
+    total_fees: u64,
+}
+
+impl BlockStats {
+    fn add(&mut self, tx: &Transaction) {
+        self.values.extend(tx.vout.iter().map(|o| o.value));
+        if let Some(fee) = transaction_fee(tx) {
+            self.rates.push(feerates::fee_rate(fee, tx.weight));
+            self.total_fees += fee;
+        }
+        if let Some(event) = lightning::classify(tx) {
+            self.channels.record(event);
+        }
+        if let Some(kind) = coinjoin::classify(tx) {
+            self.coinjoins.push((tx.txid.clone(), kind));
+        }
+        self.inputs += tx.vin.len();
+        self.outputs += tx.vout.len();
+        self.total_out += calculate_total_output(tx);
+    }
+
+    fn fee_distribution(&self) -> FeeRateDistribution {
+        FeeRateDistribution::from_rates(self.rates.iter().copied())
+    }
+}
+
+/// Confirmations of a best-chain block at `height` when the tip is at `tip`
+fn confirmations(tip: u32, height: u32) -> u32 {
+    if tip >= height {
+        tip - height + 1
+    } else {
+        0
+    }
+}
+
+/// Whether the block is on the best chain: from the status endpoint, or by
+/// checking which hash the backend has at the block's height. None when
+/// neither answers.
+fn in_best_chain(client: &EsploraClient, block: &BlockInfo) -> Option<bool> {
+    match client.get_json::<BlockStatus>(&format!("/block/{}/status", block.id)) {
+        Ok(status) => Some(status.in_best_chain),
+        Err(_) => client
+            .get_text(&format!("/block-height/{}", block.height))
+            .ok()
+            .map(|hash| hash.trim() == block.id),
+    }
+}
+
 /// Calculates total output value in satoshis
 fn calculate_total_output(tx: &Transaction) -> u64 {
     tx.vout.iter().map(|o| o.value).sum()
 }
 
-/// Converts satoshis to BTC
-fn sats_to_btc(sats: u64) -> f64 {
-    sats as f64 / 100_000_000.0
+/// Fee computed from the spent outputs; None for coinbase transactions or
+/// when the backend left out a prevout
+fn calculate_fee(tx: &Transaction) -> Option<u64> {
+    if is_coinbase_tx(tx) {
+        return None;
+    }
+    let total_in = tx
+        .vin
+        .iter()
+        .map(|input| input.prevout.as_ref().map(|prevout| prevout.value))
+        .sum::<Option<u64>>()?;
+    total_in.checked_sub(calculate_total_output(tx))
+}
+
+/// Compact input/output script type summary, e.g. `in: 2×P2WPKH, out: 1×P2TR`
+fn type_summary(tx: &Transaction) -> String {
+    let inputs = tx.vin.iter().map(|input| {
+        if input.is_coinbase {
+            scripts::ScriptType::Coinbase
+        } else {
+            input
+                .prevout
+                .as_ref()
+                .map_or(scripts::ScriptType::Unknown, |prevout| scripts::classify(&prevout.scriptpubkey))
+        }
+    });
+    let outputs = tx.vout.iter().map(|output| scripts::classify(&output.scriptpubkey));
+    format!("in: {}, out: {}", scripts::summarize(inputs), scripts::summarize(outputs))
+}
+
+/// Fee to display: computed from prevouts where possible, falling back to
+/// the backend's `fee` field, which some backends leave at 0
+fn transaction_fee(tx: &Transaction) -> Option<u64> {
+    calculate_fee(tx).or_else(|| (!is_coinbase_tx(tx) && tx.fee > 0).then_some(tx.fee))
+}
+
+/// Counts how many outputs in an outspends response have been spent
+fn count_spent(outspends: &[Outspend]) -> usize {
+    outspends.iter().filter(|o| o.spent).count()
+}
+
+/// Picks the fee rate for a confirmation target from an Esplora fee-estimates map
+fn fee_rate_for_target(estimates: &HashMap<String, f64>, target: u32) -> Option<f64> {
+    estimates.get(&target.to_string()).copied()
+}
+
+/// Heights covered by a scan: the parsed range, or a single block height
+fn scan_heights(range: Option<(u32, u32)>, block: Option<&str>, flag: &str) -> Result<(u32, u32), ExplorerError> {
+    match (range, block.map(parse_block_identifier)) {
+        (Some(range), _) => Ok(range),
+        (None, Some(BlockIdentifier::Height(height))) => Ok((height, height)),
+        _ => Err(ExplorerError::InvalidArgument(format!("{} needs a block height or height range", flag))),
+    }
+}
+
+/// Positions of the transactions to list: `limit` of them from `offset`,
+/// or everything from `offset` on when `limit` is None
+fn tx_window(offset: usize, limit: Option<usize>, tx_count: usize) -> Range<usize> {
+    let start = offset.min(tx_count);
+    let end = limit.map_or(tx_count, |limit| start.saturating_add(limit).min(tx_count));
+    start..end
+}
+
+/// Fetches every transaction in a block using the paged txs endpoint
+fn fetch_block_txs(
+    client: &EsploraClient,
+    block_hash: &str,
+    tx_count: usize,
+) -> Result<Vec<Transaction>, RequestError> {
+    fetch_block_tx_range(client, block_hash, 0..tx_count)
+}
+
+/// Fetches the transactions at the given positions, requesting only the
+/// pages that overlap the range
+fn fetch_block_tx_range(
+    client: &EsploraClient,
+    block_hash: &str,
+    range: Range<usize>,
+) -> Result<Vec<Transaction>, RequestError> {
+    stream::block_txs(client, block_hash, range).collect()
+}
+
+/// Streams every transaction of the block once, keeping only the block-wide
+/// figures; None, after a warning, when some couldn't be fetched
+fn gather_block_stats(client: &EsploraClient, block: &BlockInfo, report: &Report) -> Option<BlockStats> {
+    report.status(format!("\nFetching {} transactions in the block...", block.tx_count));
+    let progress = Progress::new(client, block.tx_count as u64, "txs", report.shows_progress());
+    let mut stats = BlockStats::default();
+    let mut complete = true;
+    for tx in stream::block_txs(client, &block.id, 0..block.tx_count) {
+        match tx {
+            Ok(tx) => stats.add(&tx),
+            Err(e) => {
+                progress.suspend(|| eprintln!("Warning: Could not fetch block transactions: {}", e));
+                complete = false;
+            }
+        }
+        progress.tick();
+    }
+    complete.then_some(stats)
+}
+
+/// The FEE RATE PERCENTILES section, for `--fee-percentiles` and `fees at`
+fn report_fee_percentiles(report: &Report, distribution: &FeeRateDistribution) {
+    let palette = report.palette();
+    report.section(&format!("FEE RATE PERCENTILES ({} transactions)", distribution.len()), "fee_percentiles");
+    if distribution.is_empty() {
+        report.line("No fee-paying transactions (coinbase only)");
+    }
+    for (percentile, rate) in distribution.rows() {
+        report.field(
+            format!("p{:<3} {} sat/vB", percentile, palette.value(format!("{:>8.1}", rate))),
+            &format!("p{}", percentile),
+            format!("{:.1}", rate),
+        );
+    }
+    report.end();
+}
+
+/// `fees at HEIGHT`: the block's height, hash and time, then its fee-rate
+/// percentiles, from every transaction fetched on demand
+fn fees_at(client: &EsploraClient, height: u32, format: OutputFormat, report: Report) -> Result<(), ExplorerError> {
+    if client.is_offline() {
+        return Err(ExplorerError::InvalidArgument(
+            "fees at needs the block's transactions, which the header store doesn't keep; drop --offline".to_string(),
+        ));
+    }
+    let block = batch::query_block(client, &height.to_string())?;
+    let report = report.with_json(format == OutputFormat::Json);
+    let stats = gather_block_stats(client, &block, &report).ok_or_else(|| {
+        ExplorerError::Network(format!("Could not fetch every transaction of block {}", block.height))
+    })?;
+
+    let palette = report.palette();
+    let mined = DateTime::<Utc>::from_timestamp(block.timestamp as i64, 0)
+        .map_or_else(|| block.timestamp.to_string(), |time| time.format("%Y-%m-%d %H:%M:%S UTC").to_string());
+    report.section("BLOCK", "block");
+    report.field(format!("Height:      {}", palette.value(block.height)), "height", block.height);
+    report.field(format!("Hash:        {}", palette.hash(&block.id)), "hash", &block.id);
+    report.field(format!("Timestamp:   {} ({})", palette.value(block.timestamp), mined), "timestamp", block.timestamp);
+    report.end();
+    report_fee_percentiles(&report, &stats.fee_distribution());
+    report.finish();
+    Ok(())
 }
 
 fn main() {
     let args = Args::parse();
 
-    // Validate network
-    let network = match validate_network(&args.network) {
-        Ok(net) => net,
-        Err(err) => {
-            eprintln!("{}", err);
-            return;
+    if let Err(err) = run(&args) {
+        eprintln!("Error: {}", err);
+        if let ExplorerError::Network(_) = err {
+            eprintln!("\nNote: This tool requires internet access to query the blockchain.");
+        }
+        std::process::exit(err.exit_code());
+    }
+}
+
+fn run(args: &Args) -> Result<(), ExplorerError> {
+    // Verbose request logging on stderr would tear through a progress bar
+    let report = Report::new(args.plain, Palette::new(args.color)).with_unit(args.unit.unwrap_or_default()).with_progress(!args.verbose);
+    let palette = report.palette();
+    let unit = report.unit();
+
+    // Config file values fill in whatever wasn't given on the command line
+    let config = match (&args.config, config::default_path()) {
+        (Some(path), _) => config::load(path, true),
+        (None, Some(path)) => config::load(&path, false),
+        (None, None) => Ok(Config::default()),
+    }
+    .map_err(ExplorerError::InvalidArgument)?;
+    let format = args.format.or(config.format).unwrap_or(OutputFormat::Text);
+    let export = match args.export.as_deref() {
+        Some([format, path]) => {
+            Some((ExportFormat::parse(format).map_err(ExplorerError::InvalidArgument)?, PathBuf::from(path)))
         }
+        _ => None,
     };
+    let limit = args.limit.or(config.limit).unwrap_or(DEFAULT_TX_LIMIT);
+
+    // Validate network
+    let network_name = args.network.as_deref().or(config.network.as_deref()).unwrap_or("testnet");
+    let network = validate_network(network_name).map_err(ExplorerError::InvalidArgument)?;
+
+    // Build Esplora client, falling back to the network default
+    let endpoints = if !args.esplora_urls.is_empty() {
+        args.esplora_urls.clone()
+    } else if !config.esplora_urls().is_empty() {
+        config.esplora_urls()
+    } else {
+        vec![get_esplora_url(network).to_string()]
+    };
+    let mut client = EsploraClient::new(&endpoints, args.verbose);
+    if let Some(proxy) = args.proxy.as_ref().or(config.proxy.as_ref()) {
+        client = client.with_proxy(proxy).map_err(ExplorerError::InvalidArgument)?;
+    }
+    let scheduler = ratelimit::Scheduler::new(args.rps.or(config.rps), args.max_requests.or(config.max_requests))
+        .map_err(ExplorerError::InvalidArgument)?;
+    let mut client = client.with_scheduler(scheduler);
 
-    println!("=== Bitcoin Block Explorer ===\n");
-    println!("Network: {}", format_network_name(network));
+    // Fetched blocks are recorded locally; only --offline depends on the store
+    match headers::default_path(network).map(|path| HeaderStore::open(&path)) {
+        Some(Ok(store)) => client = client.with_header_store(store),
+        Some(Err(err)) if args.offline => return Err(ExplorerError::InvalidArgument(err)),
+        Some(Err(err)) => eprintln!("Warning: {}", err),
+        None => {}
+    }
+    if args.offline {
+        client = client.offline();
+    }
 
-    // Build Esplora URL
-    let esplora_url = get_esplora_url(network);
+    // A height range is queried like a batch, with cumulative work per record
+    let range = args
+        .block
+        .as_deref()
+        .map(parse_height_range)
+        .transpose()
+        .map_err(ExplorerError::InvalidArgument)?
+        .flatten();
 
-    println!("API: {}\n", esplora_url);
+    if let Some(Command::At { time }) = &args.command {
+        let target = timesearch::parse_time(time).map_err(ExplorerError::InvalidArgument)?;
+        return timesearch::run(&client, target, format, report);
+    }
+    if let Some(Command::Fees { query: FeesQuery::At { height } }) = &args.command {
+        return fees_at(&client, *height, format, report);
+    }
 
-    // Determine if input is a height (number) or hash (hex string)
-    let block_hash = match parse_block_identifier(&args.block) {
-        BlockIdentifier::Height(height) => {
-            // Input is a block height - get the hash first
-            println!("Querying block at height {}...", height);
-            let url = format!("{}/block-height/{}", esplora_url, height);
+    if let Some(address) = &args.search_address {
+        let heights = scan_heights(range, args.block.as_deref(), "--search-address")?;
+        let script_hex = search::address_script_hex(address, network).map_err(ExplorerError::InvalidArgument)?;
+        return search::run(&client, address, &script_hex, heights, format, report);
+    }
 
-            match ureq::get(&url).call() {
-                Ok(response) => {
-                    match response.into_string() {
-                        Ok(hash) => {
-                            println!("Block hash: {}\n", hash);
-                            hash
-                        }
-                        Err(e) => {
-                            eprintln!("Error reading response: {}", e);
-                            return;
-                        }
-                    }
-                }
-                Err(e) => {
-                    eprintln!("Error fetching block hash: {}", e);
-                    eprintln!("\nNote: This tool requires internet access to query the blockchain.");
-                    return;
-                }
-            }
-        }
-        BlockIdentifier::Hash(hash) => {
-            // Input is assumed to be a block hash
-            println!("Querying block with hash {}...\n", hash);
-            hash
-        }
+    if args.mining_stats {
+        let heights = scan_heights(range, args.block.as_deref(), "--mining-stats")?;
+        // The table was in BTC before --unit, so it stays there unless asked
+        return mining::run(&client, heights, format, report.with_unit(args.unit.unwrap_or(Unit::Btc)));
+    }
+
+    // Batch input and height ranges emit one summary record per block
+    let identifiers = if let Some((start, end)) = range {
+        Ok((start..=end).map(|height| height.to_string()).collect())
+    } else if args.stdin {
+        batch::read_identifiers(io::stdin().lock())
+    } else if let Some(path) = &args.input_file {
+        File::open(path).and_then(|file| batch::read_identifiers(BufReader::new(file)))
+    } else {
+        Ok(args.block.iter().cloned().collect())
     };
+    let identifiers = identifiers.map_err(|e| {
+        ExplorerError::InvalidArgument(format!("Could not read block identifiers: {}", e))
+    })?;
+    if export.is_some() && (format == OutputFormat::Json || args.stdin || args.input_file.is_some() || range.is_some()) {
+        return Err(ExplorerError::InvalidArgument("--report needs a single block and text output".to_string()));
+    }
+    if args.stdin || args.input_file.is_some() || range.is_some() {
+        return batch::run(&client, &identifiers, format, range.is_some(), report.shows_progress());
+    }
+    // A single block keeps its full report, gathered into one JSON object
+    let report = report.with_json(format == OutputFormat::Json);
+    let block_input = &identifiers[0];
 
-    // Fetch block information
-    let url = format!("{}/block/{}", esplora_url, block_hash);
-    let block: BlockInfo = match ureq::get(&url).call() {
-        Ok(response) => {
-            match response.into_json() {
-                Ok(data) => data,
-                Err(e) => {
-                    eprintln!("Error parsing block data: {}", e);
-                    return;
-                }
-            }
+    report.status("=== Bitcoin Block Explorer ===\n");
+    report.status(format!("Network: {}", format_network_name(network)));
+    if client.is_offline() {
+        report.status("API: none (--offline, reading the local header store)");
+    } else {
+        report.status(format!("API: {}", client.active_url()));
+        for fallback in &client.endpoints()[1..] {
+            report.status(format!("Fallback: {}", fallback));
         }
-        Err(e) => {
-            eprintln!("Error fetching block: {}", e);
-            return;
+    }
+    report.status("");
+
+    // Determine if input is a height (number), hash, or hash prefix
+    let identifier = parse_block_identifier(block_input);
+    let block: BlockInfo = if client.is_offline() {
+        headers::require(client.headers())?.lookup(&identifier)?
+    } else {
+        let block_hash = match identifier {
+            BlockIdentifier::Height(height) => {
+                // Input is a block height - get the hash first
+                report.status(format!("Querying block at height {}...", height));
+                let hash = client
+                    .get_text(&format!("/block-height/{}", height))
+                    .map_err(|e| ExplorerError::from_request("Could not fetch block hash", e))?;
+                report.status(format!("Block hash: {}\n", palette.hash(&hash)));
+                hash
+            }
+            BlockIdentifier::Hash(hash) => {
+                // Input is assumed to be a block hash
+                report.status(format!("Querying block with hash {}...\n", palette.hash(&hash)));
+                hash
+            }
+            BlockIdentifier::HashPrefix(prefix) => {
+                // Esplora has no prefix search, so prefixes resolve against the header store
+                let hash = headers::require(client.headers())?.resolve_prefix(&prefix)?.id;
+                report.status(format!("Block hash prefix {} resolves to {}\n", prefix, palette.hash(&hash)));
+                hash
+            }
+        };
+
+        // Fetch block information
+        let block: BlockInfo = client
+            .get_json(&format!("/block/{}", block_hash))
+            .map_err(|e| ExplorerError::from_request("Could not fetch block", e))?;
+        if let Some(headers) = client.headers() {
+            headers.insert(&block);
         }
+        block
+    };
+    let block_hash = block.id.clone();
+
+    // Confirmations need the current tip, and a stale block has none
+    let tip_height = client
+        .get_text("/blocks/tip/height")
+        .ok()
+        .and_then(|height| height.trim().parse::<u32>().ok());
+    let best_chain = in_best_chain(&client, &block);
+    let block_confirmations = match (best_chain, tip_height) {
+        (Some(false), _) => Some(0),
+        (_, Some(tip)) => Some(confirmations(tip, block.height)),
+        (_, None) => None,
     };
+    if best_chain == Some(false) {
+        eprintln!(
+            "Warning: block {} is NOT on the best chain; it was orphaned (stale) and its transactions are unconfirmed",
+            block.id
+        );
+    }
+
+    // Probe the optional endpoints the requested sections use, so they can
+    // degrade gracefully; a plain lookup sends no probes
+    let wanted: Vec<Capability> = [
+        (args.fee_estimates, Capability::FeeEstimates),
+        (args.outspends, Capability::Outspends),
+        (args.audit, Capability::BlockAudit),
+    ]
+    .into_iter()
+    .filter_map(|(requested, capability)| requested.then_some(capability))
+    .collect();
+    let first_txid = || client.get_text(&format!("/block/{}/txid/0", block_hash)).unwrap_or_default();
+    let capabilities = Capabilities::probe(&client, &block_hash, first_txid, &wanted);
+    if !wanted.is_empty() && !client.is_offline() {
+        report.status(format!("Backend supports: {}", capabilities.summary(&wanted)));
+    }
 
     // Display block information
-    println!("╔════════════════════════════════════════════════════════════════════");
-    println!("║ BLOCK INFORMATION");
-    println!("╠════════════════════════════════════════════════════════════════════");
-    println!("║ Hash:        {}", block.id);
-    println!("║ Height:      {}", block.height);
-    println!("║ Version:     {}", block.version);
-
-    if let Some(prev) = &block.previousblockhash {
-        println!("║ Previous:    {}", prev);
-    } else {
-        println!("║ Previous:    None (Genesis Block)");
+    report.section("BLOCK INFORMATION", "block");
+    report.field(format!("Hash:        {}", palette.hash(&block.id)), "hash", &block.id);
+    report.field(format!("Height:      {}", palette.value(block.height)), "height", block.height);
+    match (block_confirmations, best_chain) {
+        (_, Some(false)) => {
+            report.field(format!("Confirmations: {}", palette.warning(0)), "confirmations", 0);
+            report.field(
+                format!("Status:      {}", palette.warning("⚠ ORPHANED: not on the best chain")),
+                "in_best_chain",
+                false,
+            );
+        }
+        (Some(count), _) => report.field(format!("Confirmations: {}", palette.value(count)), "confirmations", count),
+        (None, _) if client.is_offline() => report.field("Confirmations: Unavailable (offline)", "confirmations", "unavailable"),
+        (None, _) => report.field("Confirmations: Unavailable (could not fetch the tip height)", "confirmations", "unavailable"),
     }
+    report.field(format!("Version:     {}", palette.value(block.version)), "version", block.version);
 
-    println!("║ Merkle Root: {}", block.merkle_root);
+    match &block.previousblockhash {
+        Some(prev) => report.field(format!("Previous:    {}", palette.hash(prev)), "previous", prev),
+        None => report.field("Previous:    None (Genesis Block)", "previous", "none"),
+    }
+
+    report.field(format!("Merkle Root: {}", palette.hash(&block.merkle_root)), "merkle_root", &block.merkle_root);
 
     // Convert timestamp to human-readable format
     let datetime = DateTime::<Utc>::from_timestamp(block.timestamp as i64, 0)
-        .unwrap_or_else(|| Utc::now());
-    println!("║ Timestamp:   {} ({})", block.timestamp, datetime.format("%Y-%m-%d %H:%M:%S UTC"));
+        .unwrap_or_else(Utc::now);
+    report.field(
+        format!("Timestamp:   {} ({})", palette.value(block.timestamp), datetime.format("%Y-%m-%d %H:%M:%S UTC")),
+        "timestamp",
+        block.timestamp,
+    );
 
     if block.mediantime > 0 {
         let median_dt = DateTime::<Utc>::from_timestamp(block.mediantime as i64, 0)
-            .unwrap_or_else(|| Utc::now());
-        println!("║ Median Time: {} ({})", block.mediantime, median_dt.format("%Y-%m-%d %H:%M:%S UTC"));
+            .unwrap_or_else(Utc::now);
+        report.field(
+            format!("Median Time: {} ({})", palette.value(block.mediantime), median_dt.format("%Y-%m-%d %H:%M:%S UTC")),
+            "mediantime",
+            block.mediantime,
+        );
     }
 
-    println!("║ Bits:        {}", block.bits);
-    println!("║ Nonce:       {}", block.nonce);
-    println!("║ Difficulty:  {:.2}", block.difficulty);
-    println!("║ Size:        {} bytes", block.size);
-    println!("║ Weight:      {} WU", block.weight);
-    println!("║ Transactions: {}", block.tx_count);
-    println!("╚════════════════════════════════════════════════════════════════════");
+    let block_work = work::block_work(block.bits);
+    report.field(format!("Bits:        {}", palette.value(block.bits)), "bits", block.bits);
+    report.field(format!("Nonce:       {}", palette.value(block.nonce)), "nonce", block.nonce);
+    report.field(format!("Difficulty:  {}", palette.value(format!("{:.2}", block.difficulty))), "difficulty", block.difficulty);
+    report.field(format!("Work:        {}", palette.value(work::format_work(block_work))), "work", block_work);
+    report.field(format!("Size:        {} bytes", palette.value(block.size)), "size", block.size);
+    report.field(format!("Weight:      {} WU", palette.value(block.weight)), "weight", block.weight);
+    report.field(format!("Transactions: {}", palette.value(block.tx_count)), "tx_count", block.tx_count);
+    report.end();
 
-    // Show transactions if requested
-    if args.txs && block.tx_count > 0 {
-        println!("\n╔════════════════════════════════════════════════════════════════════");
-        println!("║ TRANSACTIONS (showing {} of {})",
-                 std::cmp::min(args.limit, block.tx_count), block.tx_count);
-        println!("╠════════════════════════════════════════════════════════════════════");
-
-        // Fetch transaction IDs
-        let txids_url = format!("{}/block/{}/txids", esplora_url, block_hash);
-        let txids: Vec<String> = match ureq::get(&txids_url).call() {
-            Ok(response) => {
-                match response.into_json() {
-                    Ok(data) => data,
-                    Err(e) => {
-                        eprintln!("Error parsing transaction IDs: {}", e);
-                        return;
+    let mut document = BlockDocument {
+        title: format!("Block {}", block.height),
+        fields: vec![
+            ("Hash", block.id.clone()),
+            ("Height", block.height.to_string()),
+            (
+                "Confirmations",
+                match (block_confirmations, best_chain) {
+                    (_, Some(false)) => "0 (orphaned)".to_string(),
+                    (Some(count), _) => count.to_string(),
+                    (None, _) => "unavailable".to_string(),
+                },
+            ),
+            ("Previous", block.previousblockhash.clone().unwrap_or_else(|| "None (Genesis Block)".to_string())),
+            ("Merkle Root", block.merkle_root.clone()),
+            ("Timestamp", datetime.format("%Y-%m-%d %H:%M:%S UTC").to_string()),
+            ("Difficulty", format!("{:.2}", block.difficulty)),
+            ("Size", format!("{} bytes", block.size)),
+            ("Weight", format!("{} WU", block.weight)),
+            ("Transactions", block.tx_count.to_string()),
+        ],
+        tx_count: block.tx_count,
+        ..Default::default()
+    };
+
+    if args.verify {
+        let txids: Vec<String> = client
+            .get_json(&format!("/block/{}/txids", block_hash))
+            .map_err(|e| ExplorerError::from_request("Could not fetch transaction IDs", e))?;
+        let check = verify::check(&txids, &block.merkle_root).map_err(ExplorerError::Parse)?;
+        let verified = check.matches() && txids.len() == block.tx_count;
+
+        report.section(&format!("MERKLE ROOT VERIFICATION ({} txids)", txids.len()), "verify");
+        report.field(format!("Reported:  {}", palette.hash(&check.reported)), "reported", &check.reported);
+        report.field(format!("Computed:  {}", palette.hash(&check.computed)), "computed", &check.computed);
+        if verified {
+            report.field(format!("Result:    {}", palette.value("✓ match")), "result", "match");
+        } else {
+            report.field(format!("Result:    {}", palette.warning("✗ MISMATCH")), "result", "mismatch");
+        }
+        report.end();
+
+        if !verified {
+            return Err(ExplorerError::Verification(format!(
+                "Block {} failed merkle verification: backend returned {} txids for {} transactions \
+                 and a root of {}, but the header commits to {}",
+                block_hash, txids.len(), block.tx_count, check.computed, check.reported
+            )));
+        }
+    }
+
+    if args.audit {
+        match capabilities.require(Capability::BlockAudit) {
+            Ok(()) => {
+                match client.get_json::<AuditSummary>(&capabilities::audit_summary_path(&block_hash)) {
+                    Ok(audit) => {
+                        report.section("BLOCK AUDIT", "audit");
+                        match audit.match_rate {
+                            Some(rate) => report.field(
+                                format!("Match Rate:  {}%", palette.value(format!("{:.2}", rate))),
+                                "match_rate",
+                                rate,
+                            ),
+                            None => report.field("Match Rate:  Unavailable", "match_rate", "unavailable"),
+                        }
+                        report.field(format!("Missing Txs: {}", palette.value(audit.missing_txs.len())), "missing_txs", audit.missing_txs.len());
+                        report.field(format!("Added Txs:   {}", palette.value(audit.added_txs.len())), "added_txs", audit.added_txs.len());
+                        report.end();
                     }
+                    Err(e) => eprintln!("Warning: Could not fetch block audit summary: {}", e),
                 }
             }
-            Err(e) => {
-                eprintln!("Error fetching transaction IDs: {}", e);
-                return;
-            }
-        };
+            Err(err) => report.note(err),
+        }
+    }
 
-        // Fetch details for each transaction (up to limit)
-        for (i, txid) in txids.iter().take(args.limit).enumerate() {
-            let tx_url = format!("{}/tx/{}", esplora_url, txid);
-            let tx: Transaction = match ureq::get(&tx_url).call() {
-                Ok(response) => {
-                    match response.into_json() {
-                        Ok(data) => data,
-                        Err(e) => {
-                            eprintln!("Warning: Could not parse transaction {}: {}", txid, e);
-                            continue;
+    if args.fee_estimates {
+        match capabilities.require(Capability::FeeEstimates) {
+            Ok(()) => {
+                match client.get_json::<HashMap<String, f64>>(&capabilities::fee_estimates_path()) {
+                    Ok(estimates) => {
+                        report.section("FEE ESTIMATES", "fee_estimates");
+                        for target in FEE_ESTIMATE_TARGETS {
+                            if let Some(rate) = fee_rate_for_target(&estimates, target) {
+                                report.field(
+                                    format!("{:>3} blocks:  {} sat/vB", target, palette.value(format!("{:.1}", rate))),
+                                    &format!("target_{}", target),
+                                    format!("{:.1}", rate),
+                                );
+                            }
                         }
+                        report.end();
                     }
+                    Err(e) => eprintln!("Warning: Could not fetch fee estimates: {}", e),
                 }
-                Err(e) => {
-                    eprintln!("Warning: Could not fetch transaction {}: {}", txid, e);
-                    continue;
+            }
+            Err(err) => report.note(err),
+        }
+    }
+
+    // Block-wide sections need every transaction; stream them once, keeping
+    // only the per-block figures
+    let block_stats = if args.value_histogram || args.fee_percentiles || args.lightning || args.stats || export.is_some() {
+        gather_block_stats(&client, &block, &report)
+    } else {
+        None
+    };
+
+    if let Some(stats) = block_stats.as_ref().filter(|_| args.stats) {
+        report.section("BLOCK STATISTICS", "stats");
+        report.field(format!("Inputs:       {}", palette.value(stats.inputs)), "inputs", stats.inputs);
+        report.field(format!("Outputs:      {}", palette.value(stats.outputs)), "outputs", stats.outputs);
+        report.field(format!("Total Out:    {}", palette.value(unit.format(stats.total_out))), "total_out", stats.total_out);
+        report.field(format!("Total Fees:   {}", palette.value(unit.format(stats.total_fees))), "total_fees", stats.total_fees);
+        report.field(format!("Likely CoinJoins: {}", palette.value(stats.coinjoins.len())), "coinjoins", stats.coinjoins.len());
+        for (txid, kind) in &stats.coinjoins {
+            report.field(format!("  {} ({})", palette.hash(txid), kind.label()), "coinjoin", format!("{} {}", txid, kind.label()));
+        }
+        report.end();
+    }
+
+    if let Some(stats) = block_stats.as_ref().filter(|_| args.value_histogram) {
+        let histogram = ValueHistogram::from_values(stats.values.iter().copied());
+        report.section(&format!("OUTPUT VALUE DISTRIBUTION ({} outputs)", histogram.total()), "value_histogram");
+        for ((label, count, pct), key) in histogram.rows().into_iter().zip(histogram::BUCKET_KEYS) {
+            report.field(
+                format!("{:<16} {} ({:>5.1}%) {}", label, palette.value(format!("{:>7}", count)), pct, histogram.bar(count)),
+                key,
+                count,
+            );
+        }
+        report.end();
+    }
+
+    if let Some(stats) = block_stats.as_ref().filter(|_| args.fee_percentiles) {
+        report_fee_percentiles(&report, &stats.fee_distribution());
+    }
+
+    if let Some(channels) = block_stats.as_ref().filter(|_| args.lightning).map(|stats| &stats.channels) {
+        report.section("LIGHTNING HEURISTICS", "lightning");
+        report.field(format!("Possible opens:     {}", palette.value(channels.opens)), "opens", channels.opens);
+        report.field(
+            format!("Cooperative closes: {}", palette.value(channels.cooperative_closes)),
+            "cooperative_closes",
+            channels.cooperative_closes,
+        );
+        report.field(format!("Force closes:       {}", palette.value(channels.force_closes)), "force_closes", channels.force_closes);
+        report.line("Rough signal only: taproot channels are invisible, and opens are unconfirmed guesses");
+        report.end();
+    }
+
+    if let Some(stats) = &block_stats {
+        document.fee_percentiles = stats.fee_distribution().rows();
+    }
+
+    // Show transactions if requested
+    if args.txs && block.tx_count > 0 {
+        let window = tx_window(args.offset, if args.all { None } else { Some(limit) }, block.tx_count);
+        let show_spent = args.outspends && capabilities.supports(Capability::Outspends);
+
+        let title = if window.start == 0 {
+            format!("TRANSACTIONS (showing {} of {})", window.len(), block.tx_count)
+        } else {
+            format!("TRANSACTIONS (showing {}–{} of {})", window.start + 1, window.end, block.tx_count)
+        };
+        report.section(&title, "transactions");
+        if report.is_plain() {
+            let mut columns = "# index\ttxid\tversion\tinputs\toutputs\tsize\tweight\tlocktime\tcoinbase\ttotal_out\tfee\tfee_rate\ttypes".to_string();
+            if show_spent {
+                columns.push_str("\tspent_outputs");
+            }
+            if args.lightning {
+                columns.push_str("\tlightning");
+            }
+            println!("{}", columns);
+        }
+
+        // Stream details a page at a time, printing each transaction as it
+        // arrives so --all on a full block holds only one page in memory.
+        // On a terminal the listing itself shows progress, so the bar is only
+        // drawn when stdout is redirected.
+        let progress = Progress::new(
+            &client,
+            window.len() as u64,
+            "txs",
+            report.shows_progress() && !std::io::stdout().is_terminal(),
+        );
+        for (index, tx) in (window.start + 1..).zip(stream::block_txs(&client, &block_hash, window.clone())) {
+            let tx = &tx.map_err(|e| ExplorerError::from_request("Could not fetch transactions", e))?;
+            progress.tick();
+            let total_out = calculate_total_output(tx);
+            let fee = transaction_fee(tx);
+            let fee_rate = fee.map(|fee| feerates::fee_rate(fee, tx.weight));
+            let channel_event = if args.lightning { lightning::classify(tx) } else { None };
+            if export.is_some() {
+                document.txs.push(TxRow {
+                    index,
+                    txid: tx.txid.clone(),
+                    inputs: tx.vin.len(),
+                    outputs: tx.vout.len(),
+                    weight: tx.weight,
+                    total_out,
+                    fee,
+                    fee_rate,
+                    scripts: type_summary(tx),
+                });
+            }
+            let spent = if show_spent {
+                match client.get_json::<Vec<Outspend>>(&capabilities::outspends_path(&tx.txid)) {
+                    Ok(outspends) => Some((count_spent(&outspends), outspends.len())),
+                    Err(e) => {
+                        eprintln!("Warning: Could not fetch spend status for {}: {}", tx.txid, e);
+                        None
+                    }
                 }
+            } else {
+                None
             };
 
-            println!("\n[{}] TXID: {}", i + 1, tx.txid);
-            println!("    Version:  {}", tx.version);
-            println!("    Inputs:   {}", tx.vin.len());
-            println!("    Outputs:  {}", tx.vout.len());
-            println!("    Size:     {} bytes", tx.size);
-            println!("    Weight:   {} WU", tx.weight);
-            println!("    Locktime: {}", tx.locktime);
+            if report.is_json() {
+                let mut record = json!({
+                    "index": index,
+                    "txid": tx.txid,
+                    "version": tx.version,
+                    "inputs": tx.vin.len(),
+                    "outputs": tx.vout.len(),
+                    "size": tx.size,
+                    "weight": tx.weight,
+                    "locktime": tx.locktime,
+                    "coinbase": is_coinbase_tx(tx),
+                    "total_out": total_out,
+                    "fee": fee,
+                    "fee_rate": fee_rate,
+                    "types": type_summary(tx),
+                });
+                if show_spent {
+                    record["spent_outputs"] = json!(spent.map(|(spent, _)| spent));
+                }
+                if args.lightning {
+                    record["lightning"] = json!(channel_event.map(|event| event.key()));
+                }
+                report.record(record);
+                continue;
+            }
+            if report.is_plain() {
+                let mut row = plain_row(&[
+                    &index, &tx.txid, &tx.version, &tx.vin.len(), &tx.vout.len(), &tx.size,
+                    &tx.weight, &tx.locktime, &is_coinbase_tx(tx), &total_out,
+                    &fee.map_or("-".to_string(), |fee| fee.to_string()),
+                    &fee_rate.map_or("-".to_string(), |rate| format!("{:.1}", rate)),
+                    &type_summary(tx),
+                ]);
+                if show_spent {
+                    row.push('\t');
+                    row.push_str(&spent.map_or("-".to_string(), |(spent, _)| spent.to_string()));
+                }
+                if args.lightning {
+                    row.push('\t');
+                    row.push_str(channel_event.map_or("-", |event| event.key()));
+                }
+                println!("{}", row);
+                continue;
+            }
+
+            println!("\n[{}] TXID: {}", index, palette.hash(&tx.txid));
+            println!("    Version:  {}", palette.value(tx.version));
+            println!("    Inputs:   {}", palette.value(tx.vin.len()));
+            println!("    Outputs:  {}", palette.value(tx.vout.len()));
+            println!("    Size:     {} bytes", palette.value(tx.size));
+            println!("    Weight:   {} WU", palette.value(tx.weight));
+            println!("    Locktime: {}", palette.value(tx.locktime));
+            println!("    Scripts:  {}", type_summary(tx));
 
             // Check if coinbase
-            if is_coinbase_tx(&tx) {
+            if is_coinbase_tx(tx) {
                 println!("    Type:     Coinbase (Block Reward)");
             }
 
             // Calculate total output value
-            let total_out = calculate_total_output(&tx);
-            println!("    Total Out: {} sats ({:.8} BTC)", total_out, sats_to_btc(total_out));
+            println!("    Total Out: {}", palette.value(unit.format(total_out)));
+
+            match (fee, fee_rate) {
+                (Some(fee), Some(rate)) => println!(
+                    "    Fee:      {} ({} sat/vB)",
+                    palette.value(unit.format(fee)),
+                    palette.value(format!("{:.1}", rate))
+                ),
+                _ if !is_coinbase_tx(tx) => println!("    Fee:      unknown (backend omitted prevouts)"),
+                _ => {}
+            }
+
+            if let Some((spent, total)) = spent {
+                println!("    Spent:    {}/{} outputs", palette.value(spent), palette.value(total));
+            }
+
+            if let Some(event) = channel_event {
+                println!("    Lightning: {}", palette.warning(event.label()));
+            }
 
-            if tx.fee > 0 {
-                println!("    Fee:      {} sats", tx.fee);
+            if args.witness {
+                for (i, input) in tx.vin.iter().enumerate().filter(|(_, input)| !input.is_coinbase) {
+                    let spent = input
+                        .prevout
+                        .as_ref()
+                        .map_or(scripts::ScriptType::Unknown, |prevout| scripts::classify(&prevout.scriptpubkey));
+                    println!("    Input {} witness ({}):", i, spent.label());
+                    for line in witness::describe(spent, &input.witness) {
+                        println!("      {}", line);
+                    }
+                }
             }
         }
+        drop(progress);
+
+        if args.outspends {
+            if let Err(err) = capabilities.require(Capability::Outspends) {
+                report.note(err);
+            }
+        }
+
+        if block.tx_count > window.end && !report.is_plain() && !report.is_json() {
+            println!("\n... and {} more transactions", block.tx_count - window.end);
+            println!("(use --offset {} for the next page, or --all to list the rest)", window.end);
+        }
 
-        if block.tx_count > args.limit {
-            println!("\n... and {} more transactions", block.tx_count - args.limit);
-            println!("(use --limit to show more)");
+        if !report.is_plain() && !report.is_json() {
+            println!();
         }
+        report.end();
+    }
 
-        println!("\n╚════════════════════════════════════════════════════════════════════");
+    if let Some((export_format, path)) = &export {
+        std::fs::write(path, document.render(*export_format)).map_err(|e| {
+            ExplorerError::InvalidArgument(format!("Could not write report to {}: {}", path.display(), e))
+        })?;
+        report.status(format!("\nReport written to {}", path.display()));
     }
 
-    println!("\n✓ Query completed successfully!");
+    report.status("\n✓ Query completed successfully!");
+    report.finish();
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_parse_fees_at() {
+        let args = Args::try_parse_from(["block-explorer", "--network", "bitcoin", "fees", "at", "800000"]).unwrap();
+        assert!(matches!(args.command, Some(Command::Fees { query: FeesQuery::At { height: 800_000 } })));
+        assert!(Args::try_parse_from(["block-explorer", "fees", "at", "tip"]).is_err());
+    }
+
     #[test]
     fn test_validate_network_testnet() {
         assert_eq!(validate_network("testnet"), Ok("testnet"));
@@ -392,6 +1274,42 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_block_identifier_hash_prefix() {
+        assert_eq!(
+            parse_block_identifier("0000000000000093bcb6"),
+            BlockIdentifier::HashPrefix("0000000000000093bcb6".to_string())
+        );
+        // Zero-padded prefixes must not be mistaken for heights
+        assert_eq!(
+            parse_block_identifier("000000000000"),
+            BlockIdentifier::HashPrefix("000000000000".to_string())
+        );
+        assert_eq!(
+            parse_block_identifier("0000000000000093BCB6"),
+            BlockIdentifier::HashPrefix("0000000000000093bcb6".to_string())
+        );
+        // Too short to be a prefix, and non-hex input, fall through unchanged
+        assert_eq!(
+            parse_block_identifier("00000000009"),
+            BlockIdentifier::Height(9)
+        );
+        assert_eq!(
+            parse_block_identifier("not-a-hash-at-all"),
+            BlockIdentifier::Hash("not-a-hash-at-all".to_string())
+        );
+    }
+
+    #[test]
+    fn test_parse_height_range() {
+        assert_eq!(parse_height_range("800000..800010"), Ok(Some((800000, 800010))));
+        assert_eq!(parse_height_range("5..5"), Ok(Some((5, 5))));
+        assert_eq!(parse_height_range("800000"), Ok(None));
+        assert!(parse_height_range("10..5").is_err());
+        assert!(parse_height_range("a..b").is_err());
+        assert!(parse_height_range("..5").is_err());
+    }
+
     #[test]
     fn test_format_network_name() {
         assert_eq!(format_network_name("mainnet"), "Bitcoin Mainnet");
@@ -399,110 +1317,65 @@ mod tests {
     }
 
     #[test]
-    fn test_sats_to_btc() {
-        assert_eq!(sats_to_btc(100_000_000), 1.0);
-        assert_eq!(sats_to_btc(50_000_000), 0.5);
-        assert_eq!(sats_to_btc(0), 0.0);
-        assert_eq!(sats_to_btc(1), 0.00000001);
-        assert_eq!(sats_to_btc(2_449_190), 0.02449190);
+    fn test_confirmations() {
+        assert_eq!(confirmations(840_000, 840_000), 1);
+        assert_eq!(confirmations(840_005, 840_000), 6);
+        // A tip fetched before the block propagated to this backend
+        assert_eq!(confirmations(839_999, 840_000), 0);
     }
 
     #[test]
     fn test_is_coinbase_tx() {
-        // Test coinbase transaction
-        let coinbase_tx = Transaction {
-            txid: "test".to_string(),
-            version: 1,
-            locktime: 0,
-            vin: vec![Input {
-                txid: "0000000000000000000000000000000000000000000000000000000000000000".to_string(),
-                vout: 0,
-                is_coinbase: true,
-                scriptsig: "".to_string(),
-                sequence: 0,
-            }],
-            vout: vec![],
-            size: 0,
-            weight: 0,
-            fee: 0,
-            status: TxStatus {
-                confirmed: true,
-                block_height: Some(123),
-            },
-        };
-        assert!(is_coinbase_tx(&coinbase_tx));
-
-        // Test non-coinbase transaction
-        let regular_tx = Transaction {
-            txid: "test".to_string(),
-            version: 1,
-            locktime: 0,
-            vin: vec![Input {
-                txid: "abc123".to_string(),
-                vout: 0,
-                is_coinbase: false,
-                scriptsig: "".to_string(),
-                sequence: 0,
-            }],
-            vout: vec![],
-            size: 0,
-            weight: 0,
-            fee: 0,
-            status: TxStatus {
-                confirmed: true,
-                block_height: Some(123),
-            },
-        };
-        assert!(!is_coinbase_tx(&regular_tx));
+        let coinbase = Input { is_coinbase: true, ..test_util::input(&"00".repeat(32), None) };
+        assert!(is_coinbase_tx(&test_util::tx("test", vec![coinbase], vec![])));
+        let regular = test_util::input("abc123", None);
+        assert!(!is_coinbase_tx(&test_util::tx("test", vec![regular], vec![])));
     }
 
     #[test]
     fn test_calculate_total_output() {
-        let tx = Transaction {
-            txid: "test".to_string(),
-            version: 1,
-            locktime: 0,
-            vin: vec![],
-            vout: vec![
-                Output {
-                    value: 100_000,
-                    scriptpubkey: "".to_string(),
-                },
-                Output {
-                    value: 200_000,
-                    scriptpubkey: "".to_string(),
-                },
-                Output {
-                    value: 50_000,
-                    scriptpubkey: "".to_string(),
-                },
-            ],
-            size: 0,
-            weight: 0,
-            fee: 0,
-            status: TxStatus {
-                confirmed: true,
-                block_height: Some(123),
-            },
-        };
-        assert_eq!(calculate_total_output(&tx), 350_000);
-
-        // Test with no outputs
-        let empty_tx = Transaction {
-            txid: "test".to_string(),
-            version: 1,
-            locktime: 0,
-            vin: vec![],
-            vout: vec![],
-            size: 0,
-            weight: 0,
-            fee: 0,
-            status: TxStatus {
-                confirmed: true,
-                block_height: Some(123),
-            },
-        };
-        assert_eq!(calculate_total_output(&empty_tx), 0);
+        let outputs = [100_000, 200_000, 50_000].map(|value| test_util::output("", value));
+        assert_eq!(calculate_total_output(&test_util::tx("test", vec![], outputs.into())), 350_000);
+        assert_eq!(calculate_total_output(&test_util::tx("test", vec![], vec![])), 0);
+    }
+
+    #[test]
+    fn test_calculate_fee_from_prevouts() {
+        let output = |value| test_util::output("", value);
+        let input = |prevout| test_util::input("abc123", prevout);
+        let mut tx = test_util::tx(
+            "test",
+            vec![input(Some(output(60_000))), input(Some(output(50_000)))],
+            vec![output(100_000), output(7_000)],
+        );
+        // The backend's zero fee field is ignored
+        assert_eq!(calculate_fee(&tx), Some(3_000));
+        assert_eq!(transaction_fee(&tx), Some(3_000));
+
+        // Without every prevout, fall back to the reported fee
+        tx.vin.push(input(None));
+        assert_eq!(calculate_fee(&tx), None);
+        assert_eq!(transaction_fee(&tx), None);
+        tx.fee = 2_500;
+        assert_eq!(transaction_fee(&tx), Some(2_500));
+
+        // Coinbase transactions pay no fee
+        tx.vin = vec![Input { is_coinbase: true, ..input(None) }];
+        assert_eq!(transaction_fee(&tx), None);
+    }
+
+    #[test]
+    fn test_type_summary() {
+        let p2wpkh = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+        let p2tr = "51200f9dab1a72f7c48da8a1df2f913bef649bfc0d77072dffd11329b8048293d7a3";
+        let output = |script| test_util::output(script, 1_000);
+        let input = |prevout| test_util::input("abc123", prevout);
+        let tx = test_util::tx(
+            "test",
+            vec![input(Some(output(p2wpkh))), input(Some(output(p2wpkh))), input(None)],
+            vec![output(p2tr), output(p2wpkh)],
+        );
+        assert_eq!(type_summary(&tx), "in: 2×P2WPKH 1×UNKNOWN, out: 1×P2TR 1×P2WPKH");
     }
 
     #[test]
@@ -594,4 +1467,50 @@ mod tests {
         assert_eq!(tx.vout.len(), 1);
         assert!(tx.vin[0].is_coinbase);
     }
+
+    #[test]
+    fn test_count_spent() {
+        let json = r#"[{"spent": true, "txid": "abc", "vin": 0}, {"spent": false}, {"spent": true}]"#;
+        let outspends: Vec<Outspend> = serde_json::from_str(json).unwrap();
+        assert_eq!(count_spent(&outspends), 2);
+        assert_eq!(count_spent(&[]), 0);
+    }
+
+    #[test]
+    fn test_fee_rate_for_target() {
+        let json = r#"{"1": 25.3, "6": 12.0, "144": 1.01}"#;
+        let estimates: HashMap<String, f64> = serde_json::from_str(json).unwrap();
+        assert_eq!(fee_rate_for_target(&estimates, 1), Some(25.3));
+        assert_eq!(fee_rate_for_target(&estimates, 144), Some(1.01));
+        assert_eq!(fee_rate_for_target(&estimates, 3), None);
+    }
+
+    #[test]
+    fn test_tx_window() {
+        assert_eq!(tx_window(0, Some(10), 3000), 0..10);
+        assert_eq!(tx_window(25, Some(10), 3000), 25..35);
+        assert_eq!(tx_window(2995, Some(10), 3000), 2995..3000);
+        assert_eq!(tx_window(5000, Some(10), 3000), 3000..3000);
+        assert_eq!(tx_window(100, None, 3000), 100..3000);
+        assert_eq!(tx_window(0, Some(usize::MAX), 5), 0..5);
+    }
+
+    #[test]
+    fn test_audit_summary_deserialization() {
+        let json = r#"{
+            "height": 840000,
+            "id": "0000000000000000000320283a032748cef8227873ff4872689bf23f1cda83a5",
+            "matchRate": 98.5,
+            "missingTxs": ["aa", "bb"],
+            "addedTxs": ["cc"]
+        }"#;
+        let audit: AuditSummary = serde_json::from_str(json).unwrap();
+        assert_eq!(audit.match_rate, Some(98.5));
+        assert_eq!(audit.missing_txs.len(), 2);
+        assert_eq!(audit.added_txs.len(), 1);
+
+        let audit: AuditSummary = serde_json::from_str("{}").unwrap();
+        assert!(audit.match_rate.is_none());
+        assert!(audit.missing_txs.is_empty());
+    }
 }
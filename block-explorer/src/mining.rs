@@ -0,0 +1,248 @@
+//! Per-miner statistics over a block range, from coinbase tags and payouts
+
+use crate::error::ExplorerError;
+use crate::esplora::EsploraClient;
+use crate::output::{plain_row, Report};
+use crate::progress::Progress;
+use crate::{fetch_block_tx_range, BlockInfo, OutputFormat, Transaction};
+use serde::Serialize;
+use std::collections::HashMap;
+
+/// Coinbase tag fragments of well-known pools, matched case-insensitively
+const POOL_TAGS: [(&str, &str); 16] = [
+    ("foundry usa", "Foundry USA"),
+    ("antpool", "AntPool"),
+    ("f2pool", "F2Pool"),
+    ("viabtc", "ViaBTC"),
+    ("binance", "Binance Pool"),
+    ("mara pool", "MARA Pool"),
+    ("spiderpool", "SpiderPool"),
+    ("luxor", "Luxor"),
+    ("braiins", "Braiins Pool"),
+    ("slush", "Braiins Pool"),
+    ("poolin", "Poolin"),
+    ("btc.com", "BTC.com"),
+    ("sbicrypto", "SBI Crypto"),
+    ("ocean", "OCEAN"),
+    ("secpool", "SECPOOL"),
+    ("ckpool", "Solo CK"),
+];
+
+/// Printable runs shorter than this in the coinbase script are noise
+const MIN_TAG_LEN: usize = 4;
+
+/// Maximum block weight, for fullness
+const MAX_BLOCK_WEIGHT: usize = 4_000_000;
+
+/// Blocks between subsidy halvings
+const HALVING_INTERVAL: u32 = 210_000;
+
+/// Aggregated numbers for one miner
+#[derive(Debug, Default, PartialEq, Serialize)]
+pub struct MinerStats {
+    pub miner: String,
+    pub blocks: u32,
+    pub subsidy: u64,
+    pub fees: u64,
+    /// Mean block weight as a percentage of the 4M WU limit
+    pub avg_fullness: f64,
+}
+
+/// Block subsidy in sats at the given height
+pub fn block_subsidy(height: u32) -> u64 {
+    let halvings = height / HALVING_INTERVAL;
+    if halvings >= 64 {
+        0
+    } else {
+        (50 * 100_000_000u64) >> halvings
+    }
+}
+
+/// Printable ASCII runs in a hex coinbase script, e.g. "/Foundry USA Pool #dropgold/"
+pub fn coinbase_tag(scriptsig_hex: &str) -> String {
+    let bytes: Vec<u8> = (0..scriptsig_hex.len() / 2)
+        .filter_map(|i| u8::from_str_radix(&scriptsig_hex[i * 2..i * 2 + 2], 16).ok())
+        .collect();
+
+    bytes
+        .split(|b| !(0x20..0x7f).contains(b))
+        .filter(|run| run.len() >= MIN_TAG_LEN)
+        .map(|run| String::from_utf8_lossy(run).trim().to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Names the miner from the coinbase tag, falling back to the first payout
+/// address so unknown miners still group by wallet
+pub fn identify_miner(coinbase: &Transaction) -> String {
+    let tag = coinbase.vin.first().map(|input| coinbase_tag(&input.scriptsig)).unwrap_or_default();
+    let lower = tag.to_lowercase();
+    if let Some((_, name)) = POOL_TAGS.iter().find(|(fragment, _)| lower.contains(fragment)) {
+        return name.to_string();
+    }
+
+    match coinbase.vout.iter().find(|o| o.value > 0).and_then(|o| o.scriptpubkey_address.as_ref()) {
+        Some(address) => format!("Unknown ({})", address),
+        None => "Unknown".to_string(),
+    }
+}
+
+/// Adds one block to the running totals
+fn record(stats: &mut HashMap<String, (MinerStats, usize)>, block: &BlockInfo, coinbase: &Transaction) {
+    let miner = identify_miner(coinbase);
+    let reward: u64 = coinbase.vout.iter().map(|o| o.value).sum();
+    let subsidy = block_subsidy(block.height);
+
+    let (entry, total_weight) = stats
+        .entry(miner.clone())
+        .or_insert_with(|| (MinerStats { miner, ..Default::default() }, 0));
+    entry.blocks += 1;
+    // A miner may claim less than the full subsidy, never more
+    entry.subsidy += reward.min(subsidy);
+    entry.fees += reward.saturating_sub(subsidy);
+    *total_weight += block.weight;
+}
+
+/// Final per-miner rows, most blocks first
+fn summarize(stats: HashMap<String, (MinerStats, usize)>) -> Vec<MinerStats> {
+    let mut rows: Vec<MinerStats> = stats
+        .into_values()
+        .map(|(mut row, total_weight)| {
+            row.avg_fullness = total_weight as f64 * 100.0 / (row.blocks as usize * MAX_BLOCK_WEIGHT) as f64;
+            row
+        })
+        .collect();
+    rows.sort_by(|a, b| b.blocks.cmp(&a.blocks).then_with(|| a.miner.cmp(&b.miner)));
+    rows
+}
+
+/// Fetches each block's coinbase in `start..=end` and prints the pool distribution
+pub fn run(
+    client: &EsploraClient,
+    (start, end): (u32, u32),
+    format: OutputFormat,
+    report: Report,
+) -> Result<(), ExplorerError> {
+    if format == OutputFormat::Text {
+        report.status(format!("Collecting coinbase transactions for blocks {}–{}...", start, end));
+    }
+
+    let progress = Progress::new(client, u64::from(end - start) + 1, "blocks", report.shows_progress());
+    let mut stats = HashMap::new();
+    for height in start..=end {
+        let block = crate::batch::query_block(client, &height.to_string())?;
+        let coinbase = fetch_block_tx_range(client, &block.id, 0..1)
+            .map_err(|e| ExplorerError::from_request("Could not fetch coinbase transaction", e))?
+            .into_iter()
+            .next()
+            .ok_or_else(|| ExplorerError::Parse(format!("Block {} has no transactions", block.id)))?;
+        record(&mut stats, &block, &coinbase);
+        progress.tick();
+    }
+    drop(progress);
+
+    let total_blocks = end - start + 1;
+    let rows = summarize(stats);
+    let title = format!("MINING STATISTICS ({} blocks)", total_blocks);
+    match format {
+        OutputFormat::Text if report.is_plain() => {
+            report.section(&title, "mining_stats");
+            println!("# miner\tblocks\tsubsidy\tfees\tavg_fullness");
+            for row in &rows {
+                println!("{}", plain_row(&[&row.miner, &row.blocks, &row.subsidy, &row.fees, &format!("{:.1}", row.avg_fullness)]));
+            }
+        }
+        OutputFormat::Text => {
+            report.section(&title, "mining_stats");
+            let unit = report.unit();
+            println!("║ {:<24} {:>6} {:>7} {:>18} {:>16} {:>6}", "Miner", "Blocks", "Share",
+                     format!("Subsidy ({})", unit.label()), format!("Fees ({})", unit.label()), "Full");
+            for row in &rows {
+                println!("║ {:<24} {:>6} {:>6.1}% {:>18} {:>16} {:>5.1}%",
+                         row.miner, row.blocks, row.blocks as f64 * 100.0 / total_blocks as f64,
+                         unit.number(row.subsidy), unit.number(row.fees), row.avg_fullness);
+            }
+            report.end();
+        }
+        OutputFormat::Json => {
+            for row in &rows {
+                println!("{}", serde_json::to_string(row).expect("miner stats always serialize"));
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{input, output, tx};
+    use crate::{Input, Output};
+
+    fn coinbase(scriptsig_hex: &str, address: Option<&str>, reward: u64) -> Transaction {
+        let vin =
+            Input { vout: u32::MAX, is_coinbase: true, scriptsig: scriptsig_hex.to_string(), ..input(&"00".repeat(32), None) };
+        let vout = Output { scriptpubkey_address: address.map(str::to_string), ..output("", reward) };
+        Transaction { version: 1, ..tx("cb", vec![vin], vec![vout]) }
+    }
+
+    fn block(height: u32, weight: usize) -> BlockInfo {
+        serde_json::from_value(serde_json::json!({
+            "id": "00", "height": height, "version": 1, "timestamp": 0, "tx_count": 1,
+            "size": 0, "weight": weight, "merkle_root": "", "previousblockhash": null,
+            "nonce": 0, "bits": 0, "difficulty": 1.0
+        }))
+        .unwrap()
+    }
+
+    fn hex(text: &str) -> String {
+        text.bytes().map(|b| format!("{:02x}", b)).collect()
+    }
+
+    #[test]
+    fn test_block_subsidy() {
+        assert_eq!(block_subsidy(0), 5_000_000_000);
+        assert_eq!(block_subsidy(209_999), 5_000_000_000);
+        assert_eq!(block_subsidy(210_000), 2_500_000_000);
+        assert_eq!(block_subsidy(840_000), 312_500_000);
+        assert_eq!(block_subsidy(64 * 210_000), 0);
+    }
+
+    #[test]
+    fn test_coinbase_tag_skips_binary_noise() {
+        // Height push, then the pool tag
+        let script = format!("03c0cf0c{}00ff", hex("/Foundry USA Pool #dropgold/"));
+        assert_eq!(coinbase_tag(&script), "/Foundry USA Pool #dropgold/");
+        assert_eq!(coinbase_tag("03c0cf0c"), "");
+    }
+
+    #[test]
+    fn test_identify_miner() {
+        let tagged = coinbase(&format!("03c0cf0c{}", hex("Mined by AntPool")), None, 0);
+        assert_eq!(identify_miner(&tagged), "AntPool");
+
+        let untagged = coinbase("03c0cf0c", Some("bc1qminer"), 100);
+        assert_eq!(identify_miner(&untagged), "Unknown (bc1qminer)");
+
+        assert_eq!(identify_miner(&coinbase("", None, 100)), "Unknown");
+    }
+
+    #[test]
+    fn test_stats_aggregate_subsidy_fees_and_fullness() {
+        let pool = format!("03{}", hex("/ViaBTC/"));
+        let mut stats = HashMap::new();
+        record(&mut stats, &block(840_000, 4_000_000), &coinbase(&pool, None, 312_500_000 + 20_000_000));
+        record(&mut stats, &block(840_001, 2_000_000), &coinbase(&pool, None, 312_500_000 + 10_000_000));
+        record(&mut stats, &block(840_002, 1_000_000), &coinbase("", Some("bc1qsolo"), 312_500_000));
+
+        let rows = summarize(stats);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].miner, "ViaBTC");
+        assert_eq!(rows[0].blocks, 2);
+        assert_eq!(rows[0].subsidy, 625_000_000);
+        assert_eq!(rows[0].fees, 30_000_000);
+        assert_eq!(rows[0].avg_fullness, 75.0);
+        assert_eq!(rows[1].miner, "Unknown (bc1qsolo)");
+        assert_eq!(rows[1].avg_fullness, 25.0);
+    }
+}
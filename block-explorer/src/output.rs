@@ -0,0 +1,288 @@
+//! Report rendering: the boxed layout for people, `--plain` lines for
+//! grep/awk pipelines, or one JSON object for `--format json`
+//!
+//! Plain mode has no box drawing, symbols, or color. Each section starts with
+//! a `# name` line, fields are `key: value`, and tabular data is one
+//! tab-separated row per item under a `# col1<TAB>col2…` header.
+//!
+//! JSON mode gathers the same sections and fields into an object keyed by
+//! section name, printed once the report is finished. Tabular sections become
+//! arrays of records.
+
+use crate::color::Palette;
+use serde_json::{Map, Value};
+use std::cell::RefCell;
+use std::fmt::Display;
+use std::rc::Rc;
+use units::Unit;
+
+const RULE: &str = "════════════════════════════════════════════════════════════════════";
+
+#[derive(Debug, Clone)]
+pub struct Report {
+    plain: bool,
+    palette: Palette,
+    unit: Unit,
+    progress: bool,
+    json: Option<Rc<RefCell<JsonReport>>>,
+}
+
+/// Sections gathered so far in JSON mode, and the one being filled
+#[derive(Debug, Default)]
+struct JsonReport {
+    root: Map<String, Value>,
+    section: Option<String>,
+}
+
+impl JsonReport {
+    /// The open section; fields given outside one go under an empty key
+    fn target(&mut self) -> &mut Value {
+        let key = self.section.clone().unwrap_or_default();
+        self.root.entry(key).or_insert_with(|| Value::Object(Map::new()))
+    }
+}
+
+impl Report {
+    /// Plain reports never colorize, whatever `--color` says
+    pub fn new(plain: bool, palette: Palette) -> Self {
+        Report {
+            plain,
+            palette: if plain { Palette::plain() } else { palette },
+            unit: Unit::default(),
+            progress: false,
+            json: None,
+        }
+    }
+
+    /// Gathers sections into one JSON object instead of printing them
+    pub fn with_json(self, json: bool) -> Self {
+        Report { json: json.then(Default::default), ..self }
+    }
+
+    /// Unit for amounts in the boxed layout; plain output stays in sats
+    pub fn with_unit(self, unit: Unit) -> Self {
+        Report { unit, ..self }
+    }
+
+    /// Whether long fetches may draw a progress bar on stderr
+    pub fn with_progress(self, progress: bool) -> Self {
+        Report { progress, ..self }
+    }
+
+    pub fn is_plain(&self) -> bool {
+        self.plain
+    }
+
+    pub fn is_json(&self) -> bool {
+        self.json.is_some()
+    }
+
+    pub fn palette(&self) -> Palette {
+        self.palette
+    }
+
+    pub fn unit(&self) -> Unit {
+        self.unit
+    }
+
+    pub fn shows_progress(&self) -> bool {
+        self.progress
+    }
+
+    /// Opens a section; `key` names it in plain and JSON mode
+    pub fn section(&self, title: &str, key: &str) {
+        if let Some(json) = &self.json {
+            let mut json = json.borrow_mut();
+            json.root.insert(key.to_string(), Value::Object(Map::new()));
+            json.section = Some(key.to_string());
+        } else if self.plain {
+            println!("# {}", key);
+        } else {
+            println!("\n╔{}", RULE);
+            println!("║ {}", title);
+            println!("╠{}", RULE);
+        }
+    }
+
+    pub fn end(&self) {
+        if let Some(json) = &self.json {
+            json.borrow_mut().section = None;
+        } else if !self.plain {
+            println!("╚{}", RULE);
+        }
+    }
+
+    /// A field: the preformatted boxed line, or `key: value` in plain mode.
+    /// In JSON mode a key given twice in a section collects its values in an
+    /// array.
+    pub fn field(&self, boxed: impl Display, key: &str, value: impl Display) {
+        if let Some(json) = &self.json {
+            let value = json_value(value.to_string());
+            let mut json = json.borrow_mut();
+            if let Value::Object(fields) = json.target() {
+                match fields.get_mut(key) {
+                    Some(Value::Array(values)) => values.push(value),
+                    Some(first) => *first = Value::Array(vec![first.take(), value]),
+                    None => {
+                        fields.insert(key.to_string(), value);
+                    }
+                }
+            }
+        } else if self.plain {
+            println!("{}", plain_field(key, value));
+        } else {
+            println!("║ {}", boxed);
+        }
+    }
+
+    /// One record of a tabular section; only JSON mode keeps them here, the
+    /// text layouts print their own rows
+    pub fn record(&self, record: Value) {
+        if let Some(json) = &self.json {
+            let mut json = json.borrow_mut();
+            let target = json.target();
+            if !target.is_array() {
+                *target = Value::Array(Vec::new());
+            }
+            if let Value::Array(records) = target {
+                records.push(record);
+            }
+        }
+    }
+
+    /// A line that only makes sense in the boxed layout
+    pub fn line(&self, boxed: impl Display) {
+        if !self.plain && !self.is_json() {
+            println!("║ {}", boxed);
+        }
+    }
+
+    /// Progress and banner text, omitted from plain and JSON output
+    pub fn status(&self, text: impl Display) {
+        if !self.plain && !self.is_json() {
+            println!("{}", text);
+        }
+    }
+
+    /// A backend limitation or similar aside
+    pub fn note(&self, text: impl Display) {
+        if let Some(json) = &self.json {
+            let mut json = json.borrow_mut();
+            let notes = json.root.entry("notes").or_insert_with(|| Value::Array(Vec::new()));
+            if let Value::Array(notes) = notes {
+                notes.push(Value::String(text.to_string()));
+            }
+        } else if self.plain {
+            println!("# note: {}", text);
+        } else {
+            println!("\n{}", self.palette.warning(format!("Note: {}", text)));
+        }
+    }
+
+    /// Prints what JSON mode gathered, on one line; the text layouts have
+    /// already printed everything
+    pub fn finish(&self) {
+        if let Some(json) = &self.json {
+            let root = Value::Object(std::mem::take(&mut json.borrow_mut().root));
+            println!("{}", root);
+        }
+    }
+}
+
+/// A field value as JSON: integers that fit a u64 or i64, decimals, and
+/// booleans keep their type; anything else, including larger integers such
+/// as chainwork and digit-only hashes with leading zeros, stays a string
+fn json_value(text: String) -> Value {
+    let digits = text.trim_start_matches('-');
+    if digits.len() > 1 && digits.starts_with('0') && !digits.starts_with("0.") {
+        return Value::String(text);
+    }
+    if let Ok(number) = text.parse::<u64>() {
+        return number.into();
+    }
+    if let Ok(number) = text.parse::<i64>() {
+        return number.into();
+    }
+    if text.contains('.') {
+        if let Some(number) = text.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+            return Value::Number(number);
+        }
+    }
+    match text.as_str() {
+        "true" => Value::Bool(true),
+        "false" => Value::Bool(false),
+        _ => Value::String(text),
+    }
+}
+
+/// `key: value` with the value kept on one line
+pub fn plain_field(key: &str, value: impl Display) -> String {
+    format!("{}: {}", key, one_line(&value.to_string()))
+}
+
+/// Tab-separated row; tabs and newlines inside values become spaces
+pub fn plain_row(values: &[&dyn Display]) -> String {
+    values
+        .iter()
+        .map(|value| one_line(&value.to_string()).replace('\t', " "))
+        .collect::<Vec<_>>()
+        .join("\t")
+}
+
+fn one_line(text: &str) -> String {
+    text.replace(['\n', '\r'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_plain_field() {
+        assert_eq!(plain_field("height", 800000), "height: 800000");
+        assert_eq!(plain_field("note", "two\nlines"), "note: two lines");
+    }
+
+    #[test]
+    fn test_json_value_keeps_types() {
+        assert_eq!(json_value("800000".to_string()), serde_json::json!(800000));
+        assert_eq!(json_value("-1".to_string()), serde_json::json!(-1));
+        assert_eq!(json_value("12.5".to_string()), serde_json::json!(12.5));
+        assert_eq!(json_value("false".to_string()), serde_json::json!(false));
+        assert_eq!(json_value("1000000000000000000000000".to_string()), serde_json::json!("1000000000000000000000000"));
+        assert_eq!(json_value("00000000a1".to_string()), serde_json::json!("00000000a1"));
+        assert_eq!(json_value("0006".to_string()), serde_json::json!("0006"));
+        assert_eq!(json_value("0.5".to_string()), serde_json::json!(0.5));
+    }
+
+    #[test]
+    fn test_json_report_gathers_sections() {
+        let report = Report::new(false, Palette::plain()).with_json(true);
+        report.section("BLOCK INFORMATION", "block");
+        report.field("Height: 0", "height", 0);
+        report.field("", "coinjoin", "a");
+        report.field("", "coinjoin", "b");
+        report.end();
+        report.section("TRANSACTIONS", "transactions");
+        report.record(serde_json::json!({ "index": 1 }));
+        report.end();
+        report.note("no audit");
+
+        let root = report.json.as_ref().unwrap().borrow().root.clone();
+        assert_eq!(
+            Value::Object(root),
+            serde_json::json!({
+                "block": { "height": 0, "coinjoin": ["a", "b"] },
+                "transactions": [{ "index": 1 }],
+                "notes": ["no audit"]
+            })
+        );
+    }
+
+    #[test]
+    fn test_plain_row() {
+        assert_eq!(plain_row(&[&1, &"abc", &2.5]), "1\tabc\t2.5");
+        assert_eq!(plain_row(&[&"a\tb", &"c"]), "a b\tc");
+        assert_eq!(plain_row(&[]), "");
+    }
+}
@@ -0,0 +1,83 @@
+//! Progress bar on stderr for long fetches: transaction streams and block
+//! range scans
+//!
+//! The bar shows items done, ETA, and the HTTP request rate, and is hidden
+//! entirely when stderr is not a terminal so logs and pipes stay clean.
+
+use crate::esplora::EsploraClient;
+use indicatif::{ProgressBar, ProgressDrawTarget, ProgressStyle};
+use std::io::IsTerminal;
+use std::time::Instant;
+
+const TEMPLATE: &str = "[{bar:30}] {pos}/{len} {prefix} · {msg} · ETA {eta}";
+
+pub struct Progress<'a> {
+    bar: ProgressBar,
+    client: &'a EsploraClient,
+    /// Requests already sent when the bar started, so the rate covers only this fetch
+    first_request: u64,
+    started: Instant,
+}
+
+impl<'a> Progress<'a> {
+    /// A bar over `len` items named `label` (e.g. "txs"), hidden unless
+    /// `enabled` and stderr is a terminal
+    pub fn new(client: &'a EsploraClient, len: u64, label: &str, enabled: bool) -> Self {
+        let bar = if enabled && std::io::stderr().is_terminal() {
+            let bar = ProgressBar::with_draw_target(Some(len), ProgressDrawTarget::stderr());
+            bar.set_style(ProgressStyle::with_template(TEMPLATE).expect("template is valid").progress_chars("=> "));
+            bar.set_message(request_rate(0, 0.0));
+            bar.set_prefix(label.to_string());
+            bar
+        } else {
+            ProgressBar::hidden()
+        };
+        Progress { bar, client, first_request: client.requests_sent(), started: Instant::now() }
+    }
+
+    /// Marks one more item done and refreshes the request rate
+    pub fn tick(&self) {
+        let requests = self.client.requests_sent() - self.first_request;
+        self.bar.set_message(request_rate(requests, self.started.elapsed().as_secs_f64()));
+        self.bar.inc(1);
+    }
+
+    /// Runs `f` with the bar cleared, for printing without garbling it
+    pub fn suspend<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.bar.suspend(f)
+    }
+}
+
+impl Drop for Progress<'_> {
+    fn drop(&mut self) {
+        self.bar.finish_and_clear();
+    }
+}
+
+fn request_rate(requests: u64, elapsed_secs: f64) -> String {
+    if elapsed_secs <= 0.0 {
+        return format!("{} req", requests);
+    }
+    format!("{:.1} req/s", requests as f64 / elapsed_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_rate() {
+        assert_eq!(request_rate(30, 4.0), "7.5 req/s");
+        assert_eq!(request_rate(0, 2.0), "0.0 req/s");
+        assert_eq!(request_rate(3, 0.0), "3 req");
+    }
+
+    #[test]
+    fn test_disabled_bar_is_hidden() {
+        let client = EsploraClient::new(&["http://127.0.0.1:1".to_string()], false);
+        let progress = Progress::new(&client, 10, "txs", false);
+        progress.tick();
+        assert!(progress.bar.is_hidden());
+        assert_eq!(progress.bar.position(), 1);
+    }
+}
@@ -0,0 +1,108 @@
+//! Request budget and pacing for `--max-requests` and `--rps`
+//!
+//! Every HTTP request the client makes, failover retries included, first
+//! asks the scheduler for a slot. Requests are spaced at least `1 / rps`
+//! apart, and once the budget is spent further requests are refused rather
+//! than sent.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant};
+
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    /// Minimum spacing between request starts
+    interval: Option<Duration>,
+    max_requests: Option<u64>,
+    sent: Cell<u64>,
+    last: Cell<Option<Instant>>,
+    /// Set on the first refused request, so the user is warned only once
+    refused: Cell<bool>,
+}
+
+impl Scheduler {
+    /// `rps` must be positive; None leaves that limit off
+    pub fn new(rps: Option<f64>, max_requests: Option<u64>) -> Result<Self, String> {
+        let interval = match rps {
+            Some(rps) if rps.is_finite() && rps > 0.0 => Some(Duration::from_secs_f64(1.0 / rps)),
+            Some(rps) => return Err(format!("Invalid --rps {}: must be a positive number", rps)),
+            None => None,
+        };
+        Ok(Scheduler { interval, max_requests, ..Default::default() })
+    }
+
+    /// Waits for the next slot; Err with the budget once it is spent
+    pub fn acquire(&self) -> Result<(), u64> {
+        if let Some(max) = self.max_requests {
+            if self.sent.get() >= max {
+                if !self.refused.replace(true) {
+                    eprintln!(
+                        "Warning: request budget of {} exhausted; skipping further requests (raise --max-requests to allow more)",
+                        max
+                    );
+                }
+                return Err(max);
+            }
+        }
+
+        if let Some(interval) = self.interval {
+            let wait = delay_needed(self.last.get(), Instant::now(), interval);
+            if !wait.is_zero() {
+                std::thread::sleep(wait);
+            }
+            self.last.set(Some(Instant::now()));
+        }
+        self.sent.set(self.sent.get() + 1);
+        Ok(())
+    }
+
+    /// Requests sent so far
+    pub fn sent(&self) -> u64 {
+        self.sent.get()
+    }
+}
+
+/// Time left before a request may start, given when the previous one did
+fn delay_needed(last: Option<Instant>, now: Instant, interval: Duration) -> Duration {
+    match last {
+        Some(last) => (last + interval).saturating_duration_since(now),
+        None => Duration::ZERO,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_delay_needed() {
+        let start = Instant::now();
+        let interval = Duration::from_millis(500);
+        assert_eq!(delay_needed(None, start, interval), Duration::ZERO);
+        assert_eq!(delay_needed(Some(start), start + Duration::from_millis(200), interval), Duration::from_millis(300));
+        assert_eq!(delay_needed(Some(start), start + Duration::from_secs(2), interval), Duration::ZERO);
+    }
+
+    #[test]
+    fn test_budget_is_enforced() {
+        let scheduler = Scheduler::new(None, Some(2)).unwrap();
+        assert_eq!(scheduler.acquire(), Ok(()));
+        assert_eq!(scheduler.acquire(), Ok(()));
+        assert_eq!(scheduler.acquire(), Err(2));
+        assert_eq!(scheduler.sent(), 2);
+    }
+
+    #[test]
+    fn test_rps_validation_and_pacing() {
+        assert!(Scheduler::new(Some(0.0), None).is_err());
+        assert!(Scheduler::new(Some(-1.0), None).is_err());
+        assert!(Scheduler::new(Some(f64::NAN), None).is_err());
+
+        let scheduler = Scheduler::new(Some(50.0), None).unwrap();
+        let start = Instant::now();
+        for _ in 0..3 {
+            scheduler.acquire().unwrap();
+        }
+        // Two gaps of 20ms between three requests
+        assert!(start.elapsed() >= Duration::from_millis(40));
+    }
+}
@@ -0,0 +1,137 @@
+//! Script classification and the compact per-transaction type summary,
+//! e.g. `in: 2×P2WPKH, out: 1×P2TR 1×P2WPKH`
+
+use bitcoin::ScriptBuf;
+
+/// Standard output script templates, plus catch-alls for everything else
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScriptType {
+    P2pk,
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    /// Witness program of a version or length with no standard meaning yet
+    WitnessUnknown,
+    Multisig,
+    OpReturn,
+    NonStandard,
+    /// The input of a coinbase transaction, which spends nothing
+    Coinbase,
+    /// An input whose prevout the backend did not return
+    Unknown,
+}
+
+impl ScriptType {
+    pub fn label(&self) -> &'static str {
+        match self {
+            ScriptType::P2pk => "P2PK",
+            ScriptType::P2pkh => "P2PKH",
+            ScriptType::P2sh => "P2SH",
+            ScriptType::P2wpkh => "P2WPKH",
+            ScriptType::P2wsh => "P2WSH",
+            ScriptType::P2tr => "P2TR",
+            ScriptType::WitnessUnknown => "WITNESS",
+            ScriptType::Multisig => "MULTISIG",
+            ScriptType::OpReturn => "OP_RETURN",
+            ScriptType::NonStandard => "NONSTANDARD",
+            ScriptType::Coinbase => "COINBASE",
+            ScriptType::Unknown => "UNKNOWN",
+        }
+    }
+}
+
+/// Classifies a hex-encoded scriptPubKey
+pub fn classify(script_hex: &str) -> ScriptType {
+    let script = match ScriptBuf::from_hex(script_hex) {
+        Ok(script) => script,
+        Err(_) => return ScriptType::NonStandard,
+    };
+
+    if script.is_p2wpkh() {
+        ScriptType::P2wpkh
+    } else if script.is_p2tr() {
+        ScriptType::P2tr
+    } else if script.is_p2wsh() {
+        ScriptType::P2wsh
+    } else if script.is_p2pkh() {
+        ScriptType::P2pkh
+    } else if script.is_p2sh() {
+        ScriptType::P2sh
+    } else if script.is_witness_program() {
+        ScriptType::WitnessUnknown
+    } else if script.is_op_return() {
+        ScriptType::OpReturn
+    } else if script.is_p2pk() {
+        ScriptType::P2pk
+    } else if script.is_multisig() {
+        ScriptType::Multisig
+    } else {
+        ScriptType::NonStandard
+    }
+}
+
+/// Counts each type in order of first appearance, e.g. `1×P2TR 2×P2WPKH`
+pub fn summarize(types: impl IntoIterator<Item = ScriptType>) -> String {
+    let mut counts: Vec<(ScriptType, usize)> = Vec::new();
+    for script_type in types {
+        match counts.iter_mut().find(|(t, _)| *t == script_type) {
+            Some((_, count)) => *count += 1,
+            None => counts.push((script_type, 1)),
+        }
+    }
+
+    if counts.is_empty() {
+        return "none".to_string();
+    }
+    counts
+        .iter()
+        .map(|(script_type, count)| format!("{}×{}", count, script_type.label()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_standard_scripts() {
+        assert_eq!(classify("0014751e76e8199196d454941c45d1b3a323f1433bd6"), ScriptType::P2wpkh);
+        assert_eq!(
+            classify("51200f9dab1a72f7c48da8a1df2f913bef649bfc0d77072dffd11329b8048293d7a3"),
+            ScriptType::P2tr
+        );
+        assert_eq!(
+            classify("00201863143c14c5166804bd19203356da136c985678cd4d27a1b8c6329604903262"),
+            ScriptType::P2wsh
+        );
+        assert_eq!(classify("76a91462e907b15cbf27d5425399ebf6f0fb50ebb88f1888ac"), ScriptType::P2pkh);
+        assert_eq!(classify("a914b472a266d0bd89c13706a4132ccfb16f7c3b9fcb87"), ScriptType::P2sh);
+        assert_eq!(classify("6a0b68656c6c6f20776f726c64"), ScriptType::OpReturn);
+        // Genesis coinbase output
+        assert_eq!(
+            classify("4104678afdb0fe5548271967f1a67130b7105cd6a828e03909a67962e0ea1f61deb649f6bc3f4cef38c4f35504e51ec112de5c384df7ba0b8d578a4c702b6bf11d5fac"),
+            ScriptType::P2pk
+        );
+    }
+
+    #[test]
+    fn test_classify_other_scripts() {
+        // P2A anchor: witness v1 with a 2-byte program
+        assert_eq!(classify("51024e73"), ScriptType::WitnessUnknown);
+        assert_eq!(classify("51"), ScriptType::NonStandard);
+        assert_eq!(classify("not hex"), ScriptType::NonStandard);
+    }
+
+    #[test]
+    fn test_summarize_counts_in_first_seen_order() {
+        assert_eq!(
+            summarize([ScriptType::P2tr, ScriptType::P2wpkh, ScriptType::P2tr]),
+            "2×P2TR 1×P2WPKH"
+        );
+        assert_eq!(summarize([ScriptType::Coinbase]), "1×COINBASE");
+        assert_eq!(summarize([]), "none");
+    }
+}
@@ -0,0 +1,228 @@
+//! Scans a range of blocks for transactions that touch an address
+
+use bitcoin::address::{Address, NetworkUnchecked};
+use bitcoin::Network;
+use crate::error::ExplorerError;
+use crate::esplora::EsploraClient;
+use crate::output::{plain_row, Report};
+use crate::progress::Progress;
+use crate::{fetch_block_txs, BlockInfo, OutputFormat, Transaction};
+use serde::Serialize;
+use units::Unit;
+
+/// Net flow of a transaction from the address's point of view
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Direction {
+    Received,
+    Sent,
+}
+
+impl Direction {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Direction::Received => "received",
+            Direction::Sent => "sent",
+        }
+    }
+}
+
+/// A transaction that pays to or spends from the searched address
+#[derive(Debug, PartialEq, Serialize)]
+pub struct AddressMatch {
+    pub height: u32,
+    pub block_hash: String,
+    pub txid: String,
+    pub direction: Direction,
+    /// Sats paid to the address by this transaction
+    pub received: u64,
+    /// Sats spent from the address by this transaction
+    pub sent: u64,
+}
+
+impl AddressMatch {
+    /// Received minus sent, so change back to the same address nets out
+    pub fn net(&self) -> i64 {
+        self.received as i64 - self.sent as i64
+    }
+}
+
+/// Hex scriptPubKey of an address, checked against the explorer's network
+pub fn address_script_hex(address: &str, network: &str) -> Result<String, String> {
+    let expected = if network == "mainnet" { Network::Bitcoin } else { Network::Testnet };
+    address
+        .parse::<Address<NetworkUnchecked>>()
+        .map_err(|e| format!("Invalid address '{}': {}", address, e))?
+        .require_network(expected)
+        .map(|address| address.script_pubkey().to_hex_string())
+        .map_err(|_| format!("Address {} is not valid for {}", address, network))
+}
+
+/// Sums what a transaction pays to and spends from `script_hex`, or None if
+/// the script doesn't appear in it
+pub fn match_tx(tx: &Transaction, script_hex: &str) -> Option<(u64, u64)> {
+    let outputs: Vec<u64> = tx
+        .vout
+        .iter()
+        .filter(|output| output.scriptpubkey == script_hex)
+        .map(|output| output.value)
+        .collect();
+    let prevouts: Vec<u64> = tx
+        .vin
+        .iter()
+        .filter_map(|input| input.prevout.as_ref())
+        .filter(|prevout| prevout.scriptpubkey == script_hex)
+        .map(|prevout| prevout.value)
+        .collect();
+
+    (!outputs.is_empty() || !prevouts.is_empty()).then(|| (outputs.iter().sum(), prevouts.iter().sum()))
+}
+
+/// Collects every match in one block, in block order
+pub fn scan_block(block: &BlockInfo, txs: &[Transaction], script_hex: &str) -> Vec<AddressMatch> {
+    txs.iter()
+        .filter_map(|tx| {
+            let (received, sent) = match_tx(tx, script_hex)?;
+            Some(AddressMatch {
+                height: block.height,
+                block_hash: block.id.clone(),
+                txid: tx.txid.clone(),
+                direction: if received >= sent { Direction::Received } else { Direction::Sent },
+                received,
+                sent,
+            })
+        })
+        .collect()
+}
+
+/// Scans blocks `start..=end` and prints each match as it is found
+pub fn run(
+    client: &EsploraClient,
+    address: &str,
+    script_hex: &str,
+    (start, end): (u32, u32),
+    format: OutputFormat,
+    report: Report,
+) -> Result<(), ExplorerError> {
+    if format == OutputFormat::Text {
+        report.status(format!("Scanning blocks {}–{} for {}...", start, end, address));
+        report.section("ADDRESS ACTIVITY", "address_activity");
+        if report.is_plain() {
+            println!("# height\ttxid\tdirection\treceived\tsent\tnet");
+        }
+    }
+
+    let progress = Progress::new(client, u64::from(end - start) + 1, "blocks", report.shows_progress());
+    let mut found = 0;
+    for height in start..=end {
+        let block = crate::batch::query_block(client, &height.to_string())?;
+        let txs = fetch_block_txs(client, &block.id, block.tx_count)
+            .map_err(|e| ExplorerError::from_request("Could not fetch block transactions", e))?;
+
+        for found_match in scan_block(&block, &txs, script_hex) {
+            found += 1;
+            progress.suspend(|| match format {
+                OutputFormat::Text if report.is_plain() => println!("{}", plain_match(&found_match)),
+                OutputFormat::Text => println!("{}", format_text_match(&found_match, report.unit())),
+                OutputFormat::Json => println!(
+                    "{}",
+                    serde_json::to_string(&found_match).expect("matches always serialize")
+                ),
+            });
+        }
+        progress.tick();
+    }
+    drop(progress);
+
+    if format == OutputFormat::Text {
+        if found == 0 {
+            report.line("No transactions found");
+        }
+        report.end();
+        report.status(format!("\n{} matching transactions in {} blocks", found, end - start + 1));
+    }
+    Ok(())
+}
+
+fn format_text_match(found: &AddressMatch, unit: Unit) -> String {
+    format!(
+        "║ {:>8}  {}  {:<8}  {} {}",
+        found.height,
+        found.txid,
+        found.direction.name(),
+        unit.signed_number(found.net()),
+        unit.label()
+    )
+}
+
+fn plain_match(found: &AddressMatch) -> String {
+    plain_row(&[
+        &found.height,
+        &found.txid,
+        &found.direction.name(),
+        &found.received,
+        &found.sent,
+        &found.net(),
+    ])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{self, input, output};
+    use crate::Output;
+
+    const WATCHED: &str = "0014751e76e8199196d454941c45d1b3a323f1433bd6";
+    const OTHER: &str = "001400000000000000000000000000000000000000aa";
+
+    /// Spends each of `prevouts` and pays `outputs`
+    fn tx(txid: &str, prevouts: Vec<Output>, outputs: Vec<Output>) -> Transaction {
+        let vin = prevouts.into_iter().map(|prevout| input(&"00".repeat(32), Some(prevout))).collect();
+        test_util::tx(txid, vin, outputs)
+    }
+
+    #[test]
+    fn test_address_script_hex() {
+        assert_eq!(
+            address_script_hex("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx", "testnet").unwrap(),
+            WATCHED
+        );
+        assert!(address_script_hex("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx", "mainnet").is_err());
+        assert!(address_script_hex("not-an-address", "testnet").is_err());
+    }
+
+    #[test]
+    fn test_match_tx() {
+        let receive = tx("a", vec![output(OTHER, 10_000)], vec![output(WATCHED, 7_000), output(OTHER, 2_500)]);
+        assert_eq!(match_tx(&receive, WATCHED), Some((7_000, 0)));
+
+        let spend = tx("b", vec![output(WATCHED, 7_000)], vec![output(OTHER, 4_000), output(WATCHED, 2_500)]);
+        assert_eq!(match_tx(&spend, WATCHED), Some((2_500, 7_000)));
+
+        let unrelated = tx("c", vec![output(OTHER, 1_000)], vec![output(OTHER, 900)]);
+        assert_eq!(match_tx(&unrelated, WATCHED), None);
+    }
+
+    #[test]
+    fn test_scan_block_directions() {
+        let block: BlockInfo = serde_json::from_str(
+            r#"{"id":"00aa","height":42,"version":1,"timestamp":0,"tx_count":3,"size":0,"weight":0,
+                "merkle_root":"","previousblockhash":null,"nonce":0,"bits":486604799,"difficulty":1.0}"#,
+        )
+        .unwrap();
+        let txs = vec![
+            tx("a", vec![output(OTHER, 10_000)], vec![output(WATCHED, 7_000)]),
+            tx("b", vec![output(OTHER, 1_000)], vec![output(OTHER, 900)]),
+            tx("c", vec![output(WATCHED, 7_000)], vec![output(OTHER, 4_000), output(WATCHED, 2_500)]),
+        ];
+
+        let matches = scan_block(&block, &txs, WATCHED);
+        assert_eq!(matches.len(), 2);
+        assert_eq!((matches[0].txid.as_str(), matches[0].direction, matches[0].net()), ("a", Direction::Received, 7_000));
+        assert_eq!((matches[1].txid.as_str(), matches[1].direction, matches[1].net()), ("c", Direction::Sent, -4_500));
+        assert_eq!(matches[1].height, 42);
+        assert!(format_text_match(&matches[1], Unit::Btc).ends_with("-0.00004500 BTC"));
+        assert!(format_text_match(&matches[0], Unit::Sats).ends_with("+7,000 sats"));
+        assert_eq!(plain_match(&matches[1]), "42\tc\tsent\t2500\t7000\t-4500");
+    }
+}
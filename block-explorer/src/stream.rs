@@ -0,0 +1,126 @@
+//! Streams a block's transactions one page at a time, so listing or scanning
+//! a full block never holds more than a single page in memory
+
+use crate::esplora::{EsploraClient, RequestError};
+use crate::Transaction;
+use std::ops::Range;
+
+/// Number of transactions Esplora returns per /block/:hash/txs page
+pub const TXS_PAGE_SIZE: usize = 25;
+
+/// Iterator over the items at `range`, fetching the page containing the next
+/// item only when the current one runs out. Stops after the first error.
+pub struct PagedStream<T, E, F> {
+    fetch_page: F,
+    next: usize,
+    end: usize,
+    page: std::vec::IntoIter<T>,
+    failed: bool,
+    _error: std::marker::PhantomData<E>,
+}
+
+impl<T, E, F> PagedStream<T, E, F>
+where
+    F: FnMut(usize) -> Result<Vec<T>, E>,
+{
+    /// `fetch_page` is called with page start offsets, always multiples of
+    /// [`TXS_PAGE_SIZE`]
+    pub fn new(range: Range<usize>, fetch_page: F) -> Self {
+        PagedStream {
+            fetch_page,
+            next: range.start,
+            end: range.end,
+            page: Vec::new().into_iter(),
+            failed: false,
+            _error: std::marker::PhantomData,
+        }
+    }
+}
+
+impl<T, E, F> Iterator for PagedStream<T, E, F>
+where
+    F: FnMut(usize) -> Result<Vec<T>, E>,
+{
+    type Item = Result<T, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.failed || self.next >= self.end {
+            return None;
+        }
+
+        if self.page.len() == 0 {
+            // Pages must start at a multiple of the page size
+            let page_start = self.next - self.next % TXS_PAGE_SIZE;
+            match (self.fetch_page)(page_start) {
+                Ok(page) => {
+                    self.page = page.into_iter();
+                    for _ in page_start..self.next {
+                        self.page.next();
+                    }
+                }
+                Err(err) => {
+                    self.failed = true;
+                    return Some(Err(err));
+                }
+            }
+        }
+
+        // A short page means the block has fewer transactions than expected
+        let item = self.page.next()?;
+        self.next += 1;
+        Some(Ok(item))
+    }
+}
+
+/// Streams the transactions at the given positions of a block
+pub fn block_txs<'a>(
+    client: &'a EsploraClient,
+    block_hash: &'a str,
+    range: Range<usize>,
+) -> impl Iterator<Item = Result<Transaction, RequestError>> + 'a {
+    PagedStream::new(range, move |start| client.get_json(&format!("/block/{}/txs/{}", block_hash, start)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Serves pages of a fake 60-item block, recording which pages were asked for
+    fn fake_pages(requested: &mut Vec<usize>) -> impl FnMut(usize) -> Result<Vec<usize>, String> + '_ {
+        move |start| {
+            requested.push(start);
+            Ok((start..(start + TXS_PAGE_SIZE).min(60)).collect())
+        }
+    }
+
+    #[test]
+    fn test_stream_fetches_only_overlapping_pages() {
+        let mut requested = Vec::new();
+        let items: Result<Vec<usize>, String> = PagedStream::new(20..55, fake_pages(&mut requested)).collect();
+        assert_eq!(items.unwrap(), (20..55).collect::<Vec<_>>());
+        assert_eq!(requested, vec![0, 25, 50]);
+    }
+
+    #[test]
+    fn test_stream_is_lazy() {
+        let mut requested = Vec::new();
+        let first: Vec<_> = PagedStream::new(0..60, fake_pages(&mut requested)).take(3).collect();
+        assert_eq!(first.len(), 3);
+        assert_eq!(requested, vec![0]);
+    }
+
+    #[test]
+    fn test_stream_stops_at_short_page_and_after_errors() {
+        let mut requested = Vec::new();
+        assert_eq!(PagedStream::new(50..80, fake_pages(&mut requested)).count(), 10);
+
+        let mut calls = 0;
+        let failing = PagedStream::new(0..60, |_| -> Result<Vec<usize>, String> {
+            calls += 1;
+            Err("timeout".to_string())
+        });
+        let results: Vec<_> = failing.collect();
+        assert_eq!(results, vec![Err("timeout".to_string())]);
+        assert_eq!(calls, 1);
+    }
+}
@@ -0,0 +1,40 @@
+//! Builders for the transactions the unit tests check
+//!
+//! Each builder fills in what the tests don't care about; a test that does
+//! care overrides the field with struct update syntax, e.g.
+//! `Transaction { locktime: 500_000, ..tx("t", vec![], vec![]) }`.
+
+use crate::{Input, Output, Transaction, TxStatus};
+
+/// An output paying `value` to the hex `script`
+pub fn output(script: &str, value: u64) -> Output {
+    Output { value, scriptpubkey: script.to_string(), scriptpubkey_address: None }
+}
+
+/// An input spending output 0 of `txid`, which paid `prevout` when known
+pub fn input(txid: &str, prevout: Option<Output>) -> Input {
+    Input {
+        txid: txid.to_string(),
+        vout: 0,
+        prevout,
+        is_coinbase: false,
+        scriptsig: String::new(),
+        sequence: u32::MAX,
+        witness: vec![],
+    }
+}
+
+/// Transaction `txid`, confirmed in block 1
+pub fn tx(txid: &str, vin: Vec<Input>, vout: Vec<Output>) -> Transaction {
+    Transaction {
+        txid: txid.to_string(),
+        version: 2,
+        locktime: 0,
+        vin,
+        vout,
+        size: 0,
+        weight: 0,
+        fee: 0,
+        status: TxStatus { confirmed: true, block_height: Some(1) },
+    }
+}
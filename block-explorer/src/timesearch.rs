@@ -0,0 +1,198 @@
+//! `block-explorer at <TIME>`: binary-search block heights for the first
+//! block mined at or after a given time
+//!
+//! Header timestamps are only roughly ordered (a miner may be up to two hours
+//! off), so near the boundary the search lands on a block whose timestamp is
+//! at or after the target and whose predecessor's, as probed, is before it.
+
+use crate::error::ExplorerError;
+use crate::esplora::EsploraClient;
+use crate::headers;
+use crate::output::Report;
+use crate::{BlockInfo, OutputFormat};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use serde::Serialize;
+
+/// Parses a UTC date or time: `2024-04-20`, `2024-04-20 00:00`,
+/// `2024-04-20 00:00:00 UTC`, RFC 3339, or a unix timestamp
+pub fn parse_time(input: &str) -> Result<DateTime<Utc>, String> {
+    let input = input.trim();
+    if let Ok(seconds) = input.parse::<i64>() {
+        return DateTime::from_timestamp(seconds, 0).ok_or_else(|| format!("Timestamp out of range: {}", input));
+    }
+    if let Ok(time) = DateTime::parse_from_rfc3339(input) {
+        return Ok(time.with_timezone(&Utc));
+    }
+
+    let naive = input.strip_suffix("UTC").or_else(|| input.strip_suffix('Z')).unwrap_or(input).trim();
+    for format in ["%Y-%m-%d %H:%M:%S", "%Y-%m-%d %H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%dT%H:%M"] {
+        if let Ok(time) = NaiveDateTime::parse_from_str(naive, format) {
+            return Ok(time.and_utc());
+        }
+    }
+    if let Ok(date) = NaiveDate::parse_from_str(naive, "%Y-%m-%d") {
+        return Ok(date.and_hms_opt(0, 0, 0).expect("midnight is valid").and_utc());
+    }
+
+    Err(format!(
+        "Could not parse time '{}'. Use e.g. \"2024-04-20\", \"2024-04-20 00:00 UTC\", or a unix timestamp",
+        input
+    ))
+}
+
+/// Lowest height in `0..=tip` whose timestamp is at or after `target`, or
+/// None if even the tip is older. `timestamp` is probed about log2(tip) times.
+pub fn first_at_or_after<E>(
+    tip: u32,
+    target: u64,
+    mut timestamp: impl FnMut(u32) -> Result<u64, E>,
+) -> Result<Option<u32>, E> {
+    if timestamp(tip)? < target {
+        return Ok(None);
+    }
+
+    let (mut low, mut high) = (0, tip);
+    while low < high {
+        let mid = low + (high - low) / 2;
+        if timestamp(mid)? >= target {
+            high = mid;
+        } else {
+            low = mid + 1;
+        }
+    }
+    Ok(Some(low))
+}
+
+#[derive(Debug, Serialize)]
+struct TimeMatch<'a> {
+    target: i64,
+    height: u32,
+    hash: &'a str,
+    timestamp: u64,
+}
+
+/// Finds and prints the first block at or after `target`
+pub fn run(
+    client: &EsploraClient,
+    target: DateTime<Utc>,
+    format: OutputFormat,
+    report: Report,
+) -> Result<(), ExplorerError> {
+    let palette = report.palette();
+    // Offline, the search covers the stored heights
+    let tip = if client.is_offline() {
+        headers::require(client.headers())?
+            .tip()
+            .ok_or_else(|| ExplorerError::NotFound("The local header store is empty".to_string()))?
+    } else {
+        client
+            .get_text("/blocks/tip/height")
+            .map_err(|e| ExplorerError::from_request("Could not fetch the tip height", e))?
+            .trim()
+            .parse::<u32>()
+            .map_err(|e| ExplorerError::Parse(format!("Invalid tip height: {}", e)))?
+    };
+
+    if format == OutputFormat::Text {
+        report.status(format!("Searching blocks 0–{} for {}...", tip, target.format("%Y-%m-%d %H:%M:%S UTC")));
+    }
+
+    let target_secs = target.timestamp().max(0) as u64;
+    let mut last_block: Option<BlockInfo> = None;
+    let height = first_at_or_after(tip, target_secs, |height| {
+        let block = crate::batch::query_block(client, &height.to_string())?;
+        let timestamp = block.timestamp;
+        last_block = Some(block);
+        Ok::<_, ExplorerError>(timestamp)
+    })?
+    .ok_or_else(|| {
+        ExplorerError::NotFound(format!(
+            "No block mined at or after {} yet (tip is {})",
+            target.format("%Y-%m-%d %H:%M:%S UTC"),
+            tip
+        ))
+    })?;
+
+    // The search usually ends on the block it last probed
+    let block = match last_block {
+        Some(block) if block.height == height => block,
+        _ => crate::batch::query_block(client, &height.to_string())?,
+    };
+
+    match format {
+        OutputFormat::Json => println!(
+            "{}",
+            serde_json::to_string(&TimeMatch {
+                target: target.timestamp(),
+                height: block.height,
+                hash: &block.id,
+                timestamp: block.timestamp,
+            })
+            .expect("time matches always serialize")
+        ),
+        OutputFormat::Text => {
+            let mined = DateTime::<Utc>::from_timestamp(block.timestamp as i64, 0).unwrap_or(target);
+            report.section("FIRST BLOCK AT OR AFTER", "block_at_time");
+            report.field(format!("Target:      {}", target.format("%Y-%m-%d %H:%M:%S UTC")), "target", target.timestamp());
+            report.field(format!("Height:      {}", palette.value(block.height)), "height", block.height);
+            report.field(format!("Hash:        {}", palette.hash(&block.id)), "hash", &block.id);
+            report.field(
+                format!("Timestamp:   {} ({})", palette.value(block.timestamp), mined.format("%Y-%m-%d %H:%M:%S UTC")),
+                "timestamp",
+                block.timestamp,
+            );
+            report.end();
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_time_formats() {
+        let expected = NaiveDate::from_ymd_opt(2024, 4, 20).unwrap().and_hms_opt(0, 0, 0).unwrap().and_utc();
+        for input in [
+            "2024-04-20",
+            "2024-04-20 00:00",
+            "2024-04-20 00:00 UTC",
+            "2024-04-20 00:00:00 UTC",
+            "2024-04-20T00:00:00Z",
+            "2024-04-20T02:00:00+02:00",
+            "1713571200",
+        ] {
+            assert_eq!(parse_time(input).unwrap(), expected, "{}", input);
+        }
+        assert!(parse_time("last tuesday").is_err());
+    }
+
+    #[test]
+    fn test_first_at_or_after() {
+        // Ten-minute blocks, with block 5 timestamped slightly early
+        let times = [0u64, 600, 1200, 1800, 2400, 2900, 3600, 4200];
+        let mut probes = 0;
+        let mut search = |target| {
+            first_at_or_after(7, target, |h| {
+                probes += 1;
+                Ok::<_, ()>(times[h as usize])
+            })
+            .unwrap()
+        };
+
+        assert_eq!(search(0), Some(0));
+        assert_eq!(search(1200), Some(2));
+        assert_eq!(search(1201), Some(3));
+        assert_eq!(search(2901), Some(6));
+        assert_eq!(search(4200), Some(7));
+        assert_eq!(search(4201), None);
+        assert!(probes <= 6 * 5);
+    }
+
+    #[test]
+    fn test_first_at_or_after_propagates_errors() {
+        let result = first_at_or_after(100, 5, |_| Err::<u64, _>("offline"));
+        assert_eq!(result, Err("offline"));
+    }
+}
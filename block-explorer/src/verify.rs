@@ -0,0 +1,66 @@
+//! Local merkle-root recomputation, so a lying or buggy backend can't hand
+//! back a transaction list that doesn't belong to the block header
+
+use bitcoin::{merkle_tree, Txid};
+use std::str::FromStr;
+
+/// Outcome of checking a block's txids against its header
+#[derive(Debug, PartialEq, Eq)]
+pub struct MerkleCheck {
+    pub computed: String,
+    pub reported: String,
+}
+
+impl MerkleCheck {
+    pub fn matches(&self) -> bool {
+        self.computed == self.reported
+    }
+}
+
+/// Recomputes the merkle root from txids in block order (display hex)
+pub fn merkle_root(txids: &[String]) -> Result<String, String> {
+    let hashes = txids
+        .iter()
+        .map(|txid| Txid::from_str(txid).map_err(|e| format!("Invalid txid '{}': {}", txid, e)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    merkle_tree::calculate_root(hashes.into_iter())
+        .map(|root| root.to_string())
+        .ok_or_else(|| "Block has no transactions".to_string())
+}
+
+/// Compares the recomputed root with the header's `merkle_root`
+pub fn check(txids: &[String], reported: &str) -> Result<MerkleCheck, String> {
+    Ok(MerkleCheck { computed: merkle_root(txids)?, reported: reported.to_lowercase() })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_single_tx_root_is_the_txid() {
+        // Genesis block: the coinbase txid is the merkle root
+        let genesis = "4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b";
+        assert_eq!(merkle_root(&[genesis.to_string()]).unwrap(), genesis);
+    }
+
+    #[test]
+    fn test_multi_tx_root() {
+        // Mainnet block 170, the first block with a non-coinbase transaction
+        let txids = vec![
+            "b1fea52486ce0c62bb442b530a3f0132b826c74e473d1f2c220bfa78111c5082".to_string(),
+            "f4184fc596403b9d638783cf57adfe4c75c605f6356fbc91338530e9831e9e16".to_string(),
+        ];
+        let check = check(&txids, "7DAC2C5666815C17A3B36427DE37BB9D2E2C5CCEC3F8633EB91A4205CB4C10FF").unwrap();
+        assert!(check.matches(), "{:?}", check);
+    }
+
+    #[test]
+    fn test_mismatch_and_bad_input() {
+        let txids = vec!["4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b".to_string()];
+        assert!(!check(&txids, &"00".repeat(32)).unwrap().matches());
+        assert!(merkle_root(&[]).is_err());
+        assert!(merkle_root(&["xyz".to_string()]).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Decodes input witness stacks for `--witness`: signature types and sighash
+//! flags, public keys, witness scripts, and for taproot the spend path,
+//! tapscript leaf, control block, and annex
+
+use crate::scripts::ScriptType;
+use bitcoin::ScriptBuf;
+
+/// First byte of a taproot annex (BIP 341)
+const ANNEX_TAG: u8 = 0x50;
+
+/// Human-readable lines describing one input's witness, given the type of
+/// the output it spends
+pub fn describe(spent: ScriptType, witness: &[String]) -> Vec<String> {
+    if witness.is_empty() {
+        return vec!["no witness (legacy input)".to_string()];
+    }
+    let items: Vec<Vec<u8>> = witness.iter().map(|item| decode_hex(item)).collect();
+
+    match spent {
+        ScriptType::P2tr => describe_taproot(&items),
+        ScriptType::P2wpkh => describe_items(&items),
+        // Nested segwit spends P2SH, so the last item tells them apart: a
+        // signature and compressed key is P2SH-P2WPKH, anything else a
+        // witness script
+        ScriptType::P2sh if is_p2wpkh_stack(&items) => describe_items(&items),
+        ScriptType::P2wsh | ScriptType::P2sh if items.len() > 1 => {
+            let (script, args) = items.split_last().expect("more than one item");
+            let mut lines = describe_items(args);
+            lines.push(format!("witness script ({} bytes): {}", script.len(), asm(script)));
+            lines
+        }
+        _ => describe_items(&items),
+    }
+}
+
+fn is_p2wpkh_stack(items: &[Vec<u8>]) -> bool {
+    matches!(items, [_, key] if key.len() == 33 && (key[0] == 0x02 || key[0] == 0x03))
+}
+
+fn describe_taproot(items: &[Vec<u8>]) -> Vec<String> {
+    let mut items = items;
+    let mut annex = None;
+    if items.len() >= 2 && items.last().and_then(|item| item.first()) == Some(&ANNEX_TAG) {
+        annex = items.last();
+        items = &items[..items.len() - 1];
+    }
+
+    let mut lines = Vec::new();
+    if items.len() == 1 {
+        lines.push("key path spend".to_string());
+        lines.extend(describe_items(items));
+    } else {
+        let (control, rest) = items.split_last().expect("at least two items");
+        let (script, args) = rest.split_last().expect("at least one item");
+        lines.push("script path spend".to_string());
+        lines.extend(describe_items(args));
+        lines.push(format!("tapscript ({} bytes): {}", script.len(), asm(script)));
+        lines.push(describe_control_block(control));
+    }
+
+    lines.push(match annex {
+        Some(annex) => format!("annex: present ({} bytes)", annex.len()),
+        None => "annex: none".to_string(),
+    });
+    lines
+}
+
+/// Leaf version, output key parity, internal key, and merkle path length
+fn describe_control_block(control: &[u8]) -> String {
+    if control.len() < 33 || !(control.len() - 33).is_multiple_of(32) {
+        return format!("control block: malformed ({} bytes)", control.len());
+    }
+    format!(
+        "control block ({} bytes): leaf version 0x{:02x}, parity {}, internal key {}, {} merkle branch node(s)",
+        control.len(),
+        control[0] & 0xfe,
+        if control[0] & 1 == 1 { "odd" } else { "even" },
+        to_hex(&control[1..33]),
+        (control.len() - 33) / 32
+    )
+}
+
+fn describe_items(items: &[Vec<u8>]) -> Vec<String> {
+    items
+        .iter()
+        .enumerate()
+        .map(|(i, item)| format!("[{}] {}", i, describe_item(item)))
+        .collect()
+}
+
+/// Guesses what a stack item is from its length and leading bytes
+fn describe_item(item: &[u8]) -> String {
+    match item.len() {
+        0 => "empty (multisig dummy or false)".to_string(),
+        64 => "64 bytes: Schnorr signature, SIGHASH_DEFAULT".to_string(),
+        65 if item[0] != 0x04 => format!("65 bytes: Schnorr signature, {}", sighash_name(item[64])),
+        33 if item[0] == 0x02 || item[0] == 0x03 => format!("33 bytes: public key {}", to_hex(item)),
+        32 => format!("32 bytes: {}", to_hex(item)),
+        len @ 9..=73 if item[0] == 0x30 => {
+            format!("{} bytes: ECDSA signature, {}", len, sighash_name(item[len - 1]))
+        }
+        len => format!("{} bytes", len),
+    }
+}
+
+fn sighash_name(flag: u8) -> String {
+    let base = match flag & 0x7f {
+        0x01 => "SIGHASH_ALL",
+        0x02 => "SIGHASH_NONE",
+        0x03 => "SIGHASH_SINGLE",
+        _ => return format!("sighash 0x{:02x}", flag),
+    };
+    if flag & 0x80 != 0 {
+        format!("{}|ANYONECANPAY", base)
+    } else {
+        base.to_string()
+    }
+}
+
+fn asm(script: &[u8]) -> String {
+    ScriptBuf::from_bytes(script.to_vec()).to_asm_string()
+}
+
+/// Invalid hex decodes to an empty item rather than failing the listing
+fn decode_hex(hex: &str) -> Vec<u8> {
+    (0..hex.len() / 2)
+        .map(|i| u8::from_str_radix(hex.get(i * 2..i * 2 + 2).unwrap_or(""), 16))
+        .collect::<Result<_, _>>()
+        .unwrap_or_default()
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const PUBKEY: &str = "0330d54fd0dd420a6e5f8d3624f5f3482cae350f79d5f0753bf5beef9c2d91af3c";
+
+    fn der_signature(sighash: u8) -> String {
+        format!("3044{}{:02x}", "02".repeat(68), sighash)
+    }
+
+    #[test]
+    fn test_p2wpkh_witness() {
+        let lines = describe(ScriptType::P2wpkh, &[der_signature(0x01), PUBKEY.to_string()]);
+        assert_eq!(lines[0], "[0] 71 bytes: ECDSA signature, SIGHASH_ALL");
+        assert!(lines[1].starts_with("[1] 33 bytes: public key 0330d54f"));
+    }
+
+    #[test]
+    fn test_p2sh_p2wpkh_witness() {
+        // The P2SH-P2WPKH input from the BIP 143 examples
+        let witness = [
+            "3044022047ac8e878352d3ebbde1c94ce3a10d057c24175747116f8288e5d794d12d482f0220217f36a485cae903c713331d877c1f64677e3622ad4010726870540656fe9dcb01".to_string(),
+            "03ad1d8e89212f0b92c74d23bb710c00662ad1470198ac48c43f7d6f93a2a26873".to_string(),
+        ];
+        let lines = describe(ScriptType::P2sh, &witness);
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0], "[0] 71 bytes: ECDSA signature, SIGHASH_ALL");
+        assert!(lines[1].starts_with("[1] 33 bytes: public key 03ad1d8e"));
+    }
+
+    #[test]
+    fn test_taproot_key_path_with_annex() {
+        let sig = "11".repeat(64) + "83";
+        let lines = describe(ScriptType::P2tr, &[sig, "50aabb".to_string()]);
+        assert_eq!(lines[0], "key path spend");
+        assert_eq!(lines[1], "[0] 65 bytes: Schnorr signature, SIGHASH_SINGLE|ANYONECANPAY");
+        assert_eq!(lines[2], "annex: present (3 bytes)");
+    }
+
+    #[test]
+    fn test_taproot_script_path() {
+        let sig = "22".repeat(64);
+        // <32-byte key> OP_CHECKSIG
+        let tapscript = format!("20{}ac", "33".repeat(32));
+        let control = format!("c1{}{}", "44".repeat(32), "55".repeat(32));
+        let lines = describe(ScriptType::P2tr, &[sig, tapscript, control]);
+
+        assert_eq!(lines[0], "script path spend");
+        assert_eq!(lines[1], "[0] 64 bytes: Schnorr signature, SIGHASH_DEFAULT");
+        assert!(lines[2].starts_with("tapscript (34 bytes): OP_PUSHBYTES_32 3333"));
+        assert!(lines[2].ends_with("OP_CHECKSIG"));
+        assert!(lines[3].contains("leaf version 0xc0, parity odd, internal key 4444"));
+        assert!(lines[3].ends_with("1 merkle branch node(s)"));
+        assert_eq!(lines[4], "annex: none");
+    }
+
+    #[test]
+    fn test_p2wsh_and_legacy() {
+        let script = format!("5221{}21{}52ae", PUBKEY, PUBKEY);
+        let lines = describe(
+            ScriptType::P2wsh,
+            &[String::new(), der_signature(0x01), der_signature(0x02), script],
+        );
+        assert_eq!(lines[0], "[0] empty (multisig dummy or false)");
+        assert_eq!(lines[2], "[2] 71 bytes: ECDSA signature, SIGHASH_NONE");
+        assert!(lines[3].starts_with("witness script (71 bytes): OP_PUSHNUM_2"));
+
+        assert_eq!(describe(ScriptType::P2pkh, &[]), vec!["no witness (legacy input)"]);
+    }
+}
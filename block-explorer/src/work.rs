@@ -0,0 +1,54 @@
+//! Proof-of-work accounting derived from a block's compact `bits` target
+//!
+//! Esplora doesn't report chainwork, but each block's expected work follows
+//! directly from its target, so it can be computed and summed locally.
+
+use bitcoin::{CompactTarget, Target, Work};
+
+/// Expected number of hashes needed to find a block at this target
+pub fn block_work(bits: u32) -> Work {
+    Target::from_compact(CompactTarget::from_consensus(bits)).to_work()
+}
+
+/// Zero work, the starting point for cumulative sums
+pub fn zero_work() -> Work {
+    Work::from_be_bytes([0; 32])
+}
+
+/// Formats work as a decimal hash count with its log2 for scale
+pub fn format_work(work: Work) -> String {
+    format!("{} (2^{:.2})", work, work.log2())
+}
+
+/// Formats work as 64 hex digits, matching Bitcoin Core's `chainwork` field
+pub fn format_chainwork(work: Work) -> String {
+    format!("{:x}", work)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_genesis_block_work() {
+        // Difficulty 1 target (bits 0x1d00ffff) needs 0x100010001 hashes
+        let work = block_work(0x1d00ffff);
+        assert_eq!(work.to_string(), "4295032833");
+        assert_eq!(format_work(work), "4295032833 (2^32.00)");
+    }
+
+    #[test]
+    fn test_cumulative_chainwork() {
+        let total = (0..3).fold(zero_work(), |acc, _| acc + block_work(0x1d00ffff));
+        assert_eq!(
+            format_chainwork(total),
+            "0000000000000000000000000000000000000000000000000000000300030003"
+        );
+    }
+
+    #[test]
+    fn test_higher_difficulty_means_more_work() {
+        // Block 2500000 on testnet
+        assert!(block_work(436469756) > block_work(0x1d00ffff));
+    }
+}
@@ -0,0 +1,321 @@
+use bitcoin::address::{Address, NetworkUnchecked};
+use bitcoin::Network;
+use clap::Parser;
+use std::path::PathBuf;
+use std::process::Command;
+
+#[derive(Parser, Debug)]
+#[command(name = "lookup")]
+#[command(about = "Identify a Bitcoin identifier and run the matching tool", long_about = None)]
+struct Args {
+    /// Address, xpub, txid, block hash, block height, descriptor, or PSBT
+    input: String,
+
+    /// Network (testnet or bitcoin); inferred from addresses when omitted
+    #[arg(short, long)]
+    network: Option<String>,
+
+    /// Only print what was detected and the command that would run
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// The kinds of input the tools in this workspace understand
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum InputKind {
+    Address { mainnet: bool },
+    ExtendedKey,
+    Txid,
+    BlockHash,
+    BlockHeight,
+    Descriptor,
+    Psbt,
+}
+
+impl InputKind {
+    /// Human-readable name for the detected kind
+    fn name(&self) -> &'static str {
+        match self {
+            InputKind::Address { .. } => "address",
+            InputKind::ExtendedKey => "extended public key",
+            InputKind::Txid => "transaction id",
+            InputKind::BlockHash => "block hash",
+            InputKind::BlockHeight => "block height",
+            InputKind::Descriptor => "output descriptor",
+            InputKind::Psbt => "PSBT",
+        }
+    }
+
+    /// The workspace tool that handles this kind, if any
+    fn tool(&self) -> Option<&'static str> {
+        match self {
+            // Extended keys and descriptors are scanned as wallets
+            InputKind::Address { .. } | InputKind::ExtendedKey | InputKind::Descriptor => Some("balance-checker"),
+            InputKind::BlockHash | InputKind::BlockHeight => Some("block-explorer"),
+            InputKind::Psbt => Some("psbt"),
+            InputKind::Txid => None,
+        }
+    }
+}
+
+/// Extended public key prefixes for mainnet and testnet BIP32/49/84 keys
+const EXTENDED_KEY_PREFIXES: [&str; 6] = ["xpub", "ypub", "zpub", "tpub", "upub", "vpub"];
+
+/// Script expression names that can start an output descriptor
+const DESCRIPTOR_PREFIXES: [&str; 9] = ["pk(", "pkh(", "wpkh(", "sh(", "wsh(", "tr(", "addr(", "raw(", "combo("];
+
+/// Block hashes meet the proof-of-work target, so they start with zero bytes;
+/// eight leading hex zeros separates them from txids with overwhelming odds
+const BLOCK_HASH_ZERO_PREFIX: &str = "00000000";
+
+/// What kind of private key or seed the input looks like, if any
+fn secret_kind(input: &str) -> Option<&'static str> {
+    let words: Vec<&str> = input.split_whitespace().collect();
+    if words.len() >= 12 && words.iter().all(|word| word.chars().all(|c| c.is_ascii_alphabetic())) {
+        return Some("a seed phrase");
+    }
+    if DESCRIPTOR_PREFIXES.iter().any(|p| input.starts_with(p)) {
+        return (input.contains("xprv") || input.contains("tprv")).then_some("a descriptor with a private key");
+    }
+    let is_base58 = input.chars().all(|c| c.is_ascii_alphanumeric() && !"0OIl".contains(c));
+    if !is_base58 {
+        return None;
+    }
+    if input.len() > 100 && input.get(1..4).is_some_and(|version| version == "prv") {
+        return Some("an extended private key");
+    }
+    match (input.len(), input.chars().next()) {
+        (51, Some('5' | '9')) | (52, Some('K' | 'L' | 'c')) => Some("a private key (WIF)"),
+        _ => None,
+    }
+}
+
+/// Classifies the input without touching the network
+fn classify(input: &str) -> Result<InputKind, String> {
+    let input = input.trim();
+
+    // Never echoed, nor passed on to another tool's command line
+    if let Some(secret) = secret_kind(input) {
+        return Err(format!(
+            "That input looks like {}, which can spend the funds; only public data is needed. \
+             Keep secrets out of command lines and shell history.",
+            secret
+        ));
+    }
+
+    if input.parse::<u32>().is_ok() {
+        return Ok(InputKind::BlockHeight);
+    }
+
+    if let Ok(address) = input.parse::<Address<NetworkUnchecked>>() {
+        return Ok(InputKind::Address { mainnet: address.is_valid_for_network(Network::Bitcoin) });
+    }
+
+    if EXTENDED_KEY_PREFIXES.iter().any(|p| input.starts_with(p)) {
+        return Ok(InputKind::ExtendedKey);
+    }
+
+    if DESCRIPTOR_PREFIXES.iter().any(|p| input.starts_with(p)) {
+        return Ok(InputKind::Descriptor);
+    }
+
+    // PSBT magic bytes "psbt\xff" in base64 and hex
+    if input.starts_with("cHNidP8") || input.to_lowercase().starts_with("70736274ff") {
+        return Ok(InputKind::Psbt);
+    }
+
+    if input.len() == 64 && input.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Ok(if input.starts_with(BLOCK_HASH_ZERO_PREFIX) {
+            InputKind::BlockHash
+        } else {
+            InputKind::Txid
+        });
+    }
+
+    Err(format!("Could not identify '{}'", input))
+}
+
+/// Picks the network flag to pass on: explicit choice wins, otherwise
+/// mainnet addresses imply `bitcoin` and everything else keeps the tool default
+fn network_arg(kind: InputKind, explicit: Option<&str>) -> Option<String> {
+    match (explicit, kind) {
+        (Some(network), _) => Some(network.to_string()),
+        (None, InputKind::Address { mainnet: true }) => Some("bitcoin".to_string()),
+        _ => None,
+    }
+}
+
+/// Builds the argument list for the dispatched tool
+fn tool_args(kind: InputKind, input: &str, network: Option<String>) -> Vec<String> {
+    // PSBTs carry their own network, so they are just normalized to base64
+    if kind == InputKind::Psbt {
+        return vec!["convert".to_string(), input.trim().to_string()];
+    }
+
+    let mut args = Vec::new();
+    if let Some(network) = network {
+        args.push("--network".to_string());
+        args.push(network);
+    }
+    args.push(input.trim().to_string());
+    args
+}
+
+/// Locates a sibling binary next to this one, falling back to PATH lookup
+fn tool_path(tool: &str) -> PathBuf {
+    std::env::current_exe()
+        .ok()
+        .and_then(|exe| exe.parent().map(|dir| dir.join(tool)))
+        .filter(|path| path.exists())
+        .unwrap_or_else(|| PathBuf::from(tool))
+}
+
+fn main() {
+    let args = Args::parse();
+
+    let kind = match classify(&args.input) {
+        Ok(kind) => kind,
+        Err(err) => {
+            eprintln!("{}", err);
+            eprintln!("Expected an address, xpub, txid, block hash, block height, descriptor, or PSBT.");
+            std::process::exit(2);
+        }
+    };
+
+    println!("Detected: {}", kind.name());
+
+    let tool = match kind.tool() {
+        Some(tool) => tool,
+        None => {
+            println!("No tool in this workspace handles {}s yet.", kind.name());
+            return;
+        }
+    };
+
+    let tool_args = tool_args(kind, &args.input, network_arg(kind, args.network.as_deref()));
+    println!("Running: {} {}\n", tool, tool_args.join(" "));
+
+    if args.dry_run {
+        return;
+    }
+
+    match Command::new(tool_path(tool)).args(&tool_args).status() {
+        Ok(status) => std::process::exit(status.code().unwrap_or(1)),
+        Err(e) => {
+            eprintln!("Error running {}: {}", tool, e);
+            eprintln!("Build it with: cargo build --package {}", tool);
+            std::process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_classify_block_height() {
+        assert_eq!(classify("0").unwrap(), InputKind::BlockHeight);
+        assert_eq!(classify("840000").unwrap(), InputKind::BlockHeight);
+    }
+
+    #[test]
+    fn test_classify_addresses() {
+        assert_eq!(
+            classify("tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx").unwrap(),
+            InputKind::Address { mainnet: false }
+        );
+        assert_eq!(
+            classify("bc1qxy2kgdygjrsqtzq2n0yrf2493p83kkfjhx0wlh").unwrap(),
+            InputKind::Address { mainnet: true }
+        );
+        assert_eq!(
+            classify("1A1zP1eP5QGefi2DMPTfTL5SLmv7DivfNa").unwrap(),
+            InputKind::Address { mainnet: true }
+        );
+    }
+
+    #[test]
+    fn test_classify_block_hash_vs_txid() {
+        assert_eq!(
+            classify("000000000933ea01ad0ee984209779baaec3ced90fa3f408719526f8d77f4943").unwrap(),
+            InputKind::BlockHash
+        );
+        assert_eq!(
+            classify("4a5e1e4baab89f3a32518a88c31bc87f618f76673e2cc77ab2127b7afdeda33b").unwrap(),
+            InputKind::Txid
+        );
+    }
+
+    #[test]
+    fn test_classify_keys_descriptors_and_psbts() {
+        assert_eq!(
+            classify("xpub6CUGRUonZSQ4TWtTMmzXdrXDtypWKiKrhko4egpiMZbpiaQL2jkwSB1icqYh2cfDfVxdx4df189oLKnC5fSwqPfgyP3hooxujYzAu3fDVmz").unwrap(),
+            InputKind::ExtendedKey
+        );
+        assert_eq!(classify("wpkh(tpubD6NzVbkrYhZ4X/0/*)").unwrap(), InputKind::Descriptor);
+        assert_eq!(classify("wsh(sortedmulti(2,a,b,c))#abcd1234").unwrap(), InputKind::Descriptor);
+        assert_eq!(classify("cHNidP8BAHECAAAAAQ==").unwrap(), InputKind::Psbt);
+        assert_eq!(classify("70736274ff0100").unwrap(), InputKind::Psbt);
+    }
+
+    #[test]
+    fn test_classify_refuses_secrets_without_echoing_them() {
+        let secrets = [
+            "tprv8ZgxMBicQKsPd7Uf69XL1XwhmjHopUGep8GuEiJDZmbQz6o58LninorQAfcKZWARbtRtfnLcJ5MQ2AtHcQJCCRUcMRvmDUjyEmNUWwx8UbK",
+            "xprv9s21ZrQH143K3QTDL4LXw2F7HEK3wJUD2nW2nRk4stbPy6cq3jPPqjiChkVvvNKmPGJxWUtg6LnF5kejMRNNU3TGtRBeJgk33yuGBxrMPHi",
+            "L1aW4aubDFB7yfras2S1mN3bqg9nwySY8nkoLmJebSLD5BWv3ENZ",
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "wpkh(tprv8ZgxMBicQKsPd7Uf69XL1XwhmjHopUGep8GuEiJDZmbQz6o58LninorQAfcKZWARbtRtfnLcJ5MQ2AtHcQJCCRUcMRvmDUjyEmNUWwx8UbK/0/*)",
+        ];
+        for secret in secrets {
+            let err = classify(secret).unwrap_err();
+            assert!(err.contains("can spend the funds"), "{}", err);
+            assert!(!err.contains(secret), "the secret is echoed: {}", err);
+        }
+    }
+
+    #[test]
+    fn test_classify_unknown() {
+        assert!(classify("hello world").is_err());
+        assert!(classify("abc123").is_err());
+    }
+
+    #[test]
+    fn test_tool_dispatch() {
+        assert_eq!(InputKind::Address { mainnet: false }.tool(), Some("balance-checker"));
+        assert_eq!(InputKind::BlockHeight.tool(), Some("block-explorer"));
+        assert_eq!(InputKind::BlockHash.tool(), Some("block-explorer"));
+        assert_eq!(InputKind::Psbt.tool(), Some("psbt"));
+        assert_eq!(InputKind::ExtendedKey.tool(), Some("balance-checker"));
+        assert_eq!(InputKind::Descriptor.tool(), Some("balance-checker"));
+        assert_eq!(InputKind::Txid.tool(), None);
+    }
+
+    #[test]
+    fn test_network_arg() {
+        assert_eq!(network_arg(InputKind::Address { mainnet: true }, None), Some("bitcoin".to_string()));
+        assert_eq!(network_arg(InputKind::Address { mainnet: false }, None), None);
+        assert_eq!(network_arg(InputKind::BlockHeight, Some("bitcoin")), Some("bitcoin".to_string()));
+        assert_eq!(network_arg(InputKind::BlockHeight, None), None);
+    }
+
+    #[test]
+    fn test_tool_args() {
+        assert_eq!(tool_args(InputKind::BlockHeight, " 800000 ", None), vec!["800000"]);
+        assert_eq!(
+            tool_args(InputKind::BlockHeight, "800000", Some("bitcoin".to_string())),
+            vec!["--network", "bitcoin", "800000"]
+        );
+        assert_eq!(
+            tool_args(InputKind::Psbt, "cHNidP8BAA==", Some("bitcoin".to_string())),
+            vec!["convert", "cHNidP8BAA=="]
+        );
+        // balance-checker takes a wallet as its positional input, like an address
+        assert_eq!(
+            tool_args(InputKind::Descriptor, "wpkh(tpubD6NzVbkrYhZ4X/0/*)", Some("testnet".to_string())),
+            vec!["--network", "testnet", "wpkh(tpubD6NzVbkrYhZ4X/0/*)"]
+        );
+        assert_eq!(tool_args(InputKind::ExtendedKey, "xpub6CUGRUon", None), vec!["xpub6CUGRUon"]);
+    }
+}
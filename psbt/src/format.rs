@@ -0,0 +1,126 @@
+//! PSBT encodings and automatic detection
+//!
+//! The same PSBT travels between wallets as a binary `.psbt` file (BIP174),
+//! a base64 string, or hex. All three start with the `psbt\xff` magic, which
+//! is what detection keys on.
+
+use bitcoin::hex::{DisplayHex, FromHex};
+use bitcoin::Psbt;
+use clap::ValueEnum;
+use std::str::FromStr;
+
+/// BIP174 magic bytes
+const MAGIC: &[u8] = b"psbt\xff";
+
+/// The magic bytes as they appear in base64 and hex
+const BASE64_MAGIC: &str = "cHNidP8";
+const HEX_MAGIC: &str = "70736274ff";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum PsbtFormat {
+    /// Raw BIP174 bytes, as in `.psbt` files
+    Binary,
+    Base64,
+    Hex,
+}
+
+impl PsbtFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            PsbtFormat::Binary => "binary",
+            PsbtFormat::Base64 => "base64",
+            PsbtFormat::Hex => "hex",
+        }
+    }
+}
+
+/// Identifies the encoding from the leading bytes
+pub fn detect(data: &[u8]) -> Result<PsbtFormat, String> {
+    if data.starts_with(MAGIC) {
+        return Ok(PsbtFormat::Binary);
+    }
+
+    let text = std::str::from_utf8(data).map_err(|_| "Input is neither a binary PSBT nor text".to_string())?;
+    let text = text.trim();
+    if text.starts_with(BASE64_MAGIC) {
+        Ok(PsbtFormat::Base64)
+    } else if text.to_lowercase().starts_with(HEX_MAGIC) {
+        Ok(PsbtFormat::Hex)
+    } else {
+        Err("Input is not a PSBT (expected binary, base64, or hex starting with the psbt magic)".to_string())
+    }
+}
+
+/// Detects the encoding and parses the PSBT
+pub fn decode(data: &[u8]) -> Result<(Psbt, PsbtFormat), String> {
+    let format = detect(data)?;
+    let psbt = match format {
+        PsbtFormat::Binary => Psbt::deserialize(data).map_err(|e| e.to_string()),
+        PsbtFormat::Base64 => Psbt::from_str(text(data).trim()).map_err(|e| e.to_string()),
+        PsbtFormat::Hex => Vec::<u8>::from_hex(text(data).trim())
+            .map_err(|e| e.to_string())
+            .and_then(|bytes| Psbt::deserialize(&bytes).map_err(|e| e.to_string())),
+    }
+    .map_err(|e| format!("Invalid {} PSBT: {}", format.name(), e))?;
+    Ok((psbt, format))
+}
+
+/// Serializes the PSBT; text formats end with a newline
+pub fn encode(psbt: &Psbt, format: PsbtFormat) -> Vec<u8> {
+    match format {
+        PsbtFormat::Binary => psbt.serialize(),
+        PsbtFormat::Base64 => format!("{}\n", psbt).into_bytes(),
+        PsbtFormat::Hex => format!("{}\n", psbt.serialize().to_lower_hex_string()).into_bytes(),
+    }
+}
+
+/// Text formats were already checked to be UTF-8 by [`detect`]
+fn text(data: &[u8]) -> &str {
+    std::str::from_utf8(data).unwrap_or_default()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::absolute::LockTime;
+    use bitcoin::transaction::Version;
+    use bitcoin::{Amount, ScriptBuf, Transaction, TxIn, TxOut};
+
+    fn sample_psbt() -> Psbt {
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![TxIn::default()],
+            output: vec![TxOut { value: Amount::from_sat(50_000), script_pubkey: ScriptBuf::new() }],
+        };
+        Psbt::from_unsigned_tx(tx).unwrap()
+    }
+
+    #[test]
+    fn test_detect() {
+        let psbt = sample_psbt();
+        assert_eq!(detect(&encode(&psbt, PsbtFormat::Binary)).unwrap(), PsbtFormat::Binary);
+        assert_eq!(detect(&encode(&psbt, PsbtFormat::Base64)).unwrap(), PsbtFormat::Base64);
+        assert_eq!(detect(&encode(&psbt, PsbtFormat::Hex)).unwrap(), PsbtFormat::Hex);
+        assert_eq!(detect(b"  70736274FF01").unwrap(), PsbtFormat::Hex);
+        assert!(detect(b"hello").is_err());
+        assert!(detect(&[0xff, 0xfe, 0x00]).is_err());
+    }
+
+    #[test]
+    fn test_round_trip_every_format() {
+        let psbt = sample_psbt();
+        for from in [PsbtFormat::Binary, PsbtFormat::Base64, PsbtFormat::Hex] {
+            let (decoded, format) = decode(&encode(&psbt, from)).unwrap();
+            assert_eq!(format, from);
+            assert_eq!(decoded, psbt);
+        }
+    }
+
+    #[test]
+    fn test_decode_reports_corrupt_input() {
+        let err = decode(b"cHNidP8BAAA=").unwrap_err();
+        assert!(err.starts_with("Invalid base64 PSBT"), "{}", err);
+        assert!(decode(b"70736274ffzz").is_err());
+    }
+}
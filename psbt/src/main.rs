@@ -0,0 +1,111 @@
+mod format;
+
+use clap::{Args, Parser, Subcommand};
+use format::PsbtFormat;
+use std::fs;
+use std::io::{self, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+
+#[derive(Parser, Debug)]
+#[command(name = "psbt")]
+#[command(about = "Work with Partially Signed Bitcoin Transactions", long_about = None)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Convert a PSBT between binary, base64, and hex
+    Convert(ConvertArgs),
+}
+
+#[derive(Args, Debug)]
+struct ConvertArgs {
+    /// A .psbt file, a base64 or hex string, or `-` for stdin; the format is detected
+    input: String,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "base64")]
+    to: PsbtFormat,
+
+    /// Write to this file instead of stdout
+    #[arg(short, long, value_name = "PATH")]
+    output: Option<PathBuf>,
+}
+
+/// Reads stdin for `-`, an existing file's bytes, or else the argument itself
+fn read_input(input: &str) -> Result<Vec<u8>, String> {
+    if input == "-" {
+        let mut data = Vec::new();
+        io::stdin()
+            .read_to_end(&mut data)
+            .map_err(|e| format!("Could not read stdin: {}", e))?;
+        return Ok(data);
+    }
+
+    let path = Path::new(input);
+    if path.is_file() {
+        fs::read(path).map_err(|e| format!("Could not read {}: {}", path.display(), e))
+    } else {
+        Ok(input.as_bytes().to_vec())
+    }
+}
+
+fn convert(args: &ConvertArgs) -> Result<(), String> {
+    let (psbt, detected) = format::decode(&read_input(&args.input)?)?;
+    eprintln!("Detected {} PSBT ({} inputs, {} outputs)",
+              detected.name(), psbt.inputs.len(), psbt.outputs.len());
+
+    let encoded = format::encode(&psbt, args.to);
+    match &args.output {
+        Some(path) => {
+            fs::write(path, &encoded).map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+            eprintln!("Wrote {} PSBT to {}", args.to.name(), path.display());
+        }
+        None => {
+            let mut stdout = io::stdout();
+            // Raw bytes would garble the terminal
+            if args.to == PsbtFormat::Binary && stdout.is_terminal() {
+                return Err("Refusing to write a binary PSBT to a terminal; use --output or redirect stdout".to_string());
+            }
+            stdout
+                .write_all(&encoded)
+                .map_err(|e| format!("Could not write to stdout: {}", e))?;
+        }
+    }
+    Ok(())
+}
+
+fn main() {
+    let cli = Cli::parse();
+
+    let result = match &cli.command {
+        Command::Convert(args) => convert(args),
+    };
+
+    if let Err(err) = result {
+        eprintln!("Error: {}", err);
+        std::process::exit(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_read_input_treats_non_files_as_text() {
+        assert_eq!(read_input("cHNidP8BAA==").unwrap(), b"cHNidP8BAA==");
+    }
+
+    #[test]
+    fn test_read_input_reads_files() {
+        let dir = std::env::temp_dir().join(format!("psbt-read-input-{}", rand::random::<u64>()));
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("input.psbt");
+        fs::write(&path, b"psbt\xff\x01").unwrap();
+        assert_eq!(read_input(path.to_str().unwrap()).unwrap(), b"psbt\xff\x01");
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
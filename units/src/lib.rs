@@ -0,0 +1,125 @@
+//! Amount units selected with `--unit` by the balance checker and block
+//! explorer, formatted with thousands separators
+//!
+//! Only printed reports use them. Batch records, JSON and CSV exports, and
+//! webhook payloads stay in sats for scripts to read.
+
+use clap::ValueEnum;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, ValueEnum)]
+pub enum Unit {
+    #[default]
+    Sats,
+    Btc,
+    /// 1 mBTC = 100,000 sats
+    Mbtc,
+    /// 1 bit = 100 sats
+    Bits,
+}
+
+impl Unit {
+    pub fn label(&self) -> &'static str {
+        match self {
+            Unit::Sats => "sats",
+            Unit::Btc => "BTC",
+            Unit::Mbtc => "mBTC",
+            Unit::Bits => "bits",
+        }
+    }
+
+    /// Decimal places, i.e. log10 of the sats per unit
+    fn decimals(&self) -> u32 {
+        match self {
+            Unit::Sats => 0,
+            Unit::Btc => 8,
+            Unit::Mbtc => 5,
+            Unit::Bits => 2,
+        }
+    }
+
+    /// The amount without a unit label, e.g. `1,234.56789012` for BTC
+    pub fn number(&self, sats: u64) -> String {
+        let scale = 10u64.pow(self.decimals());
+        let whole = group_thousands(sats / scale);
+        match self.decimals() {
+            0 => whole,
+            decimals => format!("{}.{:0width$}", whole, sats % scale, width = decimals as usize),
+        }
+    }
+
+    /// A signed amount, always with a leading `+` or `-`
+    pub fn signed_number(&self, sats: i64) -> String {
+        let sign = if sats < 0 { '-' } else { '+' };
+        format!("{}{}", sign, self.number(sats.unsigned_abs()))
+    }
+
+    /// The amount followed by the unit label
+    pub fn format(&self, sats: u64) -> String {
+        format!("{} {}", self.number(sats), self.label())
+    }
+
+    /// A change in value with its sign and the unit label, e.g. `+7,000 sats`
+    pub fn format_signed(&self, sats: i64) -> String {
+        format!("{} {}", self.signed_number(sats), self.label())
+    }
+
+    /// The amount without a unit label, signed only when negative, such as
+    /// a running balance
+    pub fn number_i64(&self, sats: i64) -> String {
+        let sign = if sats < 0 { "-" } else { "" };
+        format!("{}{}", sign, self.number(sats.unsigned_abs()))
+    }
+
+    /// A value that is only signed when negative, with the unit label
+    pub fn format_i64(&self, sats: i64) -> String {
+        format!("{} {}", self.number_i64(sats), self.label())
+    }
+}
+
+fn group_thousands(value: u64) -> String {
+    let digits = value.to_string();
+    let mut grouped = String::with_capacity(digits.len() + digits.len() / 3);
+    for (i, digit) in digits.chars().enumerate() {
+        if i > 0 && (digits.len() - i).is_multiple_of(3) {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_group_thousands() {
+        assert_eq!(group_thousands(0), "0");
+        assert_eq!(group_thousands(999), "999");
+        assert_eq!(group_thousands(1_000), "1,000");
+        assert_eq!(group_thousands(5_000_000_000), "5,000,000,000");
+    }
+
+    #[test]
+    fn test_format_in_each_unit() {
+        assert_eq!(Unit::Sats.format(2_449_190), "2,449,190 sats");
+        assert_eq!(Unit::Btc.format(2_449_190), "0.02449190 BTC");
+        assert_eq!(Unit::Mbtc.format(2_449_190), "24.49190 mBTC");
+        assert_eq!(Unit::Bits.format(2_449_190), "24,491.90 bits");
+        assert_eq!(Unit::Btc.format(123_456_789_000), "1,234.56789000 BTC");
+        assert_eq!(Unit::Btc.number(100_000_000), "1.00000000");
+        assert_eq!(Unit::Btc.number(50_000_000), "0.50000000");
+        assert_eq!(Unit::Btc.number(1), "0.00000001");
+        assert_eq!(Unit::Btc.number(0), "0.00000000");
+    }
+
+    #[test]
+    fn test_signed_number() {
+        assert_eq!(Unit::Btc.signed_number(-4_500), "-0.00004500");
+        assert_eq!(Unit::Sats.signed_number(7_000), "+7,000");
+        assert_eq!(Unit::Sats.signed_number(0), "+0");
+        assert_eq!(Unit::Sats.format_signed(-1_234), "-1,234 sats");
+        assert_eq!(Unit::Mbtc.format_i64(-150_000), "-1.50000 mBTC");
+        assert_eq!(Unit::Mbtc.format_i64(150_000), "1.50000 mBTC");
+    }
+}